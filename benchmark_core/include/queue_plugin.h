@@ -0,0 +1,50 @@
+#pragma once
+
+// ABI of queue plugins, shared libraries that the plugin_queue binary loads
+// with --plugin. Mirrors benchmark_core/src/plugin.rs.
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define QUEUE_PLUGIN_ABI_VERSION 1
+
+// All functions may be called concurrently from different threads, except
+// create and destroy. Items are pointers owned by the benchmark, the queue
+// only stores and returns them. They are never null.
+typedef struct queue_plugin {
+    // Must be QUEUE_PLUGIN_ABI_VERSION.
+    uint32_t abi_version;
+    // Returns the name of the queue, used in the result files.
+    const char* (*name)(void);
+    // Creates a queue. Bounded queues hold capacity items.
+    // Returns NULL if the queue could not be created.
+    void* (*create)(size_t capacity);
+    // Destroys a queue. All handles are unregistered and the queue is empty.
+    void (*destroy)(void* queue);
+    // Returns a handle for the calling thread, or NULL if no more handles
+    // can be registered.
+    void* (*register_thread)(void* queue);
+    // Releases a handle, its thread won't use it again.
+    void (*unregister_thread)(void* handle);
+    // Pushes item. Returns 1 on success and 0 if the queue is full.
+    int (*push)(void* handle, void* item);
+    // Pops an item into *item. Returns 1 on success and 0 if the queue is empty.
+    int (*pop)(void* handle, void** item);
+    // Returns the amount of items the queue holds at most, NULL for
+    // unbounded queues.
+    size_t (*capacity)(void* queue);
+    // How many handles may be registered at the same time, 0 if there is no
+    // limit.
+    size_t max_threads;
+} queue_plugin;
+
+// Every plugin exports this function.
+const queue_plugin* queue_plugin_entry(void);
+
+#ifdef __cplusplus
+}
+#endif
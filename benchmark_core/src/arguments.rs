@@ -1,4 +1,6 @@
-use clap::{ArgAction, Args as ClapArgs, Parser, Subcommand};
+use crate::backoff::BackoffStrategy;
+use crate::work::ThinkTimeDistribution;
+use clap::{ArgAction, Args as ClapArgs, Parser, Subcommand, ValueEnum};
 use std::fmt::Display;
 
 /// General arguments for all benchmarks
@@ -16,9 +18,18 @@ pub struct GeneralArgs {
     #[arg(short, long, default_value_t = 1)]
     pub iterations: u32,
 
-    /// Set the amount of floating point numbers generated between each operation. Default is 10.
-    #[arg(short, long, default_value_t = 10)]
-    pub delay: u64,
+    /// Mean time a thread busy-waits between two operations to simulate work [ns].
+    /// Has no short flag, `-d` used to set an amount of random numbers instead.
+    #[arg(long, default_value_t = 100)]
+    pub think_time: u64,
+
+    /// Distribution the think time between two operations is drawn from.
+    #[arg(long, value_enum, default_value_t = ThinkTimeDistribution::Fixed)]
+    pub think_dist: ThinkTimeDistribution,
+
+    /// Size of the private buffer each thread touches while thinking [bytes]. 0 only spins.
+    #[arg(long, default_value_t = 0)]
+    pub work_buffer: usize,
 
     /// Set the output path for the result files.
     #[arg(long = "path", default_value_t = String::from("./output"))]
@@ -32,6 +43,18 @@ pub struct GeneralArgs {
     #[arg(long, default_value_t = false, action = ArgAction::SetTrue)]
     pub print_info: bool,
 
+    /// Backoff strategy used after failed operations, e.g. a pop on an empty queue.
+    #[arg(long, value_enum, default_value_t = BackoffStrategy::None)]
+    pub backoff: BackoffStrategy,
+
+    /// How long the park backoff strategy parks a thread [µs].
+    #[arg(long, default_value_t = 50)]
+    pub park_timeout: u64,
+
+    /// Seed of all random choices of the run, to repeat it. A random seed is drawn and reported if not set.
+    #[arg(long)]
+    pub seed: Option<u64>,
+
     #[cfg(feature = "memory_tracking")]
     /// The interval of which memory tracking will update [ms].
     #[arg(long, default_value_t = 50)]
@@ -58,11 +81,44 @@ pub struct FifoQueueArgs {
     #[arg(short, long, default_value_t = 0)]
     pub prefill_amount: u64,
 
+    /// What producers should do when a push fails because a bounded queue is full.
+    #[arg(long, value_enum, default_value_t = FullPolicy::Drop)]
+    pub full_policy: FullPolicy,
+
+    /// Push and pop this many items at a time in the throughput runners.
+    /// Queues without native bulk operations fall back to single operations.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    pub batch_size: u64,
+
     /// General arguments agnostic to the FIFO Queue
     #[command(flatten)]
     pub general_args: GeneralArgs,
 }
 
+/// Arguments for benchmarking a FIFO Queue loaded from a shared library
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct PluginQueueArgs {
+    /// Path to the shared library implementing the queue plugin ABI.
+    #[arg(long)]
+    pub plugin: std::path::PathBuf,
+
+    /// Arguments for the FIFO Queue benchmark types
+    #[command(flatten)]
+    pub fifo_queue_args: FifoQueueArgs,
+}
+
+/// How producers handle a push that failed because the queue was full.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FullPolicy {
+    /// Retry the push immediately until it succeeds.
+    Retry,
+    /// Drop the item and move on to the next operation.
+    Drop,
+    /// Retry the push, waiting with the `--backoff` strategy between retries.
+    Backoff,
+}
+
 /// Benchmark runners for FIFO Queues.
 #[derive(Subcommand, Debug)]
 pub enum FifoQueueBenchmarks {
@@ -70,6 +126,15 @@ pub enum FifoQueueBenchmarks {
     /// are each bound to a thread
     ProdCon(FifoQueueProdConArgs),
 
+    /// Like prod-con, but with a single producer and a single consumer
+    Spsc,
+
+    /// Like prod-con, but with a single consumer
+    Mpsc(FifoQueueMpscArgs),
+
+    /// Like prod-con, but with a single producer
+    Spmc(FifoQueueSpmcArgs),
+
     /// A benchmark measuring throughput where a thread will switch between
     /// producing and consuming
     EnqDeq(FifoQueueEnqDeqArgs),
@@ -78,6 +143,47 @@ pub enum FifoQueueBenchmarks {
     /// and then immediately dequeue it
     EnqDeqPairs(FifoQueueEnqDeqPairsArgs),
 
+    /// A benchmark running a schedule of phases, e.g. bursts, drains and
+    /// idle periods, reporting throughput and latency per phase
+    Phased(FifoQueuePhasedArgs),
+
+    /// A benchmark chaining several copies of the queue into a pipeline of
+    /// stages, reporting end-to-end throughput, occupancy and latency
+    Pipeline(FifoQueuePipelineArgs),
+
+    /// A benchmark where consumers block on an empty queue instead of
+    /// spinning, measuring wake-up latency and CPU time spent idle. Only
+    /// available for queues implementing the blocking API.
+    Blocking(FifoQueueBlockingArgs),
+
+    /// Uses the queue as a shared task pool for recursive divide-and-conquer
+    /// workloads, measuring completion time and tasks per second
+    Tasks(FifoQueueTasksArgs),
+
+    /// Runs enqueue-dequeue pairs with more threads than cores, optionally
+    /// unpinned or with real-time scheduling, and compares throughput and
+    /// fairness to a pinned baseline with one thread per core
+    Oversubscribe(FifoQueueOversubscribeArgs),
+
+    /// Repeatedly spawns short-lived threads that register a handle, do a few
+    /// operations and exit, measuring the cost of registering and dropping
+    /// handles and the memory growth over many thread lifetimes
+    Churn(FifoQueueChurnArgs),
+
+    /// Runs enqueue-dequeue pairs while stalling some of the threads for long
+    /// intervals, checking that the other threads keep completing operations
+    Progress(FifoQueueProgressArgs),
+
+    /// Runs the per-thread operation scripts of a trace, recorded with
+    /// enq-deq --record or generated from a seed, so that every queue does
+    /// exactly the same work
+    Replay(FifoQueueReplayArgs),
+
+    /// Measures how far from FIFO order items are dequeued, as the
+    /// distribution of the rank error: how many older items were still in the
+    /// queue when an item was dequeued
+    Reorder(FifoQueueReorderArgs),
+
     /// Benchmarks how fast the FIFO Queue can complete a breadth-first search
     /// on a graph
     BFS(FifoQueueBFSArgs),
@@ -92,6 +198,38 @@ pub struct FifoQueueProdConArgs {
     /// Amount of consumers to be used
     #[arg(short, long, default_value_t = 20)]
     pub consumers: usize,
+
+    /// Run the producers open-loop, offering this many pushes per second in
+    /// total instead of pushing as fast as possible.
+    #[arg(long)]
+    pub rate: Option<f64>,
+
+    /// How the arrivals of the open-loop producers are spaced.
+    #[arg(long, value_enum, default_value_t = Arrivals::Poisson, requires = "rate")]
+    pub arrivals: Arrivals,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct FifoQueueMpscArgs {
+    /// Amount of producers to be used
+    #[arg(short, long, default_value_t = 20)]
+    pub producers: usize,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct FifoQueueSpmcArgs {
+    /// Amount of consumers to be used
+    #[arg(short, long, default_value_t = 20)]
+    pub consumers: usize,
+}
+
+/// Arrival processes for open-loop producers.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arrivals {
+    /// Evenly spaced arrivals.
+    Constant,
+    /// Exponentially distributed time between arrivals.
+    Poisson,
 }
 
 #[derive(ClapArgs, Debug)]
@@ -104,6 +242,18 @@ pub struct FifoQueueEnqDeqArgs {
     /// Ex. 0.3 means 30% produce 70% consume.
     #[arg(long = "spread", default_value_t = 0.5)]
     pub spread: f64,
+
+    /// Record the operations and think times of every thread to this trace
+    /// file, to replay them with the replay benchmark.
+    #[arg(long)]
+    pub record: Option<String>,
+
+    /// Most operations recorded per thread with --record. A thread stops
+    /// recording after that, so the trace it keeps in memory during the run
+    /// stays bounded, and its buffer is reserved for that many operations
+    /// before the run starts.
+    #[arg(long = "record-limit", default_value_t = 10_000_000)]
+    pub record_limit: usize,
 }
 
 #[derive(ClapArgs, Debug)]
@@ -113,6 +263,372 @@ pub struct FifoQueueEnqDeqPairsArgs {
     pub thread_count: usize,
 }
 
+#[derive(ClapArgs, Debug)]
+pub struct FifoQueuePhasedArgs {
+    /// Set the thread count for the phased benchmark.
+    #[arg(long = "thread-count", default_value_t = 20)]
+    pub thread_count: usize,
+
+    /// Comma separated phases as `kind:milliseconds[:producers]`, where kind
+    /// is burst, drain, idle or mixed. Producers overrides how many of the
+    /// threads produce, the rest consume.
+    #[arg(long, value_delimiter = ',', default_value = "burst:500,drain:500,idle:200,mixed:1000")]
+    pub schedule: Vec<Phase>,
+
+    /// How many times the schedule is repeated. Replaces the time limit.
+    #[arg(long, default_value_t = 1)]
+    pub cycles: usize,
+}
+
+/// The kinds of phases in a phased benchmark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhaseKind {
+    /// All threads produce.
+    Burst,
+    /// All threads consume.
+    Drain,
+    /// No thread touches the queue.
+    Idle,
+    /// Half of the threads produce, the other half consume.
+    Mixed,
+}
+
+/// A phase in the schedule of a phased benchmark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Phase {
+    pub kind: PhaseKind,
+    pub duration_ms: u64,
+    pub producers: Option<usize>,
+}
+
+impl Phase {
+    /// Returns how many of `thread_count` threads produce and consume in this phase.
+    pub fn roles(&self, thread_count: usize) -> (usize, usize) {
+        if self.kind == PhaseKind::Idle {
+            return (0, 0);
+        }
+        let producers = self.producers.unwrap_or(match self.kind {
+            PhaseKind::Burst => thread_count,
+            PhaseKind::Mixed => thread_count / 2,
+            PhaseKind::Drain | PhaseKind::Idle => 0,
+        });
+        let producers = usize::min(producers, thread_count);
+        (producers, thread_count - producers)
+    }
+}
+
+impl std::str::FromStr for Phase {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().split(':');
+        let kind = match parts.next() {
+            Some("burst") => PhaseKind::Burst,
+            Some("drain") => PhaseKind::Drain,
+            Some("idle") => PhaseKind::Idle,
+            Some("mixed") => PhaseKind::Mixed,
+            _ => return Err(format!("unknown phase kind in '{s}', expected burst, drain, idle or mixed")),
+        };
+        let duration_ms = parts
+            .next()
+            .and_then(|d| d.parse().ok())
+            .ok_or(format!("phase '{s}' needs a duration in milliseconds"))?;
+        let producers = match parts.next() {
+            Some(p) => Some(p.parse().map_err(|_| format!("invalid producer count in phase '{s}'"))?),
+            None => None,
+        };
+        if parts.next().is_some() {
+            return Err(format!("too many fields in phase '{s}'"));
+        }
+        Ok(Phase { kind, duration_ms, producers })
+    }
+}
+
+impl Display for PhaseKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PhaseKind::Burst => write!(f, "burst"),
+            PhaseKind::Drain => write!(f, "drain"),
+            PhaseKind::Idle => write!(f, "idle"),
+            PhaseKind::Mixed => write!(f, "mixed"),
+        }
+    }
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct FifoQueuePipelineArgs {
+    /// Amount of stages. The first stage produces items, the last one
+    /// consumes them and every stage in between pops from the queue in front
+    /// of it and pushes to the queue behind it.
+    #[arg(long, default_value_t = 3)]
+    pub stages: usize,
+
+    /// Comma separated amount of threads per stage, or a single value used
+    /// for every stage.
+    #[arg(long, value_delimiter = ',', default_value = "4")]
+    pub threads: Vec<usize>,
+
+    /// Comma separated think time per stage [ns], or a single value used for
+    /// every stage. Defaults to --think-time.
+    #[arg(long, value_delimiter = ',')]
+    pub stage_work: Vec<u64>,
+}
+
+impl FifoQueuePipelineArgs {
+    /// Returns the amount of threads in `stage`, or 0 if it isn't configured.
+    pub fn stage_threads(&self, stage: usize) -> usize {
+        per_stage(&self.threads, stage).unwrap_or(0)
+    }
+
+    /// Returns the think time of `stage` [ns], or `default` if it isn't
+    /// configured.
+    pub fn stage_work(&self, stage: usize, default: u64) -> u64 {
+        per_stage(&self.stage_work, stage).unwrap_or(default)
+    }
+}
+
+/// Picks the value of `stage` from a per-stage list, where a single value
+/// applies to every stage.
+fn per_stage<T: Copy>(values: &[T], stage: usize) -> Option<T> {
+    match values {
+        [value] => Some(*value),
+        values => values.get(stage).copied(),
+    }
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct FifoQueueBlockingArgs {
+    /// Amount of producers to be used
+    #[arg(short, long, default_value_t = 1)]
+    pub producers: usize,
+
+    /// Amount of consumers to be used
+    #[arg(short, long, default_value_t = 4)]
+    pub consumers: usize,
+
+    /// Time each producer sleeps between pushes [µs].
+    #[arg(long, default_value_t = 100)]
+    pub interval: u64,
+
+    /// Pop with this timeout [µs] instead of blocking until an item arrives.
+    #[arg(long)]
+    pub timeout: Option<u64>,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct FifoQueueTasksArgs {
+    /// Set the thread count for the tasks benchmark.
+    #[arg(long = "thread-count", default_value_t = 20)]
+    pub thread_count: usize,
+
+    /// The divide-and-conquer workload to run.
+    #[arg(long, value_enum, default_value_t = TaskWorkload::Fib)]
+    pub workload: TaskWorkload,
+
+    /// Problem size: n for fib, the amount of elements for quicksort and the
+    /// amount of children of the root for uts. Defaults to 30, 1000000 and 2000.
+    #[arg(long)]
+    pub size: Option<u64>,
+
+    /// Problems of this size or smaller are solved sequentially within one
+    /// task. Only used by fib and quicksort, defaults to 15 and 1000.
+    #[arg(long)]
+    pub cutoff: Option<u64>,
+
+    /// Probability that a uts node below the root has children.
+    #[arg(long, default_value_t = 0.124875)]
+    pub uts_q: f64,
+
+    /// Amount of children of a uts node that has children.
+    #[arg(long, default_value_t = 8)]
+    pub uts_m: usize,
+}
+
+impl FifoQueueTasksArgs {
+    /// Returns the problem size, or the default of the workload if it isn't set.
+    pub fn problem_size(&self) -> u64 {
+        self.size.unwrap_or(match self.workload {
+            TaskWorkload::Fib => 30,
+            TaskWorkload::Quicksort => 1_000_000,
+            TaskWorkload::Uts => 2000,
+        })
+    }
+}
+
+/// Workloads of the tasks benchmark.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskWorkload {
+    /// Naive recursive Fibonacci, every call is a task.
+    Fib,
+    /// Parallel quicksort of random numbers, every partition is a task.
+    Quicksort,
+    /// Unbalanced tree search on a binomial tree, every node is a task.
+    Uts,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct FifoQueueOversubscribeArgs {
+    /// Threads per core to run after the pinned baseline, separated by commas.
+    #[arg(long, value_delimiter = ',', default_value = "2,4,8")]
+    pub factors: Vec<usize>,
+
+    /// Amount of cores to use, defaults to all available cores.
+    #[arg(long)]
+    pub cores: Option<usize>,
+
+    /// Don't pin the oversubscribed threads, leaving placement to the OS.
+    #[arg(long, default_value_t = false)]
+    pub unpinned: bool,
+
+    /// Scheduling policy of the oversubscribed threads.
+    #[arg(long, value_enum, default_value_t = SchedPolicy::Other)]
+    pub sched: SchedPolicy,
+}
+
+impl FifoQueueOversubscribeArgs {
+    /// Returns the amount of cores to use, all available cores if not set.
+    pub fn core_count(&self) -> usize {
+        self.cores
+            .unwrap_or_else(|| core_affinity::get_core_ids().map_or(1, |c| c.len()))
+            .max(1)
+    }
+
+    /// Returns the most threads running at once, i.e. in the largest round.
+    pub fn max_threads(&self) -> usize {
+        self.core_count() * self.factors.iter().copied().fold(1, usize::max)
+    }
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct FifoQueueChurnArgs {
+    /// Amount of short-lived threads alive at the same time.
+    #[arg(long = "thread-count", default_value_t = 4)]
+    pub thread_count: usize,
+
+    /// Total amount of thread lifetimes.
+    #[arg(long, default_value_t = 10000)]
+    pub lifetimes: usize,
+
+    /// Enqueue-dequeue pairs every thread does before exiting.
+    #[arg(long, default_value_t = 100)]
+    pub ops: usize,
+
+    /// Amount of windows the lifetimes are reported in, one row each.
+    #[arg(long, default_value_t = 10)]
+    pub windows: usize,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct FifoQueueProgressArgs {
+    /// Set the thread count for the progress benchmark.
+    #[arg(long = "thread-count", default_value_t = 8)]
+    pub thread_count: usize,
+
+    /// Amount of threads stalled at the same time.
+    #[arg(long, default_value_t = 1)]
+    pub stalled: usize,
+
+    /// Amount of stalls. Replaces the time limit.
+    #[arg(long, default_value_t = 5)]
+    pub stalls: usize,
+
+    /// How long the threads are stalled [ms].
+    #[arg(long = "stall-time", default_value_t = 500)]
+    pub stall_time: u64,
+
+    /// Mean time the threads run undisturbed before every stall [ms].
+    #[arg(long, default_value_t = 200)]
+    pub gap: u64,
+
+    /// Length of the windows the throughput during a stall is sampled in [ms].
+    #[arg(long, default_value_t = 10)]
+    pub window: u64,
+
+    /// How the threads are stalled.
+    #[arg(long, value_enum, default_value_t = StallMode::Signal)]
+    pub mode: StallMode,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct FifoQueueReorderArgs {
+    /// Set the thread count for the reorder benchmark.
+    #[arg(long = "thread-count", default_value_t = 20)]
+    pub thread_count: usize,
+
+    /// Operations per thread. Replaces the time limit, which only stops runs that take too long.
+    #[arg(long, default_value_t = 100000)]
+    pub ops: usize,
+
+    /// Share of the operations that are pushes, like --spread of enq-deq.
+    #[arg(long, default_value_t = 0.5)]
+    pub spread: f64,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct FifoQueueReplayArgs {
+    /// Trace file to replay. Without one a trace is generated.
+    #[arg(long, conflicts_with = "seed")]
+    pub trace: Option<String>,
+
+    /// Generate the trace from this seed instead of the seed of the run.
+    /// Think times are drawn with --think-time and --think-dist.
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Amount of threads of a generated trace.
+    #[arg(long = "thread-count", default_value_t = 20)]
+    pub thread_count: usize,
+
+    /// Operations per thread of a generated trace.
+    #[arg(long, default_value_t = 100000)]
+    pub ops: usize,
+
+    /// Share of pushes in a generated trace, like --spread of enq-deq.
+    #[arg(long, default_value_t = 0.5)]
+    pub spread: f64,
+
+    /// Write the generated trace to this file.
+    #[arg(long, conflicts_with = "trace")]
+    pub save: Option<String>,
+
+    /// Multiply every think time of the trace by this factor.
+    #[arg(long, default_value_t = 1.0)]
+    pub dilation: f64,
+}
+
+/// Ways to stall a thread in the progress benchmark.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StallMode {
+    /// Send the thread a signal whose handler waits until the stall is over.
+    /// Stops the thread at any point, also in the middle of an operation.
+    /// Only supported on Linux.
+    Signal,
+    /// The thread pauses itself between two operations.
+    Hook,
+}
+
+/// Scheduling policies for oversubscribed threads.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchedPolicy {
+    /// The default time-sharing policy, SCHED_OTHER.
+    Other,
+    /// Real-time FIFO scheduling for every thread, SCHED_FIFO.
+    Fifo,
+    /// SCHED_FIFO for every other thread, SCHED_OTHER for the rest.
+    Mixed,
+}
+
+impl SchedPolicy {
+    /// Returns true if thread `i` should be scheduled with SCHED_FIFO.
+    pub fn is_fifo(&self, i: usize) -> bool {
+        match self {
+            SchedPolicy::Other => false,
+            SchedPolicy::Fifo => true,
+            SchedPolicy::Mixed => i % 2 == 1,
+        }
+    }
+}
+
 #[derive(ClapArgs, Debug)]
 pub struct FifoQueueBFSArgs {
     #[arg(short, long, default_value_t = 20)]
@@ -165,14 +681,95 @@ pub struct PQProdConArgs {
     pub consumers: usize,
 }
 
+impl FifoQueueBenchmarks {
+    /// Returns the most threads that push and the most threads that pop
+    /// concurrently during the benchmark.
+    pub fn roles(&self) -> (usize, usize) {
+        match self {
+            FifoQueueBenchmarks::ProdCon(a) => (a.producers, a.consumers),
+            FifoQueueBenchmarks::Spsc => (1, 1),
+            FifoQueueBenchmarks::Mpsc(a) => (a.producers, 1),
+            FifoQueueBenchmarks::Spmc(a) => (1, a.consumers),
+            FifoQueueBenchmarks::EnqDeq(a) => (a.thread_count, a.thread_count),
+            FifoQueueBenchmarks::EnqDeqPairs(a) => (a.thread_count, a.thread_count),
+            FifoQueueBenchmarks::Phased(a) => a.schedule.iter().fold((0, 0), |(p, c), phase| {
+                let (producers, consumers) = phase.roles(a.thread_count);
+                (usize::max(p, producers), usize::max(c, consumers))
+            }),
+            FifoQueueBenchmarks::Pipeline(a) => {
+                // Every queue is pushed to by one stage and popped from by the next
+                let stages = a.stages.max(1);
+                let producers = (0..stages - 1).map(|s| a.stage_threads(s)).max();
+                let consumers = (1..stages).map(|s| a.stage_threads(s)).max();
+                (producers.unwrap_or(0), consumers.unwrap_or(0))
+            }
+            FifoQueueBenchmarks::Blocking(a) => (a.producers, a.consumers),
+            FifoQueueBenchmarks::Tasks(a) => (a.thread_count, a.thread_count),
+            FifoQueueBenchmarks::Oversubscribe(a) => (a.max_threads(), a.max_threads()),
+            FifoQueueBenchmarks::Churn(a) => (a.thread_count, a.thread_count),
+            FifoQueueBenchmarks::Progress(a) => (a.thread_count, a.thread_count),
+            // The thread count of a trace file is checked once it is read
+            FifoQueueBenchmarks::Replay(a) if a.trace.is_some() => (0, 0),
+            FifoQueueBenchmarks::Replay(a) => (a.thread_count, a.thread_count),
+            FifoQueueBenchmarks::Reorder(a) => (a.thread_count, a.thread_count),
+            FifoQueueBenchmarks::BFS(a) => (a.thread_count, a.thread_count),
+        }
+    }
+
+    /// Returns the most handles registered to one queue at the same time
+    /// during the benchmark.
+    pub fn handles(&self) -> usize {
+        match self {
+            FifoQueueBenchmarks::ProdCon(a) => a.producers + a.consumers,
+            FifoQueueBenchmarks::Spsc => 2,
+            FifoQueueBenchmarks::Mpsc(a) => a.producers + 1,
+            FifoQueueBenchmarks::Spmc(a) => 1 + a.consumers,
+            FifoQueueBenchmarks::EnqDeq(a) => a.thread_count,
+            FifoQueueBenchmarks::EnqDeqPairs(a) => a.thread_count,
+            FifoQueueBenchmarks::Phased(a) => a.thread_count,
+            FifoQueueBenchmarks::Pipeline(a) => {
+                // Both stages around a queue hold a handle to it
+                let stages = a.stages.max(1);
+                (0..stages - 1)
+                    .map(|s| a.stage_threads(s) + a.stage_threads(s + 1))
+                    .max()
+                    .unwrap_or(0)
+            }
+            // The main thread closes the queue while the workers still run
+            FifoQueueBenchmarks::Blocking(a) => a.producers + a.consumers + 1,
+            FifoQueueBenchmarks::Tasks(a) => a.thread_count,
+            FifoQueueBenchmarks::Oversubscribe(a) => a.max_threads(),
+            FifoQueueBenchmarks::Churn(a) => a.thread_count,
+            FifoQueueBenchmarks::Progress(a) => a.thread_count,
+            // The thread count of a trace file is checked once it is read
+            FifoQueueBenchmarks::Replay(a) if a.trace.is_some() => 0,
+            FifoQueueBenchmarks::Replay(a) => a.thread_count,
+            FifoQueueBenchmarks::Reorder(a) => a.thread_count,
+            FifoQueueBenchmarks::BFS(a) => a.thread_count,
+        }
+    }
+}
+
 /// This is used to write the benchmark type to the output.
 /// That is why the arguments are discarded.
 impl Display for FifoQueueBenchmarks {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             FifoQueueBenchmarks::ProdCon(_) => write!(f, "ProdCon"),
+            FifoQueueBenchmarks::Spsc => write!(f, "Spsc"),
+            FifoQueueBenchmarks::Mpsc(_) => write!(f, "Mpsc"),
+            FifoQueueBenchmarks::Spmc(_) => write!(f, "Spmc"),
             FifoQueueBenchmarks::EnqDeq(_) => write!(f, "EnqDeq"),
             FifoQueueBenchmarks::EnqDeqPairs(_) => write!(f, "EnqDeqPairs"),
+            FifoQueueBenchmarks::Phased(_) => write!(f, "Phased"),
+            FifoQueueBenchmarks::Pipeline(_) => write!(f, "Pipeline"),
+            FifoQueueBenchmarks::Blocking(_) => write!(f, "Blocking"),
+            FifoQueueBenchmarks::Tasks(_) => write!(f, "Tasks"),
+            FifoQueueBenchmarks::Oversubscribe(_) => write!(f, "Oversubscribe"),
+            FifoQueueBenchmarks::Churn(_) => write!(f, "Churn"),
+            FifoQueueBenchmarks::Progress(_) => write!(f, "Progress"),
+            FifoQueueBenchmarks::Replay(_) => write!(f, "Replay"),
+            FifoQueueBenchmarks::Reorder(_) => write!(f, "Reorder"),
             // #[cfg(feature = "bfs")]
             FifoQueueBenchmarks::BFS(_) => write!(f, "BFS"),
         }
@@ -189,15 +786,70 @@ impl Display for PriorityQueueBenchmarks {
     }
 }
 
+impl Display for FullPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FullPolicy::Retry => write!(f, "retry"),
+            FullPolicy::Drop => write!(f, "drop"),
+            FullPolicy::Backoff => write!(f, "backoff"),
+        }
+    }
+}
+
+impl Display for Arrivals {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Arrivals::Constant => write!(f, "constant"),
+            Arrivals::Poisson => write!(f, "poisson"),
+        }
+    }
+}
+
+impl Display for TaskWorkload {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaskWorkload::Fib => write!(f, "fib"),
+            TaskWorkload::Quicksort => write!(f, "quicksort"),
+            TaskWorkload::Uts => write!(f, "uts"),
+        }
+    }
+}
+
+impl Display for SchedPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchedPolicy::Other => write!(f, "other"),
+            SchedPolicy::Fifo => write!(f, "fifo"),
+            SchedPolicy::Mixed => write!(f, "mixed"),
+        }
+    }
+}
+
+impl Display for StallMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StallMode::Signal => write!(f, "signal"),
+            StallMode::Hook => write!(f, "hook"),
+        }
+    }
+}
+
 /// This is used in the print_info function.
 impl Display for GeneralArgs {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "Time limit:             {}", self.time_limit)?;
         writeln!(f, "One socket?:            {}", self.one_socket)?;
         writeln!(f, "Iterations:             {}", self.iterations)?;
-        writeln!(f, "Delay:                  {}", self.delay)?;
+        writeln!(f, "Think time:             {}", self.think_time)?;
+        writeln!(f, "Think distribution:     {}", self.think_dist)?;
+        writeln!(f, "Work buffer:            {}", self.work_buffer)?;
         writeln!(f, "Output path:            {}", self.path_output)?;
         writeln!(f, "Write to stdout:        {}", self.write_to_stdout)?;
+        writeln!(f, "Backoff:                {}", self.backoff)?;
+        writeln!(f, "Park timeout:           {}", self.park_timeout)?;
+        if let Some(seed) = self.seed {
+            writeln!(f, "Seed:                   {}", seed)?;
+        }
         Ok(())
     }
 }
@@ -209,10 +861,15 @@ impl Default for GeneralArgs {
             time_limit: 1,
             one_socket: true,
             iterations: 1,
-            delay: 10,
+            think_time: 100,
+            think_dist: ThinkTimeDistribution::Fixed,
+            work_buffer: 0,
             path_output: "".to_string(),
             write_to_stdout: true,
             print_info: false,
+            backoff: BackoffStrategy::None,
+            park_timeout: 50,
+            seed: None,
             #[cfg(feature = "memory_tracking")]
             memory_tracking_interval: 50,
         }
@@ -229,9 +886,13 @@ impl Default for FifoQueueArgs {
                 FifoQueueProdConArgs {
                     producers: 20,
                     consumers: 20,
+                    rate: None,
+                    arrivals: Arrivals::Poisson,
                 },
             ),
             prefill_amount: 1000,
+            full_policy: FullPolicy::Drop,
+            batch_size: 1,
             general_args: GeneralArgs::default(),
         }
     }
@@ -0,0 +1,134 @@
+use clap::ValueEnum;
+use std::fmt::Display;
+use std::time::Duration;
+
+/// The largest exponent used by the exponential strategy, i.e. it never spins
+/// more than `2^MAX_EXPONENT` times in a row.
+const MAX_EXPONENT: u32 = 10;
+
+/// Strategies for waiting after an operation failed, e.g. a pop on an empty
+/// queue or a push on a full queue.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackoffStrategy {
+    /// Retry immediately.
+    None,
+    /// Issue a single spin loop hint before retrying.
+    SpinHint,
+    /// Spin for an exponentially growing amount of iterations.
+    Exponential,
+    /// Yield the thread to the OS scheduler.
+    Yield,
+    /// Park the thread until the park timeout runs out.
+    Park,
+}
+
+impl Display for BackoffStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackoffStrategy::None => write!(f, "none"),
+            BackoffStrategy::SpinHint => write!(f, "spin-hint"),
+            BackoffStrategy::Exponential => write!(f, "exponential"),
+            BackoffStrategy::Yield => write!(f, "yield"),
+            BackoffStrategy::Park => write!(f, "park"),
+        }
+    }
+}
+
+/// Thread-local backoff state.
+/// Call `backoff` after a failed operation and `reset` after a successful one.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    strategy: BackoffStrategy,
+    park_timeout: Duration,
+    exponent: u32,
+    count: usize,
+}
+
+impl Backoff {
+    pub fn new(strategy: BackoffStrategy, park_timeout: Duration) -> Self {
+        Backoff {
+            strategy,
+            park_timeout,
+            exponent: 0,
+            count: 0,
+        }
+    }
+
+    /// Creates a backoff from the general arguments of the benchmark.
+    pub fn from_args(args: &crate::arguments::GeneralArgs) -> Self {
+        Backoff::new(args.backoff, Duration::from_micros(args.park_timeout))
+    }
+
+    /// Waits according to the strategy.
+    pub fn backoff(&mut self) {
+        match self.strategy {
+            BackoffStrategy::None => return,
+            BackoffStrategy::SpinHint => std::hint::spin_loop(),
+            BackoffStrategy::Exponential => {
+                for _ in 0..(1u32 << self.exponent) {
+                    std::hint::spin_loop();
+                }
+                self.exponent = u32::min(self.exponent + 1, MAX_EXPONENT);
+            }
+            BackoffStrategy::Yield => std::thread::yield_now(),
+            BackoffStrategy::Park => std::thread::park_timeout(self.park_timeout),
+        }
+        self.count += 1;
+    }
+
+    /// Resets the exponential strategy after a successful operation.
+    pub fn reset(&mut self) {
+        self.exponent = 0;
+    }
+
+    /// Returns how many times the thread has backed off.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn strategy(&self) -> BackoffStrategy {
+        self.strategy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn none_never_counts() {
+        let mut backoff = Backoff::new(BackoffStrategy::None, Duration::ZERO);
+        backoff.backoff();
+        backoff.backoff();
+        assert_eq!(backoff.count(), 0);
+    }
+
+    #[test]
+    fn strategies_count_backoffs() {
+        for strategy in [
+            BackoffStrategy::SpinHint,
+            BackoffStrategy::Exponential,
+            BackoffStrategy::Yield,
+            BackoffStrategy::Park,
+        ] {
+            let mut backoff =
+                Backoff::new(strategy, Duration::from_micros(1));
+            backoff.backoff();
+            backoff.reset();
+            backoff.backoff();
+            assert_eq!(backoff.count(), 2, "{strategy}");
+        }
+    }
+
+    #[test]
+    fn exponential_is_capped() {
+        let mut backoff =
+            Backoff::new(BackoffStrategy::Exponential, Duration::ZERO);
+        for _ in 0..(MAX_EXPONENT + 5) {
+            backoff.backoff();
+        }
+        assert_eq!(backoff.exponent, MAX_EXPONENT);
+        backoff.reset();
+        assert_eq!(backoff.exponent, 0);
+    }
+}
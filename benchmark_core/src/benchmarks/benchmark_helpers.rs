@@ -1,16 +1,18 @@
-use crate::arguments::GeneralArgs;
-#[cfg(feature = "memory_tracking")]
-use crate::traits::ConcurrentQueue;
+use crate::arguments::{FifoQueueBenchmarks, FullPolicy, GeneralArgs};
+use crate::backoff::Backoff;
+use crate::error::Error;
+use crate::rng::{self, Stream};
+use crate::traits::{Capabilities, ConcurrentQueue, HandleQueue, HandleQueueBatch};
 use chrono::Local;
 #[cfg(feature = "memory_tracking")]
 use jemalloc_ctl::{epoch, stats};
-use log::{debug, error, trace};
+use log::{debug, error, info, trace, warn};
+use rand::rngs::SmallRng;
 use std::collections::hash_map::DefaultHasher;
 use std::fs::OpenOptions;
 use std::hash::{Hash, Hasher};
 use std::io::Write;
-#[cfg(feature = "memory_tracking")]
-use std::sync::atomic::AtomicBool;
+use std::sync::atomic::{AtomicBool, Ordering};
 use sysinfo::System;
 
 /// Benchmark config struct
@@ -22,6 +24,18 @@ pub struct BenchConfig {
     pub output_filename: String,
 }
 
+impl BenchConfig {
+    /// Returns the seed of the run, drawn by `create_bench_config` if none was given.
+    pub fn seed(&self) -> u64 {
+        self.args.seed.unwrap_or_default()
+    }
+
+    /// Returns the generator of thread `thread` for `stream`.
+    pub fn thread_rng(&self, stream: Stream, thread: usize) -> SmallRng {
+        rng::thread_rng(self.seed(), stream, thread)
+    }
+}
+
 #[cfg(feature = "memory_tracking")]
 pub fn create_mem_tracking_thread<Q, T>(
     bench_conf: &BenchConfig,
@@ -32,7 +46,6 @@ pub fn create_mem_tracking_thread<Q, T>(
 where
     Q: ConcurrentQueue<T>,
 {
-    use std::sync::atomic::Ordering;
     // TODO: Check if core stuff is possible here as well.
     // let mut core : CoreId = core_iter.next().unwrap();
     // if is_one_socket is true, make all thread ids even
@@ -102,6 +115,7 @@ where
 
 /// Calculates the fairness based on paper:
 /// [A Study of the Behavior of Synchronization Methods in Commonly Used Languages and Systems](https://ieeexplore.ieee.org/document/6569906).
+/// Returns 0 if no thread did any operations.
 pub fn calc_fairness(ops_per_thread: Vec<usize>) -> f64 {
     debug!("Calculating fairness");
     let sum: usize = ops_per_thread.iter().sum();
@@ -109,25 +123,17 @@ pub fn calc_fairness(ops_per_thread: Vec<usize>) -> f64 {
     let length: f64 = ops_per_thread.len() as f64;
     debug!("The vector {:?}", ops_per_thread);
     debug!("Sum: {}, Length: {}", sum, length);
+    if sum == 0 {
+        warn!("No record of operations: {:?}", ops_per_thread);
+        return 0.0;
+    }
 
     // The thread that does the least amount of ops
-    let minop: f64 = match ops_per_thread.iter().min() {
-        Some(&val) => val as f64,
-        None => {
-            error!("No record of operations: {:?}", ops_per_thread);
-            panic!();
-        }
-    };
+    let minop = ops_per_thread.iter().copied().min().unwrap_or(0) as f64;
     trace!("Minop fairness: {}", minop);
 
     // The thread that does the most amount of ops
-    let maxop: f64 = match ops_per_thread.iter().max() {
-        Some(&val) => val as f64,
-        None => {
-            error!("No record of operations: {:?}", ops_per_thread);
-            panic!();
-        }
-    };
+    let maxop = ops_per_thread.iter().copied().max().unwrap_or(0) as f64;
     trace!("Maxop fairness: {}", maxop);
 
     let fairness: f64 =
@@ -137,6 +143,275 @@ pub fn calc_fairness(ops_per_thread: Vec<usize>) -> f64 {
     fairness
 }
 
+/// Checks that a queue with `capabilities` supports as many concurrent
+/// producers, consumers and registered handles as `benchmark` uses.
+pub fn check_capabilities(
+    queue_name: &str,
+    capabilities: Capabilities,
+    benchmark: &FifoQueueBenchmarks,
+) -> Result<(), Error> {
+    let handles = benchmark.handles();
+    if !capabilities.allows_handles(handles) {
+        return Err(Error::Config(format!(
+            "{} supports {} registered handles at once, but {} uses {}",
+            queue_name,
+            capabilities.max_threads.unwrap_or(0),
+            benchmark,
+            handles
+        )));
+    }
+    let (producers, consumers) = benchmark.roles();
+    if capabilities.allows(producers, consumers) {
+        return Ok(());
+    }
+    let limit = |max: Option<usize>| match max {
+        Some(max) => max.to_string(),
+        None => "any".to_string(),
+    };
+    Err(Error::Config(format!(
+        "{} supports {} producers and {} consumers, but {} uses {} producers and {} consumers",
+        queue_name,
+        limit(capabilities.max_producers),
+        limit(capabilities.max_consumers),
+        benchmark,
+        producers,
+        consumers
+    )))
+}
+
+/// Pushes an item to the queue, handling a full queue according to `policy`.
+/// Returns whether the item ended up in the queue, and how many push attempts
+/// failed on the way. Retries stop once `done` is set.
+pub fn push_with_policy<T>(
+    handle: &mut impl HandleQueue<T>,
+    item: T,
+    policy: FullPolicy,
+    backoff: &mut Backoff,
+    done: &AtomicBool,
+) -> (bool, usize) {
+    let mut item = item;
+    let mut failed = 0;
+    loop {
+        match handle.push(item) {
+            Ok(_) => {
+                backoff.reset();
+                return (true, failed);
+            }
+            Err(returned) => {
+                failed += 1;
+                match policy {
+                    FullPolicy::Drop => return (false, failed),
+                    FullPolicy::Retry => (),
+                    FullPolicy::Backoff => backoff.backoff(),
+                }
+                if done.load(Ordering::Relaxed) {
+                    return (false, failed);
+                }
+                item = returned;
+            }
+        }
+    }
+}
+
+/// Pushes a batch of items to the queue, handling a full queue according to
+/// `policy`. Returns how many items ended up in the queue, and how many item
+/// pushes failed on the way. `items` is empty afterwards, items that could not
+/// be pushed are dropped. Retries stop once `done` is set.
+pub fn push_batch_with_policy<T>(
+    handle: &mut impl HandleQueueBatch<T>,
+    items: &mut Vec<T>,
+    policy: FullPolicy,
+    backoff: &mut Backoff,
+    done: &AtomicBool,
+) -> (usize, usize) {
+    let mut pushed = 0;
+    let mut failed = 0;
+    loop {
+        pushed += handle.push_batch(items);
+        if items.is_empty() {
+            backoff.reset();
+            return (pushed, failed);
+        }
+        failed += items.len();
+        match policy {
+            FullPolicy::Drop => {
+                items.clear();
+                return (pushed, failed);
+            }
+            FullPolicy::Retry => (),
+            FullPolicy::Backoff => backoff.backoff(),
+        }
+        if done.load(Ordering::Relaxed) {
+            items.clear();
+            return (pushed, failed);
+        }
+    }
+}
+
+/// Pushes `batch_size` default items, as a single push if `batch_size` is 1
+/// and as a batch otherwise. `batch` is scratch space that is reused between
+/// calls. Returns how many items were pushed and how many pushes failed.
+pub fn push_items<T: Default>(
+    handle: &mut impl HandleQueueBatch<T>,
+    batch: &mut Vec<T>,
+    batch_size: usize,
+    policy: FullPolicy,
+    backoff: &mut Backoff,
+    done: &AtomicBool,
+) -> (usize, usize) {
+    if batch_size == 1 {
+        let (pushed, failed) = push_with_policy(handle, T::default(), policy, backoff, done);
+        return (pushed as usize, failed);
+    }
+    batch.extend((0..batch_size).map(|_| T::default()));
+    push_batch_with_policy(handle, batch, policy, backoff, done)
+}
+
+/// Pops up to `batch_size` items, as a single pop if `batch_size` is 1 and
+/// as a batch otherwise. `buffer` is scratch space that is reused between
+/// calls. Returns how many items were popped.
+pub fn pop_items<T>(
+    handle: &mut impl HandleQueueBatch<T>,
+    buffer: &mut Vec<T>,
+    batch_size: usize,
+) -> usize {
+    if batch_size == 1 {
+        return handle.pop().is_some() as usize;
+    }
+    let popped = handle.pop_batch(buffer, batch_size);
+    buffer.clear();
+    popped
+}
+
+/// Returns the CPU time consumed by the calling thread so far, or `None` if
+/// the platform doesn't expose it.
+pub fn thread_cpu_time() -> Option<std::time::Duration> {
+    #[cfg(unix)]
+    {
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // SAFETY: `ts` is a valid timespec to write the result to.
+        let ret =
+            unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut ts) };
+        if ret != 0 {
+            return None;
+        }
+        Some(std::time::Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+    }
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+/// A thread whose scheduling policy can be changed, also from other threads.
+/// Only valid until the thread is joined.
+#[derive(Debug, Clone, Copy)]
+pub struct ThreadSched(#[cfg(target_os = "linux")] libc::pthread_t);
+
+impl ThreadSched {
+    /// Returns the calling thread.
+    pub fn current() -> Self {
+        // SAFETY: pthread_self has no preconditions.
+        #[cfg(target_os = "linux")]
+        return ThreadSched(unsafe { libc::pthread_self() });
+        #[cfg(not(target_os = "linux"))]
+        return ThreadSched();
+    }
+
+    /// Switches the thread to SCHED_FIFO with `priority` if `fifo` is set, and
+    /// back to SCHED_OTHER otherwise. Returns false if the policy couldn't be
+    /// set, e.g. because the process isn't allowed to use real-time scheduling.
+    pub fn set(&self, fifo: bool, priority: i32) -> bool {
+        #[cfg(target_os = "linux")]
+        {
+            let (policy, priority) = if fifo {
+                (libc::SCHED_FIFO, priority)
+            } else {
+                (libc::SCHED_OTHER, 0)
+            };
+            let param = libc::sched_param {
+                sched_priority: priority,
+            };
+            // SAFETY: `param` is a valid sched_param and the thread hasn't
+            // been joined yet.
+            unsafe { libc::pthread_setschedparam(self.0, policy, &param) == 0 }
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = priority;
+            !fifo
+        }
+    }
+}
+
+/// Returns the resident memory of the process in bytes, or `None` if it
+/// can't be read.
+pub fn resident_memory() -> Option<u64> {
+    let pid = sysinfo::get_current_pid().ok()?;
+    let mut sys = System::new();
+    sys.refresh_processes(sysinfo::ProcessesToUpdate::Some(&[pid]), false);
+    sys.process(pid).map(|process| process.memory())
+}
+
+/// Pushes `amount` items made by `item` before a benchmark run. Returns how
+/// many were pushed, a bounded queue may take fewer.
+pub fn prefill<C, T>(cqueue: &C, amount: u64, mut item: impl FnMut() -> T) -> usize
+where
+    C: ConcurrentQueue<T>,
+{
+    debug!("Prefilling queue with {} items.", amount);
+    let mut handle = cqueue.register();
+    let mut prefilled = 0;
+    for _ in 0..amount {
+        if handle.push(item()).is_ok() {
+            prefilled += 1;
+        }
+    }
+    prefilled
+}
+
+/// Pops every item left in the queue and returns how many there were.
+pub fn drain<C, T>(cqueue: &C) -> usize
+where
+    C: ConcurrentQueue<T>,
+{
+    let mut handle = cqueue.register();
+    let mut drained: usize = 0;
+    while handle.pop().is_some() {
+        drained += 1;
+    }
+    debug!("Drained {} items from the queue", drained);
+    drained
+}
+
+/// Pops every item left in the queue after a benchmark run and checks that
+/// the queue conserved its elements, i.e. that
+/// `prefilled + pushes - pops` equals the amount of items drained.
+/// `pushes` and `pops` should only count successful operations.
+pub fn drain_and_check_conservation<C, T>(
+    cqueue: &C,
+    prefilled: usize,
+    pushes: usize,
+    pops: usize,
+) -> bool
+where
+    C: ConcurrentQueue<T>,
+{
+    let drained = drain(cqueue);
+    let expected = (prefilled + pushes).checked_sub(pops);
+    if expected != Some(drained) {
+        error!(
+            "Queue did not conserve its elements: prefilled {} + pushed {} - popped {} != drained {}",
+            prefilled, pushes, pops, drained
+        );
+        return false;
+    }
+    true
+}
+
 /// Function to print the specifications of the hardware used and the benchmnark configs that ran
 pub fn print_info(
     queue: String,
@@ -202,8 +477,13 @@ pub fn create_bench_config(
         format!("{:x}", hasher.finish())
     };
 
+    // Draw a seed if none was given, so that every run can be repeated
+    let mut args = general_args.clone();
+    let seed = *args.seed.get_or_insert_with(rng::random_seed);
+
     debug!("Benchmark ID: {}", benchmark_id);
-    debug!("Arguments: {:?}", general_args);
+    info!("Seed: {}", seed);
+    debug!("Arguments: {:?}", args);
 
     // Create dir if it doesn't already exist.
     if !std::path::Path::new(&general_args.path_output).exists() {
@@ -213,7 +493,7 @@ pub fn create_bench_config(
     let output_filename = format!("{}/{}", general_args.path_output, date_time);
 
     Ok(BenchConfig {
-        args: general_args.clone(),
+        args,
         date_time,
         benchmark_id,
         output_filename,
@@ -236,3 +516,167 @@ pub fn output_result_header(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backoff::BackoffStrategy;
+    use crate::benchmarks::test_helpers::test_queue::TestQueue;
+    use std::time::Duration;
+
+    #[test]
+    fn capabilities_limit_roles() {
+        use crate::arguments::{FifoQueueMpscArgs, FifoQueueProdConArgs};
+        let prod_con = FifoQueueBenchmarks::ProdCon(FifoQueueProdConArgs {
+            producers: 2,
+            consumers: 1,
+            rate: None,
+            arrivals: crate::arguments::Arrivals::Poisson,
+        });
+        let mpsc = FifoQueueBenchmarks::Mpsc(FifoQueueMpscArgs { producers: 4 });
+        assert!(check_capabilities("q", Capabilities::MPMC, &prod_con).is_ok());
+        assert!(check_capabilities("q", Capabilities::MPSC, &prod_con).is_ok());
+        assert!(check_capabilities("q", Capabilities::SPSC, &prod_con).is_err());
+        assert!(check_capabilities("q", Capabilities::SPMC, &mpsc).is_err());
+        assert!(check_capabilities("q", Capabilities::SPSC, &FifoQueueBenchmarks::Spsc).is_ok());
+    }
+
+    #[test]
+    fn capabilities_limit_handles() {
+        use crate::arguments::FifoQueueProdConArgs;
+        let prod_con = FifoQueueBenchmarks::ProdCon(FifoQueueProdConArgs {
+            producers: 2,
+            consumers: 2,
+            rate: None,
+            arrivals: crate::arguments::Arrivals::Poisson,
+        });
+        let limited = |max| Capabilities {
+            max_threads: Some(max),
+            ..Capabilities::MPMC
+        };
+        assert!(check_capabilities("q", limited(4), &prod_con).is_ok());
+        assert!(matches!(
+            check_capabilities("q", limited(3), &prod_con),
+            Err(Error::Config(_))
+        ));
+    }
+
+    #[test]
+    fn fairness() {
+        assert_eq!(calc_fairness(vec![5, 5, 5]), 1.0);
+        assert_eq!(calc_fairness(vec![2, 6]), 0.5);
+        // Runs without operations are not an error
+        assert_eq!(calc_fairness(vec![]), 0.0);
+        assert_eq!(calc_fairness(vec![0, 0]), 0.0);
+    }
+
+    #[test]
+    fn conservation_holds() {
+        let q: TestQueue<usize> = TestQueue::new(0);
+        {
+            let mut handle = q.register();
+            for i in 0..10 {
+                handle.push(i).unwrap();
+            }
+            for _ in 0..4 {
+                handle.pop().unwrap();
+            }
+        }
+        assert!(drain_and_check_conservation(&q, 2, 8, 4));
+        assert!(q.register().pop().is_none());
+    }
+
+    #[test]
+    fn conservation_detects_lost_items() {
+        let q: TestQueue<usize> = TestQueue::new(0);
+        {
+            let mut handle = q.register();
+            for i in 0..5 {
+                handle.push(i).unwrap();
+            }
+        }
+        // Claim one more push than the queue actually received
+        assert!(!drain_and_check_conservation(&q, 0, 6, 0));
+    }
+
+    /// Accepts a fixed amount of pushes, then reports that it is full.
+    struct FullAfter(usize);
+
+    impl HandleQueue<usize> for FullAfter {
+        fn push(&mut self, item: usize) -> Result<(), usize> {
+            if self.0 == 0 {
+                return Err(item);
+            }
+            self.0 -= 1;
+            Ok(())
+        }
+        fn pop(&mut self) -> Option<usize> {
+            None
+        }
+    }
+
+    #[test]
+    fn drop_policy_gives_up_after_one_attempt() {
+        let done = AtomicBool::new(false);
+        let mut backoff = Backoff::new(BackoffStrategy::SpinHint, Duration::ZERO);
+        let mut handle = FullAfter(1);
+        assert_eq!(push_with_policy(&mut handle, 1, FullPolicy::Drop, &mut backoff, &done), (true, 0));
+        assert_eq!(push_with_policy(&mut handle, 2, FullPolicy::Drop, &mut backoff, &done), (false, 1));
+        // Dropping the item doesn't wait for the queue
+        assert_eq!(backoff.count(), 0);
+    }
+
+    #[test]
+    fn retry_policies_stop_when_done() {
+        let done = AtomicBool::new(true);
+        let mut backoff = Backoff::new(BackoffStrategy::SpinHint, Duration::ZERO);
+        let mut handle = FullAfter(0);
+        assert_eq!(push_with_policy(&mut handle, 1, FullPolicy::Retry, &mut backoff, &done), (false, 1));
+        assert_eq!(backoff.count(), 0);
+        assert_eq!(push_with_policy(&mut handle, 1, FullPolicy::Backoff, &mut backoff, &done), (false, 1));
+        assert_eq!(backoff.count(), 1);
+    }
+
+    #[test]
+    fn element_wise_batches_keep_order() {
+        let q: TestQueue<usize> = TestQueue::new(0);
+        let mut handle = q.register_batch();
+        let mut items: Vec<usize> = (0..10).collect();
+        assert_eq!(handle.push_batch(&mut items), 10);
+        assert!(items.is_empty());
+        let mut buffer = vec![];
+        assert_eq!(handle.pop_batch(&mut buffer, 4), 4);
+        assert_eq!(handle.pop_batch(&mut buffer, 100), 6);
+        assert_eq!(handle.pop_batch(&mut buffer, 100), 0);
+        assert_eq!(buffer, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn batch_push_leaves_rejected_items() {
+        let mut handle = crate::traits::ElementWise(FullAfter(3));
+        let mut items: Vec<usize> = (0..5).collect();
+        assert_eq!(handle.push_batch(&mut items), 3);
+        assert_eq!(items, vec![3, 4]);
+    }
+
+    #[test]
+    fn batch_drop_policy_counts_every_rejected_item() {
+        let done = AtomicBool::new(false);
+        let mut backoff = Backoff::new(BackoffStrategy::SpinHint, Duration::ZERO);
+        let mut handle = crate::traits::ElementWise(FullAfter(3));
+        let mut items: Vec<usize> = (0..5).collect();
+        assert_eq!(push_batch_with_policy(&mut handle, &mut items, FullPolicy::Drop, &mut backoff, &done), (3, 2));
+        assert!(items.is_empty());
+        assert_eq!(backoff.count(), 0);
+        let done = AtomicBool::new(true);
+        let mut items: Vec<usize> = (0..2).collect();
+        assert_eq!(push_batch_with_policy(&mut handle, &mut items, FullPolicy::Retry, &mut backoff, &done), (0, 2));
+        assert!(items.is_empty());
+    }
+
+    #[test]
+    fn conservation_detects_duplicated_items() {
+        let q: TestQueue<usize> = TestQueue::new(0);
+        assert!(!drain_and_check_conservation(&q, 0, 1, 2));
+    }
+}
@@ -1,37 +1,111 @@
 #[allow(unused_imports)]
-use crate::arguments::{FifoQueueArgs, FifoQueueBenchmarks, GeneralArgs};
+use crate::arguments::{
+    FifoQueueArgs, FifoQueueBenchmarks, FullPolicy, GeneralArgs, PluginQueueArgs,
+};
+use crate::backoff::BackoffStrategy;
 use crate::benchmarks::benchmark_helpers::{self, BenchConfig};
+use crate::error::Error;
+use crate::plugin::{self, PluginQueue};
 #[allow(unused_imports)]
-use crate::traits::{ConcurrentQueue, HandleQueue};
+use crate::traits::{BlockingConcurrentQueue, ConcurrentQueue, HandleQueue};
 use clap::Parser;
 #[allow(unused_imports)]
-use log::{self, debug, error, info};
+use log::{self, debug, error, info, warn};
 #[allow(unused_imports)]
 use std::sync::atomic::AtomicBool;
 
 pub mod bfs;
+pub mod blocking;
+pub mod churn;
 pub mod enq_deq;
 pub mod enq_deq_pairs;
+pub mod open_loop;
+pub mod oversubscribe;
+pub mod phased;
+pub mod pipeline;
 pub mod prod_con;
+pub mod progress;
+pub mod reorder;
+pub mod replay;
+pub mod tasks;
+
+/// Runner for benchmarks that need more than the `ConcurrentQueue` API
+type ExtendedRunner<Q> =
+    fn(Q, &BenchConfig, &FifoQueueArgs) -> Result<(), Error>;
 
 /// Create the fifo queue, and run the selected benchmark a set of times
-pub fn benchmark_fifo_queue<Q>(queue_name: &str) -> Result<(), std::io::Error>
+pub fn benchmark_fifo_queue<Q>(queue_name: &str) -> Result<(), Error>
+where
+    Q: ConcurrentQueue<usize> + Send,
+    for<'a> &'a Q: Send,
+{
+    run_fifo_queue::<Q>(queue_name, None, FifoQueueArgs::parse())
+}
+
+/// Same as `benchmark_fifo_queue`, but for queues that implement the blocking
+/// API, which also makes the `blocking` runner available
+pub fn benchmark_blocking_fifo_queue<Q>(
+    queue_name: &str,
+) -> Result<(), Error>
+where
+    Q: BlockingConcurrentQueue<usize> + Send,
+    for<'a> &'a Q: Send,
+{
+    run_fifo_queue::<Q>(
+        queue_name,
+        Some(blocking::benchmark_blocking::<Q>),
+        FifoQueueArgs::parse(),
+    )
+}
+
+/// Benchmarks the queue of the shared library given with `--plugin`, see
+/// `crate::plugin` for the interface it has to implement
+pub fn benchmark_plugin_queue() -> Result<(), Error> {
+    let args = PluginQueueArgs::parse();
+    let plugin = plugin::install(plugin::Plugin::load(&args.plugin)?)?;
+    info!("Loaded plugin {} from {}", plugin.name(), args.plugin.display());
+    run_fifo_queue::<PluginQueue<usize>>(
+        plugin.name(),
+        None,
+        args.fifo_queue_args,
+    )
+}
+
+fn run_fifo_queue<Q>(
+    queue_name: &str,
+    blocking_runner: Option<ExtendedRunner<Q>>,
+    fifo_queue_args: FifoQueueArgs,
+) -> Result<(), Error>
 where
     Q: ConcurrentQueue<usize> + Send,
     for<'a> &'a Q: Send,
 {
-    let (bench_conf, fifo_queue_args) = setup_benchmark()?;
+    let (bench_conf, fifo_queue_args) = setup_benchmark(fifo_queue_args)?;
 
     // The variables need to be references to avoid moving into closures below.
     // TODO(emilbjornlinger): Find a better solution.
     let bench_conf = &bench_conf;
     let fifo_queue_args = &fifo_queue_args;
 
+    if fifo_queue_args.full_policy == FullPolicy::Backoff
+        && bench_conf.args.backoff == BackoffStrategy::None
+    {
+        warn!("Full policy 'backoff' used without a --backoff strategy, full pushes will be retried immediately.");
+    }
+
     // Create a runner lambda for the different benchmarks, mainly needed for eg. BFS to load graph and so on
     let mut runner: Box<
-        dyn FnMut(Q, &BenchConfig) -> Result<(), std::io::Error>,
+        dyn FnMut(Q, &BenchConfig) -> Result<(), Error>,
     > = match &fifo_queue_args.benchmark_runner {
-        FifoQueueBenchmarks::ProdCon(_) => Box::new(move |q, bench_conf| {
+        FifoQueueBenchmarks::ProdCon(args) if args.rate.is_some() => {
+            Box::new(move |q, bench_conf| {
+                open_loop::benchmark_open_loop(q, bench_conf, fifo_queue_args)
+            })
+        }
+        FifoQueueBenchmarks::ProdCon(_)
+        | FifoQueueBenchmarks::Spsc
+        | FifoQueueBenchmarks::Mpsc(_)
+        | FifoQueueBenchmarks::Spmc(_) => Box::new(move |q, bench_conf| {
             prod_con::benchmark_prod_con(q, bench_conf, fifo_queue_args)
         }),
         FifoQueueBenchmarks::EnqDeq(_) => Box::new(move |q, bench_conf| {
@@ -46,11 +120,54 @@ where
                 )
             })
         }
+        FifoQueueBenchmarks::Phased(_) => Box::new(move |q, bench_conf| {
+            phased::benchmark_phased(q, bench_conf, fifo_queue_args)
+        }),
+        FifoQueueBenchmarks::Pipeline(_) => Box::new(move |q, bench_conf| {
+            pipeline::benchmark_pipeline(q, bench_conf, fifo_queue_args)
+        }),
+        FifoQueueBenchmarks::Blocking(_) => match blocking_runner {
+            Some(runner) => Box::new(move |q, bench_conf| {
+                runner(q, bench_conf, fifo_queue_args)
+            }),
+            None => {
+                return Err(Error::Config(format!(
+                    "{queue_name} does not implement the blocking API"
+                )))
+            }
+        },
+        FifoQueueBenchmarks::Tasks(_) => Box::new(move |q, bench_conf| {
+            tasks::benchmark_tasks(q, bench_conf, fifo_queue_args)
+        }),
+        FifoQueueBenchmarks::Churn(_) => Box::new(move |q, bench_conf| {
+            churn::benchmark_churn(q, bench_conf, fifo_queue_args)
+        }),
+        FifoQueueBenchmarks::Progress(_) => Box::new(move |q, bench_conf| {
+            progress::benchmark_progress(q, bench_conf, fifo_queue_args)
+        }),
+        FifoQueueBenchmarks::Replay(args) => {
+            let trace = replay::load_trace(args, &bench_conf.args)?;
+            Box::new(move |q, bench_conf| {
+                replay::benchmark_replay(q, bench_conf, fifo_queue_args, &trace)
+            })
+        }
+        FifoQueueBenchmarks::Reorder(_) => Box::new(move |q, bench_conf| {
+            reorder::benchmark_reorder(q, bench_conf, fifo_queue_args)
+        }),
+        FifoQueueBenchmarks::Oversubscribe(_) => {
+            Box::new(move |q, bench_conf| {
+                oversubscribe::benchmark_oversubscribe(
+                    q,
+                    bench_conf,
+                    fifo_queue_args,
+                )
+            })
+        }
         FifoQueueBenchmarks::BFS(args) => {
             let (graph, seq_ret_vec, start_node) = bfs::pre_bfs_work(
-                Q::new(fifo_queue_args.queue_size as usize),
+                Q::try_new(fifo_queue_args.queue_size as usize)?,
                 &args,
-            );
+            )?;
             Box::new(move |q, _conf| {
                 bfs::benchmark_bfs(
                     q,
@@ -66,7 +183,24 @@ where
 
     for _current_iteration in 0..bench_conf.args.iterations {
         // Create the queue.
-        let test_q: Q = Q::new(fifo_queue_args.queue_size as usize);
+        let test_q: Q = Q::try_new(fifo_queue_args.queue_size as usize)?;
+        benchmark_helpers::check_capabilities(
+            queue_name,
+            test_q.capabilities(),
+            &fifo_queue_args.benchmark_runner,
+        )?;
+        if let Some(capacity) = test_q.capacity() {
+            debug!(
+                "Queue is bounded with capacity {}, full pushes are handled with policy '{}'.",
+                capacity, fifo_queue_args.full_policy
+            );
+            if fifo_queue_args.prefill_amount as usize > capacity {
+                warn!(
+                    "Prefill amount {} is larger than the queue capacity {}.",
+                    fifo_queue_args.prefill_amount, capacity
+                );
+            }
+        }
 
         // Start memory tracking (if enabled)
         #[cfg(feature = "memory_tracking")]
@@ -111,19 +245,50 @@ where
     Ok(())
 }
 
-/// Parse arguments and start outputting result of benchmark
-pub fn setup_benchmark() -> Result<(BenchConfig, FifoQueueArgs), std::io::Error>
-{
-    let args = crate::arguments::FifoQueueArgs::parse();
+/// Create the benchmark config from the parsed arguments and start outputting
+/// result of benchmark
+pub fn setup_benchmark(
+    args: FifoQueueArgs,
+) -> Result<(BenchConfig, FifoQueueArgs), std::io::Error> {
     let bench_config =
         benchmark_helpers::create_bench_config(&args.general_args)?;
 
     let columns = match args.benchmark_runner {
         FifoQueueBenchmarks::BFS(_) => {
-            "Milliseconds,Queuetype,Thread Count,Test ID"
+            "Milliseconds,Queuetype,Thread Count,Test ID,Backoffs,Seed"
+        },
+        FifoQueueBenchmarks::Tasks(_) => {
+            "Milliseconds,Queuetype,Benchmark,Test ID,Thread Count,Workload,Size,Tasks,Tasks Per Second,Verified,Backoffs,Seed"
+        },
+        FifoQueueBenchmarks::Churn(_) => {
+            "Queuetype,Benchmark,Test ID,Window,Thread Count,Lifetimes,Ops Per Lifetime,Register Mean,Deregister Mean,Lifetime Mean,Lifetimes Per Second,Resident Memory,Memory Growth,Failed Lifetimes,Exhausted At,Conserved,Seed"
+        },
+        FifoQueueBenchmarks::Progress(_) => {
+            "Queuetype,Benchmark,Test ID,Stall,Thread Count,Stalled Threads,Mode,Stall Milliseconds,Baseline Throughput,Stall Throughput,Relative Throughput,Min Window Ops,Zero Windows,Progress,Conserved,Seed"
+        },
+        FifoQueueBenchmarks::Replay(_) => {
+            "Milliseconds,Throughput,Enqueues,Dequeues,Empty Dequeues,Failed Enqueues,Thread Count,Queuetype,Benchmark,Test ID,Trace ID,Trace Ops,Dilation,Completed,Conserved,Backoffs,Mean Think Time,Seed"
+        },
+        FifoQueueBenchmarks::Reorder(_) => {
+            "Milliseconds,Queuetype,Benchmark,Test ID,Thread Count,Spread,Enqueues,Dequeues,Empty Dequeues,Failed Enqueues,Mean Rank Error,Rank Error P50,Rank Error P90,Rank Error P99,Rank Error Max,Out Of Order,Completed,Conserved,Seed"
+        },
+        FifoQueueBenchmarks::Oversubscribe(_) => {
+            "Throughput,Enqueues,Dequeues,Thread Count,Queuetype,Benchmark,Test ID,Factor,Cores,Pinned,Scheduling,Fairness,Relative Throughput,Fairness Drop,Conserved,Failed Enqueues,Backoffs,Seed"
+        },
+        FifoQueueBenchmarks::Phased(_) => {
+            "Throughput,Enqueues,Dequeues,Thread Count,Queuetype,Benchmark,Test ID,Phase,Kind,Producers,Consumers,Milliseconds,Failed Enqueues,Empty Dequeues,Sojourn P50,Sojourn P99,Sojourn Max,Conserved,Seed"
+        },
+        FifoQueueBenchmarks::Pipeline(_) => {
+            "Throughput,Queuetype,Benchmark,Test ID,Stage,Threads,Stage Work,Enqueues,Dequeues,Failed Enqueues,Empty Dequeues,Mean Occupancy,Max Occupancy,Latency P50,Latency P99,Latency Max,Conserved,Seed"
+        },
+        FifoQueueBenchmarks::Blocking(_) => {
+            "Throughput,Enqueues,Dequeues,Consumers,Producers,Queuetype,Benchmark,Test ID,Conserved,Failed Enqueues,Timeouts,Wakeups,Wakeup P50,Wakeup P99,Wakeup Max,Idle Wall Time,Idle CPU Time,Idle CPU Share,Seed"
+        },
+        FifoQueueBenchmarks::ProdCon(ref a) if a.rate.is_some() => {
+            "Throughput,Enqueues,Dequeues,Consumers,Producers,Thread Count,Queuetype,Benchmark,Test ID,Fairness,Spread,Queue Size,Conserved,Failed Enqueues,Backoffs,Mean Think Time,Offered Rate,Achieved Rate,Sustained,Sojourn P50,Sojourn P90,Sojourn P99,Sojourn P999,Sojourn Max,Seed"
         },
         _ => {
-            "Throughput,Enqueues,Dequeues,Consumers,Producers,Thread Count,Queuetype,Benchmark,Test ID,Fairness,Spread,Queue Size"
+            "Throughput,Enqueues,Dequeues,Consumers,Producers,Thread Count,Queuetype,Benchmark,Test ID,Fairness,Spread,Queue Size,Conserved,Failed Enqueues,Backoffs,Mean Think Time,Batch Size,Seed"
         }
     };
 
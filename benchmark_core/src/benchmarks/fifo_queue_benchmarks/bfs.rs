@@ -1,5 +1,7 @@
 use crate::arguments::{FifoQueueArgs, FifoQueueBFSArgs, FifoQueueBenchmarks};
+use crate::backoff::Backoff;
 use crate::benchmarks::benchmark_helpers::BenchConfig;
+use crate::error::Error;
 use crate::traits::{ConcurrentQueue, HandleQueue};
 use core_affinity::CoreId;
 use log::{debug, error, info, trace};
@@ -13,18 +15,21 @@ use std::{
     },
 };
 
+/// The graph, the sequential solution and the node to start at.
+type BfsSetup = (Vec<Vec<usize>>, Vec<usize>, usize);
+
 /// Generates the graph, generates the sequential solution and gets which
 /// node to start at.
 pub fn pre_bfs_work<C>(
     cqueue: C,
     args: &FifoQueueBFSArgs,
-) -> (Vec<Vec<usize>>, Vec<usize>, usize)
+) -> Result<BfsSetup, Error>
 where
     C: ConcurrentQueue<usize>,
     for<'a> &'a C: Send,
 {
     info!("Loading graph now...");
-    let graph = create_graph(args.graph_file.clone()).unwrap();
+    let graph = create_graph(args.graph_file.clone())?;
     // Find start node. Currently finds node with most neighbours.
     let mut biggest = 0;
     let mut curr = 0;
@@ -42,7 +47,7 @@ where
     } else {
         vec![]
     };
-    (graph, seq_ret_vec, curr)
+    Ok((graph, seq_ret_vec, curr))
 }
 
 /*
@@ -68,7 +73,7 @@ pub fn benchmark_bfs<C>(
     seq_ret_vec: &[usize],
     start_node: usize,
     fifo_queue_args: &FifoQueueArgs
-) -> Result<(), std::io::Error>
+) -> Result<(), Error>
 where
     C: ConcurrentQueue<usize>,
     for<'a> &'a C: Send,
@@ -82,8 +87,8 @@ where
     };
     let thread_count = bfs_args.thread_count;
     debug!("Starting parallell BFS now");
-    let (dur_par, par_ret_vec) =
-        parallell_bfs(&cqueue, graph, start_node, thread_count, bench_conf);
+    let (dur_par, par_ret_vec, backoffs) =
+        parallell_bfs(&cqueue, graph, start_node, thread_count, bench_conf)?;
     debug!("Graph traversal done. Took {:?}.", dur_par);
 
     if !bfs_args.no_verify {
@@ -97,17 +102,22 @@ where
             );
             if *node != seq_ret_vec[i] {
                 error!("Parallell BFS solution arrived at wrong answer.");
-                return Ok(());
+                return Err(Error::Verification(format!(
+                    "node {} is at distance {} in the parallel BFS, but {} in the sequential one",
+                    i, *node, seq_ret_vec[i]
+                )));
             }
         }
         debug!("Solution looks good.");
     }
     let formatted = format!(
-        "{},{},{},{}",
+        "{},{},{},{},{},{}",
         dur_par.as_millis(),
         cqueue.get_id(),
         bfs_args.thread_count,
-        bench_conf.benchmark_id
+        bench_conf.benchmark_id,
+        backoffs,
+        bench_conf.seed()
     );
     if !bench_conf.args.write_to_stdout {
         let mut file = OpenOptions::new()
@@ -129,7 +139,7 @@ fn parallell_bfs<C>(
     start_node: usize,
     thread_count: usize,
     bench_conf: &BenchConfig,
-) -> (std::time::Duration, Vec<usize>)
+) -> Result<(std::time::Duration, Vec<usize>, usize), Error>
 where
     C: ConcurrentQueue<usize>,
     for<'a> &'a C: Send,
@@ -143,6 +153,7 @@ where
 
     let idle_count: AtomicUsize = AtomicUsize::new(0);
     let no_work_count: AtomicUsize = AtomicUsize::new(0);
+    let backoffs: AtomicUsize = AtomicUsize::new(0);
     let barrier = Barrier::new(thread_count + 1);
     // Add start node to queue
     let _ = cqueue.register().push(start_node);
@@ -153,9 +164,10 @@ where
     let mut core_iter = available_cores.into_iter().cycle();
 
     let scope_result =
-        std::thread::scope(|s| -> Result<std::time::Duration, ()> {
+        std::thread::scope(|s| -> Result<std::time::Duration, Error> {
             let idle_count = &idle_count;
             let no_work_count = &no_work_count;
+            let backoffs = &backoffs;
             let barrier = &barrier;
             let result_vector = &result_vector;
             let mut handles = vec![];
@@ -175,8 +187,9 @@ where
                         // Wait for other queues
                         barrier.wait();
                         // Start BFS
-                        pbfs_helper(
+                        let backoff_count = pbfs_helper(
                             handle,
+                            Backoff::from_args(&bench_conf.args),
                             result_vector,
                             graph,
                             i,
@@ -184,35 +197,44 @@ where
                             idle_count,
                             thread_count,
                         );
+                        backoffs.fetch_add(backoff_count, Ordering::Relaxed);
                     })
                 });
             }
             barrier.wait();
             let start = std::time::Instant::now();
-            for handle in handles {
-                handle.join().unwrap();
+            for (i, handle) in handles.into_iter().enumerate() {
+                if handle.join().is_err() {
+                    return Err(Error::ThreadPanic(format!(
+                        "BFS thread {i} panicked"
+                    )));
+                }
             }
             let duration = start.elapsed();
             Ok(duration)
         });
-    let duration = scope_result.expect("Should never return error");
+    let duration = scope_result?;
     let ret_vec: Vec<usize> = result_vector
         .iter()
         .map(|val| val.load(Ordering::Relaxed))
         .collect();
     trace!("Parallell sol: {:?}", ret_vec);
-    (duration, ret_vec)
+    Ok((duration, ret_vec, backoffs.into_inner()))
 }
 
+/// Processes nodes until every thread runs out of work.
+/// Returns the amount of times the thread backed off on an empty queue.
+#[allow(clippy::too_many_arguments)]
 fn pbfs_helper(
     mut handle: impl HandleQueue<usize>,
+    mut backoff: Backoff,
     result_vector: &[AtomicUsize],
     graph: &[Vec<usize>],
     i: usize,
     no_work_count: &AtomicUsize,
     idle_count: &AtomicUsize,
     thread_count: usize,
-) {
+) -> usize {
     let mut next = None;
     loop {
         if next.is_none() {
@@ -261,6 +283,7 @@ fn pbfs_helper(
                 loop {
                     next = handle.pop();
                     if next.is_some() {
+                        backoff.reset();
                         break;
                     }
                     if no_work_count.load(Ordering::Relaxed) >= thread_count
@@ -270,8 +293,9 @@ fn pbfs_helper(
                             thread_count,
                         )
                     {
-                        return;
+                        return backoff.count();
                     }
+                    backoff.backoff();
                 }
                 no_work_count.fetch_sub(1, Ordering::Relaxed);
             }
@@ -279,7 +303,9 @@ fn pbfs_helper(
     }
 }
 
-fn should_terminate(
+/// Called by a thread that found no work. Returns true once every thread is
+/// out of work, which means that nothing can be pushed anymore.
+pub(crate) fn should_terminate(
     idle_count: &AtomicUsize,
     no_work_count: &AtomicUsize,
     thread_count: usize,
@@ -369,4 +395,4 @@ pub fn create_graph(
 
     Ok(graph)
 }
-// Milliseconds,Queuetype,Thread Count,Test ID
+// Milliseconds,Queuetype,Thread Count,Test ID,Backoffs
@@ -0,0 +1,315 @@
+use core_affinity::CoreId;
+use log::{debug, error, info, trace};
+use crate::arguments::{FifoQueueArgs, FifoQueueBenchmarks};
+use crate::histogram::Histogram;
+use crate::error::Error;
+use crate::traits::{BlockingConcurrentQueue, BlockingHandleQueue, HandleQueue};
+use crate::benchmarks::benchmark_helpers::{self, BenchConfig};
+use crate::benchmarks::fifo_queue_benchmarks::open_loop::PREFILLED;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::{atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering}, Barrier, Mutex};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// # Explanation:
+/// A benchmark for queues implementing the blocking API.
+/// Producers push an item every `--interval` microseconds, while consumers
+/// block in `pop_blocking` (or `pop_timeout` with `--timeout`) instead of
+/// spinning on an empty queue. Every item carries the time it was pushed, so
+/// whenever a consumer was already waiting when the item was pushed, the time
+/// until its pop returned is recorded as wake-up latency. The wall time and
+/// thread CPU time spent inside the pops is summed up, showing how much CPU
+/// the queue burns while its consumers are idle.
+/// When the time limit is reached the queue is closed, which wakes up the
+/// consumers still waiting.
+/// Benchmark specific flags:
+/// * -p            Set specified amount of producers
+/// * -c            Set specified amount of consumers
+/// * --interval    Set the time each producer sleeps between pushes [µs]
+/// * --timeout     Pop with a timeout [µs] instead of blocking indefinitely
+#[allow(dead_code)]
+pub fn benchmark_blocking<C>(cqueue: C, bench_conf: &BenchConfig, fifo_queue_args: &FifoQueueArgs) -> Result<(), Error>
+where
+    C: BlockingConcurrentQueue<usize>,
+    for<'a> &'a C: Send
+{
+    // Extract specific arguments for this benchmark runner
+    let args = match &fifo_queue_args.benchmark_runner {
+        FifoQueueBenchmarks::Blocking(a) => a,
+        _ => panic!("benchmark_blocking called with another FIFO Queue \
+            configured. This is an implementation error.")
+    };
+
+    let prefilled =
+        benchmark_helpers::prefill(&cqueue, fifo_queue_args.prefill_amount, || PREFILLED);
+    let producers = args.producers;
+    let consumers = args.consumers;
+    let interval = Duration::from_micros(args.interval);
+    let timeout = args.timeout.map(Duration::from_micros);
+
+    let time_limit: u64 = bench_conf.args.time_limit;
+    let barrier = Barrier::new(consumers + producers + 1);
+    let pops = AtomicUsize::new(0);
+    let pushes = AtomicUsize::new(0);
+    let failed_pushes = AtomicUsize::new(0);
+    let timeouts = AtomicUsize::new(0);
+    let idle_wall = AtomicU64::new(0);
+    let idle_cpu = AtomicU64::new(0);
+    let cpu_time_available = AtomicBool::new(true);
+    let wakeups = Mutex::new(Histogram::new());
+    let done = AtomicBool::new(false);
+    info!("Starting blocking benchmark with {} consumers and {} producers", consumers, producers);
+
+    // get cores for fairness of threads
+    let available_cores: Vec<CoreId> =
+        core_affinity::get_core_ids().unwrap_or(vec![CoreId { id: 0 }]);
+    let mut core_iter = available_cores.into_iter().cycle();
+
+    // Shared atomic bool for when a thread fails
+    let thread_failed = Arc::new(AtomicBool::new(false));
+
+    // All timestamps are nanoseconds since this instant
+    let epoch = Instant::now();
+
+    let _ = std::thread::scope(|s| -> Result<(), std::io::Error>{
+        let queue = &cqueue;
+        let pushes = &pushes;
+        let failed_pushes = &failed_pushes;
+        let timeouts = &timeouts;
+        let idle_wall = &idle_wall;
+        let idle_cpu = &idle_cpu;
+        let cpu_time_available = &cpu_time_available;
+        let wakeups = &wakeups;
+        let pops = &pops;
+        let done = &done;
+        let barrier = &barrier;
+        let is_one_socket = &bench_conf.args.one_socket;
+        let thread_failed = &thread_failed;
+
+        for i in 0..producers {
+            let mut core : CoreId = core_iter.next().unwrap();
+            // if is_one_socket is true, make all thread ids even
+            // (this was used for our testing enviroment to get one socket)
+            if *is_one_socket {
+                core = core_iter.next().unwrap();
+            }
+            trace!("Thread: {} Core: {:?}", i, core);
+            s.spawn(move || {
+                let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                core_affinity::set_for_current(core);
+                let mut handle = queue.register_blocking();
+                let mut l_pushes = 0;
+                let mut l_failed_pushes = 0;
+                let _thread_failed = thread_failed.clone(); // Every thread clones the thread_failed bool
+                barrier.wait();
+                while !done.load(Ordering::Relaxed) {
+                    let now = epoch.elapsed().as_nanos() as usize;
+                    match handle.push(now) {
+                        Ok(_) => l_pushes += 1,
+                        Err(_) => l_failed_pushes += 1,
+                    }
+                    if !interval.is_zero() {
+                        std::thread::sleep(interval);
+                    }
+                }
+                pushes.fetch_add(l_pushes, Ordering::Relaxed);
+                failed_pushes.fetch_add(l_failed_pushes, Ordering::Relaxed);
+            }));
+            // A thread panicked, aborting the benchmark...
+            if let Err (e) = result {
+                error!("Thread {} panicked in pushing: {:?}. Aborting benchmark, padding results to zero", i, e);
+                thread_failed.store(true, Ordering::Relaxed);
+                done.store(true, Ordering::Relaxed);
+            }
+            });
+        }
+        for i in 0..consumers {
+            let mut core : CoreId = core_iter.next().expect("Core iter error");
+            // if is_one_socket is true, make all thread ids even
+            // (this was used for our testing enviroment to get one socket)
+            if *is_one_socket {
+                core = core_iter.next().unwrap();
+            }
+            trace!("Thread: {} Core: {:?}", i, core);
+
+            s.spawn(move || {
+                let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                core_affinity::set_for_current(core);
+                let mut handle = queue.register_blocking();
+                let mut l_wakeups = Histogram::new();
+                let mut l_pops = 0;
+                let mut l_timeouts = 0;
+                let mut l_idle_wall = Duration::ZERO;
+                let mut l_idle_cpu = Some(Duration::ZERO);
+                let _thread_failed = thread_failed.clone(); // Every thread clones the thread_failed bool
+                barrier.wait();
+                loop {
+                    let cpu_before = benchmark_helpers::thread_cpu_time();
+                    let wait_start = epoch.elapsed();
+                    let popped = match timeout {
+                        Some(t) => handle.pop_timeout(t),
+                        None => handle.pop_blocking(),
+                    };
+                    let wait_end = epoch.elapsed();
+                    l_idle_wall += wait_end - wait_start;
+                    l_idle_cpu = match (l_idle_cpu, cpu_before, benchmark_helpers::thread_cpu_time()) {
+                        (Some(total), Some(before), Some(after)) => Some(total + after.saturating_sub(before)),
+                        _ => None,
+                    };
+                    match popped {
+                        Some(pushed_at) => {
+                            // Only items pushed while this consumer was already
+                            // waiting measure how fast it is woken up.
+                            if pushed_at != PREFILLED && pushed_at as u128 >= wait_start.as_nanos() {
+                                l_wakeups.record((wait_end.as_nanos() as u64).saturating_sub(pushed_at as u64));
+                            }
+                            l_pops += 1;
+                        }
+                        // Blocking pops only return nothing once the queue is closed
+                        None if timeout.is_none() => break,
+                        None => {
+                            if done.load(Ordering::Relaxed) {
+                                break;
+                            }
+                            l_timeouts += 1;
+                        }
+                    }
+                }
+                pops.fetch_add(l_pops, Ordering::Relaxed);
+                timeouts.fetch_add(l_timeouts, Ordering::Relaxed);
+                idle_wall.fetch_add(l_idle_wall.as_nanos() as u64, Ordering::Relaxed);
+                match l_idle_cpu {
+                    Some(cpu) => { idle_cpu.fetch_add(cpu.as_nanos() as u64, Ordering::Relaxed); }
+                    None => cpu_time_available.store(false, Ordering::Relaxed),
+                }
+                wakeups.lock().unwrap().merge(&l_wakeups);
+            }));
+            // A thread panicked, aborting the benchmark...
+            if let Err(e) = result {
+                error!("Thread {} panicked while popping: {:?}. Aborting benchmark, padding results to zero", i, e);
+                thread_failed.store(true, Ordering::Relaxed);
+                done.store(true, Ordering::Relaxed);
+            }
+            });
+        }
+        debug!("Waiting for barrier");
+        barrier.wait();
+        debug!("Done waiting for barrier. Going to sleep.");
+        std::thread::sleep(std::time::Duration::from_secs(time_limit));
+        done.store(true, Ordering::Relaxed);
+        // Wake up the consumers still waiting for items
+        queue.register_blocking().close();
+        Ok(())
+    });
+    let pops = pops.into_inner();
+    let pushes = pushes.into_inner();
+    let failed_pushes = failed_pushes.into_inner();
+    let timeouts = timeouts.into_inner();
+    let wakeups = wakeups.into_inner().unwrap();
+    let idle_wall = idle_wall.into_inner();
+    let idle_cpu = idle_cpu.into_inner();
+    // Idle times are in milliseconds, -1 if the CPU time is unavailable
+    let (idle_cpu_ms, idle_cpu_share) = if cpu_time_available.into_inner() {
+        let share = if idle_wall == 0 { 0.0 } else { idle_cpu as f64 / idle_wall as f64 };
+        (idle_cpu as f64 / 1e6, share)
+    } else {
+        (-1.0, -1.0)
+    };
+
+    // If a thread crashed, pad the results with zero-values
+    let formatted = if thread_failed.load(Ordering::Relaxed) {
+        format!("0,0,0,{},{},{},{},{},false,0,0,0,0,0,0,0,0,0,{}", consumers, producers, cqueue.get_id(), fifo_queue_args.benchmark_runner, bench_conf.benchmark_id, bench_conf.seed())
+    }
+    else {
+        // Drain what is left in the queue and check that no items were lost
+        let conserved = benchmark_helpers::drain_and_check_conservation(&cqueue, prefilled, pushes, pops);
+        format!("{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            (pushes + pops) as f64 / time_limit as f64,
+            pushes,
+            pops,
+            consumers,
+            producers,
+            cqueue.get_id(),
+            fifo_queue_args.benchmark_runner,
+            bench_conf.benchmark_id,
+            conserved,
+            failed_pushes,
+            timeouts,
+            wakeups.len(),
+            wakeups.percentile(50.0),
+            wakeups.percentile(99.0),
+            wakeups.max(),
+            idle_wall as f64 / 1e6,
+            idle_cpu_ms,
+            idle_cpu_share, bench_conf.seed())
+    };
+    if !bench_conf.args.write_to_stdout {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&bench_conf.output_filename)?;
+
+        writeln!(file, "{}", formatted)?;
+
+    } else {
+        println!("{}", formatted);
+    }
+
+    if thread_failed.load(Ordering::Relaxed) {
+        return Err(Error::ThreadPanic(format!(
+            "a {} thread panicked, the results were padded with zeros",
+            fifo_queue_args.benchmark_runner
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::benchmarks::test_helpers::bench_conf;
+
+    use crate::arguments::FifoQueueBlockingArgs;
+    use crate::traits::ConcurrentQueue;
+    use crate::benchmarks::test_helpers::test_queue::TestQueue;
+
+    fn blocking_args(timeout: Option<u64>) -> FifoQueueArgs {
+        FifoQueueArgs {
+            benchmark_runner: FifoQueueBenchmarks::Blocking(
+                FifoQueueBlockingArgs { producers: 1, consumers: 2, interval: 100, timeout },
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn run_blocking() {
+        for timeout in [None, Some(1000)] {
+            let fifo_queue_args = blocking_args(timeout);
+            let bench_conf = bench_conf(&fifo_queue_args);
+            let queue: TestQueue<usize> = TestQueue::new(0);
+            if benchmark_blocking(queue, &bench_conf, &fifo_queue_args).is_err() {
+                panic!();
+            }
+        }
+    }
+
+    #[test]
+    fn close_wakes_blocked_consumer() {
+        let queue: TestQueue<usize> = TestQueue::new(0);
+        std::thread::scope(|s| {
+            let consumer = s.spawn(|| {
+                let mut handle = queue.register_blocking();
+                let first = handle.pop_blocking();
+                (first, handle.pop_blocking())
+            });
+            let mut handle = queue.register_blocking();
+            handle.push(1).unwrap();
+            std::thread::sleep(Duration::from_millis(10));
+            handle.close();
+            assert!(handle.push(2).is_err());
+            assert_eq!(consumer.join().unwrap(), (Some(1), None));
+        });
+    }
+}
@@ -0,0 +1,429 @@
+use crate::arguments::{FifoQueueArgs, FifoQueueBenchmarks};
+use crate::backoff::Backoff;
+use crate::benchmarks::benchmark_helpers::{self, BenchConfig};
+use crate::error::Error;
+use crate::traits::{ConcurrentQueue, HandleQueue};
+use log::{error, info, warn};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Statistics of one window of consecutive thread lifetimes.
+#[derive(Default)]
+struct Window {
+    /// Lifetimes of the window that have ended, including failed ones.
+    ended: AtomicUsize,
+    failed: AtomicUsize,
+    register_ns: AtomicU64,
+    deregister_ns: AtomicU64,
+    lifetime_ns: AtomicU64,
+    /// Nanoseconds from the start until the last lifetime of the window ended.
+    ended_ns: AtomicU64,
+    /// Resident memory when the window was complete, 0 if not sampled.
+    memory: AtomicU64,
+}
+
+/// What a thread did during its lifetime.
+struct Lifetime {
+    register: Duration,
+    deregister: Duration,
+    pushes: usize,
+    pops: usize,
+}
+
+/// # Explanation:
+/// Measures the cost of the per-thread state that queues set up in
+/// `register`, such as hazard pointers or thread ids. `--thread-count`
+/// spawners each repeatedly start a short-lived thread that registers a
+/// handle, does `--ops` enqueue-dequeue pairs, drops the handle and exits,
+/// until `--lifetimes` threads have lived or the time limit is reached. The
+/// lifetimes are reported in `--windows` rows, each with the mean time to
+/// register and drop a handle, the resident memory of the process when the
+/// window was complete and its growth since the start.
+/// A thread that panics while registering, e.g. because the queue ran out of
+/// thread ids, stops the benchmark. It is reported in the `Exhausted At`
+/// column as the index of the first lifetime that couldn't register.
+/// Benchmark specific flags:
+/// * `--thread-count`    (OPTIONAL)  Short-lived threads alive at the same time (standard 4).
+/// * `--lifetimes`       (OPTIONAL)  Total amount of thread lifetimes (standard 10000).
+/// * `--ops`             (OPTIONAL)  Enqueue-dequeue pairs per lifetime (standard 100).
+/// * `--windows`         (OPTIONAL)  Amount of rows the lifetimes are reported in (standard 10).
+pub fn benchmark_churn<C, T>(
+    cqueue: C,
+    bench_conf: &BenchConfig,
+    fifo_queue_args: &FifoQueueArgs,
+) -> Result<(), Error>
+where
+    C: ConcurrentQueue<T>,
+    T: Default,
+    for<'a> &'a C: Send,
+{
+    // Extract specific arguments for this benchmark runner
+    let churn_args = match &fifo_queue_args.benchmark_runner {
+        FifoQueueBenchmarks::Churn(a) => a,
+        _ => panic!(
+            "benchmark_churn called with another FIFO Queue \
+            configured. This is an implementation error."
+        ),
+    };
+    if churn_args.thread_count == 0 || churn_args.windows == 0 {
+        return Err(Error::Config(
+            "churn needs at least one thread and one window".to_string(),
+        ));
+    }
+
+    let prefilled =
+        benchmark_helpers::prefill(&cqueue, fifo_queue_args.prefill_amount, T::default);
+
+    let lifetimes = churn_args.lifetimes;
+    let window_len = lifetimes.div_ceil(churn_args.windows).max(1);
+    let windows: Vec<Window> = (0..lifetimes.div_ceil(window_len))
+        .map(|_| Window::default())
+        .collect();
+    let next = AtomicUsize::new(0);
+    let spawners_done = AtomicUsize::new(0);
+    let exhausted_at = AtomicUsize::new(usize::MAX);
+    let pushes = AtomicUsize::new(0);
+    let pops = AtomicUsize::new(0);
+    let done = AtomicBool::new(false);
+    let time_limit = Duration::from_secs(bench_conf.args.time_limit);
+    info!(
+        "Starting churn benchmark with {} lifetimes on {} threads",
+        lifetimes, churn_args.thread_count
+    );
+
+    let start_memory = benchmark_helpers::resident_memory();
+    let start = Instant::now();
+    std::thread::scope(|s| {
+        let queue = &cqueue;
+        let windows = &windows;
+        let next = &next;
+        let spawners_done = &spawners_done;
+        let exhausted_at = &exhausted_at;
+        let pushes = &pushes;
+        let pops = &pops;
+        let done = &done;
+        for _ in 0..churn_args.thread_count {
+            s.spawn(move || {
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    if i >= lifetimes
+                        || done.load(Ordering::Relaxed)
+                        || exhausted_at.load(Ordering::Relaxed) != usize::MAX
+                    {
+                        break;
+                    }
+                    let window = &windows[i / window_len];
+                    let registered = AtomicBool::new(false);
+                    let lifetime_start = Instant::now();
+                    let result = std::thread::scope(|inner| {
+                        let registered = &registered;
+                        std::thread::Builder::new()
+                            .spawn_scoped(inner, move || {
+                                run_lifetime(queue, bench_conf, fifo_queue_args, churn_args.ops, registered, done)
+                            })
+                            .map(|thread| thread.join())
+                    });
+                    let elapsed = lifetime_start.elapsed();
+                    match result {
+                        Ok(Ok(lifetime)) => {
+                            window.register_ns.fetch_add(lifetime.register.as_nanos() as u64, Ordering::Relaxed);
+                            window.deregister_ns.fetch_add(lifetime.deregister.as_nanos() as u64, Ordering::Relaxed);
+                            window.lifetime_ns.fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+                            pushes.fetch_add(lifetime.pushes, Ordering::Relaxed);
+                            pops.fetch_add(lifetime.pops, Ordering::Relaxed);
+                        }
+                        Ok(Err(e)) => {
+                            window.failed.fetch_add(1, Ordering::Relaxed);
+                            if !registered.load(Ordering::Relaxed) {
+                                error!("Lifetime {} panicked while registering: {:?}. Stopping the benchmark", i, e);
+                                exhausted_at.fetch_min(i, Ordering::Relaxed);
+                            } else {
+                                error!("Lifetime {} panicked: {:?}", i, e);
+                            }
+                        }
+                        Err(e) => {
+                            error!("Couldn't spawn thread for lifetime {}: {}. Stopping the benchmark", i, e);
+                            window.failed.fetch_add(1, Ordering::Relaxed);
+                            exhausted_at.fetch_min(i, Ordering::Relaxed);
+                        }
+                    }
+                    window.ended_ns.fetch_max(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+                    // The last lifetime of a complete window samples the memory
+                    let window_size = usize::min(window_len, lifetimes - i / window_len * window_len);
+                    if window.ended.fetch_add(1, Ordering::Relaxed) + 1 == window_size {
+                        let memory = benchmark_helpers::resident_memory().unwrap_or(0);
+                        window.memory.store(memory, Ordering::Relaxed);
+                    }
+                }
+                spawners_done.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        while spawners_done.load(Ordering::Relaxed) < churn_args.thread_count {
+            if start.elapsed() >= time_limit {
+                warn!("Time limit reached after {} lifetimes.", next.load(Ordering::Relaxed));
+                done.store(true, Ordering::Relaxed);
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    });
+
+    let pushes = pushes.into_inner();
+    let pops = pops.into_inner();
+    // Drain what is left in the queue and check that no items were lost
+    let conserved =
+        benchmark_helpers::drain_and_check_conservation(&cqueue, prefilled, pushes, pops);
+    let exhausted_at = match exhausted_at.into_inner() {
+        usize::MAX => -1,
+        i => i as i64,
+    };
+    let start_memory = start_memory.unwrap_or(0);
+    let end_memory = benchmark_helpers::resident_memory().unwrap_or(0);
+
+    let mut output = String::new();
+    let mut total_ended = 0;
+    let mut previous_end = 0;
+    for (w, window) in windows.iter().enumerate() {
+        let ended = window.ended.load(Ordering::Relaxed);
+        if ended == 0 {
+            continue;
+        }
+        total_ended += ended;
+        let failed = window.failed.load(Ordering::Relaxed);
+        let lived = (ended - failed).max(1) as f64;
+        let ended_ns = window.ended_ns.load(Ordering::Relaxed);
+        let per_second = ended as f64 / (ended_ns.saturating_sub(previous_end).max(1) as f64 / 1e9);
+        previous_end = ended_ns;
+        // Windows cut short by the time limit or exhaustion use the last sample
+        let memory = match window.memory.load(Ordering::Relaxed) {
+            0 => end_memory,
+            memory => memory,
+        };
+        let (memory, growth) = if memory == 0 || start_memory == 0 {
+            (-1, 0)
+        } else {
+            (memory as i64, memory as i64 - start_memory as i64)
+        };
+        output.push_str(&format!(
+            "{},{},{},{},{},{},{},{:.2},{:.2},{:.2},{:.2},{},{},{},{},{},{}\n",
+            cqueue.get_id(),
+            fifo_queue_args.benchmark_runner,
+            bench_conf.benchmark_id,
+            w,
+            churn_args.thread_count,
+            total_ended,
+            churn_args.ops,
+            window.register_ns.load(Ordering::Relaxed) as f64 / lived,
+            window.deregister_ns.load(Ordering::Relaxed) as f64 / lived,
+            window.lifetime_ns.load(Ordering::Relaxed) as f64 / lived,
+            per_second,
+            memory,
+            growth,
+            failed,
+            exhausted_at,
+            conserved,
+            bench_conf.seed()
+        ));
+    }
+
+    // Write to file or stdout depending on flag
+    if !bench_conf.args.write_to_stdout {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&bench_conf.output_filename)?;
+        write!(file, "{}", output)?;
+    } else {
+        print!("{}", output);
+    }
+    Ok(())
+}
+
+/// Body of a short-lived thread: registers a handle, does `ops`
+/// enqueue-dequeue pairs and drops the handle again.
+fn run_lifetime<C, T>(
+    cqueue: &C,
+    bench_conf: &BenchConfig,
+    fifo_queue_args: &FifoQueueArgs,
+    ops: usize,
+    registered: &AtomicBool,
+    done: &AtomicBool,
+) -> Lifetime
+where
+    C: ConcurrentQueue<T>,
+    T: Default,
+{
+    let register_start = Instant::now();
+    let mut handle = cqueue.register();
+    let register = register_start.elapsed();
+    registered.store(true, Ordering::Relaxed);
+
+    let mut backoff = Backoff::from_args(&bench_conf.args);
+    let mut l_pushes = 0;
+    let mut l_pops = 0;
+    for _ in 0..ops {
+        let (pushed, _) = benchmark_helpers::push_with_policy(
+            &mut handle,
+            T::default(),
+            fifo_queue_args.full_policy,
+            &mut backoff,
+            done,
+        );
+        l_pushes += pushed as usize;
+        if handle.pop().is_some() {
+            l_pops += 1;
+        }
+    }
+
+    let deregister_start = Instant::now();
+    handle.unregister();
+    Lifetime {
+        register,
+        deregister: deregister_start.elapsed(),
+        pushes: l_pushes,
+        pops: l_pops,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arguments::FifoQueueChurnArgs;
+    use crate::benchmarks::test_helpers::bench_conf;
+    use crate::benchmarks::test_helpers::test_queue::TestQueue;
+    use crate::traits::{AllocatedId, Capabilities, ThreadIdAllocator};
+
+    /// A queue that supports ten registered handles at once. If `leak` is set
+    /// handles never release their id, like a queue without deregistration,
+    /// except that the thread that created the queue can register without
+    /// one to prefill and drain it.
+    struct LimitedQueue {
+        queue: TestQueue<usize>,
+        ids: ThreadIdAllocator,
+        leak: bool,
+        owner: std::thread::ThreadId,
+    }
+
+    struct LimitedHandle<'a, H> {
+        handle: H,
+        _id: Option<AllocatedId<'a>>,
+    }
+
+    impl<H: HandleQueue<usize>> HandleQueue<usize> for LimitedHandle<'_, H> {
+        fn push(&mut self, item: usize) -> Result<(), usize> {
+            self.handle.push(item)
+        }
+        fn pop(&mut self) -> Option<usize> {
+            self.handle.pop()
+        }
+    }
+
+    impl ConcurrentQueue<usize> for LimitedQueue {
+        fn register(&self) -> impl HandleQueue<usize> {
+            let id = if !self.leak {
+                Some(self.ids.acquire().expect("out of thread ids"))
+            } else {
+                if std::thread::current().id() != self.owner {
+                    std::mem::forget(self.ids.acquire().expect("out of thread ids"));
+                }
+                None
+            };
+            LimitedHandle {
+                handle: self.queue.register(),
+                _id: id,
+            }
+        }
+        fn get_id(&self) -> String {
+            "limited_queue".to_string()
+        }
+        fn new(size: usize) -> Self {
+            LimitedQueue {
+                queue: TestQueue::new(size),
+                ids: ThreadIdAllocator::new(10),
+                leak: false,
+                owner: std::thread::current().id(),
+            }
+        }
+        fn capabilities(&self) -> Capabilities {
+            Capabilities {
+                max_threads: Some(self.ids.max()),
+                ..Capabilities::MPMC
+            }
+        }
+    }
+
+    fn churn_args(thread_count: usize) -> FifoQueueArgs {
+        FifoQueueArgs {
+            benchmark_runner: FifoQueueBenchmarks::Churn(FifoQueueChurnArgs {
+                thread_count,
+                lifetimes: 200,
+                ops: 10,
+                windows: 4,
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// Runs churn into a file and returns the `Exhausted At` column of the
+    /// rows it wrote.
+    fn exhausted_at(queue: LimitedQueue, fifo_queue_args: &FifoQueueArgs, name: &str) -> Vec<i64> {
+        let path = std::env::temp_dir().join(format!("churn_{}_{}.csv", name, std::process::id()));
+        let mut bench_conf = bench_conf(fifo_queue_args);
+        bench_conf.args.write_to_stdout = false;
+        bench_conf.output_filename = path.to_str().unwrap().to_string();
+        benchmark_churn(queue, &bench_conf, fifo_queue_args).unwrap();
+        let rows = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        rows.lines()
+            .map(|row| row.split(',').nth(14).unwrap().parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn run_churn() {
+        let fifo_queue_args = churn_args(4);
+        let queue: TestQueue<usize> = TestQueue::new(0);
+        benchmark_churn(queue, &bench_conf(&fifo_queue_args), &fifo_queue_args)
+            .unwrap();
+    }
+
+    #[test]
+    fn thread_limit() {
+        // Recycled ids let more threads than ids use the queue
+        let fifo_queue_args = churn_args(4);
+        let queue = LimitedQueue::new(0);
+        let rows = exhausted_at(queue, &fifo_queue_args, "recycled");
+        assert!(!rows.is_empty());
+        assert!(rows.iter().all(|&i| i == -1), "ids ran out: {rows:?}");
+
+        // Leaked ids run out, which stops the benchmark instead of failing it.
+        // With one thread at a time the lifetimes register in order, so the
+        // first that can't is the one after the last id was handed out.
+        let fifo_queue_args = churn_args(1);
+        let mut queue = LimitedQueue::new(0);
+        queue.leak = true;
+        let limit = queue.ids.max() as i64;
+        assert_eq!(exhausted_at(queue, &fifo_queue_args, "leaked"), [limit]);
+
+        // More threads than ids are refused before the run
+        let fifo_queue_args = churn_args(20);
+        let queue = LimitedQueue::new(0);
+        assert!(benchmark_helpers::check_capabilities(
+            &queue.get_id(),
+            queue.capabilities(),
+            &fifo_queue_args.benchmark_runner
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn rejects_zero_threads() {
+        let fifo_queue_args = churn_args(0);
+        let queue: TestQueue<usize> = TestQueue::new(0);
+        let err = benchmark_churn(queue, &bench_conf(&fifo_queue_args), &fifo_queue_args)
+            .unwrap_err();
+        assert!(matches!(err, Error::Config(_)));
+    }
+}
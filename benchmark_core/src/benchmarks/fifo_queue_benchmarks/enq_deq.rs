@@ -1,13 +1,18 @@
 use crate::arguments::{FifoQueueArgs, FifoQueueBenchmarks};
+use crate::backoff::Backoff;
+use crate::work::{self, Worker};
 use crate::benchmarks::benchmark_helpers::{self, BenchConfig};
-use crate::traits::{ConcurrentQueue, HandleQueue};
+use crate::error::Error;
+use crate::rng::Stream;
+use crate::trace::{ThreadTrace, Trace, TraceOp, TraceOpKind};
+use crate::traits::ConcurrentQueue;
 use core_affinity::CoreId;
-use log::{debug, error, info, trace};
+use log::{error, info, trace, warn};
 use rand::Rng;
 use std::fs::OpenOptions;
 use std::io::Write;
-use std::sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, Barrier};
-use std::sync::{mpsc, Arc};
+use std::sync::{atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering}, Barrier};
+use std::sync::{mpsc, Arc, Mutex};
 
 /// # Explanation:
 /// A benchmark that measures the throughput of a queue.
@@ -15,12 +20,14 @@ use std::sync::{mpsc, Arc};
 /// Benchmark specific flags:
 /// * --spread              Set the spread of consumers/producers, value between 0 - 1.        Ex. --spread 0.3,  gives 30% consume, 70% produce        
 /// * --thread-count        Set the amount of threads to run in the benchmark
+/// * --record              Record the operations of every thread to a trace file
+/// * --record-limit        Most operations recorded per thread
 #[allow(dead_code)]
 pub fn benchmark_enq_deq<C, T> (
     cqueue: C,
     bench_conf: &BenchConfig,
     fifo_queue_args: &FifoQueueArgs,
-) -> Result<(), std::io::Error>
+) -> Result<(), Error>
 where
 C: ConcurrentQueue<T>,
 T: Default,
@@ -35,20 +42,22 @@ T: Default,
         ),
     };
 
-    {
-        debug!("Prefilling queue with {} items.", fifo_queue_args.prefill_amount);
-        let mut tmp_handle = cqueue.register();
-        for _ in 0..fifo_queue_args.prefill_amount {
-            let _ = tmp_handle.push(Default::default());
-        } 
-    }
+    let prefilled =
+        benchmark_helpers::prefill(&cqueue, fifo_queue_args.prefill_amount, T::default);
     let thread_count = enq_deq_args.thread_count;
     let time_limit: u64 = bench_conf.args.time_limit;
+    let batch_size = fifo_queue_args.batch_size as usize;
     let barrier = Barrier::new(thread_count + 1);
     let pops  = AtomicUsize::new(0);
     let pushes = AtomicUsize::new(0);
+    let empty_pops = AtomicUsize::new(0);
+    let failed_pushes = AtomicUsize::new(0);
+    let backoffs = AtomicUsize::new(0);
+    let think_time = AtomicU64::new(0);
+    let thinks = AtomicUsize::new(0);
     let done = AtomicBool::new(false);
     let (tx, rx) = mpsc::channel();
+    let traces: Mutex<Vec<ThreadTrace>> = Mutex::new(vec![ThreadTrace::new(); thread_count]);
     info!("Starting pingpong benchmark with {} threads", thread_count);
     
 
@@ -67,12 +76,18 @@ T: Default,
         let thread_failed = &thread_failed; // Every thread clones the thread_failed bool
         let pushes = &pushes;
         let pops = &pops;
+        let empty_pops = &empty_pops;
+        let failed_pushes = &failed_pushes;
+        let backoffs = &backoffs;
+        let think_time = &think_time;
+        let thinks = &thinks;
         let done = &done;
         let barrier = &barrier;
         let &thread_count = &thread_count; 
         let &spread = &enq_deq_args.spread;
         let is_one_socket = &bench_conf.args.one_socket;
         let tx = &tx;
+        let traces = &traces;
         for _i in 0..thread_count{
             let mut core : CoreId = core_iter.next().unwrap();
             // if is_one_socket is true, make all thread ids even 
@@ -84,34 +99,73 @@ T: Default,
             s.spawn(move || {
                 let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
                     core_affinity::set_for_current(core);
-                    let mut handle = queue.register();
+                    let mut handle = queue.register_batch();
+                    let mut backoff = Backoff::from_args(&bench_conf.args);
+                    let mut worker = Worker::from_args(&bench_conf.args, _i);
+                    let mut rng = bench_conf.thread_rng(Stream::Operations, _i);
+                    let mut batch = Vec::with_capacity(batch_size);
+                    let mut buffer = Vec::with_capacity(batch_size);
                     let mut l_pushes = 0; 
                     let mut l_pops = 0;
+                    let mut l_empty_pops = 0;
+                    let mut l_failed_pushes = 0;
                     let _thread_failed = thread_failed.clone();
+                    let mut l_trace = enq_deq_args
+                        .record
+                        .as_ref()
+                        .map(|_| ThreadTrace::with_capacity(enq_deq_args.record_limit));
                     barrier.wait();
                     while !done.load(Ordering::Relaxed) {
-                        let random_float = rand::rng().random::<f64>();
-                        if random_float > spread {
-                            match handle.pop() {
-                                Some(_) => l_pops += 1,
-                                None => {
+                        let random_float = rng.random::<f64>();
+                        let kind = if random_float > spread {
+                            match benchmark_helpers::pop_items(&mut handle, &mut buffer, batch_size) {
+                                0 => {
                                     if fifo_queue_args.empty_pops {
-                                        l_pops += 1;
+                                        l_empty_pops += 1;
                                     }
+                                    backoff.backoff();
+                                }
+                                popped => {
+                                    l_pops += popped;
+                                    backoff.reset();
                                 }
                             }
+                            TraceOpKind::Pop
                         } else {
-                            let _ = handle.push(T::default());
-                            l_pushes += 1;
+                            let (pushed, failed) = benchmark_helpers::push_items(
+                                &mut handle,
+                                &mut batch,
+                                batch_size,
+                                fifo_queue_args.full_policy,
+                                &mut backoff,
+                                done,
+                            );
+                            l_pushes += pushed;
+                            l_failed_pushes += failed;
+                            TraceOpKind::Push
+                        };
+                        let think_time = worker.think();
+                        if let Some(trace) = &mut l_trace {
+                            if trace.len() < enq_deq_args.record_limit {
+                                trace.push(TraceOp { kind, think_time });
+                            }
                         }
-                        for _ in 0..bench_conf.args.delay {
-                            let _some_num = rand::rng().random::<f64>();
+                    }
+                    if let Some(trace) = l_trace {
+                        if trace.len() == enq_deq_args.record_limit {
+                            warn!("{}: Only recorded the first {} operations", _i, trace.len());
                         }
+                        traces.lock().unwrap()[_i] = trace;
                     }
             
                     pushes.fetch_add(l_pushes, Ordering::Relaxed);
                     pops.fetch_add(l_pops, Ordering::Relaxed);
-                    tx.send(l_pops + l_pushes).unwrap();
+                    empty_pops.fetch_add(l_empty_pops, Ordering::Relaxed);
+                    failed_pushes.fetch_add(l_failed_pushes, Ordering::Relaxed);
+                    backoffs.fetch_add(backoff.count(), Ordering::Relaxed);
+                    think_time.fetch_add(worker.total_think_time().as_nanos() as u64, Ordering::Relaxed);
+                    thinks.fetch_add(worker.thinks(), Ordering::Relaxed);
+                    tx.send(l_pops + l_empty_pops + l_pushes + l_failed_pushes).unwrap();
                     trace!("{}: Pushed: {}, Popped: {}", _i, l_pushes, l_pops);
                 }));
                 // A thread panicked, aborting the benchmark...
@@ -131,6 +185,10 @@ T: Default,
     drop(tx);
     let pops = pops.into_inner();
     let pushes = pushes.into_inner();
+    let empty_pops = empty_pops.into_inner();
+    let failed_pushes = failed_pushes.into_inner();
+    let backoffs = backoffs.into_inner();
+    let mean_think_time = work::mean_think_time(think_time.into_inner(), thinks.into_inner());
     // Fairness
     let ops_per_thread = {
         let mut vals = vec![];
@@ -139,21 +197,35 @@ T: Default,
         }
         vals
     };
-    let fairness = benchmark_helpers::calc_fairness(ops_per_thread);
+
+    if let Some(path) = &enq_deq_args.record {
+        if !thread_failed.load(Ordering::Relaxed) {
+            let trace = Trace::new(traces.into_inner().unwrap());
+            info!("Recording trace with {} operations to {}", trace.ops(), path);
+            trace.save(path)?;
+        }
+    }
 
     // If a thread crashed, pad the results with zero-values
     let formatted = if thread_failed.load(Ordering::Relaxed) {
-        format!("0,0,0,-1,-1,{},{},{},{},0,{},{}",
+        format!("0,0,0,-1,-1,{},{},{},{},0,{},{},false,0,0,0,{},{}",
             thread_count, 
             cqueue.get_id(),
             fifo_queue_args.benchmark_runner,
             bench_conf.benchmark_id,
             enq_deq_args.spread,
-            fifo_queue_args.queue_size
+            fifo_queue_args.queue_size,
+            batch_size,
+            bench_conf.seed()
             )
     }
     else {
-        format!("{},{},{},{},{},{},{},{},{},{},{},{}",
+        let fairness = benchmark_helpers::calc_fairness(ops_per_thread);
+        // Drain what is left in the queue and check that no items were lost
+        let conserved = benchmark_helpers::drain_and_check_conservation(&cqueue, prefilled, pushes, pops);
+        // Empty pops are only counted if the flag is set
+        let pops = pops + empty_pops;
+        format!("{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
         (pushes + pops) as f64 / time_limit as f64,
         pushes,
         pops,
@@ -165,7 +237,13 @@ T: Default,
         bench_conf.benchmark_id,
         fairness,
         enq_deq_args.spread,
-        fifo_queue_args.queue_size
+        fifo_queue_args.queue_size,
+        conserved,
+        failed_pushes,
+        backoffs,
+        mean_think_time,
+        batch_size,
+        bench_conf.seed()
         )
     };
     // Write to file or stdout depending on flag
@@ -178,6 +256,12 @@ T: Default,
     } else {
         println!("{}", formatted);
     }
+    if thread_failed.load(Ordering::Relaxed) {
+        return Err(Error::ThreadPanic(format!(
+            "a {} thread panicked, the results were padded with zeros",
+            fifo_queue_args.benchmark_runner
+        )));
+    }
     Ok(())
 }
 
@@ -189,22 +273,18 @@ mod tests {
 
     use super::*;
 
+    use crate::benchmarks::test_helpers::bench_conf;
     use crate::benchmarks::test_helpers::test_queue::TestQueue;
 
     #[test]
     fn run_pingpong() {
         let fifo_queue_args = FifoQueueArgs {
             benchmark_runner: FifoQueueBenchmarks::EnqDeq(
-                FifoQueueEnqDeqArgs { thread_count: 10 , spread: 0.5 },
+                FifoQueueEnqDeqArgs { thread_count: 10 , spread: 0.5, record: None, record_limit: 10_000_000 },
             ),
             ..Default::default()
         };
-        let bench_conf = benchmark_helpers::BenchConfig {
-            args: fifo_queue_args.general_args.clone(),
-            date_time: "".to_string(),
-            benchmark_id: "test2".to_string(),
-            output_filename: "".to_string(),
-        };
+        let bench_conf = bench_conf(&fifo_queue_args);
         let queue: TestQueue<usize> = TestQueue::new(0);
         if benchmark_enq_deq(queue, &bench_conf, &fifo_queue_args)
             .is_err()
@@ -217,16 +297,11 @@ mod tests {
     fn run_pingpong_with_bool() {
         let fifo_queue_args = FifoQueueArgs {
             benchmark_runner: FifoQueueBenchmarks::EnqDeq(
-                FifoQueueEnqDeqArgs { thread_count: 10 , spread: 0.5 },
+                FifoQueueEnqDeqArgs { thread_count: 10 , spread: 0.5, record: None, record_limit: 10_000_000 },
             ),
             ..Default::default()
         };
-        let bench_conf = benchmark_helpers::BenchConfig {
-            args: fifo_queue_args.general_args.clone(),
-            date_time: "".to_string(),
-            benchmark_id: "test2".to_string(),
-            output_filename: "".to_string(),
-        };
+        let bench_conf = bench_conf(&fifo_queue_args);
         let queue: TestQueue<bool> = TestQueue::new(0);
         if benchmark_enq_deq(queue, &bench_conf, &fifo_queue_args)
             .is_err()
@@ -234,4 +309,42 @@ mod tests {
             panic!();
         }
     }
+
+    #[test]
+    fn records_a_trace() {
+        let path = std::env::temp_dir().join(format!("enq_deq_test_{}.trace", std::process::id()));
+        let fifo_queue_args = FifoQueueArgs {
+            benchmark_runner: FifoQueueBenchmarks::EnqDeq(
+                FifoQueueEnqDeqArgs { thread_count: 4 , spread: 0.5, record: Some(path.to_str().unwrap().to_string()), record_limit: 10_000_000 },
+            ),
+            ..Default::default()
+        };
+        let bench_conf = bench_conf(&fifo_queue_args);
+        let queue: TestQueue<usize> = TestQueue::new(0);
+        benchmark_enq_deq(queue, &bench_conf, &fifo_queue_args).unwrap();
+        let trace = Trace::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(trace.thread_count(), 4);
+        assert!(trace.threads().iter().all(|thread| !thread.is_empty()));
+        // Recorded think times are the drawn ones, with the fixed default of 100 ns
+        assert!(trace.threads()[0].iter().all(|op| op.think_time == 100));
+    }
+
+    #[test]
+    fn records_at_most_the_limit() {
+        let path = std::env::temp_dir().join(format!("enq_deq_limit_test_{}.trace", std::process::id()));
+        let fifo_queue_args = FifoQueueArgs {
+            benchmark_runner: FifoQueueBenchmarks::EnqDeq(
+                FifoQueueEnqDeqArgs { thread_count: 4 , spread: 0.5, record: Some(path.to_str().unwrap().to_string()), record_limit: 100 },
+            ),
+            ..Default::default()
+        };
+        let bench_conf = bench_conf(&fifo_queue_args);
+        let queue: TestQueue<usize> = TestQueue::new(0);
+        benchmark_enq_deq(queue, &bench_conf, &fifo_queue_args).unwrap();
+        let trace = Trace::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // The threads run for the whole time limit, far past the limit
+        assert!(trace.threads().iter().all(|thread| thread.len() == 100));
+    }
 }
@@ -1,13 +1,15 @@
 use crate::arguments::{FifoQueueArgs, FifoQueueBenchmarks};
+use crate::backoff::Backoff;
+use crate::work::{self, Worker};
 use crate::benchmarks::benchmark_helpers::{self, BenchConfig};
-use crate::traits::{ConcurrentQueue, HandleQueue};
+use crate::error::Error;
+use crate::traits::ConcurrentQueue;
 use core_affinity::CoreId;
-use log::{debug, error, info, trace};
-use rand::Rng;
+use log::{error, info, trace};
 use std::fs::OpenOptions;
 use std::io::Write;
 use std::sync::{
-    atomic::{AtomicBool, AtomicUsize, Ordering},
+    atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
     Barrier,
 };
 use std::sync::{mpsc, Arc};
@@ -18,7 +20,7 @@ pub fn benchmark_enq_deq_pairs<C, T>(
     cqueue: C,
     bench_conf: &BenchConfig,
     fifo_queue_args: &FifoQueueArgs,
-) -> Result<(), std::io::Error>
+) -> Result<(), Error>
 where
     C: ConcurrentQueue<T>,
     T: Default,
@@ -33,23 +35,20 @@ where
         ),
     };
 
-    {
-        debug!(
-            "Prefilling queue with {} items.",
-            fifo_queue_args.prefill_amount
-        );
-
-        let mut tmp_handle = cqueue.register();
-        for _ in 0..fifo_queue_args.prefill_amount {
-            let _ = tmp_handle.push(Default::default());
-        }
-    }
+    let prefilled =
+        benchmark_helpers::prefill(&cqueue, fifo_queue_args.prefill_amount, T::default);
 
     let thread_count = enq_deq_pairs_args.thread_count;
     let time_limit: u64 = bench_conf.args.time_limit;
+    let batch_size = fifo_queue_args.batch_size as usize;
     let barrier = Barrier::new(thread_count + 1);
     let pops = AtomicUsize::new(0);
     let pushes = AtomicUsize::new(0);
+    let empty_pops = AtomicUsize::new(0);
+    let failed_pushes = AtomicUsize::new(0);
+    let backoffs = AtomicUsize::new(0);
+    let think_time = AtomicU64::new(0);
+    let thinks = AtomicUsize::new(0);
     let done = AtomicBool::new(false);
     let (tx, rx) = mpsc::channel();
     info!("Starting pingpong benchmark with {} threads", thread_count);
@@ -67,6 +66,11 @@ where
         let thread_failed = &thread_failed; // Every thread clones the thread_failed bool
         let pushes = &pushes;
         let pops = &pops;
+        let empty_pops = &empty_pops;
+        let failed_pushes = &failed_pushes;
+        let backoffs = &backoffs;
+        let think_time = &think_time;
+        let thinks = &thinks;
         let done = &done;
         let barrier = &barrier;
         let &thread_count = &thread_count;
@@ -83,23 +87,48 @@ where
             s.spawn(move || {
                 let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
                     core_affinity::set_for_current(core);
-                    let mut handle = queue.register();
+                    let mut handle = queue.register_batch();
+                    let mut backoff = Backoff::from_args(&bench_conf.args);
+                    let mut worker = Worker::from_args(&bench_conf.args, _i);
+                    let mut batch = Vec::with_capacity(batch_size);
+                    let mut buffer = Vec::with_capacity(batch_size);
                     let mut l_pushes = 0;
                     let mut l_pops = 0;
+                    let mut l_empty_pops = 0;
+                    let mut l_failed_pushes = 0;
                     let _thread_failed = thread_failed.clone();
                     barrier.wait();
                     while !done.load(Ordering::Relaxed) {
-                        let _ = handle.push(T::default());
-                        l_pushes += 1;
-                        let _ = handle.pop();
-                        l_pops += 1;
-                        for _ in 0..bench_conf.args.delay {
-                            let _some_num = rand::rng().random::<f64>();
+                        let (pushed, failed) = benchmark_helpers::push_items(
+                            &mut handle,
+                            &mut batch,
+                            batch_size,
+                            fifo_queue_args.full_policy,
+                            &mut backoff,
+                            done,
+                        );
+                        l_pushes += pushed;
+                        l_failed_pushes += failed;
+                        match benchmark_helpers::pop_items(&mut handle, &mut buffer, batch_size) {
+                            0 => {
+                                l_empty_pops += 1;
+                                backoff.backoff();
+                            }
+                            popped => {
+                                l_pops += popped;
+                                backoff.reset();
+                            }
                         }
+                        worker.think();
                     }
                     pushes.fetch_add(l_pushes, Ordering::Relaxed);
                     pops.fetch_add(l_pops, Ordering::Relaxed);
-                    tx.send(l_pops + l_pushes).unwrap();
+                    empty_pops.fetch_add(l_empty_pops, Ordering::Relaxed);
+                    failed_pushes.fetch_add(l_failed_pushes, Ordering::Relaxed);
+                    backoffs.fetch_add(backoff.count(), Ordering::Relaxed);
+                    think_time.fetch_add(worker.total_think_time().as_nanos() as u64, Ordering::Relaxed);
+                    thinks.fetch_add(worker.thinks(), Ordering::Relaxed);
+                    tx.send(l_pops + l_empty_pops + l_pushes + l_failed_pushes).unwrap();
                     trace!("{}: Pushed: {}, Popped: {}", _i, l_pushes, l_pops);
                 }));
                 // A thread panicked, aborting the benchmark...
@@ -118,6 +147,10 @@ where
     drop(tx);
     let pops = pops.into_inner();
     let pushes = pushes.into_inner();
+    let empty_pops = empty_pops.into_inner();
+    let failed_pushes = failed_pushes.into_inner();
+    let backoffs = backoffs.into_inner();
+    let mean_think_time = work::mean_think_time(think_time.into_inner(), thinks.into_inner());
     // Fairness
     let ops_per_thread = {
         let mut vals = vec![];
@@ -126,22 +159,30 @@ where
         }
         vals
     };
-    let fairness = benchmark_helpers::calc_fairness(ops_per_thread);
 
     // If a thread crashed, pad the results with zero-values
     let formatted = if thread_failed.load(Ordering::Relaxed) {
         format!(
-            "0,0,0,-1,-1,{},{},{},{},0,{},{}",
+            "0,0,0,-1,-1,{},{},{},{},0,{},{},false,0,0,0,{},{}",
             thread_count,
             cqueue.get_id(),
             fifo_queue_args.benchmark_runner,
             bench_conf.benchmark_id,
             -1,
-            fifo_queue_args.queue_size
+            fifo_queue_args.queue_size,
+            batch_size,
+            bench_conf.seed()
         )
     } else {
+        let fairness = benchmark_helpers::calc_fairness(ops_per_thread);
+        // Drain what is left in the queue and check that no items were lost
+        let conserved = benchmark_helpers::drain_and_check_conservation(
+            &cqueue, prefilled, pushes, pops,
+        );
+        // Every pair counts its pop, also the ones that found the queue empty
+        let pops = pops + empty_pops;
         format!(
-            "{},{},{},{},{},{},{},{},{},{},{},{}",
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
             (pushes + pops) as f64 / time_limit as f64,
             pushes,
             pops,
@@ -153,7 +194,13 @@ where
             bench_conf.benchmark_id,
             fairness,
             -1,
-            fifo_queue_args.queue_size
+            fifo_queue_args.queue_size,
+            conserved,
+            failed_pushes,
+            backoffs,
+            mean_think_time,
+            batch_size,
+            bench_conf.seed()
         )
     };
     // Write to file or stdout depending on flag
@@ -166,6 +213,12 @@ where
     } else {
         println!("{}", formatted);
     }
+    if thread_failed.load(Ordering::Relaxed) {
+        return Err(Error::ThreadPanic(format!(
+            "a {} thread panicked, the results were padded with zeros",
+            fifo_queue_args.benchmark_runner
+        )));
+    }
     Ok(())
 }
 
@@ -176,6 +229,7 @@ mod tests {
     use crate::arguments::FifoQueueEnqDeqPairsArgs;
 
     use super::*;
+    use crate::benchmarks::test_helpers::bench_conf;
 
     use crate::benchmarks::test_helpers::test_queue::TestQueue;
 
@@ -187,12 +241,7 @@ mod tests {
             ),
             ..Default::default()
         };
-        let bench_conf = benchmark_helpers::BenchConfig {
-            args: fifo_queue_args.general_args.clone(),
-            date_time: "".to_string(),
-            benchmark_id: "test2".to_string(),
-            output_filename: "".to_string(),
-        };
+        let bench_conf = bench_conf(&fifo_queue_args);
         let queue: TestQueue<usize> = TestQueue::new(0);
         if benchmark_enq_deq_pairs(queue, &bench_conf, &fifo_queue_args)
             .is_err()
@@ -0,0 +1,386 @@
+use core_affinity::CoreId;
+use log::{debug, error, info, trace, warn};
+use rand::Rng;
+use crate::arguments::{Arrivals, FifoQueueArgs, FifoQueueBenchmarks};
+use crate::backoff::Backoff;
+use crate::histogram::Histogram;
+use crate::work::{self, Worker};
+use crate::error::Error;
+use crate::rng::Stream;
+use crate::traits::{ConcurrentQueue, HandleQueue};
+use crate::benchmarks::benchmark_helpers::{self, BenchConfig};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::{atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering}, Barrier, Mutex};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+/// Payload of the prefilled items. They never arrived through a producer so
+/// they carry no timestamp and are left out of the sojourn times.
+pub(crate) const PREFILLED: usize = usize::MAX;
+
+/// Producers that are further than this ahead of their next arrival sleep
+/// instead of spinning [ns].
+const SLEEP_THRESHOLD: u64 = 100_000;
+
+/// Longest a producer sleeps before checking if the benchmark is done [ns].
+const MAX_SLEEP: u64 = 1_000_000;
+
+/// A producer missing more than this share of its scheduled arrivals did not
+/// sustain the offered rate.
+const MAX_MISSED_SHARE: f64 = 0.01;
+
+/// # Explanation:
+/// The open-loop variant of `prod-con`, used when `--rate` is set.
+/// Producers don't push as fast as they can but follow an arrival schedule
+/// offering `--rate` pushes per second in total, with constant or Poisson
+/// distributed arrivals. Every item carries the time it was scheduled to
+/// arrive, so consumers can measure the sojourn time from arrival to pop.
+/// Timestamps are taken from the schedule and not from the actual push, so a
+/// producer falling behind shows up in the sojourn times instead of being
+/// hidden (coordinated omission).
+/// Benchmark specific flags:
+/// * -p            Set specified amount of producers
+/// * -c            Set specified amount of consumers
+/// * --rate        Set the offered rate [pushes/s]
+/// * --arrivals    Set the arrival process, constant or poisson
+#[allow(dead_code)]
+pub fn benchmark_open_loop<C>(cqueue: C, bench_conf: &BenchConfig, fifo_queue_args: &FifoQueueArgs) -> Result<(), Error>
+where
+    C: ConcurrentQueue<usize>,
+    for<'a> &'a C: Send
+{
+    // Extract specific arguments for this benchmark runner
+    let prod_con_args = match &fifo_queue_args.benchmark_runner {
+        FifoQueueBenchmarks::ProdCon(a) => a,
+        _ => panic!("benchmark_open_loop called with another FIFO Queue \
+            configured. This is an implementation error.")
+    };
+    let rate = match prod_con_args.rate {
+        Some(rate) if rate > 0.0 && rate.is_finite() => rate,
+        _ => return Err(Error::Config(
+            "--rate has to be a positive number of pushes per second".to_string(),
+        )),
+    };
+
+    let prefilled =
+        benchmark_helpers::prefill(&cqueue, fifo_queue_args.prefill_amount, || PREFILLED);
+    let producers = prod_con_args.producers;
+    let consumers = prod_con_args.consumers;
+    // Every producer offers an equal share of the rate
+    let mean_interarrival = 1e9 * producers as f64 / rate;
+
+    let time_limit: u64 = bench_conf.args.time_limit;
+    let barrier = Barrier::new(consumers + producers + 1);
+    let pops  = AtomicUsize::new(0);
+    let pushes = AtomicUsize::new(0);
+    let failed_pushes = AtomicUsize::new(0);
+    let scheduled = AtomicUsize::new(0);
+    let backoffs = AtomicUsize::new(0);
+    let think_time = AtomicU64::new(0);
+    let thinks = AtomicUsize::new(0);
+    let sojourn = Mutex::new(Histogram::new());
+    let done = AtomicBool::new(false);
+    let (tx, rx) = mpsc::channel();
+    info!("Starting open-loop benchmark with {} consumer and {} producers offering {} pushes/s", consumers, producers, rate);
+
+    // get cores for fairness of threads
+    let available_cores: Vec<CoreId> =
+        core_affinity::get_core_ids().unwrap_or(vec![CoreId { id: 0 }]);
+    let mut core_iter = available_cores.into_iter().cycle();
+
+    // Shared atomic bool for when a thread fails
+    let thread_failed = Arc::new(AtomicBool::new(false));
+
+    // All timestamps are nanoseconds since this instant
+    let epoch = Instant::now();
+
+    let _ = std::thread::scope(|s| -> Result<(), std::io::Error>{
+        let queue = &cqueue;
+        let pushes = &pushes;
+        let failed_pushes = &failed_pushes;
+        let scheduled = &scheduled;
+        let backoffs = &backoffs;
+        let think_time = &think_time;
+        let thinks = &thinks;
+        let sojourn = &sojourn;
+        let pops = &pops;
+        let done = &done;
+        let barrier = &barrier;
+        let tx = &tx;
+        let is_one_socket = &bench_conf.args.one_socket;
+        let thread_failed = &thread_failed;
+
+        for i in 0..producers {
+            let mut core : CoreId = core_iter.next().unwrap();
+            // if is_one_socket is true, make all thread ids even
+            // (this was used for our testing enviroment to get one socket)
+            if *is_one_socket {
+                core = core_iter.next().unwrap();
+            }
+            trace!("Thread: {} Core: {:?}", i, core);
+            s.spawn(move || {
+                let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                core_affinity::set_for_current(core);
+                let mut handle = queue.register();
+                let mut backoff = Backoff::from_args(&bench_conf.args);
+                let mut rng = bench_conf.thread_rng(Stream::Arrivals, i);
+                let mut interarrival = || match prod_con_args.arrivals {
+                    Arrivals::Constant => mean_interarrival,
+                    // Inverse transform sampling, 1 - u is in (0, 1] so ln never sees 0.
+                    Arrivals::Poisson => -mean_interarrival * (1.0 - rng.random::<f64>()).ln(),
+                };
+                let mut l_pushes = 0;
+                let mut l_failed_pushes = 0;
+                let mut l_scheduled = 0;
+                let _thread_failed = thread_failed.clone(); // Every thread clones the thread_failed bool
+                barrier.wait();
+                let mut next_arrival = epoch.elapsed().as_nanos() as f64 + interarrival();
+                while wait_until(epoch, next_arrival as u64, done) {
+                    l_scheduled += 1;
+                    let (pushed, failed) = benchmark_helpers::push_with_policy(
+                        &mut handle,
+                        next_arrival as usize,
+                        fifo_queue_args.full_policy,
+                        &mut backoff,
+                        done,
+                    );
+                    if pushed {
+                        l_pushes += 1;
+                    }
+                    l_failed_pushes += failed;
+                    next_arrival += interarrival();
+                }
+                // Arrivals that were due before the end but never issued
+                let end = epoch.elapsed().as_nanos() as f64;
+                while next_arrival < end {
+                    l_scheduled += 1;
+                    next_arrival += interarrival();
+                }
+                pushes.fetch_add(l_pushes, Ordering::Relaxed);
+                failed_pushes.fetch_add(l_failed_pushes, Ordering::Relaxed);
+                scheduled.fetch_add(l_scheduled, Ordering::Relaxed);
+                backoffs.fetch_add(backoff.count(), Ordering::Relaxed);
+                // Thread sends its total operations down the channel for fairness calculations
+                if let Err(e) = tx.send(l_pushes + l_failed_pushes) {
+                    error!("Error sending operations down the channel: {}", e);
+                };
+            }));
+            // A thread panicked, aborting the benchmark...
+            if let Err (e) = result {
+                error!("Thread {} panicked in pushing: {:?}. Aborting benchmark, padding results to zero", i, e);
+                thread_failed.store(true, Ordering::Relaxed);
+                done.store(true, Ordering::Relaxed);
+            }
+            });
+        }
+        for i in 0..consumers {
+            let mut core : CoreId = core_iter.next().expect("Core iter error");
+            // if is_one_socket is true, make all thread ids even
+            // (this was used for our testing enviroment to get one socket)
+            if *is_one_socket {
+                core = core_iter.next().unwrap();
+            }
+            trace!("Thread: {} Core: {:?}", i, core);
+
+            s.spawn(move || {
+                let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                core_affinity::set_for_current(core);
+                let mut handle = queue.register();
+                let mut backoff = Backoff::from_args(&bench_conf.args);
+                let mut worker = Worker::from_args(&bench_conf.args, producers + i);
+                let mut l_sojourn = Histogram::new();
+                let mut l_pops = 0;
+                let mut empty_pops = 0;
+                let _thread_failed = thread_failed.clone(); // Every thread clones the thread_failed bool
+                barrier.wait();
+                while !done.load(Ordering::Relaxed) {
+                    match handle.pop() {
+                        Some(arrival) => {
+                            if arrival != PREFILLED {
+                                let now = epoch.elapsed().as_nanos() as u64;
+                                l_sojourn.record(now.saturating_sub(arrival as u64));
+                            }
+                            l_pops += 1;
+                            backoff.reset();
+                        }
+                        None => {
+                            empty_pops += 1;
+                            backoff.backoff();
+                        }
+                    }
+                    worker.think();
+                }
+                pops.fetch_add(l_pops, Ordering::Relaxed);
+                backoffs.fetch_add(backoff.count(), Ordering::Relaxed);
+                think_time.fetch_add(worker.total_think_time().as_nanos() as u64, Ordering::Relaxed);
+                thinks.fetch_add(worker.thinks(), Ordering::Relaxed);
+                sojourn.lock().unwrap().merge(&l_sojourn);
+                // Thread sends its total operations down the channel for fairness calculations
+                if let Err(e) = tx.send(l_pops + empty_pops) {
+                    error!("Error sending operations down the channel: {}", e);
+                };
+            }));
+            // A thread panicked, aborting the benchmark...
+            if let Err(e) = result {
+                error!("Thread {} panicked while popping: {:?}. Aborting benchmark, padding results to zero", i, e);
+                thread_failed.store(true, Ordering::Relaxed);
+                done.store(true, Ordering::Relaxed);
+            }
+            });
+        }
+        debug!("Waiting for barrier");
+        barrier.wait();
+        debug!("Done waiting for barrier. Going to sleep.");
+        std::thread::sleep(std::time::Duration::from_secs(time_limit));
+        done.store(true, Ordering::Relaxed);
+        Ok(())
+    });
+    drop(tx);
+    debug!("TX Dropped");
+    let pops = pops.into_inner();
+    let pushes = pushes.into_inner();
+    let failed_pushes = failed_pushes.into_inner();
+    let scheduled = scheduled.into_inner();
+    let backoffs = backoffs.into_inner();
+    let mean_think_time = work::mean_think_time(think_time.into_inner(), thinks.into_inner());
+    let sojourn = sojourn.into_inner().unwrap();
+
+    // Fairness
+    // Get total operations per thread
+    let ops_per_thread = {
+        let mut vals = vec![];
+        for received in rx {
+            vals.push(received);
+        };
+        vals
+    };
+    // If a thread crashed, pad the results with zero-values
+    let formatted = if thread_failed.load(Ordering::Relaxed) {
+        format!("0,0,0,{},{},-1,{},{},{},0,-1,{},false,0,0,0,{},0,false,0,0,0,0,0,{}", producers, consumers, cqueue.get_id(), fifo_queue_args.benchmark_runner, bench_conf.benchmark_id, fifo_queue_args.queue_size, rate, bench_conf.seed())
+    }
+    else {
+        let fairness = benchmark_helpers::calc_fairness(ops_per_thread);
+        // Drain what is left in the queue and check that no items were lost
+        let conserved = benchmark_helpers::drain_and_check_conservation(&cqueue, prefilled, pushes, pops);
+        // Dropped pushes on a full queue still count as issued arrivals
+        let issued = pushes + failed_pushes;
+        let sustained = scheduled.saturating_sub(issued) as f64 <= MAX_MISSED_SHARE * scheduled as f64;
+        if !sustained {
+            warn!("Producers only issued {} of {} scheduled arrivals, the offered rate was not sustained.", issued, scheduled);
+        }
+        format!("{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            (pushes + pops) as f64 / time_limit as f64,
+            pushes,
+            pops,
+            consumers,
+            producers,
+            -1,
+            cqueue.get_id(),
+            fifo_queue_args.benchmark_runner,
+            bench_conf.benchmark_id,
+            fairness,
+            -1,
+            fifo_queue_args.queue_size,
+            conserved,
+            failed_pushes,
+            backoffs,
+            mean_think_time,
+            rate,
+            issued as f64 / time_limit as f64,
+            sustained,
+            sojourn.percentile(50.0),
+            sojourn.percentile(90.0),
+            sojourn.percentile(99.0),
+            sojourn.percentile(99.9),
+            sojourn.max(), bench_conf.seed())
+    };
+    if !bench_conf.args.write_to_stdout {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&bench_conf.output_filename)?;
+
+        writeln!(file, "{}", formatted)?;
+
+    } else {
+        println!("{}", formatted);
+    }
+
+    if thread_failed.load(Ordering::Relaxed) {
+        return Err(Error::ThreadPanic(format!(
+            "a {} thread panicked, the results were padded with zeros",
+            fifo_queue_args.benchmark_runner
+        )));
+    }
+    Ok(())
+}
+
+/// Waits until `target` nanoseconds after `epoch`.
+/// Returns false if the benchmark ended before that.
+fn wait_until(epoch: Instant, target: u64, done: &AtomicBool) -> bool {
+    loop {
+        if done.load(Ordering::Relaxed) {
+            return false;
+        }
+        let now = epoch.elapsed().as_nanos() as u64;
+        if now >= target {
+            return true;
+        }
+        let remaining = target - now;
+        if remaining > SLEEP_THRESHOLD {
+            std::thread::sleep(Duration::from_nanos(u64::min(remaining - SLEEP_THRESHOLD, MAX_SLEEP)));
+        } else {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::benchmarks::test_helpers::bench_conf;
+
+    use crate::arguments::FifoQueueProdConArgs;
+    use crate::benchmarks::test_helpers::test_queue::TestQueue;
+
+    fn open_loop_args(rate: Option<f64>, arrivals: Arrivals) -> FifoQueueArgs {
+        FifoQueueArgs {
+            benchmark_runner: FifoQueueBenchmarks::ProdCon(
+                FifoQueueProdConArgs { producers: 2, consumers: 2, rate, arrivals },
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn run_open_loop() {
+        for arrivals in [Arrivals::Constant, Arrivals::Poisson] {
+            let fifo_queue_args = open_loop_args(Some(10000.0), arrivals);
+            let bench_conf = bench_conf(&fifo_queue_args);
+            let queue: TestQueue<usize> = TestQueue::new(0);
+            if benchmark_open_loop(queue, &bench_conf, &fifo_queue_args).is_err() {
+                panic!();
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_rate() {
+        let fifo_queue_args = open_loop_args(Some(0.0), Arrivals::Poisson);
+        let bench_conf = bench_conf(&fifo_queue_args);
+        let queue: TestQueue<usize> = TestQueue::new(0);
+        assert!(matches!(
+            benchmark_open_loop(queue, &bench_conf, &fifo_queue_args),
+            Err(Error::Config(_))
+        ));
+    }
+
+    #[test]
+    fn wait_until_stops_when_done() {
+        let done = AtomicBool::new(true);
+        assert!(!wait_until(Instant::now(), u64::MAX, &done));
+        let done = AtomicBool::new(false);
+        assert!(wait_until(Instant::now(), 1000, &done));
+    }
+}
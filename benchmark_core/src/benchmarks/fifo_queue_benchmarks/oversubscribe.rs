@@ -0,0 +1,445 @@
+use crate::arguments::{FifoQueueArgs, FifoQueueBenchmarks, SchedPolicy};
+use crate::backoff::Backoff;
+use crate::benchmarks::benchmark_helpers::{self, BenchConfig, ThreadSched};
+use crate::error::Error;
+use crate::traits::{ConcurrentQueue, HandleQueue};
+use crate::work::Worker;
+use core_affinity::CoreId;
+use log::{error, info, warn};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Barrier, Mutex};
+
+/// Priority of oversubscribed threads running with SCHED_FIFO. The main
+/// thread runs one above, so it can always stop the round.
+const FIFO_PRIORITY: i32 = 1;
+
+/// Results of one round of the oversubscription benchmark.
+struct Round {
+    threads: usize,
+    pushes: usize,
+    pops: usize,
+    failed_pushes: usize,
+    backoffs: usize,
+    fairness: f64,
+    conserved: bool,
+    /// False if a thread couldn't switch to the requested policy.
+    sched_applied: bool,
+}
+
+impl Round {
+    fn throughput(&self, time_limit: u64) -> f64 {
+        (self.pushes + self.pops) as f64 / time_limit as f64
+    }
+}
+
+/// # Explanation:
+/// Measures how the queue copes with being preempted in the middle of an
+/// operation. Every thread enqueues an item, dequeues one and then thinks,
+/// like in `enq-deq-pairs`. First a baseline round runs with one pinned thread per
+/// core, then one round per oversubscription factor with `factor` threads per
+/// core. Every round runs for `--time-limit` seconds and writes its own row,
+/// which includes the throughput relative to the baseline and how much the
+/// fairness dropped compared to it.
+/// Benchmark specific flags:
+/// * `--factors`     (OPTIONAL)  Threads per core of the oversubscribed rounds (standard 2,4,8).
+/// * `--cores`       (OPTIONAL)  Amount of cores to use (standard all available cores).
+/// * `--unpinned`    (OPTIONAL)  Let the OS place the oversubscribed threads instead of pinning them.
+/// * `--sched`       (OPTIONAL)  Scheduling policy of the oversubscribed threads: other, fifo or mixed.
+pub fn benchmark_oversubscribe<C, T>(
+    cqueue: C,
+    bench_conf: &BenchConfig,
+    fifo_queue_args: &FifoQueueArgs,
+) -> Result<(), Error>
+where
+    C: ConcurrentQueue<T>,
+    T: Default,
+    for<'a> &'a C: Send,
+{
+    // Extract specific arguments for this benchmark runner
+    let oversubscribe_args = match &fifo_queue_args.benchmark_runner {
+        FifoQueueBenchmarks::Oversubscribe(a) => a,
+        _ => panic!(
+            "benchmark_oversubscribe called with another FIFO Queue \
+            configured. This is an implementation error."
+        ),
+    };
+    if oversubscribe_args.factors.contains(&0) {
+        return Err(Error::Config(
+            "oversubscription factors must be at least 1".to_string(),
+        ));
+    }
+
+    // Use every other core on one socket, like the other runners do
+    let step = if bench_conf.args.one_socket { 2 } else { 1 };
+    let mut cores: Vec<CoreId> = core_affinity::get_core_ids()
+        .unwrap_or(vec![CoreId { id: 0 }])
+        .into_iter()
+        .skip(step - 1)
+        .step_by(step)
+        .take(oversubscribe_args.core_count())
+        .collect();
+    if cores.is_empty() {
+        cores.push(CoreId { id: 0 });
+    }
+    if cores.len() < oversubscribe_args.core_count() {
+        warn!(
+            "Only {} cores available, using them instead of {}.",
+            cores.len(),
+            oversubscribe_args.core_count()
+        );
+    }
+
+    let time_limit = bench_conf.args.time_limit;
+    let mut baseline: Option<(f64, f64)> = None;
+    let mut failed_factors = vec![];
+    let rounds = std::iter::once((1, true, SchedPolicy::Other)).chain(
+        oversubscribe_args.factors.iter().map(|&factor| {
+            (factor, !oversubscribe_args.unpinned, oversubscribe_args.sched)
+        }),
+    );
+    for (factor, pinned, sched) in rounds {
+        info!(
+            "Starting oversubscription round with {} threads per core on {} cores",
+            factor,
+            cores.len()
+        );
+        let round = run_round(
+            &cqueue,
+            bench_conf,
+            fifo_queue_args,
+            &cores,
+            factor,
+            pinned,
+            sched,
+        );
+        let formatted = match round {
+            Some(round) => {
+                let throughput = round.throughput(time_limit);
+                let (base_throughput, base_fairness) =
+                    *baseline.get_or_insert((throughput, round.fairness));
+                let relative = if base_throughput > 0.0 {
+                    throughput / base_throughput
+                } else {
+                    0.0
+                };
+                let scheduling = if round.sched_applied {
+                    sched
+                } else {
+                    SchedPolicy::Other
+                };
+                format!(
+                    "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                    throughput,
+                    round.pushes,
+                    round.pops,
+                    round.threads,
+                    cqueue.get_id(),
+                    fifo_queue_args.benchmark_runner,
+                    bench_conf.benchmark_id,
+                    factor,
+                    cores.len(),
+                    pinned,
+                    scheduling,
+                    round.fairness,
+                    relative,
+                    base_fairness - round.fairness,
+                    round.conserved,
+                    round.failed_pushes,
+                    round.backoffs,
+                    bench_conf.seed()
+                )
+            }
+            // If a thread crashed, pad the results with zero-values
+            None => {
+                failed_factors.push(factor);
+                format!(
+                    "0,0,0,{},{},{},{},{},{},{},{},-1,0,0,false,0,0,{}",
+                    factor * cores.len(),
+                    cqueue.get_id(),
+                    fifo_queue_args.benchmark_runner,
+                    bench_conf.benchmark_id,
+                    factor,
+                    cores.len(),
+                    pinned,
+                    sched,
+                    bench_conf.seed()
+                )
+            }
+        };
+        // Write to file or stdout depending on flag
+        if !bench_conf.args.write_to_stdout {
+            let mut file = OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(&bench_conf.output_filename)?;
+            writeln!(file, "{}", formatted)?;
+        } else {
+            println!("{}", formatted);
+        }
+    }
+    if !failed_factors.is_empty() {
+        return Err(Error::ThreadPanic(format!(
+            "a thread panicked in the rounds with factor {:?}, their results were padded with zeros",
+            failed_factors
+        )));
+    }
+    Ok(())
+}
+
+/// Runs `factor` threads per core for one time limit. Returns `None` if a
+/// thread panicked.
+fn run_round<C, T>(
+    cqueue: &C,
+    bench_conf: &BenchConfig,
+    fifo_queue_args: &FifoQueueArgs,
+    cores: &[CoreId],
+    factor: usize,
+    pinned: bool,
+    sched: SchedPolicy,
+) -> Option<Round>
+where
+    C: ConcurrentQueue<T>,
+    T: Default,
+    for<'a> &'a C: Send,
+{
+    let prefilled =
+        benchmark_helpers::prefill(cqueue, fifo_queue_args.prefill_amount, T::default);
+
+    let thread_count = cores.len() * factor;
+    let uses_fifo = (0..thread_count).any(|i| sched.is_fifo(i));
+    let barrier = Barrier::new(thread_count + 1);
+    let pushes = AtomicUsize::new(0);
+    let pops = AtomicUsize::new(0);
+    let failed_pushes = AtomicUsize::new(0);
+    let backoffs = AtomicUsize::new(0);
+    let sched_applied = AtomicBool::new(true);
+    let fifo_threads = Mutex::new(Vec::new());
+    let thread_failed = AtomicBool::new(false);
+    let done = AtomicBool::new(false);
+    let (tx, rx) = mpsc::channel();
+
+    // Spinning SCHED_FIFO threads would starve a SCHED_OTHER main thread
+    let main_thread = ThreadSched::current();
+    if uses_fifo && !main_thread.set(true, FIFO_PRIORITY + 1) {
+        warn!("Couldn't raise the main thread to SCHED_FIFO, the round may overrun its time limit.");
+    }
+
+    std::thread::scope(|s| {
+        let pushes = &pushes;
+        let pops = &pops;
+        let failed_pushes = &failed_pushes;
+        let backoffs = &backoffs;
+        let sched_applied = &sched_applied;
+        let fifo_threads = &fifo_threads;
+        let thread_failed = &thread_failed;
+        let done = &done;
+        let barrier = &barrier;
+        for i in 0..thread_count {
+            let core = cores[i % cores.len()];
+            let tx = tx.clone();
+            s.spawn(move || {
+                let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    if pinned {
+                        core_affinity::set_for_current(core);
+                    }
+                    // Threads inherit the policy of the main thread, so
+                    // SCHED_OTHER threads have to switch back as well
+                    if uses_fifo {
+                        let thread = ThreadSched::current();
+                        let fifo = sched.is_fifo(i);
+                        if !thread.set(fifo, FIFO_PRIORITY) {
+                            sched_applied.store(false, Ordering::Relaxed);
+                        } else if fifo {
+                            fifo_threads.lock().unwrap().push(thread);
+                        }
+                    }
+                    let mut handle = cqueue.register();
+                    let mut backoff = Backoff::from_args(&bench_conf.args);
+                    let mut worker = Worker::from_args(&bench_conf.args, i);
+                    let mut l_pushes = 0;
+                    let mut l_pops = 0;
+                    let mut l_empty_pops = 0;
+                    let mut l_failed_pushes = 0;
+                    barrier.wait();
+                    while !done.load(Ordering::Relaxed) {
+                        let (pushed, failed) = benchmark_helpers::push_with_policy(
+                            &mut handle,
+                            T::default(),
+                            fifo_queue_args.full_policy,
+                            &mut backoff,
+                            done,
+                        );
+                        l_pushes += pushed as usize;
+                        l_failed_pushes += failed;
+                        match handle.pop() {
+                            Some(_) => {
+                                l_pops += 1;
+                                backoff.reset();
+                            }
+                            None => {
+                                l_empty_pops += 1;
+                                backoff.backoff();
+                            }
+                        }
+                        worker.think();
+                    }
+                    pushes.fetch_add(l_pushes, Ordering::Relaxed);
+                    pops.fetch_add(l_pops, Ordering::Relaxed);
+                    failed_pushes.fetch_add(l_failed_pushes, Ordering::Relaxed);
+                    backoffs.fetch_add(backoff.count(), Ordering::Relaxed);
+                    tx.send(l_pushes + l_pops + l_empty_pops + l_failed_pushes)
+                        .unwrap();
+                }));
+                // A thread panicked, aborting the round...
+                if let Err(e) = result {
+                    error!("Thread {} panicked: {:?}. Aborting round, padding results to zero", i, e);
+                    thread_failed.store(true, Ordering::Relaxed);
+                    done.store(true, Ordering::Relaxed);
+                }
+            });
+        }
+        drop(tx);
+        barrier.wait();
+        std::thread::sleep(std::time::Duration::from_secs(bench_conf.args.time_limit));
+        done.store(true, Ordering::Relaxed);
+        // A SCHED_FIFO thread can spin forever inside an operation that waits
+        // for a preempted SCHED_OTHER thread, so let those run again
+        for thread in fifo_threads.lock().unwrap().iter() {
+            thread.set(false, 0);
+        }
+    });
+
+    if uses_fifo {
+        main_thread.set(false, 0);
+    }
+
+    if thread_failed.load(Ordering::Relaxed) {
+        // Leave an empty queue, so the next round's conservation check only
+        // counts its own items
+        benchmark_helpers::drain(cqueue);
+        return None;
+    }
+    let ops_per_thread: Vec<usize> = rx.into_iter().collect();
+    let pushes = pushes.into_inner();
+    let pops = pops.into_inner();
+    // Drain what is left in the queue and check that no items were lost
+    let conserved =
+        benchmark_helpers::drain_and_check_conservation(cqueue, prefilled, pushes, pops);
+    Some(Round {
+        threads: thread_count,
+        pushes,
+        pops,
+        failed_pushes: failed_pushes.into_inner(),
+        backoffs: backoffs.into_inner(),
+        fairness: benchmark_helpers::calc_fairness(ops_per_thread),
+        conserved,
+        sched_applied: sched_applied.into_inner(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arguments::FifoQueueOversubscribeArgs;
+    use crate::benchmarks::test_helpers::bench_conf;
+    use crate::benchmarks::test_helpers::test_queue::TestQueue;
+
+    fn oversubscribe_args(factors: Vec<usize>) -> FifoQueueArgs {
+        FifoQueueArgs {
+            benchmark_runner: FifoQueueBenchmarks::Oversubscribe(
+                FifoQueueOversubscribeArgs {
+                    factors,
+                    cores: Some(2),
+                    unpinned: true,
+                    sched: SchedPolicy::Other,
+                },
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn run_oversubscribe() {
+        let fifo_queue_args = oversubscribe_args(vec![2]);
+        let bench_conf = bench_conf(&fifo_queue_args);
+        let queue: TestQueue<usize> = TestQueue::new(0);
+        benchmark_oversubscribe(queue, &bench_conf, &fifo_queue_args).unwrap();
+    }
+
+    #[test]
+    fn rejects_zero_factor() {
+        let fifo_queue_args = oversubscribe_args(vec![2, 0]);
+        let bench_conf = bench_conf(&fifo_queue_args);
+        let queue: TestQueue<usize> = TestQueue::new(0);
+        let err = benchmark_oversubscribe(queue, &bench_conf, &fifo_queue_args)
+            .unwrap_err();
+        assert!(matches!(err, Error::Config(_)));
+    }
+
+    /// Panics on the push after `panic_at` pushes, once.
+    struct PanickingQueue {
+        queue: TestQueue<usize>,
+        pushes: AtomicUsize,
+        panic_at: usize,
+    }
+
+    struct PanickingHandle<'a, H> {
+        handle: H,
+        queue: &'a PanickingQueue,
+    }
+
+    impl<H: HandleQueue<usize>> HandleQueue<usize> for PanickingHandle<'_, H> {
+        fn push(&mut self, item: usize) -> Result<(), usize> {
+            if self.queue.pushes.fetch_add(1, Ordering::Relaxed) == self.queue.panic_at {
+                panic!("push failed");
+            }
+            self.handle.push(item)
+        }
+        fn pop(&mut self) -> Option<usize> {
+            self.handle.pop()
+        }
+    }
+
+    impl ConcurrentQueue<usize> for PanickingQueue {
+        fn register(&self) -> impl HandleQueue<usize> {
+            PanickingHandle {
+                handle: self.queue.register(),
+                queue: self,
+            }
+        }
+        fn get_id(&self) -> String {
+            "panicking_queue".to_string()
+        }
+        fn new(size: usize) -> Self {
+            PanickingQueue {
+                queue: TestQueue::new(size),
+                pushes: AtomicUsize::new(0),
+                panic_at: 0,
+            }
+        }
+    }
+
+    #[test]
+    fn failed_round_leaves_empty_queue() {
+        let fifo_queue_args = oversubscribe_args(vec![2]);
+        let bench_conf = bench_conf(&fifo_queue_args);
+        let mut queue = PanickingQueue::new(0);
+        queue.panic_at = fifo_queue_args.prefill_amount as usize + 10;
+        let cores = [CoreId { id: 0 }];
+        let round = |queue: &PanickingQueue| {
+            run_round(queue, &bench_conf, &fifo_queue_args, &cores, 2, false, SchedPolicy::Other)
+        };
+        assert!(round(&queue).is_none());
+        assert!(round(&queue).unwrap().conserved);
+    }
+
+    #[test]
+    fn mixed_policy_alternates() {
+        let fifo: Vec<bool> = (0..4).map(|i| SchedPolicy::Mixed.is_fifo(i)).collect();
+        assert_eq!(fifo, vec![false, true, false, true]);
+        assert!(!SchedPolicy::Other.is_fifo(1));
+        assert!(SchedPolicy::Fifo.is_fifo(0));
+    }
+}
@@ -0,0 +1,324 @@
+use crate::arguments::{FifoQueueArgs, FifoQueueBenchmarks};
+use crate::backoff::Backoff;
+use crate::histogram::Histogram;
+use crate::work::Worker;
+use crate::benchmarks::benchmark_helpers::{self, BenchConfig};
+use crate::benchmarks::fifo_queue_benchmarks::open_loop::PREFILLED;
+use crate::error::Error;
+use crate::traits::{ConcurrentQueue, HandleQueue};
+use core_affinity::CoreId;
+use log::{debug, error, info, trace};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, Barrier, Mutex};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How long threads without a role in the current phase sleep before they
+/// check for the next phase.
+const IDLE_SLEEP: Duration = Duration::from_micros(100);
+
+/// Operations and latencies of a thread during one phase of the schedule.
+#[derive(Debug, Clone, Default)]
+struct PhaseStats {
+    pushes: usize,
+    pops: usize,
+    failed_pushes: usize,
+    empty_pops: usize,
+    sojourn: Histogram,
+}
+
+impl PhaseStats {
+    fn merge(&mut self, other: &PhaseStats) {
+        self.pushes += other.pushes;
+        self.pops += other.pops;
+        self.failed_pushes += other.failed_pushes;
+        self.empty_pops += other.empty_pops;
+        self.sojourn.merge(&other.sojourn);
+    }
+}
+
+/// # Explanation:
+/// A benchmark running a schedule of phases, to see how a queue handles the
+/// transitions between empty and full. Every phase gives the threads a role:
+/// in a burst all threads produce, in a drain all threads consume, in an idle
+/// phase nobody touches the queue and in a mixed phase half of the threads
+/// produce and the other half consume. Items carry the time they were pushed
+/// so the sojourn time of each popped item can be attributed to a phase.
+/// One row is written per phase, summed over all cycles of the schedule.
+/// Benchmark specific flags:
+/// * --thread-count    Set the amount of threads to run in the benchmark
+/// * --schedule        Set the phases, e.g. burst:500,drain:500,idle:200,mixed:1000:4
+/// * --cycles          Set how many times the schedule is repeated
+#[allow(dead_code)]
+pub fn benchmark_phased<C>(
+    cqueue: C,
+    bench_conf: &BenchConfig,
+    fifo_queue_args: &FifoQueueArgs,
+) -> Result<(), Error>
+where
+    C: ConcurrentQueue<usize>,
+    for<'a> &'a C: Send,
+{
+    // Extract specific arguments for this benchmark runner
+    let phased_args = match &fifo_queue_args.benchmark_runner {
+        FifoQueueBenchmarks::Phased(a) => a,
+        _ => panic!(
+            "benchmark_phased called with another FIFO Queue \
+            configured. This is an implementation error."
+        ),
+    };
+    let schedule = &phased_args.schedule;
+    if schedule.is_empty() || phased_args.cycles == 0 {
+        return Err(Error::Config(
+            "--schedule and --cycles need at least one phase to run".to_string(),
+        ));
+    }
+
+    let prefilled =
+        benchmark_helpers::prefill(&cqueue, fifo_queue_args.prefill_amount, || PREFILLED);
+
+    let thread_count = phased_args.thread_count;
+    let steps = schedule.len() * phased_args.cycles;
+    let barrier = Barrier::new(thread_count + 1);
+    // The index of the running phase in the repeated schedule
+    let step = AtomicUsize::new(0);
+    // Set when the step at the same index is over, stops retried pushes
+    let step_over: Vec<AtomicBool> = (0..steps).map(|_| AtomicBool::new(false)).collect();
+    let stats = Mutex::new(vec![PhaseStats::default(); schedule.len()]);
+    let done = AtomicBool::new(false);
+    info!("Starting phased benchmark with {} threads and {} phases", thread_count, steps);
+
+    // Get cores for fairness of threads
+    let available_cores: Vec<CoreId> =
+        core_affinity::get_core_ids().unwrap_or(vec![CoreId { id: 0 }]);
+    let mut core_iter = available_cores.into_iter().cycle();
+
+    // Shared atomic bool for when a thread fails
+    let thread_failed = Arc::new(AtomicBool::new(false));
+
+    // All timestamps are nanoseconds since this instant
+    let epoch = Instant::now();
+
+    let _ = std::thread::scope(|s| -> Result<(), std::io::Error> {
+        let queue = &cqueue;
+        let thread_failed = &thread_failed;
+        let step = &step;
+        let step_over = &step_over;
+        let stats = &stats;
+        let done = &done;
+        let barrier = &barrier;
+        let is_one_socket = &bench_conf.args.one_socket;
+        for i in 0..thread_count {
+            let mut core: CoreId = core_iter.next().unwrap();
+            // if is_one_socket is true, make all thread ids even
+            // (this was used for our testing enviroment to get one socket)
+            if *is_one_socket {
+                core = core_iter.next().unwrap();
+            }
+            trace!("Thread: {} Core: {:?}", i, core);
+            s.spawn(move || {
+                let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    core_affinity::set_for_current(core);
+                    let mut handle = queue.register();
+                    let mut backoff = Backoff::from_args(&bench_conf.args);
+                    let mut worker = Worker::from_args(&bench_conf.args, i);
+                    let mut l_stats = vec![PhaseStats::default(); schedule.len()];
+                    let _thread_failed = thread_failed.clone();
+                    barrier.wait();
+                    while !done.load(Ordering::Relaxed) {
+                        let current_step = step.load(Ordering::Relaxed);
+                        let phase_index = current_step % schedule.len();
+                        let (producers, consumers) = schedule[phase_index].roles(thread_count);
+                        let phase_stats = &mut l_stats[phase_index];
+                        if i < producers {
+                            let (pushed, failed) = benchmark_helpers::push_with_policy(
+                                &mut handle,
+                                epoch.elapsed().as_nanos() as usize,
+                                fifo_queue_args.full_policy,
+                                &mut backoff,
+                                &step_over[current_step],
+                            );
+                            if pushed {
+                                phase_stats.pushes += 1;
+                            }
+                            phase_stats.failed_pushes += failed;
+                        } else if i < producers + consumers {
+                            match handle.pop() {
+                                Some(pushed_at) => {
+                                    if pushed_at != PREFILLED {
+                                        let now = epoch.elapsed().as_nanos() as u64;
+                                        phase_stats.sojourn.record(now.saturating_sub(pushed_at as u64));
+                                    }
+                                    phase_stats.pops += 1;
+                                    backoff.reset();
+                                }
+                                None => {
+                                    phase_stats.empty_pops += 1;
+                                    backoff.backoff();
+                                }
+                            }
+                        } else {
+                            std::thread::sleep(IDLE_SLEEP);
+                            continue;
+                        }
+                        worker.think();
+                    }
+                    let mut stats = stats.lock().unwrap();
+                    for (total, phase_stats) in stats.iter_mut().zip(&l_stats) {
+                        total.merge(phase_stats);
+                    }
+                }));
+                // A thread panicked, aborting the benchmark...
+                if let Err(e) = result {
+                    error!("Thread {} panicked: {:?}. Aborting benchmark, padding results to zero", i, e);
+                    thread_failed.store(true, Ordering::Relaxed);
+                    done.store(true, Ordering::Relaxed);
+                }
+            });
+        }
+        barrier.wait();
+        for (current_step, over) in step_over.iter().enumerate() {
+            if done.load(Ordering::Relaxed) {
+                break;
+            }
+            let phase = schedule[current_step % schedule.len()];
+            debug!("Starting phase {} ({})", current_step, phase.kind);
+            step.store(current_step, Ordering::Relaxed);
+            std::thread::sleep(Duration::from_millis(phase.duration_ms));
+            over.store(true, Ordering::Relaxed);
+        }
+        done.store(true, Ordering::Relaxed);
+        Ok(())
+    });
+    let stats = stats.into_inner().unwrap();
+
+    let mut rows = Vec::with_capacity(schedule.len());
+    // If a thread crashed, pad the results with zero-values
+    if thread_failed.load(Ordering::Relaxed) {
+        for (phase_index, phase) in schedule.iter().enumerate() {
+            let (producers, consumers) = phase.roles(thread_count);
+            rows.push(format!("0,0,0,{},{},{},{},{},{},{},{},{},0,0,0,0,0,false,{}",
+                thread_count,
+                cqueue.get_id(),
+                fifo_queue_args.benchmark_runner,
+                bench_conf.benchmark_id,
+                phase_index,
+                phase.kind,
+                producers,
+                consumers,
+                phase.duration_ms * phased_args.cycles as u64, bench_conf.seed()));
+        }
+    }
+    else {
+        // Drain what is left in the queue and check that no items were lost
+        let pushes = stats.iter().map(|s| s.pushes).sum();
+        let pops = stats.iter().map(|s| s.pops).sum();
+        let conserved = benchmark_helpers::drain_and_check_conservation(&cqueue, prefilled, pushes, pops);
+        for (phase_index, (phase, stats)) in schedule.iter().zip(&stats).enumerate() {
+            let (producers, consumers) = phase.roles(thread_count);
+            let milliseconds = phase.duration_ms * phased_args.cycles as u64;
+            let throughput = if milliseconds == 0 {
+                0.0
+            } else {
+                (stats.pushes + stats.pops) as f64 * 1000.0 / milliseconds as f64
+            };
+            rows.push(format!("{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                throughput,
+                stats.pushes,
+                stats.pops,
+                thread_count,
+                cqueue.get_id(),
+                fifo_queue_args.benchmark_runner,
+                bench_conf.benchmark_id,
+                phase_index,
+                phase.kind,
+                producers,
+                consumers,
+                milliseconds,
+                stats.failed_pushes,
+                stats.empty_pops,
+                stats.sojourn.percentile(50.0),
+                stats.sojourn.percentile(99.0),
+                stats.sojourn.max(),
+                conserved, bench_conf.seed()));
+        }
+    }
+    // Write to file or stdout depending on flag
+    if !bench_conf.args.write_to_stdout {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&bench_conf.output_filename)?;
+        for row in rows {
+            writeln!(file, "{}", row)?;
+        }
+    } else {
+        for row in rows {
+            println!("{}", row);
+        }
+    }
+    if thread_failed.load(Ordering::Relaxed) {
+        return Err(Error::ThreadPanic(format!(
+            "a {} thread panicked, the results were padded with zeros",
+            fifo_queue_args.benchmark_runner
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::benchmarks::test_helpers::bench_conf;
+
+    use crate::arguments::{FifoQueuePhasedArgs, Phase, PhaseKind};
+    use crate::benchmarks::test_helpers::test_queue::TestQueue;
+
+    #[test]
+    fn parse_phases() {
+        assert_eq!(
+            "burst:500".parse::<Phase>(),
+            Ok(Phase { kind: PhaseKind::Burst, duration_ms: 500, producers: None })
+        );
+        assert_eq!(
+            "mixed:20:3".parse::<Phase>(),
+            Ok(Phase { kind: PhaseKind::Mixed, duration_ms: 20, producers: Some(3) })
+        );
+        assert!("sleep:10".parse::<Phase>().is_err());
+        assert!("idle".parse::<Phase>().is_err());
+        assert!("idle:10:1:2".parse::<Phase>().is_err());
+    }
+
+    #[test]
+    fn phase_roles() {
+        let phase = |kind, producers| Phase { kind, duration_ms: 0, producers };
+        assert_eq!(phase(PhaseKind::Burst, None).roles(10), (10, 0));
+        assert_eq!(phase(PhaseKind::Drain, None).roles(10), (0, 10));
+        assert_eq!(phase(PhaseKind::Idle, Some(3)).roles(10), (0, 0));
+        assert_eq!(phase(PhaseKind::Mixed, None).roles(10), (5, 5));
+        assert_eq!(phase(PhaseKind::Mixed, Some(20)).roles(10), (10, 0));
+    }
+
+    #[test]
+    fn run_phased() {
+        let fifo_queue_args = FifoQueueArgs {
+            benchmark_runner: FifoQueueBenchmarks::Phased(FifoQueuePhasedArgs {
+                thread_count: 4,
+                schedule: vec![
+                    "burst:50".parse().unwrap(),
+                    "drain:50".parse().unwrap(),
+                    "idle:20".parse().unwrap(),
+                    "mixed:50:1".parse().unwrap(),
+                ],
+                cycles: 2,
+            }),
+            ..Default::default()
+        };
+        let bench_conf = bench_conf(&fifo_queue_args);
+        let queue: TestQueue<usize> = TestQueue::new(0);
+        if benchmark_phased(queue, &bench_conf, &fifo_queue_args).is_err() {
+            panic!();
+        }
+    }
+}
@@ -0,0 +1,380 @@
+use crate::arguments::{FifoQueueArgs, FifoQueueBenchmarks};
+use crate::backoff::Backoff;
+use crate::histogram::Histogram;
+use crate::work::Worker;
+use crate::benchmarks::benchmark_helpers::{self, BenchConfig};
+use crate::benchmarks::fifo_queue_benchmarks::open_loop::PREFILLED;
+use crate::error::Error;
+use crate::rng::Stream;
+use crate::traits::{ConcurrentQueue, HandleQueue};
+use core_affinity::CoreId;
+use log::{error, info, trace};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, Barrier, Mutex};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How often the occupancy of the queues is sampled.
+const OCCUPANCY_SAMPLE: Duration = Duration::from_millis(1);
+
+/// Items a thread has pushed and popped so far. Padded so that threads
+/// updating their progress don't share cache lines.
+#[repr(align(128))]
+#[derive(Default)]
+struct Progress {
+    pushed: AtomicUsize,
+    popped: AtomicUsize,
+}
+
+/// Operations and latencies of the threads in one stage.
+#[derive(Debug, Clone, Default)]
+struct StageStats {
+    pushes: usize,
+    pops: usize,
+    failed_pushes: usize,
+    empty_pops: usize,
+    latency: Histogram,
+}
+
+impl StageStats {
+    fn merge(&mut self, other: &StageStats) {
+        self.pushes += other.pushes;
+        self.pops += other.pops;
+        self.failed_pushes += other.failed_pushes;
+        self.empty_pops += other.empty_pops;
+        self.latency.merge(&other.latency);
+    }
+}
+
+/// # Explanation:
+/// A benchmark chaining copies of the queue into a pipeline. Stage 0 creates
+/// items and pushes them to queue 0, stage i pops from queue i - 1, thinks
+/// for its stage work and pushes to queue i, and the last stage pops the
+/// items from the last queue. Items carry the time they were created, so
+/// every stage records how old the items it pops are, which for the last
+/// stage is the latency through the whole pipeline. The queues are sampled
+/// while the benchmark runs to get the occupancy in front of every stage.
+/// Prefilled items start in the first queue and are left out of the latencies.
+/// One row is written per stage.
+/// Benchmark specific flags:
+/// * --stages      Set the amount of stages, at least 2
+/// * --threads     Set the threads per stage, e.g. 1,4,4,1
+/// * --stage-work  Set the think time per stage [ns], e.g. 0,500,100,0
+#[allow(dead_code)]
+pub fn benchmark_pipeline<C>(
+    cqueue: C,
+    bench_conf: &BenchConfig,
+    fifo_queue_args: &FifoQueueArgs,
+) -> Result<(), Error>
+where
+    C: ConcurrentQueue<usize>,
+    for<'a> &'a C: Send,
+{
+    // Extract specific arguments for this benchmark runner
+    let pipeline_args = match &fifo_queue_args.benchmark_runner {
+        FifoQueueBenchmarks::Pipeline(a) => a,
+        _ => panic!(
+            "benchmark_pipeline called with another FIFO Queue \
+            configured. This is an implementation error."
+        ),
+    };
+    let stages = pipeline_args.stages;
+    let per_stage_list = |len: usize| len == 1 || len == stages;
+    if stages < 2
+        || !per_stage_list(pipeline_args.threads.len())
+        || !(pipeline_args.stage_work.is_empty() || per_stage_list(pipeline_args.stage_work.len()))
+        || (0..stages).any(|stage| pipeline_args.stage_threads(stage) == 0)
+    {
+        return Err(Error::Config(
+            "--stages needs at least 2 stages, and --threads and --stage-work \
+            one value or one per stage, with at least one thread per stage"
+                .to_string(),
+        ));
+    }
+
+    // The queue under test connects the first two stages, the others are copies
+    let mut queues = vec![cqueue];
+    for _ in 2..stages {
+        queues.push(C::try_new(fifo_queue_args.queue_size as usize)?);
+    }
+
+    let prefilled =
+        benchmark_helpers::prefill(&queues[0], fifo_queue_args.prefill_amount, || PREFILLED);
+
+    // Stage of every thread, in spawn order
+    let thread_stages: Vec<usize> = (0..stages)
+        .flat_map(|stage| std::iter::repeat_n(stage, pipeline_args.stage_threads(stage)))
+        .collect();
+    let stage_work: Vec<u64> = (0..stages)
+        .map(|stage| pipeline_args.stage_work(stage, bench_conf.args.think_time))
+        .collect();
+    let time_limit: u64 = bench_conf.args.time_limit;
+    let barrier = Barrier::new(thread_stages.len() + 1);
+    let progress: Vec<Progress> = thread_stages.iter().map(|_| Progress::default()).collect();
+    let stats = Mutex::new(vec![StageStats::default(); stages]);
+    let done = AtomicBool::new(false);
+    info!("Starting pipeline benchmark with {} stages and {} threads", stages, thread_stages.len());
+
+    // Get cores for fairness of threads
+    let available_cores: Vec<CoreId> =
+        core_affinity::get_core_ids().unwrap_or(vec![CoreId { id: 0 }]);
+    let mut core_iter = available_cores.into_iter().cycle();
+
+    // Shared atomic bool for when a thread fails
+    let thread_failed = Arc::new(AtomicBool::new(false));
+
+    // Summed and highest sampled occupancy of every queue
+    let mut occupancy_sum = vec![0usize; stages - 1];
+    let mut occupancy_max = vec![0usize; stages - 1];
+    let mut samples = 0;
+
+    // All timestamps are nanoseconds since this instant
+    let epoch = Instant::now();
+
+    let _ = std::thread::scope(|s| -> Result<(), std::io::Error> {
+        let queues = &queues;
+        let thread_failed = &thread_failed;
+        let progress = &progress;
+        let stats = &stats;
+        let done = &done;
+        let barrier = &barrier;
+        let is_one_socket = &bench_conf.args.one_socket;
+        for (i, &stage) in thread_stages.iter().enumerate() {
+            let mut core: CoreId = core_iter.next().unwrap();
+            // if is_one_socket is true, make all thread ids even
+            // (this was used for our testing enviroment to get one socket)
+            if *is_one_socket {
+                core = core_iter.next().unwrap();
+            }
+            trace!("Thread: {} Stage: {} Core: {:?}", i, stage, core);
+            let work = stage_work[stage];
+            // Only the references are sent to the thread, not the whole vector
+            let input_queue = stage.checked_sub(1).map(|q| &queues[q]);
+            let output_queue = queues.get(stage);
+            s.spawn(move || {
+                let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    core_affinity::set_for_current(core);
+                    let mut input = input_queue.map(|q| q.register());
+                    let mut output = output_queue.map(|q| q.register());
+                    let mut backoff = Backoff::from_args(&bench_conf.args);
+                    let mut worker = Worker::new(
+                        work,
+                        bench_conf.args.think_dist,
+                        bench_conf.args.work_buffer,
+                        bench_conf.thread_rng(Stream::ThinkTime, i),
+                    );
+                    let mut l_stats = StageStats::default();
+                    let _thread_failed = thread_failed.clone();
+                    barrier.wait();
+                    while !done.load(Ordering::Relaxed) {
+                        // The first stage creates the items
+                        let item = match &mut input {
+                            None => epoch.elapsed().as_nanos() as usize,
+                            Some(handle) => match handle.pop() {
+                                Some(created_at) => {
+                                    if created_at != PREFILLED {
+                                        let now = epoch.elapsed().as_nanos() as u64;
+                                        l_stats.latency.record(now.saturating_sub(created_at as u64));
+                                    }
+                                    l_stats.pops += 1;
+                                    progress[i].popped.store(l_stats.pops, Ordering::Relaxed);
+                                    backoff.reset();
+                                    created_at
+                                }
+                                None => {
+                                    l_stats.empty_pops += 1;
+                                    backoff.backoff();
+                                    continue;
+                                }
+                            },
+                        };
+                        worker.think();
+                        // The last stage consumes the items
+                        if let Some(handle) = &mut output {
+                            let (pushed, failed) = benchmark_helpers::push_with_policy(
+                                handle,
+                                item,
+                                fifo_queue_args.full_policy,
+                                &mut backoff,
+                                done,
+                            );
+                            if pushed {
+                                l_stats.pushes += 1;
+                                progress[i].pushed.store(l_stats.pushes, Ordering::Relaxed);
+                            }
+                            l_stats.failed_pushes += failed;
+                        }
+                    }
+                    stats.lock().unwrap()[stage].merge(&l_stats);
+                }));
+                // A thread panicked, aborting the benchmark...
+                if let Err(e) = result {
+                    error!("Thread {} panicked: {:?}. Aborting benchmark, padding results to zero", i, e);
+                    thread_failed.store(true, Ordering::Relaxed);
+                    done.store(true, Ordering::Relaxed);
+                }
+            });
+        }
+        barrier.wait();
+        let start = Instant::now();
+        let end = Duration::from_secs(time_limit);
+        while !done.load(Ordering::Relaxed) && start.elapsed() < end {
+            std::thread::sleep(OCCUPANCY_SAMPLE);
+            // Queue q holds what stage q pushed and stage q + 1 did not pop yet
+            let mut pushed = vec![0usize; stages];
+            let mut popped = vec![0usize; stages];
+            for (&stage, p) in thread_stages.iter().zip(progress) {
+                pushed[stage] += p.pushed.load(Ordering::Relaxed);
+                popped[stage] += p.popped.load(Ordering::Relaxed);
+            }
+            pushed[0] += prefilled;
+            for q in 0..stages - 1 {
+                let occupancy = pushed[q].saturating_sub(popped[q + 1]);
+                occupancy_sum[q] += occupancy;
+                occupancy_max[q] = usize::max(occupancy_max[q], occupancy);
+            }
+            samples += 1;
+        }
+        done.store(true, Ordering::Relaxed);
+        Ok(())
+    });
+    let stats = stats.into_inner().unwrap();
+
+    let mut rows = Vec::with_capacity(stages);
+    // If a thread crashed, pad the results with zero-values
+    if thread_failed.load(Ordering::Relaxed) {
+        for (stage, work) in stage_work.iter().enumerate() {
+            rows.push(format!("0,{},{},{},{},{},{},0,0,0,0,0,0,0,0,0,false,{}",
+                queues[0].get_id(),
+                fifo_queue_args.benchmark_runner,
+                bench_conf.benchmark_id,
+                stage,
+                pipeline_args.stage_threads(stage),
+                work, bench_conf.seed()));
+        }
+    }
+    else {
+        // Drain what is left in every queue and check that no items were lost
+        let mut conserved = true;
+        for (q, queue) in queues.iter().enumerate() {
+            let prefilled = if q == 0 { prefilled } else { 0 };
+            conserved &= benchmark_helpers::drain_and_check_conservation(
+                queue,
+                prefilled,
+                stats[q].pushes,
+                stats[q + 1].pops,
+            );
+        }
+        // Items that made it through the whole pipeline
+        let throughput = stats[stages - 1].pops as f64 / time_limit as f64;
+        for (stage, (stats, work)) in stats.iter().zip(&stage_work).enumerate() {
+            // Occupancy of the queue in front of the stage, the first stage has none
+            let (mean_occupancy, max_occupancy) = match stage.checked_sub(1) {
+                Some(q) if samples > 0 => (occupancy_sum[q] as f64 / samples as f64, occupancy_max[q] as i64),
+                Some(_) => (0.0, 0),
+                None => (-1.0, -1),
+            };
+            rows.push(format!("{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                throughput,
+                queues[0].get_id(),
+                fifo_queue_args.benchmark_runner,
+                bench_conf.benchmark_id,
+                stage,
+                pipeline_args.stage_threads(stage),
+                work,
+                stats.pushes,
+                stats.pops,
+                stats.failed_pushes,
+                stats.empty_pops,
+                mean_occupancy,
+                max_occupancy,
+                stats.latency.percentile(50.0),
+                stats.latency.percentile(99.0),
+                stats.latency.max(),
+                conserved, bench_conf.seed()));
+        }
+    }
+    // Write to file or stdout depending on flag
+    if !bench_conf.args.write_to_stdout {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&bench_conf.output_filename)?;
+        for row in rows {
+            writeln!(file, "{}", row)?;
+        }
+    } else {
+        for row in rows {
+            println!("{}", row);
+        }
+    }
+    if thread_failed.load(Ordering::Relaxed) {
+        return Err(Error::ThreadPanic(format!(
+            "a {} thread panicked, the results were padded with zeros",
+            fifo_queue_args.benchmark_runner
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::benchmarks::test_helpers::bench_conf;
+
+    use crate::arguments::FifoQueuePipelineArgs;
+    use crate::benchmarks::test_helpers::test_queue::TestQueue;
+
+    fn pipeline_args(stages: usize, threads: Vec<usize>, stage_work: Vec<u64>) -> FifoQueueArgs {
+        FifoQueueArgs {
+            benchmark_runner: FifoQueueBenchmarks::Pipeline(FifoQueuePipelineArgs {
+                stages,
+                threads,
+                stage_work,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn stage_values() {
+        let args = FifoQueuePipelineArgs { stages: 3, threads: vec![2], stage_work: vec![0, 50, 10] };
+        assert_eq!(args.stage_threads(2), 2);
+        assert_eq!(args.stage_work(1, 100), 50);
+        let args = FifoQueuePipelineArgs { stages: 3, threads: vec![1, 3, 2], stage_work: vec![] };
+        assert_eq!(args.stage_threads(1), 3);
+        assert_eq!(args.stage_threads(3), 0);
+        assert_eq!(args.stage_work(1, 100), 100);
+        let benchmark = FifoQueueBenchmarks::Pipeline(args);
+        assert_eq!(benchmark.roles(), (3, 3));
+        assert_eq!(benchmark.handles(), 5);
+    }
+
+    #[test]
+    fn run_pipeline() {
+        let fifo_queue_args = pipeline_args(3, vec![1, 2, 1], vec![0, 100, 0]);
+        let bench_conf = bench_conf(&fifo_queue_args);
+        let queue: TestQueue<usize> = TestQueue::new(0);
+        if benchmark_pipeline(queue, &bench_conf, &fifo_queue_args).is_err() {
+            panic!();
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_stages() {
+        let bench_conf = bench_conf(&FifoQueueArgs::default());
+        for fifo_queue_args in [
+            pipeline_args(1, vec![1], vec![]),
+            pipeline_args(3, vec![1, 1], vec![]),
+            pipeline_args(3, vec![1, 0, 1], vec![]),
+            pipeline_args(3, vec![1], vec![1, 2]),
+        ] {
+            let queue: TestQueue<usize> = TestQueue::new(0);
+            assert!(matches!(
+                benchmark_pipeline(queue, &bench_conf, &fifo_queue_args),
+                Err(Error::Config(_))
+            ));
+        }
+    }
+}
@@ -1,49 +1,55 @@
 use core_affinity::CoreId;
 use log::{debug, error, info, trace};
-use rand::Rng;
 use crate::arguments::{FifoQueueArgs, FifoQueueBenchmarks};
-use crate::traits::{ConcurrentQueue, HandleQueue};
+use crate::backoff::Backoff;
+use crate::work::{self, Worker};
+use crate::error::Error;
+use crate::traits::ConcurrentQueue;
 use crate::benchmarks::benchmark_helpers::{self, BenchConfig};
 use std::fs::OpenOptions;
 use std::io::Write;
-use std::sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, Barrier};
+use std::sync::{atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering}, Barrier};
 use std::sync::{mpsc, Arc};
 
 /// # Explanation:
 /// A simple benchmark that measures the throughput of a queue.
-/// Has by default a 10 floating points generated delay between each operation, but this can be changed
+/// Threads by default think for 100 ns between each operation, but this can be changed
 /// through flags passed to the program.
+/// Producers and consumers use the handles from `register_producer` and
+/// `register_consumer`, which also makes this the runner behind `spsc`,
+/// `mpsc` and `spmc`, where one or both roles are a single thread.
 /// Benchmark specific flags:
-/// * -p        Set specified amount of producers
-/// * -c        Set specified amount of consumers
+/// * -p        Set specified amount of producers (not for spsc and spmc)
+/// * -c        Set specified amount of consumers (not for spsc and mpsc)
 #[allow(dead_code)]
-pub fn benchmark_prod_con<C, T>(cqueue: C, bench_conf: &BenchConfig, fifo_queue_args: &FifoQueueArgs) -> Result<(), std::io::Error>
+pub fn benchmark_prod_con<C, T>(cqueue: C, bench_conf: &BenchConfig, fifo_queue_args: &FifoQueueArgs) -> Result<(), Error>
 where 
     C: ConcurrentQueue<T>,
     T: Default,
     for<'a> &'a C: Send
 {
     // Extract specific arguments for this benchmark runner
-    let prod_con_args = match &fifo_queue_args.benchmark_runner {
-        FifoQueueBenchmarks::ProdCon(a) => a,
+    let (producers, consumers) = match &fifo_queue_args.benchmark_runner {
+        runner @ (FifoQueueBenchmarks::ProdCon(_)
+            | FifoQueueBenchmarks::Spsc
+            | FifoQueueBenchmarks::Mpsc(_)
+            | FifoQueueBenchmarks::Spmc(_)) => runner.roles(),
         _ => panic!("benchmark_prod_con called with another FIFO Queue \
             configured. This is an implementation error.")
     };
 
-    {
-        debug!("Prefilling queue with {} items.", fifo_queue_args.prefill_amount);
-        let mut tmp_handle = cqueue.register();
-        for _ in 0..fifo_queue_args.prefill_amount {
-            let _ = tmp_handle.push(Default::default());
-        } 
-    }
-    let producers = prod_con_args.producers;
-    let consumers = prod_con_args.consumers;
+    let prefilled =
+        benchmark_helpers::prefill(&cqueue, fifo_queue_args.prefill_amount, T::default);
 
     let time_limit: u64 = bench_conf.args.time_limit;
+    let batch_size = fifo_queue_args.batch_size as usize;
     let barrier = Barrier::new(consumers + producers + 1);
     let pops  = AtomicUsize::new(0);
     let pushes = AtomicUsize::new(0);
+    let failed_pushes = AtomicUsize::new(0);
+    let backoffs = AtomicUsize::new(0);
+    let think_time = AtomicU64::new(0);
+    let thinks = AtomicUsize::new(0);
     let done = AtomicBool::new(false);
     let (tx, rx) = mpsc::channel();
     info!("Starting throughput benchmark with {} consumer and {} producers", consumers, producers);
@@ -59,6 +65,10 @@ where
     let _ = std::thread::scope(|s| -> Result<(), std::io::Error>{
         let queue = &cqueue;
         let pushes = &pushes;
+        let failed_pushes = &failed_pushes;
+        let backoffs = &backoffs;
+        let think_time = &think_time;
+        let thinks = &thinks;
         let pops = &pops;
         let done = &done;
         let barrier = &barrier;
@@ -79,23 +89,38 @@ where
             s.spawn(move || {
                 let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
                 core_affinity::set_for_current(core);
-                let mut handle = queue.register();
+                let mut handle = queue.register_producer();
+                let mut backoff = Backoff::from_args(&bench_conf.args);
+                let mut worker = Worker::from_args(&bench_conf.args, i);
+                let mut batch = Vec::with_capacity(batch_size);
                 // push
                 let mut l_pushes = 0; 
+                let mut l_failed_pushes = 0;
                 let _thread_failed = thread_failed.clone(); // Every thread clones the thread_failed bool
                 barrier.wait();
                 while !done.load(Ordering::Relaxed) {
-                    // NOTE: Maybe we should care about this result?
-                    let _ = handle.push(T::default());
-                    l_pushes += 1;
-                    // Add some delay to simulate real workload
-                    for _ in 0..bench_conf.args.delay {
-                        let _some_num = rand::rng().random::<f64>();
-                    }
+                    // Only successful pushes are counted, failed pushes on a
+                    // full queue are counted separately.
+                    let (pushed, failed) = benchmark_helpers::push_items(
+                        &mut handle,
+                        &mut batch,
+                        batch_size,
+                        fifo_queue_args.full_policy,
+                        &mut backoff,
+                        done,
+                    );
+                    l_pushes += pushed;
+                    l_failed_pushes += failed;
+                    // Simulate work between operations
+                    worker.think();
                 }
                 pushes.fetch_add(l_pushes, Ordering::Relaxed);
+                failed_pushes.fetch_add(l_failed_pushes, Ordering::Relaxed);
+                backoffs.fetch_add(backoff.count(), Ordering::Relaxed);
+                think_time.fetch_add(worker.total_think_time().as_nanos() as u64, Ordering::Relaxed);
+                thinks.fetch_add(worker.thinks(), Ordering::Relaxed);
                 // Thread sends its total operations down the channel for fairness calculations
-                if let Err(e) = tx.send(l_pushes) {
+                if let Err(e) = tx.send(l_pushes + l_failed_pushes) {
                     error!("Error sending operations down the channel: {}", e);
                 };
             }));
@@ -119,7 +144,10 @@ where
             s.spawn(move || {
                 let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
                 core_affinity::set_for_current(core);
-                let mut handle = queue.register();
+                let mut handle = queue.register_consumer();
+                let mut backoff = Backoff::from_args(&bench_conf.args);
+                let mut worker = Worker::from_args(&bench_conf.args, producers + i);
+                let mut buffer = Vec::with_capacity(batch_size);
                 // pop
                 let mut l_pops = 0; 
                 let mut empty_pops = 0;
@@ -127,20 +155,25 @@ where
                 barrier.wait();
                 // TODO: add empty pops probably to fairness calculations
                 while !done.load(Ordering::Relaxed) {
-                    match handle.pop() {
-                        Some(_) => l_pops += 1,
-                        None => {
+                    match benchmark_helpers::pop_items(&mut handle, &mut buffer, batch_size) {
+                        0 => {
                             // if bench_conf.args.empty_pops {
                             //     l_pops += 1;
                             // }
                             empty_pops += 1;
+                            backoff.backoff();
+                        }
+                        popped => {
+                            l_pops += popped;
+                            backoff.reset();
                         }
                     }
-                    for _ in 0..bench_conf.args.delay {
-                        let _some_num = rand::rng().random::<f64>();
-                    }
+                    worker.think();
                 }
                 pops.fetch_add(l_pops, Ordering::Relaxed);
+                backoffs.fetch_add(backoff.count(), Ordering::Relaxed);
+                think_time.fetch_add(worker.total_think_time().as_nanos() as u64, Ordering::Relaxed);
+                thinks.fetch_add(worker.thinks(), Ordering::Relaxed);
                 // Thread sends its total operations down the channel for fairness calculations
                 if let Err(e) = tx.send(l_pops + empty_pops) {
                     error!("Error sending operations down the channel: {}", e);
@@ -165,6 +198,9 @@ where
     debug!("TX Dropped");
     let pops = pops.into_inner();
     let pushes = pushes.into_inner();
+    let failed_pushes = failed_pushes.into_inner();
+    let backoffs = backoffs.into_inner();
+    let mean_think_time = work::mean_think_time(think_time.into_inner(), thinks.into_inner());
 
     // Fairness
     // Get total operations per thread
@@ -177,11 +213,13 @@ where
     };
     // If a thread crashed, pad the results with zero-values 
     let formatted = if thread_failed.load(Ordering::Relaxed) {
-        format!("0,0,0,{},{},-1,{},{},{},0,-1,{}", producers, consumers, cqueue.get_id(), fifo_queue_args.benchmark_runner, bench_conf.benchmark_id, fifo_queue_args.queue_size)
+        format!("0,0,0,{},{},-1,{},{},{},0,-1,{},false,0,0,0,{},{}", producers, consumers, cqueue.get_id(), fifo_queue_args.benchmark_runner, bench_conf.benchmark_id, fifo_queue_args.queue_size, batch_size, bench_conf.seed())
     }
     else {
         let fairness = benchmark_helpers::calc_fairness(ops_per_thread);
-        format!("{},{},{},{},{},{},{},{},{},{},{},{}",
+        // Drain what is left in the queue and check that no items were lost
+        let conserved = benchmark_helpers::drain_and_check_conservation(&cqueue, prefilled, pushes, pops);
+        format!("{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
             (pushes + pops) as f64 / time_limit as f64,
             pushes,
             pops,
@@ -193,7 +231,12 @@ where
             bench_conf.benchmark_id,
             fairness,
             -1,
-            fifo_queue_args.queue_size)
+            fifo_queue_args.queue_size,
+            conserved,
+            failed_pushes,
+            backoffs,
+            mean_think_time,
+            batch_size, bench_conf.seed())
     };
     if !bench_conf.args.write_to_stdout {
         let mut file = OpenOptions::new()
@@ -207,6 +250,12 @@ where
         println!("{}", formatted);
     }
 
+    if thread_failed.load(Ordering::Relaxed) {
+        return Err(Error::ThreadPanic(format!(
+            "a {} thread panicked, the results were padded with zeros",
+            fifo_queue_args.benchmark_runner
+        )));
+    }
     Ok(())
 }
 
@@ -214,33 +263,57 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::benchmarks::test_helpers::bench_conf;
 
+    use crate::arguments::{FifoQueueMpscArgs, FifoQueueSpmcArgs};
     use crate::benchmarks::test_helpers::test_queue::TestQueue;
 
     #[test]
     fn run_basic_prod_con() {
         let fifo_queue_args = FifoQueueArgs::default();
-        let bench_conf = benchmark_helpers::BenchConfig {
-            args: fifo_queue_args.general_args.clone(),
-            date_time: "".to_string(),
-            benchmark_id: "test1".to_string(),
-            output_filename: "".to_string()
-        };
+        let bench_conf = bench_conf(&fifo_queue_args);
         let queue: TestQueue<i32> = TestQueue::new(0);
         if benchmark_prod_con(queue, &bench_conf, &fifo_queue_args).is_err() {
             panic!();
         }
     }
 
+    #[test]
+    fn run_batched_prod_con() {
+        let fifo_queue_args = FifoQueueArgs {
+            batch_size: 16,
+            ..Default::default()
+        };
+        let bench_conf = bench_conf(&fifo_queue_args);
+        let queue: TestQueue<usize> = TestQueue::new(0);
+        if benchmark_prod_con(queue, &bench_conf, &fifo_queue_args).is_err() {
+            panic!();
+        }
+    }
+
+    #[test]
+    fn run_single_role_runners() {
+        for runner in [
+            FifoQueueBenchmarks::Spsc,
+            FifoQueueBenchmarks::Mpsc(FifoQueueMpscArgs { producers: 3 }),
+            FifoQueueBenchmarks::Spmc(FifoQueueSpmcArgs { consumers: 3 }),
+        ] {
+            let fifo_queue_args = FifoQueueArgs {
+                benchmark_runner: runner,
+                ..Default::default()
+            };
+            let bench_conf = bench_conf(&fifo_queue_args);
+            let queue: TestQueue<usize> = TestQueue::new(0);
+            if benchmark_prod_con(queue, &bench_conf, &fifo_queue_args).is_err() {
+                panic!();
+            }
+        }
+    }
+
     #[test]
     fn run_basic_with_string() {
         let fifo_queue_args = FifoQueueArgs::default();
-        let bench_conf = benchmark_helpers::BenchConfig {
-            args: fifo_queue_args.general_args.clone(),
-            date_time: "".to_string(),
-            benchmark_id: "test1".to_string(),
-            output_filename: "".to_string()
-        };
+        let bench_conf = bench_conf(&fifo_queue_args);
         let queue: TestQueue<String> = TestQueue::new(0);
         if benchmark_prod_con(queue, &bench_conf, &fifo_queue_args).is_err() {
             panic!();
@@ -250,12 +323,7 @@ mod tests {
     #[test]
     fn run_basic_with_struct() {
         let fifo_queue_args = FifoQueueArgs::default();
-        let bench_conf = benchmark_helpers::BenchConfig {
-            args: fifo_queue_args.general_args.clone(),
-            date_time: "".to_string(),
-            benchmark_id: "test1".to_string(),
-            output_filename: "".to_string()
-        };
+        let bench_conf = bench_conf(&fifo_queue_args);
         let queue: TestQueue<FifoQueueArgs> = TestQueue::new(0);
         if benchmark_prod_con(queue, &bench_conf, &fifo_queue_args).is_err() {
             panic!();
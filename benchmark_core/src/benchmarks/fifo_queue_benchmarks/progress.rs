@@ -0,0 +1,609 @@
+use crate::arguments::{FifoQueueArgs, FifoQueueBenchmarks, StallMode};
+use crate::backoff::Backoff;
+use crate::benchmarks::benchmark_helpers::{self, BenchConfig};
+use crate::error::Error;
+use crate::rng::Stream;
+use crate::traits::{ConcurrentQueue, HandleQueue};
+use crate::work::Worker;
+use log::{debug, error, info, warn};
+use rand::Rng;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Barrier;
+use std::time::{Duration, Instant};
+
+/// How often a stalled thread checks whether its stall is over.
+const STALL_POLL: Duration = Duration::from_micros(100);
+
+/// How long to wait for threads to stop at the start of a stall, or to
+/// continue at its end.
+const STALL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Operations a thread has completed so far, and whether it should pause
+/// itself in hook mode. Padded so that threads updating their progress
+/// don't share cache lines.
+#[repr(align(128))]
+#[derive(Default)]
+struct ThreadState {
+    ops: AtomicUsize,
+    pause: AtomicBool,
+}
+
+/// Throughput of the threads that kept running, before and during one stall.
+#[derive(Debug)]
+struct Stall {
+    baseline: f64,
+    throughput: f64,
+    min_window_ops: usize,
+    zero_windows: usize,
+}
+
+impl Stall {
+    /// Summarizes a stall from the operations completed in the gap before it
+    /// and in each of the windows it was sampled in.
+    fn new(
+        gap_ops: usize,
+        gap: Duration,
+        window_ops: &[usize],
+        duration: Duration,
+    ) -> Self {
+        let per_second = |ops: usize, time: Duration| {
+            ops as f64 / time.as_secs_f64().max(f64::MIN_POSITIVE)
+        };
+        Stall {
+            baseline: per_second(gap_ops, gap),
+            throughput: per_second(window_ops.iter().sum(), duration),
+            min_window_ops: window_ops.iter().copied().min().unwrap_or(0),
+            zero_windows: window_ops.iter().filter(|ops| **ops == 0).count(),
+        }
+    }
+
+    /// Throughput during the stall relative to the gap before it.
+    fn relative(&self) -> f64 {
+        if self.baseline > 0.0 {
+            self.throughput / self.baseline
+        } else {
+            0.0
+        }
+    }
+
+    /// False if the threads that kept running completed no operation in at
+    /// least one window of the stall.
+    fn progress(&self) -> bool {
+        self.zero_windows == 0
+    }
+}
+
+/// Stalls and releases worker threads.
+enum Staller<'a> {
+    #[cfg(target_os = "linux")]
+    Signal(signal::Staller, &'a [signal::Thread]),
+    Hook(&'a [ThreadState], &'a AtomicUsize),
+}
+
+impl Staller<'_> {
+    fn mode(&self) -> StallMode {
+        match self {
+            #[cfg(target_os = "linux")]
+            Staller::Signal(..) => StallMode::Signal,
+            Staller::Hook(..) => StallMode::Hook,
+        }
+    }
+
+    /// Stalls `victims`. Returns how many of them couldn't be stalled.
+    fn stall(&self, victims: &[usize]) -> usize {
+        match self {
+            #[cfg(target_os = "linux")]
+            Staller::Signal(staller, threads) => {
+                let threads: Vec<signal::Thread> = victims.iter().map(|&v| threads[v]).collect();
+                staller.stall(&threads)
+            }
+            Staller::Hook(states, _) => {
+                for &v in victims {
+                    states[v].pause.store(true, Ordering::Relaxed);
+                }
+                0
+            }
+        }
+    }
+
+    fn release(&self, victims: &[usize]) {
+        match self {
+            #[cfg(target_os = "linux")]
+            Staller::Signal(staller, _) => staller.release(),
+            Staller::Hook(states, _) => {
+                for &v in victims {
+                    states[v].pause.store(false, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    /// Returns how many threads are currently stalled.
+    fn stalled(&self) -> usize {
+        match self {
+            #[cfg(target_os = "linux")]
+            Staller::Signal(staller, _) => staller.stalled(),
+            Staller::Hook(_, hooked) => hooked.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Waits until `expected` threads are stalled. Returns false if they
+    /// weren't within the timeout, or a thread failed.
+    fn wait_for(&self, expected: usize, thread_failed: &AtomicBool) -> bool {
+        let start = Instant::now();
+        while self.stalled() != expected {
+            if start.elapsed() > STALL_TIMEOUT || thread_failed.load(Ordering::Relaxed) {
+                return false;
+            }
+            std::thread::sleep(STALL_POLL);
+        }
+        true
+    }
+}
+
+/// # Explanation:
+/// Tests the progress guarantee of the queue empirically. Every thread
+/// enqueues an item and then dequeues one, like in `enq-deq-pairs`. The
+/// threads first run undisturbed for a random gap, then `--stalled` randomly
+/// chosen threads are stalled for `--stall-time` milliseconds while the
+/// operations of the other threads are sampled in windows of `--window`
+/// milliseconds. In signal mode a stalled thread waits in a signal handler,
+/// so it can be stopped in the middle of an operation, e.g. while holding a
+/// lock. In hook mode it pauses itself between two operations.
+/// Every stall writes its own row, comparing the throughput of the other
+/// threads during the stall to the gap before it. A window in which they
+/// completed no operation at all means the queue didn't make progress, which
+/// a lock-free queue never does.
+/// Benchmark specific flags:
+/// * `--thread-count`    (OPTIONAL)  Amount of threads (standard 8).
+/// * `--stalled`         (OPTIONAL)  Threads stalled at the same time (standard 1).
+/// * `--stalls`          (OPTIONAL)  Amount of stalls, replaces the time limit (standard 5).
+/// * `--stall-time`      (OPTIONAL)  How long the threads are stalled in milliseconds (standard 500).
+/// * `--gap`             (OPTIONAL)  Mean time between two stalls in milliseconds (standard 200).
+/// * `--window`          (OPTIONAL)  Sample window during a stall in milliseconds (standard 10).
+/// * `--mode`            (OPTIONAL)  How threads are stalled: signal or hook (standard signal).
+pub fn benchmark_progress<C, T>(
+    cqueue: C,
+    bench_conf: &BenchConfig,
+    fifo_queue_args: &FifoQueueArgs,
+) -> Result<(), Error>
+where
+    C: ConcurrentQueue<T>,
+    T: Default,
+    for<'a> &'a C: Send,
+{
+    // Extract specific arguments for this benchmark runner
+    let progress_args = match &fifo_queue_args.benchmark_runner {
+        FifoQueueBenchmarks::Progress(a) => a,
+        _ => panic!(
+            "benchmark_progress called with another FIFO Queue \
+            configured. This is an implementation error."
+        ),
+    };
+    let thread_count = progress_args.thread_count;
+    if progress_args.stalled == 0 || progress_args.stalled >= thread_count {
+        return Err(Error::Config(format!(
+            "progress needs between 1 and {} stalled threads with {} threads, got {}",
+            thread_count.saturating_sub(1),
+            thread_count,
+            progress_args.stalled
+        )));
+    }
+    if progress_args.window == 0 || progress_args.stall_time < progress_args.window {
+        return Err(Error::Config(
+            "the stall time has to be at least one window, which can't be empty".to_string(),
+        ));
+    }
+
+    let prefilled =
+        benchmark_helpers::prefill(&cqueue, fifo_queue_args.prefill_amount, T::default);
+
+    let states: Vec<ThreadState> = (0..thread_count).map(|_| ThreadState::default()).collect();
+    let hooked = AtomicUsize::new(0);
+    #[cfg(target_os = "linux")]
+    let threads = std::sync::Mutex::new(vec![None; thread_count]);
+    let start_barrier = Barrier::new(thread_count + 1);
+    // Threads only exit once every stall is over, so no signal is ever
+    // sent to a thread that is gone
+    let exit_barrier = Barrier::new(thread_count + 1);
+    let pushes = AtomicUsize::new(0);
+    let pops = AtomicUsize::new(0);
+    let thread_failed = AtomicBool::new(false);
+    let done = AtomicBool::new(false);
+    let mut stalls: Vec<Stall> = Vec::with_capacity(progress_args.stalls);
+    let mut mode = progress_args.mode;
+    info!(
+        "Starting progress benchmark with {} stalls of {} out of {} threads",
+        progress_args.stalls, progress_args.stalled, thread_count
+    );
+
+    std::thread::scope(|s| {
+        let states = &states;
+        let hooked = &hooked;
+        #[cfg(target_os = "linux")]
+        let threads = &threads;
+        let start_barrier = &start_barrier;
+        let exit_barrier = &exit_barrier;
+        let pushes = &pushes;
+        let pops = &pops;
+        let thread_failed = &thread_failed;
+        let done = &done;
+        let queue = &cqueue;
+        for (i, state) in states.iter().enumerate() {
+            s.spawn(move || {
+                #[cfg(target_os = "linux")]
+                {
+                    threads.lock().unwrap()[i] = Some(signal::Thread::current());
+                }
+                let mut started = false;
+                let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    let mut handle = queue.register();
+                    let mut backoff = Backoff::from_args(&bench_conf.args);
+                    let mut worker = Worker::from_args(&bench_conf.args, i);
+                    let mut l_pushes = 0;
+                    let mut l_pops = 0;
+                    start_barrier.wait();
+                    started = true;
+                    while !done.load(Ordering::Relaxed) {
+                        let (pushed, _) = benchmark_helpers::push_with_policy(
+                            &mut handle,
+                            T::default(),
+                            fifo_queue_args.full_policy,
+                            &mut backoff,
+                            done,
+                        );
+                        l_pushes += pushed as usize;
+                        match handle.pop() {
+                            Some(_) => {
+                                l_pops += 1;
+                                backoff.reset();
+                            }
+                            None => backoff.backoff(),
+                        }
+                        state.ops.store(l_pushes + l_pops, Ordering::Relaxed);
+                        // The cooperative pause hook
+                        if state.pause.load(Ordering::Relaxed) {
+                            hooked.fetch_add(1, Ordering::Relaxed);
+                            while state.pause.load(Ordering::Relaxed) {
+                                std::thread::sleep(STALL_POLL);
+                            }
+                            hooked.fetch_sub(1, Ordering::Relaxed);
+                        }
+                        worker.think();
+                    }
+                    pushes.fetch_add(l_pushes, Ordering::Relaxed);
+                    pops.fetch_add(l_pops, Ordering::Relaxed);
+                }));
+                // A thread panicked, aborting the benchmark...
+                if let Err(e) = result {
+                    error!("Thread {} panicked: {:?}. Aborting benchmark, padding results to zero", i, e);
+                    thread_failed.store(true, Ordering::Relaxed);
+                    done.store(true, Ordering::Relaxed);
+                    // The others are still waiting for this thread to start
+                    if !started {
+                        start_barrier.wait();
+                    }
+                }
+                exit_barrier.wait();
+            });
+        }
+        start_barrier.wait();
+
+        #[cfg(target_os = "linux")]
+        let signal_threads: Vec<signal::Thread> =
+            threads.lock().unwrap().iter().map(|t| t.unwrap()).collect();
+        let staller = match mode {
+            #[cfg(target_os = "linux")]
+            StallMode::Signal => match signal::Staller::new() {
+                Some(staller) => Staller::Signal(staller, &signal_threads),
+                None => {
+                    warn!("Couldn't install the stall signal handler, stalling threads with hooks instead.");
+                    Staller::Hook(states, hooked)
+                }
+            },
+            #[cfg(not(target_os = "linux"))]
+            StallMode::Signal => {
+                warn!("Stalling threads with signals is only supported on Linux, using hooks instead.");
+                Staller::Hook(states, hooked)
+            }
+            StallMode::Hook => Staller::Hook(states, hooked),
+        };
+        mode = staller.mode();
+
+        let mut rng = bench_conf.thread_rng(Stream::Stalls, 0);
+        let others_ops = |victims: &[usize]| -> usize {
+            states
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !victims.contains(i))
+                .map(|(_, state)| state.ops.load(Ordering::Relaxed))
+                .sum()
+        };
+        for n in 0..progress_args.stalls {
+            if thread_failed.load(Ordering::Relaxed) {
+                break;
+            }
+            let victims = rand::seq::index::sample(&mut rng, thread_count, progress_args.stalled).into_vec();
+            let gap = rng.random_range(progress_args.gap / 2..=progress_args.gap + progress_args.gap / 2);
+
+            // Run undisturbed to get the baseline of the threads that keep running
+            let gap_start = Instant::now();
+            let before = others_ops(&victims);
+            std::thread::sleep(Duration::from_millis(gap));
+            let gap_ops = others_ops(&victims) - before;
+            let gap_time = gap_start.elapsed();
+
+            debug!("Stall {}: stalling threads {:?}", n, victims);
+            // A stalled thread may hold the lock of the allocator or the
+            // logger, so nothing is allocated or logged until it is released
+            let windows = (progress_args.stall_time / progress_args.window + 1) as usize;
+            let mut window_ops = Vec::with_capacity(windows);
+            let unsignalled = staller.stall(&victims);
+            let stalled_in_time = staller.wait_for(victims.len(), thread_failed);
+            let stalled = staller.stalled();
+            let stall_start = Instant::now();
+            let mut last = others_ops(&victims);
+            while stall_start.elapsed() < Duration::from_millis(progress_args.stall_time)
+                && window_ops.len() < windows
+                && !thread_failed.load(Ordering::Relaxed)
+            {
+                std::thread::sleep(Duration::from_millis(progress_args.window));
+                let now = others_ops(&victims);
+                window_ops.push(now - last);
+                last = now;
+            }
+            let stall_time = stall_start.elapsed();
+            staller.release(&victims);
+            let continued_in_time = staller.wait_for(0, thread_failed);
+
+            if unsignalled > 0 {
+                warn!("Couldn't send the stall signal to {} threads for stall {}.", unsignalled, n);
+            }
+            if !stalled_in_time {
+                warn!(
+                    "Only {} of {} threads stalled in time for stall {}.",
+                    stalled,
+                    victims.len(),
+                    n
+                );
+            }
+            if !continued_in_time {
+                warn!("Stalled threads didn't continue in time after stall {}.", n);
+            }
+
+            let stall = Stall::new(gap_ops, gap_time, &window_ops, stall_time);
+            if !stall.progress() {
+                warn!(
+                    "{} made no progress for {} windows while {} threads were stalled.",
+                    cqueue.get_id(),
+                    stall.zero_windows,
+                    victims.len()
+                );
+            }
+            stalls.push(stall);
+        }
+        // Also release threads stalled by an aborted stall
+        staller.release(&(0..thread_count).collect::<Vec<usize>>());
+        done.store(true, Ordering::Relaxed);
+        exit_barrier.wait();
+    });
+
+    let failed = thread_failed.into_inner();
+    let conserved = !failed
+        && benchmark_helpers::drain_and_check_conservation(
+            &cqueue,
+            prefilled,
+            pushes.into_inner(),
+            pops.into_inner(),
+        );
+    let mut output = String::new();
+    for n in 0..progress_args.stalls {
+        let columns = match stalls.get(n) {
+            Some(stall) if !failed => format!(
+                "{:.2},{:.2},{:.4},{},{},{},{}",
+                stall.baseline,
+                stall.throughput,
+                stall.relative(),
+                stall.min_window_ops,
+                stall.zero_windows,
+                stall.progress(),
+                conserved
+            ),
+            // If a thread crashed, pad the results with zero-values
+            _ => "0,0,0,0,0,false,false".to_string(),
+        };
+        output.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{},{}\n",
+            cqueue.get_id(),
+            fifo_queue_args.benchmark_runner,
+            bench_conf.benchmark_id,
+            n,
+            thread_count,
+            progress_args.stalled,
+            mode,
+            progress_args.stall_time,
+            columns,
+            bench_conf.seed()
+        ));
+    }
+
+    // Write to file or stdout depending on flag
+    if !bench_conf.args.write_to_stdout {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&bench_conf.output_filename)?;
+        write!(file, "{}", output)?;
+    } else {
+        print!("{}", output);
+    }
+    if failed {
+        return Err(Error::ThreadPanic(
+            "a thread panicked during the progress benchmark, its results were padded with zeros"
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Stalls threads with a signal whose handler waits until the stall is
+/// over, which stops a thread wherever it is, also in the middle of an
+/// operation. The handler is process-wide, so its state is global and only
+/// one `Staller` can exist at a time.
+#[cfg(target_os = "linux")]
+mod signal {
+    use super::STALL_POLL;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Mutex, MutexGuard, OnceLock};
+
+    const STALL_SIGNAL: libc::c_int = libc::SIGUSR1;
+
+    /// Number of the latest stall. The handler waits until `RELEASED` has
+    /// caught up with the stall it was sent for.
+    static STALL: AtomicUsize = AtomicUsize::new(0);
+    static RELEASED: AtomicUsize = AtomicUsize::new(0);
+    /// Threads currently waiting in the handler.
+    static STALLED: AtomicUsize = AtomicUsize::new(0);
+    static LOCK: Mutex<()> = Mutex::new(());
+    static INSTALLED: OnceLock<bool> = OnceLock::new();
+
+    /// A thread that can be stalled from other threads. Only valid until the
+    /// thread has exited.
+    #[derive(Debug, Clone, Copy)]
+    pub struct Thread(libc::pthread_t);
+
+    impl Thread {
+        /// Returns the calling thread.
+        pub fn current() -> Self {
+            // SAFETY: pthread_self has no preconditions.
+            Thread(unsafe { libc::pthread_self() })
+        }
+    }
+
+    extern "C" fn handle_stall(_: libc::c_int) {
+        // Only atomics and nanosleep are used, which are async-signal-safe.
+        // The interrupted code may be about to read errno, so it is restored.
+        // SAFETY: __errno_location returns a valid pointer for this thread.
+        let errno = unsafe { *libc::__errno_location() };
+        let stall = STALL.load(Ordering::SeqCst);
+        STALLED.fetch_add(1, Ordering::SeqCst);
+        let poll = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: STALL_POLL.as_nanos() as libc::c_long,
+        };
+        while RELEASED.load(Ordering::SeqCst) < stall {
+            // SAFETY: `poll` is a valid timespec and the remainder is unused.
+            unsafe { libc::nanosleep(&poll, std::ptr::null_mut()) };
+        }
+        STALLED.fetch_sub(1, Ordering::SeqCst);
+        // SAFETY: As above.
+        unsafe { *libc::__errno_location() = errno };
+    }
+
+    /// Stalls threads until released. Holds the global lock while it exists.
+    pub struct Staller {
+        _lock: MutexGuard<'static, ()>,
+    }
+
+    impl Staller {
+        /// Installs the signal handler. Returns `None` if it couldn't be
+        /// installed.
+        pub fn new() -> Option<Self> {
+            let lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+            let installed = *INSTALLED.get_or_init(|| {
+                // SAFETY: `action` is fully initialized before it is passed on,
+                // and the handler only does async-signal-safe work.
+                unsafe {
+                    let mut action: libc::sigaction = std::mem::zeroed();
+                    action.sa_sigaction = handle_stall as extern "C" fn(libc::c_int) as libc::sighandler_t;
+                    action.sa_flags = libc::SA_RESTART;
+                    libc::sigemptyset(&mut action.sa_mask);
+                    libc::sigaction(STALL_SIGNAL, &action, std::ptr::null_mut()) == 0
+                }
+            });
+            installed.then_some(Staller { _lock: lock })
+        }
+
+        /// Sends the stall signal to `threads`. They stay stalled until
+        /// `release` is called, also if the signal arrives late. Returns how
+        /// many threads the signal couldn't be sent to, without logging,
+        /// since the threads that got it may hold the logger's lock.
+        pub fn stall(&self, threads: &[Thread]) -> usize {
+            STALL.fetch_add(1, Ordering::SeqCst);
+            threads
+                .iter()
+                // SAFETY: The thread hasn't exited yet.
+                .filter(|thread| unsafe { libc::pthread_kill(thread.0, STALL_SIGNAL) } != 0)
+                .count()
+        }
+
+        /// Lets every stalled thread continue.
+        pub fn release(&self) {
+            RELEASED.store(STALL.load(Ordering::SeqCst), Ordering::SeqCst);
+        }
+
+        /// Returns how many threads are waiting in the signal handler.
+        pub fn stalled(&self) -> usize {
+            STALLED.load(Ordering::SeqCst)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arguments::FifoQueueProgressArgs;
+    use crate::benchmarks::test_helpers::bench_conf;
+    use crate::benchmarks::test_helpers::test_queue::TestQueue;
+
+    fn progress_args(stalled: usize, mode: StallMode) -> FifoQueueArgs {
+        FifoQueueArgs {
+            benchmark_runner: FifoQueueBenchmarks::Progress(FifoQueueProgressArgs {
+                thread_count: 3,
+                stalled,
+                stalls: 2,
+                stall_time: 50,
+                gap: 20,
+                window: 10,
+                mode,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn run_progress() {
+        for mode in [StallMode::Signal, StallMode::Hook] {
+            let fifo_queue_args = progress_args(1, mode);
+            let queue: TestQueue<usize> = TestQueue::new(0);
+            benchmark_progress(queue, &bench_conf(&fifo_queue_args), &fifo_queue_args)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_stalled() {
+        for stalled in [0, 3] {
+            let fifo_queue_args = progress_args(stalled, StallMode::Hook);
+            let queue: TestQueue<usize> = TestQueue::new(0);
+            let err = benchmark_progress(queue, &bench_conf(&fifo_queue_args), &fifo_queue_args)
+                .unwrap_err();
+            assert!(matches!(err, Error::Config(_)));
+        }
+    }
+
+    #[test]
+    fn zero_windows_mean_no_progress() {
+        let second = Duration::from_secs(1);
+        let stall = Stall::new(100, second, &[30, 20, 10], second);
+        assert_eq!(stall.min_window_ops, 10);
+        assert!(stall.progress());
+        assert_eq!(stall.relative(), 0.6);
+
+        let stall = Stall::new(100, second, &[30, 0, 0], second);
+        assert_eq!(stall.zero_windows, 2);
+        assert!(!stall.progress());
+    }
+}
@@ -0,0 +1,506 @@
+use crate::arguments::{FifoQueueArgs, FifoQueueBenchmarks, FifoQueueReorderArgs};
+use crate::backoff::Backoff;
+use crate::benchmarks::benchmark_helpers::{self, BenchConfig};
+use crate::error::Error;
+use crate::histogram::Histogram;
+use crate::rng::Stream;
+use crate::traits::{ConcurrentQueue, HandleQueue};
+use crate::work::Worker;
+use core_affinity::CoreId;
+use log::{debug, error, info, warn};
+use rand::Rng;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Barrier, Mutex};
+use std::time::{Duration, Instant};
+
+/// Bits of an item holding its sequence number among the items of the thread
+/// that pushed it. The bits above hold the thread.
+const SEQ_BITS: u32 = usize::BITS / 2;
+
+fn item(thread: usize, seq: usize) -> usize {
+    (thread << SEQ_BITS) | seq
+}
+
+fn split(item: usize) -> (usize, usize) {
+    (item >> SEQ_BITS, item & ((1 << SEQ_BITS) - 1))
+}
+
+/// The operations one thread completed: the ticket of every item it pushed,
+/// indexed by the sequence number of the item, and the ticket and item of
+/// every pop.
+#[derive(Debug, Default)]
+struct ThreadLog {
+    pushed: Vec<u64>,
+    popped: Vec<(u64, usize)>,
+}
+
+/// Distribution of the rank errors of all pops of a run.
+#[derive(Debug, Default)]
+struct RankErrors {
+    histogram: Histogram,
+    sum: u64,
+    out_of_order: u64,
+}
+
+impl RankErrors {
+    fn record(&mut self, error: u64) {
+        self.histogram.record(error);
+        self.sum += error;
+        self.out_of_order += (error > 0) as u64;
+    }
+
+    fn mean(&self) -> f64 {
+        self.sum as f64 / self.histogram.len().max(1) as f64
+    }
+
+    /// Share of the pops that didn't return the oldest item.
+    fn out_of_order_share(&self) -> f64 {
+        self.out_of_order as f64 / self.histogram.len().max(1) as f64
+    }
+}
+
+/// Counts of the items in the queue, indexed by the order of their pushes,
+/// answering how many older items are in the queue in logarithmic time.
+struct Fenwick {
+    tree: Vec<i64>,
+}
+
+impl Fenwick {
+    fn new(len: usize) -> Self {
+        Fenwick { tree: vec![0; len + 1] }
+    }
+
+    fn add(&mut self, index: usize, delta: i64) {
+        let mut i = index + 1;
+        while i < self.tree.len() {
+            self.tree[i] += delta;
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    /// Sum of the counts below `index`.
+    fn prefix(&self, index: usize) -> i64 {
+        let mut sum = 0;
+        let mut i = index;
+        while i > 0 {
+            sum += self.tree[i];
+            i -= i & i.wrapping_neg();
+        }
+        sum
+    }
+}
+
+/// Computes the rank error of every pop in the logs, i.e. how many items
+/// pushed before the popped item were still in the queue. Replays the pushes
+/// and pops in the order of their tickets.
+/// A pop can get its ticket before the push of the same item does, such an
+/// item never counts as being in the queue. Items that were never pushed,
+/// which only a broken queue returns, are skipped.
+fn rank_errors(logs: &[ThreadLog]) -> RankErrors {
+    let mut pushes: Vec<(u64, usize, usize)> = logs
+        .iter()
+        .enumerate()
+        .flat_map(|(thread, log)| {
+            log.pushed.iter().enumerate().map(move |(seq, &ticket)| (ticket, thread, seq))
+        })
+        .collect();
+    pushes.sort_unstable();
+    let mut ranks: Vec<Vec<usize>> = logs.iter().map(|log| vec![0; log.pushed.len()]).collect();
+    for (rank, &(_, thread, seq)) in pushes.iter().enumerate() {
+        ranks[thread][seq] = rank;
+    }
+    let mut pops: Vec<(u64, usize)> = logs.iter().flat_map(|log| log.popped.iter().copied()).collect();
+    pops.sort_unstable();
+
+    let mut queue = Fenwick::new(pushes.len());
+    let mut in_queue = vec![false; pushes.len()];
+    let mut popped = vec![false; pushes.len()];
+    let mut next_push = pushes.iter().enumerate().peekable();
+    let mut errors = RankErrors::default();
+    for (ticket, item) in pops {
+        while let Some((rank, _)) = next_push.next_if(|(_, push)| push.0 < ticket) {
+            if !popped[rank] {
+                queue.add(rank, 1);
+                in_queue[rank] = true;
+            }
+        }
+        let (thread, seq) = split(item);
+        let Some(&rank) = ranks.get(thread).and_then(|ranks| ranks.get(seq)) else {
+            continue;
+        };
+        errors.record(queue.prefix(rank) as u64);
+        if in_queue[rank] {
+            queue.add(rank, -1);
+            in_queue[rank] = false;
+        }
+        popped[rank] = true;
+    }
+    errors
+}
+
+/// Totals of one run.
+#[derive(Debug)]
+struct Reorder {
+    pushes: usize,
+    pops: usize,
+    empty_pops: usize,
+    failed_pushes: usize,
+    errors: RankErrors,
+    elapsed: Duration,
+    /// False if the time limit was reached before every thread did all of
+    /// its operations.
+    completed: bool,
+    conserved: bool,
+}
+
+/// # Explanation:
+/// Measures how far from FIFO order a queue dequeues its items. Every thread
+/// randomly pushes or pops like in `enq-deq`. Right after an operation
+/// completes, the thread takes a ticket from a global counter, which orders
+/// all pushes and pops. The rank error of a pop is the amount of items that
+/// were pushed before the popped item and were still in the queue, 0 for a
+/// pop that returned the oldest item.
+/// The tickets are taken after the operations, so a thread that is slow to
+/// take its ticket makes even a strict FIFO queue show small errors. Compare
+/// queues against each other, e.g. against `basic_queue`.
+/// The operations replace the time limit, which only stops runs that take
+/// too long. `--batch-size` is not used.
+/// Benchmark specific flags:
+/// * `--thread-count`    (OPTIONAL)  Amount of threads (standard 20).
+/// * `--ops`             (OPTIONAL)  Operations per thread (standard 100000).
+/// * `--spread`          (OPTIONAL)  Share of pushes (standard 0.5).
+pub fn benchmark_reorder<C>(
+    cqueue: C,
+    bench_conf: &BenchConfig,
+    fifo_queue_args: &FifoQueueArgs,
+) -> Result<(), Error>
+where
+    C: ConcurrentQueue<usize>,
+    for<'a> &'a C: Send,
+{
+    // Extract specific arguments for this benchmark runner
+    let reorder_args = match &fifo_queue_args.benchmark_runner {
+        FifoQueueBenchmarks::Reorder(a) => a,
+        _ => panic!(
+            "benchmark_reorder called with another FIFO Queue \
+            configured. This is an implementation error."
+        ),
+    };
+    let max_ops = 1usize << SEQ_BITS;
+    if reorder_args.ops >= max_ops || fifo_queue_args.prefill_amount as usize >= max_ops {
+        return Err(Error::Config(format!(
+            "reorder numbers the items of a thread with {} bits, --ops and --prefill-amount must be below {}",
+            SEQ_BITS, max_ops
+        )));
+    }
+
+    let reorder = run_reorder(&cqueue, bench_conf, fifo_queue_args, reorder_args);
+    let formatted = match &reorder {
+        Some(reorder) => format!(
+            "{},{},{},{},{},{},{},{},{},{},{:.4},{},{},{},{},{:.4},{},{},{}",
+            reorder.elapsed.as_millis(),
+            cqueue.get_id(),
+            fifo_queue_args.benchmark_runner,
+            bench_conf.benchmark_id,
+            reorder_args.thread_count,
+            reorder_args.spread,
+            reorder.pushes,
+            reorder.pops,
+            reorder.empty_pops,
+            reorder.failed_pushes,
+            reorder.errors.mean(),
+            reorder.errors.histogram.percentile(50.0),
+            reorder.errors.histogram.percentile(90.0),
+            reorder.errors.histogram.percentile(99.0),
+            reorder.errors.histogram.max(),
+            reorder.errors.out_of_order_share(),
+            reorder.completed,
+            reorder.conserved,
+            bench_conf.seed()
+        ),
+        // If a thread crashed, pad the results with zero-values
+        None => format!(
+            "0,{},{},{},{},{},0,0,0,0,0,0,0,0,0,0,false,false,{}",
+            cqueue.get_id(),
+            fifo_queue_args.benchmark_runner,
+            bench_conf.benchmark_id,
+            reorder_args.thread_count,
+            reorder_args.spread,
+            bench_conf.seed()
+        ),
+    };
+    // Write to file or stdout depending on flag
+    if !bench_conf.args.write_to_stdout {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&bench_conf.output_filename)?;
+        writeln!(file, "{}", formatted)?;
+    } else {
+        println!("{}", formatted);
+    }
+    if reorder.is_none() {
+        return Err(Error::ThreadPanic(
+            "a reorder thread panicked, the results were padded with zeros".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Runs the threads against the queue and computes the rank errors from
+/// their logs. Returns `None` if a thread panicked.
+fn run_reorder<C>(
+    cqueue: &C,
+    bench_conf: &BenchConfig,
+    fifo_queue_args: &FifoQueueArgs,
+    reorder_args: &FifoQueueReorderArgs,
+) -> Option<Reorder>
+where
+    C: ConcurrentQueue<usize>,
+    for<'a> &'a C: Send,
+{
+    let thread_count = reorder_args.thread_count;
+    let ops = reorder_args.ops;
+    let spread = reorder_args.spread;
+    let clock = AtomicU64::new(0);
+
+    // The prefilled items are logged as the items of one more thread
+    let mut logs: Vec<ThreadLog> = (0..=thread_count).map(|_| ThreadLog::default()).collect();
+    {
+        debug!(
+            "Prefilling queue with {} items.",
+            fifo_queue_args.prefill_amount
+        );
+        let mut tmp_handle = cqueue.register();
+        let prefill_log = &mut logs[thread_count];
+        for _ in 0..fifo_queue_args.prefill_amount {
+            if tmp_handle.push(item(thread_count, prefill_log.pushed.len())).is_ok() {
+                prefill_log.pushed.push(clock.fetch_add(1, Ordering::SeqCst));
+            }
+        }
+    }
+    let prefilled = logs[thread_count].pushed.len();
+    let logs = Mutex::new(logs);
+
+    let barrier = Barrier::new(thread_count + 1);
+    let empty_pops = AtomicUsize::new(0);
+    let failed_pushes = AtomicUsize::new(0);
+    let finished = AtomicUsize::new(0);
+    let thread_failed = AtomicBool::new(false);
+    let done = AtomicBool::new(false);
+    let time_limit = Duration::from_secs(bench_conf.args.time_limit);
+    info!(
+        "Starting reorder benchmark with {} threads and {} operations per thread",
+        thread_count, ops
+    );
+
+    // Get cores for fairness of threads
+    let available_cores: Vec<CoreId> =
+        core_affinity::get_core_ids().unwrap_or(vec![CoreId { id: 0 }]);
+    let mut core_iter = available_cores.into_iter().cycle();
+
+    let start = std::thread::scope(|s| {
+        let clock = &clock;
+        let logs = &logs;
+        let empty_pops = &empty_pops;
+        let failed_pushes = &failed_pushes;
+        let finished = &finished;
+        let thread_failed = &thread_failed;
+        let done = &done;
+        let barrier = &barrier;
+        let is_one_socket = &bench_conf.args.one_socket;
+        for i in 0..thread_count {
+            let mut core: CoreId = core_iter.next().unwrap();
+            // if is_one_socket is true, make all thread ids even
+            // (this was used for our testing enviroment to get one socket)
+            if *is_one_socket {
+                core = core_iter.next().unwrap();
+            }
+            s.spawn(move || {
+                let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    core_affinity::set_for_current(core);
+                    let mut handle = cqueue.register();
+                    let mut backoff = Backoff::from_args(&bench_conf.args);
+                    let mut worker = Worker::from_args(&bench_conf.args, i);
+                    let mut rng = bench_conf.thread_rng(Stream::Operations, i);
+                    let mut log = ThreadLog::default();
+                    let mut l_empty_pops = 0;
+                    let mut l_failed_pushes = 0;
+                    barrier.wait();
+                    for _ in 0..ops {
+                        if done.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        if rng.random::<f64>() > spread {
+                            match handle.pop() {
+                                Some(popped) => {
+                                    log.popped.push((clock.fetch_add(1, Ordering::SeqCst), popped));
+                                    backoff.reset();
+                                }
+                                None => {
+                                    l_empty_pops += 1;
+                                    backoff.backoff();
+                                }
+                            }
+                        } else {
+                            let (pushed, failed) = benchmark_helpers::push_with_policy(
+                                &mut handle,
+                                item(i, log.pushed.len()),
+                                fifo_queue_args.full_policy,
+                                &mut backoff,
+                                done,
+                            );
+                            if pushed {
+                                log.pushed.push(clock.fetch_add(1, Ordering::SeqCst));
+                            }
+                            l_failed_pushes += failed;
+                        }
+                        worker.think();
+                    }
+                    empty_pops.fetch_add(l_empty_pops, Ordering::Relaxed);
+                    failed_pushes.fetch_add(l_failed_pushes, Ordering::Relaxed);
+                    logs.lock().unwrap()[i] = log;
+                }));
+                // A thread panicked, aborting the benchmark...
+                if let Err(e) = result {
+                    error!("Thread {} panicked: {:?}. Aborting benchmark, padding results to zero", i, e);
+                    thread_failed.store(true, Ordering::Relaxed);
+                    done.store(true, Ordering::Relaxed);
+                }
+                finished.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        barrier.wait();
+        let start = Instant::now();
+        while finished.load(Ordering::Relaxed) < thread_count {
+            if !done.load(Ordering::Relaxed) && start.elapsed() >= time_limit {
+                warn!("Time limit reached before every thread did its operations.");
+                done.store(true, Ordering::Relaxed);
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        start
+    });
+    let elapsed = start.elapsed();
+
+    if thread_failed.into_inner() {
+        return None;
+    }
+    let logs = logs.into_inner().unwrap();
+    let pushes = logs[..thread_count].iter().map(|log| log.pushed.len()).sum();
+    let pops = logs.iter().map(|log| log.popped.len()).sum();
+    // Drain what is left in the queue and check that no items were lost
+    let conserved =
+        benchmark_helpers::drain_and_check_conservation(cqueue, prefilled, pushes, pops);
+    debug!("Computing the rank errors of {} pops", pops);
+    Some(Reorder {
+        pushes,
+        pops,
+        empty_pops: empty_pops.into_inner(),
+        failed_pushes: failed_pushes.into_inner(),
+        errors: rank_errors(&logs),
+        elapsed,
+        completed: !done.into_inner(),
+        conserved,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::benchmarks::test_helpers::bench_conf;
+    use crate::benchmarks::test_helpers::test_queue::TestQueue;
+
+    fn reorder_args(thread_count: usize) -> FifoQueueArgs {
+        FifoQueueArgs {
+            benchmark_runner: FifoQueueBenchmarks::Reorder(FifoQueueReorderArgs {
+                thread_count,
+                ops: 1000,
+                spread: 0.5,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn run_reorder_benchmark() {
+        let fifo_queue_args = reorder_args(4);
+        let queue: TestQueue<usize> = TestQueue::new(0);
+        benchmark_reorder(queue, &bench_conf(&fifo_queue_args), &fifo_queue_args).unwrap();
+    }
+
+    #[test]
+    fn one_thread_sees_no_reordering() {
+        let fifo_queue_args = FifoQueueArgs {
+            prefill_amount: 10,
+            ..reorder_args(1)
+        };
+        let FifoQueueBenchmarks::Reorder(args) = &fifo_queue_args.benchmark_runner else {
+            unreachable!()
+        };
+        let queue: TestQueue<usize> = TestQueue::new(0);
+        let reorder = run_reorder(&queue, &bench_conf(&fifo_queue_args), &fifo_queue_args, args).unwrap();
+        assert!(reorder.completed);
+        assert!(reorder.conserved);
+        assert!(reorder.pops > 0);
+        assert_eq!(reorder.errors.histogram.len(), reorder.pops as u64);
+        assert_eq!(reorder.errors.histogram.max(), 0);
+    }
+
+    #[test]
+    fn rank_error_counts_older_items_left_behind() {
+        // Thread 0 pushes items 0..4 with tickets 0..4, thread 1 pops them
+        // as 0, 2, 3, 1
+        let logs = [
+            ThreadLog {
+                pushed: vec![0, 1, 2, 3],
+                popped: vec![],
+            },
+            ThreadLog {
+                pushed: vec![],
+                popped: vec![(4, item(0, 0)), (5, item(0, 2)), (6, item(0, 3)), (7, item(0, 1))],
+            },
+        ];
+        let errors = rank_errors(&logs);
+        assert_eq!(errors.histogram.len(), 4);
+        assert_eq!(errors.histogram.max(), 1);
+        assert_eq!(errors.sum, 2);
+        assert_eq!(errors.out_of_order, 2);
+        assert_eq!(errors.mean(), 0.5);
+    }
+
+    #[test]
+    fn rank_errors_of_a_stack() {
+        // Three items popped in reverse order, interleaved with a push
+        let logs = [ThreadLog {
+            pushed: vec![0, 1, 3],
+            popped: vec![(2, item(0, 1)), (4, item(0, 2)), (5, item(0, 0)), (6, 1 << (usize::BITS - 1))],
+        }];
+        let errors = rank_errors(&logs);
+        // The unknown item at ticket 6 is skipped
+        assert_eq!(errors.histogram.len(), 3);
+        assert_eq!(errors.sum, 1 + 1);
+        assert_eq!(errors.histogram.max(), 1);
+    }
+
+    #[test]
+    fn pop_before_the_push_ticket() {
+        // The pop of item 1 got its ticket before the push did
+        let logs = [ThreadLog {
+            pushed: vec![0, 3],
+            popped: vec![(2, item(0, 1)), (4, item(0, 0))],
+        }];
+        let errors = rank_errors(&logs);
+        assert_eq!(errors.sum, 1);
+        // Item 1 never counts as being in the queue, so item 0 has no error
+        assert_eq!(errors.out_of_order, 1);
+    }
+
+    #[test]
+    fn items_round_trip() {
+        assert_eq!(split(item(3, 12345)), (3, 12345));
+        assert_eq!(split(item(0, (1 << SEQ_BITS) - 1)), (0, (1 << SEQ_BITS) - 1));
+    }
+}
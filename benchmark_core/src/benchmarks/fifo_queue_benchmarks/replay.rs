@@ -0,0 +1,434 @@
+use crate::arguments::{FifoQueueArgs, FifoQueueBenchmarks, FifoQueueReplayArgs, GeneralArgs};
+use crate::backoff::Backoff;
+use crate::benchmarks::benchmark_helpers::{self, BenchConfig};
+use crate::error::Error;
+use crate::rng::Stream;
+use crate::trace::{Trace, TraceOpKind};
+use crate::traits::ConcurrentQueue;
+use crate::work::{self, Worker};
+use core_affinity::CoreId;
+use log::{debug, error, info, warn};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Barrier;
+use std::time::{Duration, Instant};
+
+/// Totals of one replay of a trace.
+#[derive(Debug)]
+struct Replay {
+    pushes: usize,
+    pops: usize,
+    empty_pops: usize,
+    failed_pushes: usize,
+    backoffs: usize,
+    mean_think_time: f64,
+    elapsed: Duration,
+    /// False if the time limit was reached before every thread got through
+    /// its script.
+    completed: bool,
+    conserved: bool,
+}
+
+/// Reads the trace to replay from its file, or generates it from its seed,
+/// which is the seed of the run unless given.
+/// Done once before the iterations, so that all of them replay the same
+/// trace.
+pub fn load_trace(
+    replay_args: &FifoQueueReplayArgs,
+    general_args: &GeneralArgs,
+) -> Result<Trace, Error> {
+    if replay_args.dilation < 0.0 || !replay_args.dilation.is_finite() {
+        return Err(Error::Config(format!(
+            "the dilation must be a positive factor, got {}",
+            replay_args.dilation
+        )));
+    }
+    let trace = match &replay_args.trace {
+        Some(path) => {
+            info!("Loading trace from {}", path);
+            Trace::load(path)?
+        }
+        None => {
+            let seed = replay_args.seed.unwrap_or(general_args.seed.unwrap_or_default());
+            let trace = Trace::generate(
+                seed,
+                replay_args.thread_count,
+                replay_args.ops,
+                replay_args.spread,
+                general_args.think_time,
+                general_args.think_dist,
+            );
+            if let Some(path) = &replay_args.save {
+                info!("Saving generated trace to {}", path);
+                trace.save(path)?;
+            }
+            trace
+        }
+    };
+    if trace.thread_count() == 0 {
+        return Err(Error::Config("the trace has no threads".to_string()));
+    }
+    debug!(
+        "Trace {} has {} threads and {} operations",
+        trace.id(),
+        trace.thread_count(),
+        trace.ops()
+    );
+    Ok(trace)
+}
+
+/// # Explanation:
+/// Replays a trace: every thread runs its script from the trace, pushing or
+/// popping and then thinking for exactly the recorded think time, scaled by
+/// `--dilation`. Every queue replaying the same trace does the same work, so
+/// differences in the results come from the queue alone. The queue still
+/// decides which pops find it empty and which pushes find it full, those are
+/// handled like in `enq-deq`.
+/// The trace replaces the time limit, which only stops replays that take
+/// too long, e.g. because a producer retries on a queue that stays full.
+/// Benchmark specific flags:
+/// * `--trace`           (OPTIONAL)  Trace file to replay, e.g. recorded with `enq-deq --record`.
+/// * `--seed`            (OPTIONAL)  Generate the trace from this seed instead of the seed of the run.
+/// * `--thread-count`    (OPTIONAL)  Threads of a generated trace (standard 20).
+/// * `--ops`             (OPTIONAL)  Operations per thread of a generated trace (standard 100000).
+/// * `--spread`          (OPTIONAL)  Share of pushes of a generated trace (standard 0.5).
+/// * `--save`            (OPTIONAL)  Write the generated trace to this file.
+/// * `--dilation`        (OPTIONAL)  Factor applied to every think time (standard 1).
+pub fn benchmark_replay<C, T>(
+    cqueue: C,
+    bench_conf: &BenchConfig,
+    fifo_queue_args: &FifoQueueArgs,
+    trace: &Trace,
+) -> Result<(), Error>
+where
+    C: ConcurrentQueue<T>,
+    T: Default,
+    for<'a> &'a C: Send,
+{
+    // Extract specific arguments for this benchmark runner
+    let replay_args = match &fifo_queue_args.benchmark_runner {
+        FifoQueueBenchmarks::Replay(a) => a,
+        _ => panic!(
+            "benchmark_replay called with another FIFO Queue \
+            configured. This is an implementation error."
+        ),
+    };
+    let thread_count = trace.thread_count();
+    let capabilities = cqueue.capabilities();
+    if !capabilities.allows(thread_count, thread_count)
+        || !capabilities.allows_handles(thread_count)
+    {
+        return Err(Error::Config(format!(
+            "{} doesn't support the {} concurrent producers and consumers of the trace",
+            cqueue.get_id(),
+            thread_count
+        )));
+    }
+
+    let replay = run_trace(&cqueue, bench_conf, fifo_queue_args, trace, replay_args.dilation);
+    let formatted = match &replay {
+        Some(replay) => format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            replay.elapsed.as_millis(),
+            (replay.pushes + replay.pops) as f64 / replay.elapsed.as_secs_f64().max(f64::MIN_POSITIVE),
+            replay.pushes,
+            replay.pops,
+            replay.empty_pops,
+            replay.failed_pushes,
+            thread_count,
+            cqueue.get_id(),
+            fifo_queue_args.benchmark_runner,
+            bench_conf.benchmark_id,
+            trace.id(),
+            trace.ops(),
+            replay_args.dilation,
+            replay.completed,
+            replay.conserved,
+            replay.backoffs,
+            replay.mean_think_time,
+            bench_conf.seed()
+        ),
+        // If a thread crashed, pad the results with zero-values
+        None => format!(
+            "0,0,0,0,0,0,{},{},{},{},{},{},{},false,false,0,0,{}",
+            thread_count,
+            cqueue.get_id(),
+            fifo_queue_args.benchmark_runner,
+            bench_conf.benchmark_id,
+            trace.id(),
+            trace.ops(),
+            replay_args.dilation,
+            bench_conf.seed()
+        ),
+    };
+    // Write to file or stdout depending on flag
+    if !bench_conf.args.write_to_stdout {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&bench_conf.output_filename)?;
+        writeln!(file, "{}", formatted)?;
+    } else {
+        println!("{}", formatted);
+    }
+    if replay.is_none() {
+        return Err(Error::ThreadPanic(
+            "a thread panicked during the replay, the results were padded with zeros".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Runs every thread of the trace against the queue. Returns `None` if a
+/// thread panicked.
+fn run_trace<C, T>(
+    cqueue: &C,
+    bench_conf: &BenchConfig,
+    fifo_queue_args: &FifoQueueArgs,
+    trace: &Trace,
+    dilation: f64,
+) -> Option<Replay>
+where
+    C: ConcurrentQueue<T>,
+    T: Default,
+    for<'a> &'a C: Send,
+{
+    let prefilled =
+        benchmark_helpers::prefill(cqueue, fifo_queue_args.prefill_amount, T::default);
+
+    let thread_count = trace.thread_count();
+    let batch_size = fifo_queue_args.batch_size as usize;
+    let barrier = Barrier::new(thread_count + 1);
+    let pushes = AtomicUsize::new(0);
+    let pops = AtomicUsize::new(0);
+    let empty_pops = AtomicUsize::new(0);
+    let failed_pushes = AtomicUsize::new(0);
+    let backoffs = AtomicUsize::new(0);
+    let think_time = AtomicU64::new(0);
+    let thinks = AtomicUsize::new(0);
+    let finished = AtomicUsize::new(0);
+    let thread_failed = AtomicBool::new(false);
+    let done = AtomicBool::new(false);
+    let time_limit = Duration::from_secs(bench_conf.args.time_limit);
+    info!(
+        "Replaying trace {} with {} threads and {} operations",
+        trace.id(),
+        thread_count,
+        trace.ops()
+    );
+
+    // Pin the threads like enq-deq does, so that replays of its recordings
+    // run under the same conditions
+    let step = if bench_conf.args.one_socket { 2 } else { 1 };
+    let mut cores = core_affinity::get_core_ids()
+        .unwrap_or(vec![CoreId { id: 0 }])
+        .into_iter()
+        .cycle()
+        .skip(step - 1)
+        .step_by(step);
+
+    let start = std::thread::scope(|s| {
+        let pushes = &pushes;
+        let pops = &pops;
+        let empty_pops = &empty_pops;
+        let failed_pushes = &failed_pushes;
+        let backoffs = &backoffs;
+        let think_time = &think_time;
+        let thinks = &thinks;
+        let finished = &finished;
+        let thread_failed = &thread_failed;
+        let done = &done;
+        let barrier = &barrier;
+        for (i, script) in trace.threads().iter().enumerate() {
+            let core = cores.next().unwrap();
+            s.spawn(move || {
+                let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    core_affinity::set_for_current(core);
+                    let mut handle = cqueue.register_batch();
+                    let mut backoff = Backoff::from_args(&bench_conf.args);
+                    let mut worker = Worker::new(
+                        0,
+                        bench_conf.args.think_dist,
+                        bench_conf.args.work_buffer,
+                        bench_conf.thread_rng(Stream::ThinkTime, i),
+                    );
+                    // Created without a think time, so calibrate it here
+                    // instead of in the first think of the timed part
+                    if script.iter().any(|op| op.think_time > 0) {
+                        worker.calibrate();
+                    }
+                    let mut batch = Vec::with_capacity(batch_size);
+                    let mut buffer = Vec::with_capacity(batch_size);
+                    let mut l_pushes = 0;
+                    let mut l_pops = 0;
+                    let mut l_empty_pops = 0;
+                    let mut l_failed_pushes = 0;
+                    barrier.wait();
+                    for op in script.iter() {
+                        if done.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        match op.kind {
+                            TraceOpKind::Push => {
+                                let (pushed, failed) = benchmark_helpers::push_items(
+                                    &mut handle,
+                                    &mut batch,
+                                    batch_size,
+                                    fifo_queue_args.full_policy,
+                                    &mut backoff,
+                                    done,
+                                );
+                                l_pushes += pushed;
+                                l_failed_pushes += failed;
+                            }
+                            TraceOpKind::Pop => {
+                                match benchmark_helpers::pop_items(&mut handle, &mut buffer, batch_size) {
+                                    0 => {
+                                        l_empty_pops += 1;
+                                        backoff.backoff();
+                                    }
+                                    popped => {
+                                        l_pops += popped;
+                                        backoff.reset();
+                                    }
+                                }
+                            }
+                        }
+                        if op.think_time > 0 {
+                            worker.think_for((op.think_time as f64 * dilation) as u64);
+                        }
+                    }
+                    pushes.fetch_add(l_pushes, Ordering::Relaxed);
+                    pops.fetch_add(l_pops, Ordering::Relaxed);
+                    empty_pops.fetch_add(l_empty_pops, Ordering::Relaxed);
+                    failed_pushes.fetch_add(l_failed_pushes, Ordering::Relaxed);
+                    backoffs.fetch_add(backoff.count(), Ordering::Relaxed);
+                    think_time.fetch_add(worker.total_think_time().as_nanos() as u64, Ordering::Relaxed);
+                    thinks.fetch_add(worker.thinks(), Ordering::Relaxed);
+                }));
+                // A thread panicked, aborting the benchmark...
+                if let Err(e) = result {
+                    error!("Thread {} panicked: {:?}. Aborting benchmark, padding results to zero", i, e);
+                    thread_failed.store(true, Ordering::Relaxed);
+                    done.store(true, Ordering::Relaxed);
+                }
+                finished.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        barrier.wait();
+        let start = Instant::now();
+        while finished.load(Ordering::Relaxed) < thread_count {
+            if !done.load(Ordering::Relaxed) && start.elapsed() >= time_limit {
+                warn!("Time limit reached before the replay was complete.");
+                done.store(true, Ordering::Relaxed);
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        start
+    });
+    let elapsed = start.elapsed();
+
+    if thread_failed.into_inner() {
+        return None;
+    }
+    let pushes = pushes.into_inner();
+    let pops = pops.into_inner();
+    // Drain what is left in the queue and check that no items were lost
+    let conserved =
+        benchmark_helpers::drain_and_check_conservation(cqueue, prefilled, pushes, pops);
+    Some(Replay {
+        pushes,
+        pops,
+        empty_pops: empty_pops.into_inner(),
+        failed_pushes: failed_pushes.into_inner(),
+        backoffs: backoffs.into_inner(),
+        mean_think_time: work::mean_think_time(think_time.into_inner(), thinks.into_inner()),
+        elapsed,
+        completed: !done.into_inner(),
+        conserved,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::benchmarks::test_helpers::bench_conf;
+    use crate::benchmarks::test_helpers::test_queue::TestQueue;
+    use crate::work::ThinkTimeDistribution;
+
+    fn replay_args(trace: Option<String>, seed: Option<u64>, dilation: f64) -> FifoQueueArgs {
+        FifoQueueArgs {
+            benchmark_runner: FifoQueueBenchmarks::Replay(FifoQueueReplayArgs {
+                trace,
+                seed,
+                thread_count: 4,
+                ops: 1000,
+                spread: 0.5,
+                save: None,
+                dilation,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn run_replay() {
+        let fifo_queue_args = replay_args(None, Some(42), 1.0);
+        let FifoQueueBenchmarks::Replay(args) = &fifo_queue_args.benchmark_runner else {
+            unreachable!()
+        };
+        let trace = load_trace(args, &fifo_queue_args.general_args).unwrap();
+        let queue: TestQueue<usize> = TestQueue::new(0);
+        benchmark_replay(queue, &bench_conf(&fifo_queue_args), &fifo_queue_args, &trace)
+            .unwrap();
+    }
+
+    #[test]
+    fn replay_runs_every_operation() {
+        let fifo_queue_args = replay_args(None, Some(1), 0.5);
+        let trace = Trace::generate(1, 4, 1000, 0.6, 100, ThinkTimeDistribution::Exponential);
+        let queue: TestQueue<usize> = TestQueue::new(0);
+        let replay = run_trace(&queue, &bench_conf(&fifo_queue_args), &fifo_queue_args, &trace, 0.5)
+            .unwrap();
+        let scripted_pushes = trace
+            .threads()
+            .iter()
+            .flat_map(|thread| thread.iter())
+            .filter(|op| op.kind == TraceOpKind::Push)
+            .count();
+        assert!(replay.completed);
+        assert!(replay.conserved);
+        assert_eq!(replay.pushes + replay.failed_pushes, scripted_pushes);
+        assert_eq!(replay.pops + replay.empty_pops, trace.ops() - scripted_pushes);
+    }
+
+    #[test]
+    fn replays_a_trace_file() {
+        let path = std::env::temp_dir().join(format!("replay_test_{}.trace", std::process::id()));
+        let path_str = path.to_str().unwrap().to_string();
+        let trace = Trace::generate(9, 2, 100, 0.5, 0, ThinkTimeDistribution::Fixed);
+        trace.save(&path).unwrap();
+
+        let fifo_queue_args = replay_args(Some(path_str), None, 1.0);
+        let FifoQueueBenchmarks::Replay(args) = &fifo_queue_args.benchmark_runner else {
+            unreachable!()
+        };
+        let loaded = load_trace(args, &fifo_queue_args.general_args).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, trace);
+        let queue: TestQueue<usize> = TestQueue::new(0);
+        benchmark_replay(queue, &bench_conf(&fifo_queue_args), &fifo_queue_args, &loaded)
+            .unwrap();
+    }
+
+    #[test]
+    fn rejects_negative_dilation() {
+        let fifo_queue_args = replay_args(None, Some(1), -1.0);
+        let FifoQueueBenchmarks::Replay(args) = &fifo_queue_args.benchmark_runner else {
+            unreachable!()
+        };
+        let err = load_trace(args, &fifo_queue_args.general_args).unwrap_err();
+        assert!(matches!(err, Error::Config(_)));
+    }
+}
@@ -0,0 +1,493 @@
+use crate::arguments::{FifoQueueArgs, FifoQueueBenchmarks, FifoQueueTasksArgs, TaskWorkload};
+use crate::backoff::Backoff;
+use crate::benchmarks::benchmark_helpers::BenchConfig;
+use crate::benchmarks::fifo_queue_benchmarks::bfs::should_terminate;
+use crate::error::Error;
+use crate::rng::Stream;
+use crate::traits::{ConcurrentQueue, HandleQueue};
+use crate::work::Worker;
+use core_affinity::CoreId;
+use log::{debug, error, info, trace};
+use rand::Rng;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Barrier;
+use std::time::{Duration, Instant};
+
+/// A recursive divide-and-conquer workload whose tasks fit in a `usize`.
+trait Workload: Sync {
+    /// The tasks the benchmark starts with.
+    fn initial_tasks(&self) -> Vec<usize>;
+    /// Runs `task`, passing the subtasks it creates to `spawn`.
+    /// Returns the task's share of the result.
+    fn run(&self, task: usize, spawn: &mut dyn FnMut(usize)) -> u64;
+    /// Checks the summed result of all tasks against the sequential solution.
+    fn verify(&self, result: u64) -> bool;
+}
+
+/// Naive recursive Fibonacci. The leaves sum up to fib(n).
+struct Fib {
+    n: u64,
+    cutoff: u64,
+}
+
+impl Fib {
+    fn sequential(n: u64) -> u64 {
+        if n < 2 {
+            n
+        } else {
+            Fib::sequential(n - 1) + Fib::sequential(n - 2)
+        }
+    }
+}
+
+impl Workload for Fib {
+    fn initial_tasks(&self) -> Vec<usize> {
+        vec![self.n as usize]
+    }
+
+    fn run(&self, task: usize, spawn: &mut dyn FnMut(usize)) -> u64 {
+        let n = task as u64;
+        if n <= self.cutoff {
+            return Fib::sequential(n);
+        }
+        spawn(task - 1);
+        spawn(task - 2);
+        0
+    }
+
+    fn verify(&self, result: u64) -> bool {
+        // Iterative Fibonacci as the sequential solution
+        let (mut a, mut b) = (0u64, 1u64);
+        for _ in 0..self.n {
+            (a, b) = (b, a + b);
+        }
+        result == a
+    }
+}
+
+/// Parallel quicksort of random numbers. A task is the range `lo..hi` of the
+/// array, packed into the upper and lower half of the `usize`, and its share
+/// of the result is the amount of elements it put in their final place.
+struct Quicksort {
+    data: Vec<AtomicU64>,
+    sorted: Vec<u64>,
+    cutoff: u64,
+}
+
+impl Quicksort {
+    fn new(size: u64, cutoff: u64, rng: &mut impl Rng) -> Self {
+        let input: Vec<u64> = (0..size).map(|_| rng.random()).collect();
+        let mut sorted = input.clone();
+        sorted.sort_unstable();
+        Quicksort {
+            data: input.into_iter().map(AtomicU64::new).collect(),
+            sorted,
+            cutoff: cutoff.max(1),
+        }
+    }
+
+    fn pack(lo: usize, hi: usize) -> usize {
+        (lo << 32) | hi
+    }
+
+    fn unpack(task: usize) -> (usize, usize) {
+        (task >> 32, task & 0xFFFF_FFFF)
+    }
+
+    /// Partitions `lo..hi` around its middle element and returns the
+    /// pivot's final index.
+    // Indexed since `swap` moves the elements behind `i`
+    #[allow(clippy::needless_range_loop)]
+    fn partition(&self, lo: usize, hi: usize) -> usize {
+        let data = &self.data;
+        let swap = |a: usize, b: usize| {
+            let tmp = data[a].load(Ordering::Relaxed);
+            data[a].store(data[b].load(Ordering::Relaxed), Ordering::Relaxed);
+            data[b].store(tmp, Ordering::Relaxed);
+        };
+        swap(lo + (hi - lo) / 2, hi - 1);
+        let pivot = data[hi - 1].load(Ordering::Relaxed);
+        let mut store = lo;
+        for i in lo..hi - 1 {
+            if data[i].load(Ordering::Relaxed) < pivot {
+                swap(i, store);
+                store += 1;
+            }
+        }
+        swap(store, hi - 1);
+        store
+    }
+}
+
+impl Workload for Quicksort {
+    fn initial_tasks(&self) -> Vec<usize> {
+        vec![Quicksort::pack(0, self.data.len())]
+    }
+
+    fn run(&self, task: usize, spawn: &mut dyn FnMut(usize)) -> u64 {
+        let (lo, hi) = Quicksort::unpack(task);
+        if (hi - lo) as u64 <= self.cutoff {
+            let mut part: Vec<u64> = self.data[lo..hi].iter().map(|v| v.load(Ordering::Relaxed)).collect();
+            part.sort_unstable();
+            for (slot, value) in self.data[lo..hi].iter().zip(part) {
+                slot.store(value, Ordering::Relaxed);
+            }
+            return (hi - lo) as u64;
+        }
+        let pivot = self.partition(lo, hi);
+        spawn(Quicksort::pack(lo, pivot));
+        spawn(Quicksort::pack(pivot + 1, hi));
+        1
+    }
+
+    fn verify(&self, result: u64) -> bool {
+        result == self.data.len() as u64
+            && self.data.iter().zip(&self.sorted).all(|(v, s)| v.load(Ordering::Relaxed) == *s)
+    }
+}
+
+/// Unbalanced tree search on a binomial tree. The root has `root_children`
+/// children, every other node has `m` children with probability `q`. A node
+/// is identified by a hash that decides its children, so the tree is the same
+/// every time it is searched. The result is the amount of nodes.
+struct Uts {
+    root: u64,
+    root_children: u64,
+    q: f64,
+    m: usize,
+    nodes: u64,
+}
+
+impl Uts {
+    fn new(root_children: u64, q: f64, m: usize, rng: &mut impl Rng) -> Self {
+        let mut uts = Uts { root: rng.random(), root_children, q, m, nodes: 0 };
+        // Count the nodes sequentially for the verification
+        let mut stack = uts.initial_tasks();
+        let mut nodes = 1;
+        while let Some(node) = stack.pop() {
+            nodes += uts.run(node, &mut |child| stack.push(child));
+        }
+        uts.nodes = nodes;
+        uts
+    }
+
+    /// SplitMix64, used to derive the children of a node.
+    fn hash(x: u64) -> u64 {
+        let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn child(node: u64, index: u64) -> u64 {
+        Uts::hash(node ^ Uts::hash(index))
+    }
+}
+
+impl Workload for Uts {
+    fn initial_tasks(&self) -> Vec<usize> {
+        (0..self.root_children).map(|i| Uts::child(self.root, i) as usize).collect()
+    }
+
+    fn run(&self, task: usize, spawn: &mut dyn FnMut(usize)) -> u64 {
+        let node = task as u64;
+        // The top 53 bits of the hash as a uniform number in [0, 1)
+        let roll = (Uts::hash(node) >> 11) as f64 / (1u64 << 53) as f64;
+        if roll < self.q {
+            for i in 0..self.m as u64 {
+                spawn(Uts::child(node, i) as usize);
+            }
+        }
+        1
+    }
+
+    fn verify(&self, result: u64) -> bool {
+        // The root is not a task
+        result + 1 == self.nodes
+    }
+}
+
+/// Creates the workload, including the solution to verify against. Random
+/// inputs are drawn from `rng`.
+fn create_workload(args: &FifoQueueTasksArgs, rng: &mut impl Rng) -> Result<Box<dyn Workload>, Error> {
+    let invalid = |msg: &str| Err(Error::Config(msg.to_string()));
+    match args.workload {
+        TaskWorkload::Fib => {
+            let n = args.problem_size();
+            if n > 90 {
+                return invalid("fib only supports --size up to 90");
+            }
+            Ok(Box::new(Fib { n, cutoff: args.cutoff.unwrap_or(15).max(1) }))
+        }
+        TaskWorkload::Quicksort => {
+            let size = args.problem_size();
+            if usize::BITS < 64 || size >= 1 << 32 {
+                return invalid("quicksort needs a 64-bit platform and a --size below 2^32");
+            }
+            Ok(Box::new(Quicksort::new(size, args.cutoff.unwrap_or(1000), rng)))
+        }
+        TaskWorkload::Uts => {
+            if !(0.0..=1.0).contains(&args.uts_q) || args.uts_q * args.uts_m as f64 >= 1.0 {
+                return invalid("uts needs --uts-q between 0 and 1 and --uts-q * --uts-m below 1 for the tree to be finite");
+            }
+            Ok(Box::new(Uts::new(args.problem_size(), args.uts_q, args.uts_m, rng)))
+        }
+    }
+}
+
+/// # Explanation:
+/// A task-parallel benchmark using the queue as the shared task pool of a
+/// scheduler. Threads pop a task, run it and push the subtasks it creates,
+/// until every thread is out of work, detected the same way as in the BFS
+/// benchmark. A subtask that can't be pushed because the queue is full is
+/// kept by the thread and run by itself. The result is verified against a
+/// sequential solution computed before the run.
+/// Workloads:
+/// * fib        Naive recursive Fibonacci
+/// * quicksort  Parallel quicksort of random numbers
+/// * uts        Unbalanced tree search on a binomial tree
+///
+/// Benchmark specific flags:
+/// * --thread-count    Set the amount of threads
+/// * --workload        Set the workload
+/// * --size            Set the problem size
+/// * --cutoff          Set the size below which fib and quicksort run sequentially
+/// * --uts-q, --uts-m  Set the shape of the uts tree
+#[allow(dead_code)]
+pub fn benchmark_tasks<C>(
+    cqueue: C,
+    bench_conf: &BenchConfig,
+    fifo_queue_args: &FifoQueueArgs,
+) -> Result<(), Error>
+where
+    C: ConcurrentQueue<usize>,
+    for<'a> &'a C: Send,
+{
+    // Extract specific arguments for this benchmark runner
+    let tasks_args = match &fifo_queue_args.benchmark_runner {
+        FifoQueueBenchmarks::Tasks(a) => a,
+        _ => panic!(
+            "benchmark_tasks called with another FIFO Queue \
+            configured. This is an implementation error."
+        ),
+    };
+    let thread_count = tasks_args.thread_count;
+    debug!("Creating {} workload", tasks_args.workload);
+    let workload = create_workload(tasks_args, &mut bench_conf.thread_rng(Stream::Tasks, 0))?;
+    let workload = workload.as_ref();
+
+    // Tasks that don't fit in the queue are kept by the thread pushing them
+    let mut leftover = vec![];
+    {
+        let mut handle = cqueue.register();
+        for task in workload.initial_tasks() {
+            if let Err(task) = handle.push(task) {
+                leftover.push(task);
+            }
+        }
+    }
+
+    let idle_count = AtomicUsize::new(0);
+    let no_work_count = AtomicUsize::new(0);
+    let tasks = AtomicUsize::new(0);
+    let result = AtomicU64::new(0);
+    let backoffs = AtomicUsize::new(0);
+    let barrier = Barrier::new(thread_count + 1);
+    info!("Starting tasks benchmark with {} threads", thread_count);
+
+    // Get cores for fairness of threads
+    let available_cores: Vec<CoreId> =
+        core_affinity::get_core_ids().unwrap_or(vec![CoreId { id: 0 }]);
+    let mut core_iter = available_cores.into_iter().cycle();
+
+    let duration = std::thread::scope(|s| -> Result<Duration, Error> {
+        let queue = &cqueue;
+        let idle_count = &idle_count;
+        let no_work_count = &no_work_count;
+        let tasks = &tasks;
+        let result = &result;
+        let backoffs = &backoffs;
+        let barrier = &barrier;
+        let is_one_socket = &bench_conf.args.one_socket;
+        let mut handles = vec![];
+        for i in 0..thread_count {
+            let mut core: CoreId = core_iter.next().unwrap();
+            // if is_one_socket is true, make all thread ids even
+            // (this was used for our testing enviroment to get one socket)
+            if *is_one_socket {
+                core = core_iter.next().unwrap();
+            }
+            trace!("Thread: {} Core: {:?}", i, core);
+            // The first thread starts with the tasks that didn't fit
+            let local = if i == 0 { std::mem::take(&mut leftover) } else { vec![] };
+            handles.push(s.spawn(move || {
+                core_affinity::set_for_current(core);
+                let mut handle = queue.register();
+                let mut backoff = Backoff::from_args(&bench_conf.args);
+                let mut worker = Worker::from_args(&bench_conf.args, i);
+                let mut local = local;
+                let mut l_tasks = 0;
+                let mut l_result = 0;
+                barrier.wait();
+                loop {
+                    let task = match local.pop().or_else(|| handle.pop()) {
+                        Some(task) => task,
+                        None => {
+                            trace!("Thread: {i}; Did not acquire any work");
+                            no_work_count.fetch_add(1, Ordering::Relaxed);
+                            let task = loop {
+                                if let Some(task) = handle.pop() {
+                                    backoff.reset();
+                                    break Some(task);
+                                }
+                                if no_work_count.load(Ordering::Relaxed) >= thread_count
+                                    && should_terminate(idle_count, no_work_count, thread_count)
+                                {
+                                    break None;
+                                }
+                                backoff.backoff();
+                            };
+                            match task {
+                                Some(task) => {
+                                    no_work_count.fetch_sub(1, Ordering::Relaxed);
+                                    task
+                                }
+                                None => break,
+                            }
+                        }
+                    };
+                    l_result += workload.run(task, &mut |subtask| {
+                        if let Err(subtask) = handle.push(subtask) {
+                            local.push(subtask);
+                        }
+                    });
+                    l_tasks += 1;
+                    worker.think();
+                }
+                tasks.fetch_add(l_tasks, Ordering::Relaxed);
+                result.fetch_add(l_result, Ordering::Relaxed);
+                backoffs.fetch_add(backoff.count(), Ordering::Relaxed);
+            }));
+        }
+        barrier.wait();
+        let start = Instant::now();
+        for (i, handle) in handles.into_iter().enumerate() {
+            if handle.join().is_err() {
+                return Err(Error::ThreadPanic(format!("task thread {i} panicked")));
+            }
+        }
+        Ok(start.elapsed())
+    })?;
+    debug!("Tasks done. Took {:?}.", duration);
+
+    let tasks = tasks.into_inner();
+    let verified = workload.verify(result.into_inner());
+    if !verified {
+        error!("Parallel {} solution arrived at wrong answer.", tasks_args.workload);
+    }
+    let formatted = format!(
+        "{},{},{},{},{},{},{},{},{},{},{},{}",
+        duration.as_millis(),
+        cqueue.get_id(),
+        fifo_queue_args.benchmark_runner,
+        bench_conf.benchmark_id,
+        thread_count,
+        tasks_args.workload,
+        tasks_args.problem_size(),
+        tasks,
+        tasks as f64 / duration.as_secs_f64(),
+        verified,
+        backoffs.into_inner(),
+        bench_conf.seed()
+    );
+    if !bench_conf.args.write_to_stdout {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&bench_conf.output_filename)?;
+
+        writeln!(file, "{}", formatted)?;
+    } else {
+        println!("{}", formatted);
+    }
+
+    if !verified {
+        return Err(Error::Verification(format!(
+            "the parallel {} solution doesn't match the sequential one",
+            tasks_args.workload
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::benchmarks::test_helpers::bench_conf;
+
+    use crate::benchmarks::test_helpers::test_queue::TestQueue;
+
+    fn tasks_args(workload: TaskWorkload, size: u64, cutoff: u64) -> FifoQueueTasksArgs {
+        FifoQueueTasksArgs {
+            thread_count: 4,
+            workload,
+            size: Some(size),
+            cutoff: Some(cutoff),
+            uts_q: 0.2,
+            uts_m: 4,
+        }
+    }
+
+    /// Runs a workload sequentially the way the threads do.
+    fn run_sequential(workload: &dyn Workload) -> u64 {
+        let mut stack = workload.initial_tasks();
+        let mut result = 0;
+        while let Some(task) = stack.pop() {
+            result += workload.run(task, &mut |subtask| stack.push(subtask));
+        }
+        result
+    }
+
+    #[test]
+    fn workloads_verify() {
+        for args in [
+            tasks_args(TaskWorkload::Fib, 20, 5),
+            tasks_args(TaskWorkload::Quicksort, 10_000, 16),
+            tasks_args(TaskWorkload::Uts, 100, 0),
+        ] {
+            let workload = create_workload(&args, &mut crate::rng::thread_rng(0, Stream::Tasks, 0)).unwrap();
+            let result = run_sequential(workload.as_ref());
+            assert!(workload.verify(result), "{} failed", args.workload);
+            assert!(!workload.verify(result + 1));
+        }
+    }
+
+    #[test]
+    fn rejects_infinite_uts() {
+        let mut args = tasks_args(TaskWorkload::Uts, 100, 0);
+        args.uts_q = 0.5;
+        assert!(matches!(create_workload(&args, &mut crate::rng::thread_rng(0, Stream::Tasks, 0)), Err(Error::Config(_))));
+    }
+
+    #[test]
+    fn run_tasks() {
+        for workload in [TaskWorkload::Fib, TaskWorkload::Quicksort, TaskWorkload::Uts] {
+            let size = match workload {
+                TaskWorkload::Fib => 20,
+                TaskWorkload::Quicksort => 100_000,
+                TaskWorkload::Uts => 500,
+            };
+            let fifo_queue_args = FifoQueueArgs {
+                benchmark_runner: FifoQueueBenchmarks::Tasks(tasks_args(workload, size, 8)),
+                ..Default::default()
+            };
+            let bench_conf = bench_conf(&fifo_queue_args);
+            let queue: TestQueue<usize> = TestQueue::new(0);
+            if benchmark_tasks(queue, &bench_conf, &fifo_queue_args).is_err() {
+                panic!();
+            }
+        }
+    }
+}
@@ -3,6 +3,7 @@ use crate::arguments::{
     GeneralArgs, PriorityQueueArgs, PriorityQueueBenchmarks,
 };
 use crate::benchmarks::benchmark_helpers::{self, BenchConfig};
+use crate::error::Error;
 #[allow(unused_imports)]
 use crate::traits::{ConcurrentPriorityQueue, HandlePriorityQueue};
 use clap::Parser;
@@ -16,7 +17,7 @@ pub mod prod_con;
 /// Create the queue, and run the selected benchmark a set of times
 pub fn benchmark_priority_queue<Q, T>(
     queue_name: &str,
-) -> Result<(), std::io::Error>
+) -> Result<(), Error>
 where
     Q: ConcurrentPriorityQueue<usize, T> + Send,
     T: Default,
@@ -34,7 +35,7 @@ where
         dyn FnMut(
             Q,
             &benchmark_helpers::BenchConfig,
-        ) -> Result<(), std::io::Error>,
+        ) -> Result<(), Error>,
     > = match &pq_args.benchmark_runner {
         PriorityQueueBenchmarks::ProdCon(_) => {
             Box::new(move |q, bench_conf| {
@@ -45,7 +46,7 @@ where
 
     for _current_iteration in 0..bench_conf.args.iterations {
         // Create the queue.
-        let test_q: Q = Q::new(pq_args.queue_size as usize);
+        let test_q: Q = Q::try_new(pq_args.queue_size as usize)?;
 
         // Start memory tracking (if enabled)
         #[cfg(feature = "memory_tracking")]
@@ -98,7 +99,7 @@ pub fn setup_benchmark(
         benchmark_helpers::create_bench_config(&args.general_args)?;
 
     let columns = "Throughput,Enqueues,Dequeues,Consumers,Producers,\
-        Thread Count,Queuetype,Benchmark,Test ID,Fairness,Spread,Queue Size";
+        Thread Count,Queuetype,Benchmark,Test ID,Fairness,Spread,Queue Size,Backoffs,Mean Think Time,Seed";
 
     benchmark_helpers::output_result_header(
         columns.to_string(),
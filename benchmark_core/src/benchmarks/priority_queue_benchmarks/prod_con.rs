@@ -1,25 +1,27 @@
 use core_affinity::CoreId;
 use log::{debug, error, info, trace};
-use rand::Rng;
 use crate::arguments::{PriorityQueueArgs, PriorityQueueBenchmarks};
+use crate::backoff::Backoff;
+use crate::work::{self, Worker};
+use crate::error::Error;
 use crate::traits::{ConcurrentPriorityQueue, HandlePriorityQueue};
 use crate::benchmarks::benchmark_helpers::{self, BenchConfig};
 use std::fs::OpenOptions;
 use std::io::Write;
-use std::sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, Barrier};
+use std::sync::{atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering}, Barrier};
 use std::sync::{mpsc, Arc};
 
 // TODO(emilbjornlinger): Change benchmark to something reasonable
 
 /// # Explanation:
 /// A simple benchmark that measures the throughput of a queue.
-/// Has by default a 10 floating points generated delay between each operation, but this can be changed
+/// Threads by default think for 100 ns between each operation, but this can be changed
 /// through flags passed to the program.
 /// Benchmark specific flags:
 /// * -p        Set specified amount of producers
 /// * -c        Set specified amount of consumers
 #[allow(dead_code)]
-pub fn benchmark_prod_con<C, P, T>(cqueue: C, bench_conf: &BenchConfig, pq_args: &PriorityQueueArgs) -> Result<(), std::io::Error>
+pub fn benchmark_prod_con<C, P, T>(cqueue: C, bench_conf: &BenchConfig, pq_args: &PriorityQueueArgs) -> Result<(), Error>
 where 
     C: ConcurrentPriorityQueue<P, T>,
     P: Ord + From<usize>,
@@ -46,6 +48,9 @@ where
     let barrier = Barrier::new(consumers + producers + 1);
     let pops  = AtomicUsize::new(0);
     let pushes = AtomicUsize::new(0);
+    let backoffs = AtomicUsize::new(0);
+    let think_time = AtomicU64::new(0);
+    let thinks = AtomicUsize::new(0);
     let done = AtomicBool::new(false);
     let (tx, rx) = mpsc::channel();
     info!("Starting throughput benchmark with {} consumer and {} producers", consumers, producers);
@@ -61,6 +66,9 @@ where
     let _ = std::thread::scope(|s| -> Result<(), std::io::Error>{
         let queue = &cqueue;
         let pushes = &pushes;
+        let backoffs = &backoffs;
+        let think_time = &think_time;
+        let thinks = &thinks;
         let pops = &pops;
         let done = &done;
         let barrier = &barrier;
@@ -82,20 +90,26 @@ where
                 let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
                 core_affinity::set_for_current(core);
                 let mut handle = queue.register();
+                let mut backoff = Backoff::from_args(&bench_conf.args);
+                let mut worker = Worker::from_args(&bench_conf.args, i);
                 // push
                 let mut l_pushes= 0; 
                 let _thread_failed = thread_failed.clone(); // Every thread clones the thread_failed bool
                 barrier.wait();
                 while !done.load(Ordering::Relaxed) {
                     // NOTE: Maybe we should care about this result?
-                    let _ = handle.insert(P::from(l_pushes), T::default());
-                    l_pushes += 1;
-                    // Add some delay to simulate real workload
-                    for _ in 0..bench_conf.args.delay {
-                        let _some_num = rand::rng().random::<f64>();
+                    match handle.insert(P::from(l_pushes), T::default()) {
+                        Ok(_) => backoff.reset(),
+                        Err(_) => backoff.backoff(),
                     }
+                    l_pushes += 1;
+                    // Simulate work between operations
+                    worker.think();
                 }
                 pushes.fetch_add(l_pushes, Ordering::Relaxed);
+                backoffs.fetch_add(backoff.count(), Ordering::Relaxed);
+                think_time.fetch_add(worker.total_think_time().as_nanos() as u64, Ordering::Relaxed);
+                thinks.fetch_add(worker.thinks(), Ordering::Relaxed);
                 // Thread sends its total operations down the channel for fairness calculations
                 if let Err(e) = tx.send(l_pushes) {
                     error!("Error sending operations down the channel: {}", e);
@@ -122,6 +136,8 @@ where
                 let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
                 core_affinity::set_for_current(core);
                 let mut handle = queue.register();
+                let mut backoff = Backoff::from_args(&bench_conf.args);
+                let mut worker = Worker::from_args(&bench_conf.args, producers + i);
                 // pop
                 let mut l_pops = 0; 
                 let mut empty_pops = 0;
@@ -130,19 +146,24 @@ where
                 // TODO: add empty pops probably to fairness calculations
                 while !done.load(Ordering::Relaxed) {
                     match handle.delete_min() {
-                        Some(_) => l_pops += 1,
+                        Some(_) => {
+                            l_pops += 1;
+                            backoff.reset();
+                        }
                         None => {
                             // if bench_conf.args.empty_pops {
                             //     l_pops += 1;
                             // }
                             empty_pops += 1;
+                            backoff.backoff();
                         }
                     }
-                    for _ in 0..bench_conf.args.delay {
-                        let _some_num = rand::rng().random::<f64>();
-                    }
+                    worker.think();
                 }
                 pops.fetch_add(l_pops, Ordering::Relaxed);
+                backoffs.fetch_add(backoff.count(), Ordering::Relaxed);
+                think_time.fetch_add(worker.total_think_time().as_nanos() as u64, Ordering::Relaxed);
+                thinks.fetch_add(worker.thinks(), Ordering::Relaxed);
                 // Thread sends its total operations down the channel for fairness calculations
                 if let Err(e) = tx.send(l_pops + empty_pops) {
                     error!("Error sending operations down the channel: {}", e);
@@ -167,6 +188,8 @@ where
     debug!("TX Dropped");
     let pops = pops.into_inner();
     let pushes = pushes.into_inner();
+    let backoffs = backoffs.into_inner();
+    let mean_think_time = work::mean_think_time(think_time.into_inner(), thinks.into_inner());
 
     // Fairness
     // Get total operations per thread
@@ -179,11 +202,11 @@ where
     };
     // If a thread crashed, pad the results with zero-values 
     let formatted = if thread_failed.load(Ordering::Relaxed) {
-        format!("0,0,0,{},{},-1,{},{},{},0,-1,{}", producers, consumers, cqueue.get_id(), pq_args.benchmark_runner, bench_conf.benchmark_id, pq_args.queue_size)
+        format!("0,0,0,{},{},-1,{},{},{},0,-1,{},0,0,{}", producers, consumers, cqueue.get_id(), pq_args.benchmark_runner, bench_conf.benchmark_id, pq_args.queue_size, bench_conf.seed())
     }
     else {
         let fairness = benchmark_helpers::calc_fairness(ops_per_thread);
-        format!("{},{},{},{},{},{},{},{},{},{},{},{}",
+        format!("{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
             (pushes + pops) as f64 / time_limit as f64,
             pushes,
             pops,
@@ -195,7 +218,9 @@ where
             bench_conf.benchmark_id,
             fairness,
             -1,
-            pq_args.queue_size)
+            pq_args.queue_size,
+            backoffs,
+            mean_think_time, bench_conf.seed())
     };
     if !bench_conf.args.write_to_stdout {
         let mut file = OpenOptions::new()
@@ -209,6 +234,12 @@ where
         println!("{}", formatted);
     }
 
+    if thread_failed.load(Ordering::Relaxed) {
+        return Err(Error::ThreadPanic(format!(
+            "a {} thread panicked, the results were padded with zeros",
+            pq_args.benchmark_runner
+        )));
+    }
     Ok(())
 }
 
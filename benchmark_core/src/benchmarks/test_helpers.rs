@@ -1,12 +1,35 @@
-/// A simple ConcurrentQueue implementation for testing
+#[cfg(test)]
+use crate::arguments::FifoQueueArgs;
+#[cfg(test)]
+use crate::benchmarks::benchmark_helpers::BenchConfig;
+
+/// Configuration for running a FIFO queue benchmark in a test, with the
+/// general arguments of `fifo_queue_args`
+#[cfg(test)]
+pub(crate) fn bench_conf(fifo_queue_args: &FifoQueueArgs) -> BenchConfig {
+    BenchConfig {
+        args: fifo_queue_args.general_args.clone(),
+        date_time: "".to_string(),
+        benchmark_id: format!("test_{}", fifo_queue_args.benchmark_runner),
+        output_filename: "".to_string(),
+    }
+}
+
+/// A simple ConcurrentQueue implementation for testing, also supporting the
+/// blocking API
 #[cfg(test)]
 pub(crate) mod test_queue {
-    use crate::traits::{ConcurrentQueue, HandleQueue};
+    use crate::traits::{
+        BlockingConcurrentQueue, BlockingHandleQueue, ConcurrentQueue,
+        HandleQueue,
+    };
     use std::collections::VecDeque;
-    use std::sync::Mutex;
+    use std::sync::{Condvar, Mutex};
+    use std::time::Duration;
 
     pub struct TestQueue<T> {
-        queue: Mutex<VecDeque<T>>,
+        queue: Mutex<(VecDeque<T>, bool)>,
+        available: Condvar,
     }
 
     pub struct TestQueueHandle<'a, T> {
@@ -15,12 +38,46 @@ pub(crate) mod test_queue {
 
     impl<T> HandleQueue<T> for TestQueueHandle<'_, T> {
         fn push(&mut self, item: T) -> Result<(), T> {
-            self.queue.queue.lock().unwrap().push_back(item);
+            let mut guard = self.queue.queue.lock().unwrap();
+            if guard.1 {
+                return Err(item);
+            }
+            guard.0.push_back(item);
+            self.queue.available.notify_one();
             Ok(())
         }
 
         fn pop(&mut self) -> Option<T> {
-            self.queue.queue.lock().unwrap().pop_front()
+            self.queue.queue.lock().unwrap().0.pop_front()
+        }
+    }
+
+    impl<T> BlockingHandleQueue<T> for TestQueueHandle<'_, T> {
+        fn pop_blocking(&mut self) -> Option<T> {
+            let guard = self.queue.queue.lock().unwrap();
+            let mut guard = self
+                .queue
+                .available
+                .wait_while(guard, |(q, closed)| q.is_empty() && !*closed)
+                .unwrap();
+            guard.0.pop_front()
+        }
+
+        fn pop_timeout(&mut self, timeout: Duration) -> Option<T> {
+            let guard = self.queue.queue.lock().unwrap();
+            let (mut guard, _) = self
+                .queue
+                .available
+                .wait_timeout_while(guard, timeout, |(q, closed)| {
+                    q.is_empty() && !*closed
+                })
+                .unwrap();
+            guard.0.pop_front()
+        }
+
+        fn close(&mut self) {
+            self.queue.queue.lock().unwrap().1 = true;
+            self.queue.available.notify_all();
         }
     }
 
@@ -35,10 +92,17 @@ pub(crate) mod test_queue {
 
         fn new(_size: usize) -> Self {
             TestQueue {
-                queue: Mutex::new(VecDeque::new()),
+                queue: Mutex::new((VecDeque::new(), false)),
+                available: Condvar::new(),
             }
         }
     }
+
+    impl<T> BlockingConcurrentQueue<T> for TestQueue<T> {
+        fn register_blocking(&self) -> impl BlockingHandleQueue<T> {
+            TestQueueHandle { queue: self }
+        }
+    }
 }
 
 /// A very simple ConcurrentPriorityQueue implementation for testing
@@ -0,0 +1,581 @@
+//! Checks that every FIFO queue should pass, run through the
+//! `queue_conformance_tests!` macro from the tests of each queue crate.
+//! The checks panic with a description of what the queue got wrong.
+
+use crate::traits::{ConcurrentQueue, HandleQueue};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Size the queues are created with.
+pub const QUEUE_SIZE: usize = 1024;
+/// Size used for the capacity check, small enough to fill the queue quickly.
+pub const SMALL_QUEUE_SIZE: usize = 16;
+/// Items every producer pushes in the concurrent checks.
+pub const ITEMS_PER_PRODUCER: usize = 5_000;
+/// The concurrent checks fail if they haven't seen every item by then.
+pub const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Names of the checks, which can be passed to the `skip` list of the macro.
+pub const CHECKS: &[&str] = &[
+    "sequential_fifo",
+    "empty_pop",
+    "bounded_capacity",
+    "producer_order",
+    "conservation",
+    "drops_remaining_once",
+    "drop_safety",
+];
+
+/// Generates a `conformance` test module for a FIFO queue type.
+/// The queue type is given without its item type, the checks use `usize`
+/// items, and `TrackedItem`s to count drops.
+///
+/// Queues that only hold `Copy` items can't have their drops counted, they
+/// pass `copy_items` to leave those checks out. Checks a queue knowingly
+/// fails are listed in `skip` with the reason, they are generated as
+/// ignored tests that still show up in the results and run with
+/// `cargo test -- --ignored`.
+/// ```ignore
+/// benchmark_core::queue_conformance_tests!(SQueue);
+/// benchmark_core::queue_conformance_tests!(SCCQueue, copy_items);
+/// benchmark_core::queue_conformance_tests!(
+///     BBQueue,
+///     skip: [bounded_capacity],
+///     reason: "pushes to a full queue block until there is room"
+/// );
+/// ```
+#[macro_export]
+macro_rules! queue_conformance_tests {
+    (@test $name:ident, $item:ty, $queue:ident) => {
+        $crate::conformance::$name::<$queue<$item>>();
+    };
+    // A check that is in the skip list is ignored, the list is searched by
+    // matching its first name against every check
+    (@check sequential_fifo, $item:ty, $queue:ident, [sequential_fifo $(, $rest:ident)*], $reason:literal) => {
+        $crate::queue_conformance_tests!(@ignored sequential_fifo, $item, $queue, $reason);
+    };
+    (@check empty_pop, $item:ty, $queue:ident, [empty_pop $(, $rest:ident)*], $reason:literal) => {
+        $crate::queue_conformance_tests!(@ignored empty_pop, $item, $queue, $reason);
+    };
+    (@check bounded_capacity, $item:ty, $queue:ident, [bounded_capacity $(, $rest:ident)*], $reason:literal) => {
+        $crate::queue_conformance_tests!(@ignored bounded_capacity, $item, $queue, $reason);
+    };
+    (@check producer_order, $item:ty, $queue:ident, [producer_order $(, $rest:ident)*], $reason:literal) => {
+        $crate::queue_conformance_tests!(@ignored producer_order, $item, $queue, $reason);
+    };
+    (@check conservation, $item:ty, $queue:ident, [conservation $(, $rest:ident)*], $reason:literal) => {
+        $crate::queue_conformance_tests!(@ignored conservation, $item, $queue, $reason);
+    };
+    (@check drops_remaining_once, $item:ty, $queue:ident, [drops_remaining_once $(, $rest:ident)*], $reason:literal) => {
+        $crate::queue_conformance_tests!(@ignored drops_remaining_once, $item, $queue, $reason);
+    };
+    (@check drop_safety, $item:ty, $queue:ident, [drop_safety $(, $rest:ident)*], $reason:literal) => {
+        $crate::queue_conformance_tests!(@ignored drop_safety, $item, $queue, $reason);
+    };
+    (@check $name:ident, $item:ty, $queue:ident, [$first:ident $(, $rest:ident)*], $reason:literal) => {
+        $crate::queue_conformance_tests!(@check $name, $item, $queue, [$($rest),*], $reason);
+    };
+    (@check $name:ident, $item:ty, $queue:ident, [], $reason:literal) => {
+        #[test]
+        fn $name() {
+            $crate::queue_conformance_tests!(@test $name, $item, $queue);
+        }
+    };
+    (@ignored $name:ident, $item:ty, $queue:ident, $reason:literal) => {
+        #[test]
+        #[ignore = $reason]
+        fn $name() {
+            $crate::queue_conformance_tests!(@test $name, $item, $queue);
+        }
+    };
+    (@names [$($skip:ident),*]) => {
+        &[$(stringify!($skip)),*]
+    };
+    (@suite $queue:ident, $skip:tt, $reason:literal, $($drops:ident),*) => {
+        #[cfg(test)]
+        mod conformance {
+            #[allow(unused_imports)]
+            use super::*;
+
+            const SKIP: &[&str] = $crate::queue_conformance_tests!(@names $skip);
+
+            #[test]
+            fn skip_list_is_valid() {
+                for name in SKIP {
+                    assert!(
+                        $crate::conformance::CHECKS.contains(name),
+                        "{} is not a conformance check",
+                        name
+                    );
+                }
+            }
+
+            $crate::queue_conformance_tests!(@check sequential_fifo, usize, $queue, $skip, $reason);
+            $crate::queue_conformance_tests!(@check empty_pop, usize, $queue, $skip, $reason);
+            $crate::queue_conformance_tests!(@check bounded_capacity, usize, $queue, $skip, $reason);
+            $crate::queue_conformance_tests!(@check producer_order, usize, $queue, $skip, $reason);
+            $crate::queue_conformance_tests!(@check conservation, usize, $queue, $skip, $reason);
+            $(
+                $crate::queue_conformance_tests!(
+                    @check $drops, $crate::conformance::TrackedItem, $queue, $skip, $reason
+                );
+            )*
+        }
+    };
+    ($queue:ident) => {
+        $crate::queue_conformance_tests!(@suite $queue, [], "", drops_remaining_once, drop_safety);
+    };
+    ($queue:ident, skip: [$($skip:ident),+ $(,)?], reason: $reason:literal $(,)?) => {
+        $crate::queue_conformance_tests!(
+            @suite $queue, [$($skip),+], $reason, drops_remaining_once, drop_safety
+        );
+    };
+    ($queue:ident, copy_items) => {
+        $crate::queue_conformance_tests!(@suite $queue, [], "",);
+    };
+    ($queue:ident, copy_items, skip: [$($skip:ident),+ $(,)?], reason: $reason:literal $(,)?) => {
+        $crate::queue_conformance_tests!(@suite $queue, [$($skip),+], $reason,);
+    };
+}
+
+/// Written to every live `TrackedItem`.
+const ALIVE: u64 = 0x7472_6163_6b65_6421;
+/// Written to a `TrackedItem` when it is dropped.
+const DEAD: u64 = 0x6465_6164_6465_6164;
+
+static CONSTRUCTED: AtomicUsize = AtomicUsize::new(0);
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+static BAD_DROPS: AtomicUsize = AtomicUsize::new(0);
+static TRACKING: Mutex<()> = Mutex::new(());
+
+/// Item that counts its constructions and drops in global counters and
+/// carries a canary, so that a queue handing out a dropped item or dropping
+/// an item twice can be caught.
+/// Default items aren't counted, they are only there for queues that need
+/// to fill their slots with something, but their canary is still checked.
+#[derive(Debug)]
+pub struct TrackedItem {
+    pub id: usize,
+    canary: u64,
+    counted: bool,
+}
+
+impl TrackedItem {
+    pub fn new(id: usize) -> Self {
+        CONSTRUCTED.fetch_add(1, Ordering::SeqCst);
+        TrackedItem {
+            id,
+            canary: ALIVE,
+            counted: true,
+        }
+    }
+
+    /// Panics if the item has already been dropped.
+    pub fn assert_alive(&self) {
+        // Volatile, since reading a dropped item is what is being tested
+        let canary = unsafe { std::ptr::read_volatile(&self.canary) };
+        assert!(canary != DEAD, "item {} was used after it was dropped", self.id);
+        assert!(canary == ALIVE, "item {} is not a valid item", self.id);
+    }
+}
+
+impl Default for TrackedItem {
+    fn default() -> Self {
+        TrackedItem {
+            id: usize::MAX,
+            canary: ALIVE,
+            counted: false,
+        }
+    }
+}
+
+impl Drop for TrackedItem {
+    fn drop(&mut self) {
+        let canary = unsafe { std::ptr::read_volatile(&self.canary) };
+        if canary != ALIVE {
+            // Don't panic, the drop might be running in the drop of a queue
+            BAD_DROPS.fetch_add(1, Ordering::SeqCst);
+            return;
+        }
+        // Volatile, the write would otherwise be removed as the item is gone
+        unsafe { std::ptr::write_volatile(&mut self.canary, DEAD) };
+        if self.counted {
+            DROPPED.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Reads the `TrackedItem` counters relative to when it was started.
+/// The counters are global, so only one `Tracking` exists at a time, others
+/// wait in `start` until it is dropped.
+pub struct Tracking {
+    _guard: MutexGuard<'static, ()>,
+    constructed: usize,
+    dropped: usize,
+    bad_drops: usize,
+}
+
+impl Tracking {
+    pub fn start() -> Self {
+        // A failed check poisons the lock, which doesn't matter for the next
+        let guard = TRACKING.lock().unwrap_or_else(|e| e.into_inner());
+        Tracking {
+            _guard: guard,
+            constructed: CONSTRUCTED.load(Ordering::SeqCst),
+            dropped: DROPPED.load(Ordering::SeqCst),
+            bad_drops: BAD_DROPS.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Items created since the start.
+    pub fn constructed(&self) -> usize {
+        CONSTRUCTED.load(Ordering::SeqCst) - self.constructed
+    }
+
+    /// Items dropped since the start.
+    pub fn dropped(&self) -> usize {
+        DROPPED.load(Ordering::SeqCst) - self.dropped
+    }
+
+    /// Drops of items that were already dropped, or never were items.
+    pub fn bad_drops(&self) -> usize {
+        BAD_DROPS.load(Ordering::SeqCst) - self.bad_drops
+    }
+
+    /// Items created since the start that are still alive.
+    pub fn live(&self) -> usize {
+        self.constructed().saturating_sub(self.dropped())
+    }
+
+    /// Panics if an item was dropped twice, or if any item created since
+    /// the start is still alive.
+    pub fn assert_all_dropped_once(&self) {
+        assert_eq!(self.bad_drops(), 0, "items were dropped twice");
+        assert_eq!(
+            self.dropped(),
+            self.constructed(),
+            "{} items were never dropped",
+            self.live()
+        );
+    }
+}
+
+/// Items pushed by one thread come out in the same order, also when the
+/// queue wraps around several times.
+pub fn sequential_fifo<Q>()
+where
+    Q: ConcurrentQueue<usize>,
+{
+    let queue = Q::new(QUEUE_SIZE);
+    let batch = queue.capacity().unwrap_or(QUEUE_SIZE).min(100);
+    let mut handle = queue.register();
+    let mut next_push = 0;
+    let mut next_pop = 0;
+    for round in 0..10 {
+        // Alternate between filling the batch and interleaving the operations
+        for _ in 0..batch {
+            assert!(handle.push(next_push).is_ok(), "push {} failed below the capacity", next_push);
+            next_push += 1;
+            if round % 2 == 1 {
+                assert_eq!(handle.pop(), Some(next_pop), "items came out of order");
+                next_pop += 1;
+            }
+        }
+        while next_pop < next_push {
+            assert_eq!(handle.pop(), Some(next_pop), "items came out of order");
+            next_pop += 1;
+        }
+        assert_eq!(handle.pop(), None, "popped an item that was never pushed");
+    }
+}
+
+/// Popping an empty queue returns `None`, both before anything has been
+/// pushed and after the queue has been emptied.
+pub fn empty_pop<Q>()
+where
+    Q: ConcurrentQueue<usize>,
+{
+    let queue = Q::new(QUEUE_SIZE);
+    let mut handle = queue.register();
+    assert_eq!(handle.pop(), None, "a new queue is not empty");
+    assert_eq!(handle.pop(), None, "popping an empty queue changed it");
+    assert!(handle.push(7).is_ok());
+    assert_eq!(handle.pop(), Some(7));
+    assert_eq!(handle.pop(), None, "the emptied queue is not empty");
+    // A second handle sees the same empty queue
+    let mut other = queue.register();
+    assert_eq!(other.pop(), None, "another handle popped from an empty queue");
+}
+
+/// A bounded queue takes exactly `capacity()` items, returns the item from
+/// a push to a full queue and takes items again once one has been popped.
+/// Unbounded queues have to take many more items than their size.
+pub fn bounded_capacity<Q>()
+where
+    Q: ConcurrentQueue<usize>,
+{
+    let queue = Q::new(SMALL_QUEUE_SIZE);
+    let mut handle = queue.register();
+    let Some(capacity) = queue.capacity() else {
+        for i in 0..SMALL_QUEUE_SIZE * 100 {
+            assert!(handle.push(i).is_ok(), "push {} to an unbounded queue failed", i);
+        }
+        return;
+    };
+    assert!(queue.is_bounded());
+    let mut pushed = 0;
+    loop {
+        match handle.push(pushed) {
+            Ok(()) => pushed += 1,
+            Err(item) => {
+                assert_eq!(item, pushed, "a full push has to return the item");
+                break;
+            }
+        }
+        assert!(pushed <= capacity, "took more than the capacity of {} items", capacity);
+    }
+    assert_eq!(pushed, capacity, "got full before reaching the capacity");
+    assert_eq!(handle.pop(), Some(0));
+    assert!(handle.push(pushed).is_ok(), "no room after popping from a full queue");
+    assert_eq!(handle.push(pushed + 1), Err(pushed + 1));
+}
+
+/// Items from every producer reach a single consumer in the order that
+/// producer pushed them.
+pub fn producer_order<Q>()
+where
+    Q: ConcurrentQueue<usize>,
+    for<'a> &'a Q: Send,
+{
+    let queue = Q::new(QUEUE_SIZE);
+    let producers = queue.capabilities().max_producers.unwrap_or(3).min(3);
+    let total = producers * ITEMS_PER_PRODUCER;
+    std::thread::scope(|s| {
+        let queue = &queue;
+        for p in 0..producers {
+            s.spawn(move || {
+                let mut handle = queue.register();
+                for seq in 0..ITEMS_PER_PRODUCER {
+                    push_until_ok(&mut handle, seq * producers + p);
+                }
+            });
+        }
+        let mut handle = queue.register();
+        let mut next = vec![0; producers];
+        let start = Instant::now();
+        let mut received = 0;
+        while received < total {
+            assert!(start.elapsed() < TIMEOUT, "only {} of {} items arrived", received, total);
+            let Some(item) = handle.pop() else {
+                std::hint::spin_loop();
+                continue;
+            };
+            let (seq, p) = (item / producers, item % producers);
+            assert_eq!(seq, next[p], "items from producer {} came out of order", p);
+            next[p] += 1;
+            received += 1;
+        }
+    });
+    assert_eq!(queue.register().pop(), None, "items left after all were popped");
+}
+
+/// Every item pushed by concurrent producers is popped exactly once by
+/// concurrent consumers.
+pub fn conservation<Q>()
+where
+    Q: ConcurrentQueue<usize>,
+    for<'a> &'a Q: Send,
+{
+    let queue = Q::new(QUEUE_SIZE);
+    let capabilities = queue.capabilities();
+    let producers = capabilities.max_producers.unwrap_or(2).min(2);
+    let consumers = capabilities.max_consumers.unwrap_or(2).min(2);
+    let total = producers * ITEMS_PER_PRODUCER;
+    let popped = AtomicUsize::new(0);
+    let mut seen: Vec<usize> = std::thread::scope(|s| {
+        let queue = &queue;
+        let popped = &popped;
+        for p in 0..producers {
+            s.spawn(move || {
+                let mut handle = queue.register();
+                for i in 0..ITEMS_PER_PRODUCER {
+                    push_until_ok(&mut handle, p * ITEMS_PER_PRODUCER + i);
+                }
+            });
+        }
+        let handles: Vec<_> = (0..consumers)
+            .map(|_| {
+                s.spawn(move || {
+                    let mut handle = queue.register();
+                    let mut seen = vec![];
+                    let start = Instant::now();
+                    while popped.load(Ordering::Relaxed) < total {
+                        assert!(start.elapsed() < TIMEOUT, "items went missing");
+                        if let Some(item) = handle.pop() {
+                            seen.push(item);
+                            popped.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                    seen
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    });
+    assert_eq!(seen.len(), total, "popped more items than were pushed");
+    seen.sort_unstable();
+    for (expected, item) in seen.into_iter().enumerate() {
+        assert_eq!(item, expected, "an item was lost or popped twice");
+    }
+    assert_eq!(queue.register().pop(), None, "items left after all were popped");
+}
+
+/// Popped items are dropped by whoever popped them, and the items still in
+/// the queue are dropped exactly once when the queue is dropped.
+pub fn drops_remaining_once<Q>()
+where
+    Q: ConcurrentQueue<TrackedItem>,
+{
+    let tracking = Tracking::start();
+    let queue = Q::new(QUEUE_SIZE);
+    let items = queue.capacity().unwrap_or(QUEUE_SIZE).min(100);
+    {
+        let mut handle = queue.register();
+        for id in 0..items {
+            assert!(handle.push(TrackedItem::new(id)).is_ok(), "push {} failed below the capacity", id);
+        }
+        for id in 0..items / 2 {
+            let item = handle.pop().expect("the queue lost an item");
+            item.assert_alive();
+            assert_eq!(item.id, id, "items came out of order");
+        }
+    }
+    assert_eq!(tracking.dropped(), items / 2, "popping dropped the wrong amount of items");
+    assert_eq!(tracking.bad_drops(), 0, "popped items were dropped twice");
+    drop(queue);
+    assert_eq!(tracking.dropped(), items, "dropping the queue didn't drop every remaining item once");
+    tracking.assert_all_dropped_once();
+}
+
+/// Items pushed and popped by concurrent threads are alive when they come
+/// out, and every item is dropped exactly once, whether it was popped or
+/// left in the queue when it was dropped.
+pub fn drop_safety<Q>()
+where
+    Q: ConcurrentQueue<TrackedItem>,
+    for<'a> &'a Q: Send,
+{
+    let tracking = Tracking::start();
+    let queue = Q::new(QUEUE_SIZE);
+    let capabilities = queue.capabilities();
+    let producers = capabilities.max_producers.unwrap_or(2).min(2);
+    let consumers = capabilities.max_consumers.unwrap_or(2).min(2);
+    let total = producers * ITEMS_PER_PRODUCER;
+    // Leave some items behind for the queue to drop
+    let left = queue.capacity().unwrap_or(QUEUE_SIZE).min(QUEUE_SIZE) / 2;
+    let popped = AtomicUsize::new(0);
+    std::thread::scope(|s| {
+        let queue = &queue;
+        let popped = &popped;
+        for p in 0..producers {
+            s.spawn(move || {
+                let mut handle = queue.register();
+                for i in 0..ITEMS_PER_PRODUCER {
+                    let mut item = TrackedItem::new(p * ITEMS_PER_PRODUCER + i);
+                    let start = Instant::now();
+                    while let Err(returned) = handle.push(item) {
+                        assert!(start.elapsed() < TIMEOUT, "the queue stayed full");
+                        returned.assert_alive();
+                        item = returned;
+                        std::thread::yield_now();
+                    }
+                }
+            });
+        }
+        for _ in 0..consumers {
+            s.spawn(move || {
+                let mut handle = queue.register();
+                while popped.fetch_add(1, Ordering::Relaxed) < total - left {
+                    let start = Instant::now();
+                    let item = loop {
+                        assert!(start.elapsed() < TIMEOUT, "items went missing");
+                        if let Some(item) = handle.pop() {
+                            break item;
+                        }
+                    };
+                    item.assert_alive();
+                }
+            });
+        }
+    });
+    assert_eq!(tracking.bad_drops(), 0, "popped items were dropped twice");
+    assert_eq!(tracking.live(), left, "items were dropped while still in the queue");
+    drop(queue);
+    tracking.assert_all_dropped_once();
+}
+
+/// Pushes the item, retrying while the queue is full.
+fn push_until_ok(handle: &mut impl HandleQueue<usize>, mut item: usize) {
+    let start = Instant::now();
+    while let Err(returned) = handle.push(item) {
+        assert!(start.elapsed() < TIMEOUT, "the queue stayed full");
+        item = returned;
+        std::thread::yield_now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::benchmarks::test_helpers::test_queue::TestQueue;
+    use std::mem::ManuallyDrop;
+
+    /// Queue that never drops the items left in it.
+    struct LeakingQueue<T>(ManuallyDrop<TestQueue<T>>);
+
+    impl<T> ConcurrentQueue<T> for LeakingQueue<T> {
+        fn register(&self) -> impl HandleQueue<T> {
+            self.0.register()
+        }
+
+        fn get_id(&self) -> String {
+            "leaking_queue".to_string()
+        }
+
+        fn new(size: usize) -> Self {
+            LeakingQueue(ManuallyDrop::new(TestQueue::new(size)))
+        }
+    }
+
+    #[test]
+    fn tracking_counts_items() {
+        let tracking = Tracking::start();
+        let items: Vec<_> = (0..10).map(TrackedItem::new).collect();
+        let default = TrackedItem::default();
+        assert_eq!(tracking.constructed(), 10);
+        assert_eq!(tracking.live(), 10);
+        items.iter().for_each(TrackedItem::assert_alive);
+        drop(items);
+        drop(default);
+        assert_eq!(tracking.dropped(), 10);
+        tracking.assert_all_dropped_once();
+    }
+
+    #[test]
+    fn test_queue_passes_drop_checks() {
+        drops_remaining_once::<TestQueue<TrackedItem>>();
+        drop_safety::<TestQueue<TrackedItem>>();
+    }
+
+    #[test]
+    fn leaked_items_are_caught() {
+        let result = std::panic::catch_unwind(drops_remaining_once::<LeakingQueue<TrackedItem>>);
+        assert!(result.is_err());
+        let result = std::panic::catch_unwind(drop_safety::<LeakingQueue<TrackedItem>>);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,89 @@
+use std::fmt;
+
+/// Errors that can end a benchmark run.
+/// Each variant maps to its own process exit code, so that scripts driving
+/// the benchmarks can tell a bad configuration from a broken queue.
+#[derive(Debug)]
+pub enum Error {
+    /// The arguments describe a run that can't be executed,
+    /// eg. a runner the queue doesn't support.
+    Config(String),
+    /// Writing the results or reading input files failed.
+    Io(std::io::Error),
+    /// The queue could not be created with the requested size.
+    QueueConstruction(String),
+    /// The benchmark produced a result that doesn't match the expected one.
+    Verification(String),
+    /// A benchmark thread panicked.
+    ThreadPanic(String),
+}
+
+impl Error {
+    /// Exit code the benchmark binaries use when they fail with this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Config(_) => 2,
+            Error::Io(_) => 3,
+            Error::QueueConstruction(_) => 4,
+            Error::Verification(_) => 5,
+            Error::ThreadPanic(_) => 6,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Config(msg) => write!(f, "invalid configuration: {msg}"),
+            Error::Io(e) => write!(f, "I/O error: {e}"),
+            Error::QueueConstruction(msg) => {
+                write!(f, "could not create queue: {msg}")
+            }
+            Error::Verification(msg) => write!(f, "verification failed: {msg}"),
+            Error::ThreadPanic(msg) => write!(f, "thread panicked: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes_are_distinct() {
+        let errors = [
+            Error::Config(String::new()),
+            Error::Io(std::io::Error::other("")),
+            Error::QueueConstruction(String::new()),
+            Error::Verification(String::new()),
+            Error::ThreadPanic(String::new()),
+        ];
+        let mut codes: Vec<i32> = errors.iter().map(Error::exit_code).collect();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), errors.len());
+        assert!(!codes.contains(&0) && !codes.contains(&1));
+    }
+
+    #[test]
+    fn io_errors_convert() {
+        let err: Error = std::io::Error::other("disk full").into();
+        assert!(matches!(err, Error::Io(_)));
+        assert_eq!(err.exit_code(), 3);
+    }
+}
@@ -0,0 +1,158 @@
+/// Amount of bits of each recorded value that are kept exactly. Values are
+/// bucketed with a relative error of at most `2^-SUB_BUCKET_BITS`, i.e. < 1%.
+const SUB_BUCKET_BITS: u32 = 7;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+const BUCKETS: usize = (64 - SUB_BUCKET_BITS as usize + 1) * SUB_BUCKETS;
+
+/// Log-linear histogram for latencies.
+/// Records `u64` values in constant time and memory, so every thread can keep
+/// its own histogram and merge them when the benchmark is done.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    total: u64,
+    max: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram::new()
+    }
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Histogram {
+            counts: vec![0; BUCKETS],
+            total: 0,
+            max: 0,
+        }
+    }
+
+    /// Index of the bucket `value` falls into. Values below `SUB_BUCKETS`
+    /// get a bucket each, larger values share a bucket with the values that
+    /// have the same top `SUB_BUCKET_BITS + 1` bits.
+    fn index(value: u64) -> usize {
+        if value < SUB_BUCKETS as u64 {
+            return value as usize;
+        }
+        let shift = 63 - SUB_BUCKET_BITS - value.leading_zeros();
+        let sub_bucket = (value >> shift) as usize;
+        (shift as usize) * SUB_BUCKETS + sub_bucket
+    }
+
+    /// The largest value that falls into the bucket at `index`.
+    fn highest_in_bucket(index: usize) -> u64 {
+        if index < 2 * SUB_BUCKETS {
+            return index as u64;
+        }
+        let shift = (index / SUB_BUCKETS - 1) as u32;
+        let sub_bucket = (index % SUB_BUCKETS + SUB_BUCKETS) as u64;
+        (sub_bucket << shift) | ((1 << shift) - 1)
+    }
+
+    pub fn record(&mut self, value: u64) {
+        self.counts[Histogram::index(value)] += 1;
+        self.total += 1;
+        self.max = u64::max(self.max, value);
+    }
+
+    /// Adds all values recorded in `other` to this histogram.
+    pub fn merge(&mut self, other: &Histogram) {
+        for (count, other_count) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other_count;
+        }
+        self.total += other.total;
+        self.max = u64::max(self.max, other.max);
+    }
+
+    /// Returns how many values have been recorded.
+    pub fn len(&self) -> u64 {
+        self.total
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    /// Returns the value below which `percentile` percent of the recorded
+    /// values fall, or 0 if nothing was recorded.
+    pub fn percentile(&self, percentile: f64) -> u64 {
+        if self.total == 0 {
+            return 0;
+        }
+        let rank = ((percentile / 100.0) * self.total as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return u64::min(Histogram::highest_in_bucket(index), self.max);
+            }
+        }
+        self.max
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_histogram_reports_zero() {
+        let histogram = Histogram::new();
+        assert!(histogram.is_empty());
+        assert_eq!(histogram.percentile(50.0), 0);
+        assert_eq!(histogram.max(), 0);
+    }
+
+    #[test]
+    fn small_values_are_exact() {
+        let mut histogram = Histogram::new();
+        for value in 1..=100 {
+            histogram.record(value);
+        }
+        assert_eq!(histogram.percentile(50.0), 50);
+        assert_eq!(histogram.percentile(99.0), 99);
+        assert_eq!(histogram.percentile(100.0), 100);
+    }
+
+    #[test]
+    fn large_values_are_within_one_percent() {
+        let mut histogram = Histogram::new();
+        for value in 1..=1_000_000u64 {
+            histogram.record(value * 1000);
+        }
+        for (percentile, expected) in [(50.0, 500_000_000.0), (99.0, 990_000_000.0), (99.9, 999_000_000.0)] {
+            let value = histogram.percentile(percentile) as f64;
+            assert!((value - expected).abs() / expected < 0.01, "p{percentile}: {value}");
+        }
+        assert_eq!(histogram.percentile(100.0), 1_000_000_000);
+    }
+
+    #[test]
+    fn buckets_cover_their_values() {
+        for value in [0, 1, 127, 128, 255, 256, 1 << 40, (1 << 40) + 12345, u64::MAX] {
+            let index = Histogram::index(value);
+            assert!(index < BUCKETS);
+            assert!(Histogram::highest_in_bucket(index) >= value, "{value}");
+            assert_eq!(Histogram::index(Histogram::highest_in_bucket(index)), index, "{value}");
+        }
+    }
+
+    #[test]
+    fn merge_adds_counts() {
+        let mut a = Histogram::new();
+        let mut b = Histogram::new();
+        a.record(10);
+        b.record(20);
+        b.record(u64::MAX);
+        a.merge(&b);
+        assert_eq!(a.len(), 3);
+        assert_eq!(a.max(), u64::MAX);
+        assert_eq!(a.percentile(50.0), 20);
+    }
+}
@@ -5,7 +5,17 @@ use jemallocator::Jemalloc;
 #[global_allocator]
 static GLOBAL: Jemalloc = Jemalloc;
 
+pub mod backoff;
 pub mod benchmarks;
+pub mod conformance;
+pub mod error;
+pub mod histogram;
+pub mod model_check;
 pub mod order;
+pub mod plugin;
 pub mod arguments;
+pub mod rng;
+pub mod sync;
+pub mod trace;
 pub mod traits;
+pub mod work;
@@ -0,0 +1,226 @@
+//! Small scenarios that `loom` runs under every interleaving of the queue
+//! operations, run through the `queue_model_checks!` macro from the tests
+//! of the lock-free queue crates.
+//! Only queues that take their atomics from `benchmark_core::sync` are
+//! explored, and only with the tests built with `RUSTFLAGS="--cfg loom"`:
+//! ```bash
+//! RUSTFLAGS="--cfg loom" cargo test -p lcrq --release model_check
+//! ```
+
+#[cfg(loom)]
+use crate::traits::{ConcurrentQueue, HandleQueue};
+#[cfg(loom)]
+use loom::{sync::Arc, thread};
+
+/// Preemptions explored per execution, unless `LOOM_MAX_PREEMPTIONS` is set.
+/// Each one multiplies the number of executions, three is enough for the
+/// races between two or three threads these scenarios set up.
+#[cfg(loom)]
+pub const PREEMPTION_BOUND: usize = 3;
+
+/// Names of the checks, which can be passed to the `skip` list of the macro.
+#[cfg(loom)]
+pub const CHECKS: &[&str] = &[
+    "two_producers_one_consumer",
+    "push_pop_on_empty",
+    "push_pop_at_wrap_around",
+    "push_pop_at_segment_switch",
+];
+
+/// Generates a `model_check` test module for a lock-free queue type, which
+/// only exists when built with `--cfg loom`. `size` is what the queue is
+/// created with, bounded queues need room for four items.
+///
+/// Queues storing items in a ring pass its number of slots under loom as
+/// `ring`, to race a push and a pop across the end of the ring. Unbounded
+/// queues made of rings or arrays pass it as `segment` instead, which also
+/// races them across the switch to a new segment. Like with
+/// `queue_conformance_tests!`, checks a queue knowingly fails are listed
+/// in `skip` with the reason and generated as ignored tests.
+/// ```ignore
+/// benchmark_core::queue_model_checks!(LCRQueue, size: 8, segment: RING_SIZE);
+/// benchmark_core::queue_model_checks!(
+///     TZQueue,
+///     size: 4,
+///     ring: 6,
+///     skip: [two_producers_one_consumer],
+///     reason: "reorders items under contention"
+/// );
+/// ```
+#[macro_export]
+macro_rules! queue_model_checks {
+    (@test two_producers_one_consumer, $queue:ident, $size:expr, $pushed:expr, $popped:expr) => {
+        $crate::model_check::two_producers_one_consumer::<$queue<usize>>($size);
+    };
+    (@test $name:ident, $queue:ident, $size:expr, $pushed:expr, $popped:expr) => {
+        let (pushed, popped): (usize, usize) = ($pushed as usize, $popped as usize);
+        $crate::model_check::push_pop_race::<$queue<usize>>($size, pushed, popped);
+    };
+    // A check in the skip list is generated as an ignored test
+    (@check two_producers_one_consumer, $queue:ident, $size:expr, $pushed:expr, $popped:expr, [two_producers_one_consumer $(, $rest:ident)*], ($reason:literal)) => {
+        $crate::queue_model_checks!(@ignored two_producers_one_consumer, $queue, $size, $pushed, $popped, $reason);
+    };
+    (@check push_pop_on_empty, $queue:ident, $size:expr, $pushed:expr, $popped:expr, [push_pop_on_empty $(, $rest:ident)*], ($reason:literal)) => {
+        $crate::queue_model_checks!(@ignored push_pop_on_empty, $queue, $size, $pushed, $popped, $reason);
+    };
+    (@check push_pop_at_wrap_around, $queue:ident, $size:expr, $pushed:expr, $popped:expr, [push_pop_at_wrap_around $(, $rest:ident)*], ($reason:literal)) => {
+        $crate::queue_model_checks!(@ignored push_pop_at_wrap_around, $queue, $size, $pushed, $popped, $reason);
+    };
+    (@check push_pop_at_segment_switch, $queue:ident, $size:expr, $pushed:expr, $popped:expr, [push_pop_at_segment_switch $(, $rest:ident)*], ($reason:literal)) => {
+        $crate::queue_model_checks!(@ignored push_pop_at_segment_switch, $queue, $size, $pushed, $popped, $reason);
+    };
+    (@check $name:ident, $queue:ident, $size:expr, $pushed:expr, $popped:expr, [$first:ident $(, $rest:ident)*], $reason:tt) => {
+        $crate::queue_model_checks!(@check $name, $queue, $size, $pushed, $popped, [$($rest),*], $reason);
+    };
+    (@check $name:ident, $queue:ident, $size:expr, $pushed:expr, $popped:expr, [], $reason:tt) => {
+        #[test]
+        fn $name() {
+            $crate::queue_model_checks!(@test $name, $queue, $size, $pushed, $popped);
+        }
+    };
+    (@ignored $name:ident, $queue:ident, $size:expr, $pushed:expr, $popped:expr, $reason:literal) => {
+        #[test]
+        #[ignore = $reason]
+        fn $name() {
+            $crate::queue_model_checks!(@test $name, $queue, $size, $pushed, $popped);
+        }
+    };
+    (@names [$($skip:ident),*]) => {
+        &[$(stringify!($skip)),*]
+    };
+    (@suite $queue:ident, $size:expr, $skip:tt, $reason:tt, [$($name:ident: $pushed:expr, $popped:expr);*]) => {
+        #[cfg(loom)]
+        mod model_check {
+            #[allow(unused_imports)]
+            use super::*;
+
+            const SKIP: &[&str] = $crate::queue_model_checks!(@names $skip);
+
+            #[test]
+            fn skip_list_is_valid() {
+                for name in SKIP {
+                    assert!(
+                        $crate::model_check::CHECKS.contains(name),
+                        "{} is not a model check",
+                        name
+                    );
+                }
+            }
+
+            $crate::queue_model_checks!(
+                @check two_producers_one_consumer, $queue, $size, 0, 0, $skip, $reason
+            );
+            $crate::queue_model_checks!(
+                @check push_pop_on_empty, $queue, $size, 0, 0, $skip, $reason
+            );
+            $(
+                $crate::queue_model_checks!(
+                    @check $name, $queue, $size, $pushed, $popped, $skip, $reason
+                );
+            )*
+        }
+    };
+    ($queue:ident, size: $size:expr $(, skip: [$($skip:ident),+ $(,)?], reason: $reason:literal)? $(,)?) => {
+        $crate::queue_model_checks!(@suite $queue, $size, [$($($skip),+)?], ($($reason)?), []);
+    };
+    ($queue:ident, size: $size:expr, ring: $ring:expr $(, skip: [$($skip:ident),+ $(,)?], reason: $reason:literal)? $(,)?) => {
+        $crate::queue_model_checks!(@suite $queue, $size, [$($($skip),+)?], ($($reason)?), [
+            push_pop_at_wrap_around: $ring - 1, $ring - 1
+        ]);
+    };
+    ($queue:ident, size: $size:expr, segment: $segment:expr $(, skip: [$($skip:ident),+ $(,)?], reason: $reason:literal)? $(,)?) => {
+        $crate::queue_model_checks!(@suite $queue, $size, [$($($skip),+)?], ($($reason)?), [
+            push_pop_at_wrap_around: $segment - 1, $segment - 1;
+            push_pop_at_segment_switch: $segment - 1, 0
+        ]);
+    };
+}
+
+/// Runs the scenario under loom with the default preemption bound.
+#[cfg(loom)]
+fn check(scenario: impl Fn() + Sync + Send + 'static) {
+    let mut builder = loom::model::Builder::new();
+    if builder.preemption_bound.is_none() {
+        builder.preemption_bound = Some(PREEMPTION_BOUND);
+    }
+    builder.check(scenario);
+}
+
+/// Two producers push two items each while a consumer pops. Every item
+/// comes out exactly once, and the items of each producer in order.
+#[cfg(loom)]
+pub fn two_producers_one_consumer<Q>(size: usize)
+where
+    Q: ConcurrentQueue<usize> + Send + Sync + 'static,
+{
+    check(move || {
+        let queue = Arc::new(Q::new(size));
+        let producers: Vec<_> = (0..2)
+            .map(|p| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    let mut handle = queue.register();
+                    for seq in 0..2 {
+                        assert!(handle.push(seq * 2 + p).is_ok(), "push failed below the capacity");
+                    }
+                })
+            })
+            .collect();
+        let mut handle = queue.register();
+        // Race the pops against the pushes, then take what is left
+        let mut seen: Vec<usize> = (0..2).filter_map(|_| handle.pop()).collect();
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        while let Some(item) = handle.pop() {
+            seen.push(item);
+        }
+        assert_eq!(seen.len(), 4, "items were lost or popped twice: {:?}", seen);
+        for p in 0..2 {
+            let from_p: Vec<usize> = seen
+                .iter()
+                .filter(|item| *item % 2 == p)
+                .map(|item| item / 2)
+                .collect();
+            assert_eq!(from_p, [0, 1], "items from producer {} came out of order", p);
+        }
+    });
+}
+
+/// Pushes `pushed` items and pops `popped` of them before racing a thread
+/// pushing two more against a thread popping. Choosing where the queue
+/// stands puts the race on a boundary, like the end of a ring or a full
+/// segment. All items come out once and in order.
+#[cfg(loom)]
+pub fn push_pop_race<Q>(size: usize, pushed: usize, popped: usize)
+where
+    Q: ConcurrentQueue<usize> + Send + Sync + 'static,
+{
+    check(move || {
+        let queue = Arc::new(Q::new(size));
+        let mut handle = queue.register();
+        // Pop as we go, so that moving a bounded queue along doesn't fill it
+        for item in 0..pushed {
+            assert!(handle.push(item).is_ok(), "push failed below the capacity");
+            if item < popped {
+                assert_eq!(handle.pop(), Some(item));
+            }
+        }
+        let pusher = {
+            let queue = queue.clone();
+            thread::spawn(move || {
+                let mut handle = queue.register();
+                for item in pushed..pushed + 2 {
+                    assert!(handle.push(item).is_ok(), "push failed below the capacity");
+                }
+            })
+        };
+        let mut seen: Vec<usize> = (0..2).filter_map(|_| handle.pop()).collect();
+        pusher.join().unwrap();
+        while let Some(item) = handle.pop() {
+            seen.push(item);
+        }
+        let expected: Vec<usize> = (popped..pushed + 2).collect();
+        assert_eq!(seen, expected, "items were lost, duplicated or reordered");
+    });
+}
@@ -1,228 +1,334 @@
+//! Checks that a queue keeps the items of every producer in FIFO order.
+//!
+//! All producers push concurrently, each numbering its own items, and every
+//! consumer remembers the last number it got from each producer. In a FIFO
+//! queue one consumer can never get an item of a producer after a later item
+//! of the same producer, so a number that doesn't grow is a violation.
+//! Passing the check doesn't prove that the queue is FIFO, a violation
+//! proves that it isn't.
+
 use core_affinity::CoreId;
 use log::{debug, error, info, trace};
 use rand::Rng;
+use std::borrow::Borrow;
+use std::fmt;
 use std::sync::{
-    atomic::{AtomicBool, Ordering},
+    atomic::{AtomicBool, AtomicUsize, Ordering},
     Barrier,
 };
+use std::time::{Duration, Instant};
 
+use crate::rng::{self, Stream};
 use crate::traits::{ConcurrentQueue, HandleQueue};
 
-#[allow(clippy::result_unit_err)]
-pub fn benchmark_order_box<C>(
+/// How many items all producers push together, at most.
+const ITEMS: u64 = 10_000_000;
+
+/// Bits of an item that hold the sequence number, the rest holds the
+/// producer.
+const SEQ_BITS: u32 = 32;
+
+/// Encodes the `seq`th item of `producer`.
+fn item(producer: usize, seq: u64) -> u64 {
+    ((producer as u64) << SEQ_BITS) | seq
+}
+
+/// Splits an item into its producer and sequence number.
+fn split(value: u64) -> (usize, u64) {
+    ((value >> SEQ_BITS) as usize, value & ((1 << SEQ_BITS) - 1))
+}
+
+/// What a run of the order check saw when it found no violation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrderReport {
+    /// Items pushed by all producers.
+    pub pushed: u64,
+    /// Items popped by all consumers.
+    pub popped: u64,
+}
+
+/// The first violation a consumer found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderViolation {
+    /// The consumer got item `got` of `producer` after item `previous`,
+    /// which the producer pushed later or which is the same item.
+    Reordered {
+        consumer: usize,
+        producer: usize,
+        previous: u64,
+        got: u64,
+    },
+    /// The consumer got a value that no producer pushed.
+    Unknown { consumer: usize, value: u64 },
+}
+
+impl fmt::Display for OrderViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OrderViolation::Reordered {
+                consumer,
+                producer,
+                previous,
+                got,
+            } => write!(
+                f,
+                "consumer {consumer} popped item {got} of producer {producer} after item {previous}"
+            ),
+            OrderViolation::Unknown { consumer, value } => {
+                write!(f, "consumer {consumer} popped {value:#x}, which no producer pushed")
+            }
+        }
+    }
+}
+
+impl std::error::Error for OrderViolation {}
+
+/// Runs the order check on `cqueue` with `producers` and `consumers`
+/// threads until all items are popped, a consumer finds a violation or
+/// `time_limit` seconds have passed. Producers draw `delay` random numbers
+/// between pushes to give the consumers time to catch up.
+///
+/// Works for any payload that can be built from and read as a `u64`, e.g.
+/// `u64` or `Box<u64>`.
+pub fn benchmark_order<C, T>(
     cqueue: C,
-    thread_count: usize,
+    producers: usize,
+    consumers: usize,
     time_limit: u64,
     one_socket: bool,
     delay: usize,
-) -> Result<(), ()>
+) -> Result<OrderReport, OrderViolation>
 where
-    C: ConcurrentQueue<Box<i32>>,
+    C: ConcurrentQueue<T>,
     for<'a> &'a C: Send,
+    T: From<u64> + Borrow<u64>,
 {
-    use std::sync::Mutex;
-    use std::time::Duration;
-
-    let barrier = Barrier::new(thread_count + 1);
-    let done_pushing = AtomicBool::new(false);
-    let order = Mutex::new((1..=10_000_000).collect());
-    let mut order2: Vec<i32> = (1..=10_000_000).collect();
-    let done_popping = AtomicBool::new(false);
-    let was_ordered = AtomicBool::new(true);
-    info!("Starting order benchmark with {} threads", thread_count);
+    assert!(
+        producers > 0 && consumers > 0,
+        "The order check needs at least one producer and one consumer"
+    );
+    let per_producer = (ITEMS / producers as u64).min((1 << SEQ_BITS) - 1);
+    let barrier = Barrier::new(producers + consumers);
+    let stop = AtomicBool::new(false);
+    let producers_done = AtomicUsize::new(0);
+    let consumers_done = AtomicUsize::new(0);
+    info!("Starting order check with {producers} producers and {consumers} consumers");
 
     // get cores for fairness of threads
     let available_cores: Vec<CoreId> =
         core_affinity::get_core_ids().unwrap_or(vec![CoreId { id: 0 }]);
     let mut core_iter = available_cores.into_iter().cycle();
+    let mut next_core = || {
+        // if one_socket is true, make all thread ids even
+        // (this was used for our testing enviroment to get one socket)
+        if one_socket {
+            core_iter.next();
+        }
+        core_iter.next().unwrap()
+    };
 
-    std::thread::scope(|s| -> Result<(), ()> {
+    std::thread::scope(|s| {
         let queue = &cqueue;
-        let done_pushing = &done_pushing;
         let barrier = &barrier;
-        let &thread_count = &thread_count;
-        let is_one_socket = one_socket;
-        let lock: &Mutex<Vec<i32>> = &order;
-        let order2 = &mut order2;
-        let done_popping = &done_popping;
-        let was_ordered = &was_ordered;
-        for _i in 0..thread_count {
-            let mut core: CoreId = core_iter.next().unwrap();
-            // if is_one_socket is true, make all thread ids even
-            // (this was used for our testing enviroment to get one socket)
-            if is_one_socket {
-                core = core_iter.next().unwrap();
-            }
-            // println!("{:?}", core);
-            s.spawn(move || {
-                core_affinity::set_for_current(core);
-                let mut handle = queue.register();
-                barrier.wait();
-                while !done_pushing.load(Ordering::Relaxed) {
-                    for _ in 0..delay {
-                        let _some_num = rand::rng().random::<f64>();
+        let stop = &stop;
+        let producers_done = &producers_done;
+        let consumers_done = &consumers_done;
+
+        let producer_handles: Vec<_> = (0..producers)
+            .map(|i| {
+                let core = next_core();
+                s.spawn(move || {
+                    core_affinity::set_for_current(core);
+                    let mut handle = queue.register();
+                    // The numbers only burn time, so a fixed seed is enough
+                    let mut rng = rng::thread_rng(0, Stream::Delay, i);
+                    // Numbering starts at 1, some queues don't take zero
+                    let mut seq = 1;
+                    barrier.wait();
+                    while seq <= per_producer && !stop.load(Ordering::Relaxed) {
+                        for _ in 0..delay {
+                            let _some_num = rng.random::<f64>();
+                        }
+                        match handle.push(T::from(item(i, seq))) {
+                            Ok(()) => seq += 1,
+                            Err(_) => trace!("Producer {i} failed to push {seq}"),
+                        }
                     }
-                    {
-                        let mut q = lock.lock().unwrap();
-                        let elem = match q.pop() {
-                            Some(e) => e,
-                            None => {
-                                done_pushing.store(true, Ordering::Relaxed);
-                                break;
+                    producers_done.fetch_add(1, Ordering::Release);
+                    seq - 1
+                })
+            })
+            .collect();
+
+        debug!("Starting dequeue threads");
+        let consumer_handles: Vec<_> = (0..consumers)
+            .map(|consumer| {
+                let core = next_core();
+                s.spawn(move || -> Result<u64, OrderViolation> {
+                    core_affinity::set_for_current(core);
+                    let mut handle = queue.register();
+                    let mut last = vec![0; producers];
+                    let mut popped = 0;
+                    barrier.wait();
+                    let result = loop {
+                        if stop.load(Ordering::Relaxed) {
+                            break Ok(popped);
+                        }
+                        // Read before popping, so that an empty pop after
+                        // all pushes means that every item was popped
+                        let all_pushed = producers_done.load(Ordering::Acquire) == producers;
+                        match handle.pop() {
+                            Some(val) => {
+                                if let Err(e) = check(&mut last, consumer, *val.borrow()) {
+                                    break Err(e);
+                                }
+                                popped += 1;
                             }
-                        };
-                        let elem_c = elem;
-                        if handle.push(Box::new(elem)).is_err() {
-                            trace!("failed to push {elem_c}");
-                            q.push(elem_c);
-                            continue;
+                            None if all_pushed => break Ok(popped),
+                            None => {}
                         }
-                        trace!("Pushed {elem}");
+                    };
+                    if let Err(e) = &result {
+                        error!("Not ordered: {e}");
+                        stop.store(true, Ordering::Relaxed);
                     }
-                }
-            });
+                    consumers_done.fetch_add(1, Ordering::Release);
+                    result
+                })
+            })
+            .collect();
+
+        let deadline = Instant::now() + Duration::from_secs(time_limit);
+        while !stop.load(Ordering::Relaxed)
+            && consumers_done.load(Ordering::Acquire) < consumers
+            && Instant::now() < deadline
+        {
+            std::thread::sleep(Duration::from_millis(10));
         }
-        // TODO: Make it quit after it finds that it is unordered
-        s.spawn(move || {
-            let mut handle = queue.register();
-            barrier.wait();
-            while !done_pushing.load(Ordering::Relaxed) {
-                if let Some(val) = handle.pop() {
-                    let value = order2.pop().unwrap();
-                    trace!("{} = {}", value, val);
-                    std::thread::sleep(Duration::from_millis(1));
-                    if value != *val {
-                        error!(
-                            "Not ordered, failed at value {}, should have had value {val}",
-                            value
-                        );
-                        was_ordered.store(false, Ordering::Relaxed);
-                        break;
-                    }
-                }
-            }
-        });
-        done_popping.store(true, Ordering::Relaxed);
-        std::thread::sleep(std::time::Duration::from_secs(time_limit));
-        done_pushing.store(true, Ordering::Relaxed);
+        stop.store(true, Ordering::Relaxed);
 
-        println!("Order test over.");
-        if was_ordered.load(Ordering::Relaxed) {
-            println!("Queue seems ordered");
-            Ok(())
-        } else {
-            println!("Queue was unordered");
-            Err(())
+        let pushed = producer_handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .sum();
+        let mut report = OrderReport { pushed, popped: 0 };
+        for h in consumer_handles {
+            report.popped += h.join().unwrap()?;
         }
+        info!(
+            "Order check over, pushed {} and popped {} items",
+            report.pushed, report.popped
+        );
+        Ok(report)
     })
 }
 
-#[allow(clippy::result_unit_err)]
-pub fn benchmark_order_i32<C>(
-    cqueue: C,
-    thread_count: usize,
-    time_limit: u64,
-    one_socket: bool,
-    delay: usize,
-) -> Result<(), ()>
-where
-    C: ConcurrentQueue<i32>,
-    for<'a> &'a C: Send,
-{
+/// Checks the next value a consumer popped against the last item it got
+/// from each producer.
+fn check(last: &mut [u64], consumer: usize, value: u64) -> Result<(), OrderViolation> {
+    let (producer, got) = split(value);
+    if producer >= last.len() || got == 0 {
+        return Err(OrderViolation::Unknown { consumer, value });
+    }
+    let previous = last[producer];
+    if got <= previous {
+        return Err(OrderViolation::Reordered {
+            consumer,
+            producer,
+            previous,
+            got,
+        });
+    }
+    last[producer] = got;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::benchmarks::test_helpers::test_queue::TestQueue;
     use std::sync::Mutex;
-    use std::time::Duration;
 
-    let barrier = Barrier::new(thread_count + 1);
-    let done_pushing = AtomicBool::new(false);
-    let order = Mutex::new((1..=10_000_000).collect());
-    let mut order2: Vec<i32> = (1..=10_000_000).collect();
-    let done_popping = AtomicBool::new(false);
-    let was_ordered = AtomicBool::new(true);
-    info!("Starting order benchmark with {} threads", thread_count);
+    /// Pops the newest item, so it breaks FIFO order as soon as two items
+    /// of a producer are in it.
+    struct StackQueue<T>(Mutex<Vec<T>>);
 
-    // get cores for fairness of threads
-    let available_cores: Vec<CoreId> =
-        core_affinity::get_core_ids().unwrap_or(vec![CoreId { id: 0 }]);
-    let mut core_iter = available_cores.into_iter().cycle();
+    struct StackHandle<'a, T>(&'a StackQueue<T>);
 
-    std::thread::scope(|s| -> Result<(), ()> {
-        let queue = &cqueue;
-        let done_pushing = &done_pushing;
-        let barrier = &barrier;
-        let &thread_count = &thread_count;
-        let is_one_socket = one_socket;
-        let lock: &Mutex<Vec<i32>> = &order;
-        let order2 = &mut order2;
-        let done_popping = &done_popping;
-        let was_ordered = &was_ordered;
-        for _i in 0..thread_count {
-            let mut core: CoreId = core_iter.next().unwrap();
-            // if is_one_socket is true, make all thread ids even
-            // (this was used for our testing enviroment to get one socket)
-            if is_one_socket {
-                core = core_iter.next().unwrap();
-            }
-            // println!("{:?}", core);
-            s.spawn(move || {
-                core_affinity::set_for_current(core);
-                let mut handle = queue.register();
-                barrier.wait();
-                while !done_pushing.load(Ordering::Relaxed) {
-                    for _ in 0..delay {
-                        let _some_num = rand::rng().random::<f64>();
-                    }
-                    {
-                        let mut q = lock.lock().unwrap();
-                        let elem = match q.pop() {
-                            Some(e) => e,
-                            None => {
-                                done_pushing.store(true, Ordering::Relaxed);
-                                break;
-                            }
-                        };
-                        let elem_c = elem;
-                        if handle.push(elem).is_err() {
-                            trace!("failed to push {elem_c}");
-                            q.push(elem_c);
-                            continue;
-                        }
-                        trace!("Pushed {elem}");
-                    }
-                }
-            });
+    impl<T> HandleQueue<T> for StackHandle<'_, T> {
+        fn push(&mut self, item: T) -> Result<(), T> {
+            self.0 .0.lock().unwrap().push(item);
+            Ok(())
         }
-        // TODO: Make it quit after it finds that it is unordered
-        debug!("Starting dequeue thread");
-        s.spawn(move || {
-            let mut handle = queue.register();
-            barrier.wait();
-            while !done_pushing.load(Ordering::Relaxed) {
-                if let Some(val) = handle.pop() {
-                    let value = order2.pop().unwrap();
-                    // trace!("{} = {}",value, val);
-                    std::thread::sleep(Duration::from_millis(1));
-                    if value != val {
-                        error!(
-                            "Not ordered, failed at value {}, should have had value {val}",
-                            value
-                        );
-                        was_ordered.store(false, Ordering::Relaxed);
-                        break;
-                    }
-                }
-            }
-        });
-        done_popping.store(true, Ordering::Relaxed);
-        std::thread::sleep(std::time::Duration::from_secs(time_limit));
-        done_pushing.store(true, Ordering::Relaxed);
 
-        println!("Order test over.");
-        if was_ordered.load(Ordering::Relaxed) {
-            println!("Queue seems ordered");
-            Ok(())
-        } else {
-            println!("Queue was unordered");
-            Err(())
+        fn pop(&mut self) -> Option<T> {
+            self.0 .0.lock().unwrap().pop()
         }
-    })
+    }
+
+    impl<T> ConcurrentQueue<T> for StackQueue<T> {
+        fn register(&self) -> impl HandleQueue<T> {
+            StackHandle(self)
+        }
+
+        fn get_id(&self) -> String {
+            "stack_queue".to_string()
+        }
+
+        fn new(_size: usize) -> Self {
+            StackQueue(Mutex::new(Vec::new()))
+        }
+    }
+
+    #[test]
+    fn fifo_queue_passes() {
+        let queue: TestQueue<u64> = TestQueue::new(0);
+        let report = benchmark_order(queue, 4, 3, 2, false, 0).unwrap();
+        assert!(report.pushed > 0);
+        assert!(report.popped <= report.pushed);
+    }
+
+    #[test]
+    fn boxed_items_pass() {
+        let queue: TestQueue<Box<u64>> = TestQueue::new(0);
+        assert!(benchmark_order(queue, 2, 2, 2, false, 0).is_ok());
+    }
+
+    #[test]
+    fn reordering_queue_fails() {
+        let queue: StackQueue<u64> = StackQueue::new(0);
+        let start = Instant::now();
+        match benchmark_order(queue, 2, 2, 10, false, 0) {
+            Err(OrderViolation::Reordered { previous, got, .. }) => assert!(got < previous),
+            other => panic!("expected a reordering, got {other:?}"),
+        }
+        // The check stops at the first violation instead of the time limit
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn check_reports_the_offending_item() {
+        let mut last = vec![0; 2];
+        assert!(check(&mut last, 0, item(1, 1)).is_ok());
+        assert!(check(&mut last, 0, item(0, 1)).is_ok());
+        assert!(check(&mut last, 0, item(1, 3)).is_ok());
+        assert_eq!(
+            check(&mut last, 0, item(1, 2)),
+            Err(OrderViolation::Reordered {
+                consumer: 0,
+                producer: 1,
+                previous: 3,
+                got: 2
+            })
+        );
+        assert_eq!(
+            check(&mut last, 4, item(2, 1)),
+            Err(OrderViolation::Unknown {
+                consumer: 4,
+                value: item(2, 1)
+            })
+        );
+        // The same item twice is also a violation
+        assert!(check(&mut last, 0, item(0, 1)).is_err());
+    }
 }
@@ -0,0 +1,369 @@
+//! Queues loaded at run time from a shared library, so that queues written
+//! in any language with a C ABI can be benchmarked without adding them to
+//! the workspace.
+//!
+//! A plugin exports the function `queue_plugin_entry`, which returns a
+//! `QueuePlugin` describing the queue. `include/queue_plugin.h` declares the
+//! same ABI for C and C++. Items are passed as pointers to boxed values, the
+//! queue only stores and returns them and never dereferences them.
+
+use crate::error::Error;
+use crate::traits::{Capabilities, ConcurrentQueue, HandleQueue};
+use libloading::{Library, Symbol};
+use std::ffi::{c_char, c_int, c_void, CStr};
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Version of the ABI below. Plugins built for another version are refused.
+pub const ABI_VERSION: u32 = 1;
+
+/// Name of the function every plugin exports.
+pub const ENTRY_POINT: &str = "queue_plugin_entry";
+
+/// The function table a plugin returns from its entry point.
+/// All functions may be called concurrently from different threads, except
+/// `create` and `destroy`.
+#[repr(C)]
+pub struct QueuePlugin {
+    /// Must be `ABI_VERSION`.
+    pub abi_version: u32,
+    /// Returns the name of the queue, used in the result files.
+    pub name: unsafe extern "C" fn() -> *const c_char,
+    /// Creates a queue. Bounded queues hold `capacity` items.
+    /// Returns null if the queue could not be created.
+    pub create: unsafe extern "C" fn(capacity: usize) -> *mut c_void,
+    /// Destroys a queue. All handles are unregistered and the queue is empty.
+    pub destroy: unsafe extern "C" fn(queue: *mut c_void),
+    /// Returns a handle for the calling thread, or null if no more handles
+    /// can be registered.
+    pub register_thread: unsafe extern "C" fn(queue: *mut c_void) -> *mut c_void,
+    /// Releases a handle, its thread won't use it again.
+    pub unregister_thread: unsafe extern "C" fn(handle: *mut c_void),
+    /// Pushes `item`. Returns 1 on success and 0 if the queue is full.
+    pub push: unsafe extern "C" fn(handle: *mut c_void, item: *mut c_void) -> c_int,
+    /// Pops an item into `item`. Returns 1 on success and 0 if the queue
+    /// is empty.
+    pub pop: unsafe extern "C" fn(handle: *mut c_void, item: *mut *mut c_void) -> c_int,
+    /// Returns the amount of items the queue holds at most, null for
+    /// unbounded queues.
+    pub capacity: Option<unsafe extern "C" fn(queue: *mut c_void) -> usize>,
+    /// How many handles may be registered at the same time, 0 if there is
+    /// no limit.
+    pub max_threads: usize,
+}
+
+/// A loaded plugin.
+pub struct Plugin {
+    vtable: &'static QueuePlugin,
+    name: String,
+    // Keeps the library mapped, the function table points into it
+    _library: Option<Library>,
+}
+
+impl Plugin {
+    /// Loads the plugin in the shared library at `path`.
+    pub fn load(path: &Path) -> Result<Plugin, Error> {
+        let config_error =
+            |e| Error::Config(format!("could not load plugin {}: {e}", path.display()));
+        // SAFETY: Loading runs the initializers of the library, the plugin
+        // is trusted like the queues compiled into the binaries.
+        let library = unsafe { Library::new(path) }.map_err(config_error)?;
+        let vtable = unsafe {
+            let entry: Symbol<unsafe extern "C" fn() -> *const QueuePlugin> =
+                library.get(ENTRY_POINT.as_bytes()).map_err(config_error)?;
+            entry().as_ref()
+        };
+        let vtable = vtable.ok_or_else(|| {
+            Error::Config(format!(
+                "{ENTRY_POINT} of plugin {} returned null",
+                path.display()
+            ))
+        })?;
+        Plugin::from_vtable(vtable, Some(library))
+    }
+
+    fn from_vtable(
+        vtable: &'static QueuePlugin,
+        library: Option<Library>,
+    ) -> Result<Plugin, Error> {
+        if vtable.abi_version != ABI_VERSION {
+            return Err(Error::Config(format!(
+                "plugin uses ABI version {}, expected {ABI_VERSION}",
+                vtable.abi_version
+            )));
+        }
+        let name = unsafe { (vtable.name)() };
+        if name.is_null() {
+            return Err(Error::Config("plugin returned no name".to_string()));
+        }
+        let name = unsafe { CStr::from_ptr(name) }.to_string_lossy().into_owned();
+        Ok(Plugin {
+            vtable,
+            name,
+            _library: library,
+        })
+    }
+
+    /// Returns the name of the queue.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// The plugin `PluginQueue`s are created from. `ConcurrentQueue::new` takes
+/// no context, so there is one per process and it stays loaded until exit.
+static PLUGIN: OnceLock<Plugin> = OnceLock::new();
+
+/// Makes `plugin` the plugin of all `PluginQueue`s.
+pub fn install(plugin: Plugin) -> Result<&'static Plugin, Error> {
+    let mut plugin = Some(plugin);
+    let installed = PLUGIN.get_or_init(|| plugin.take().unwrap());
+    match plugin {
+        None => Ok(installed),
+        Some(_) => Err(Error::Config(format!(
+            "plugin {} is already loaded",
+            installed.name
+        ))),
+    }
+}
+
+/// A queue of the installed plugin.
+pub struct PluginQueue<T> {
+    plugin: &'static Plugin,
+    raw: *mut c_void,
+    phantom_data: PhantomData<T>,
+}
+
+// The plugin ABI requires queues to be usable from any thread. The items
+// move between threads through them, so they must be Send.
+unsafe impl<T: Send> Send for PluginQueue<T> {}
+unsafe impl<T: Send> Sync for PluginQueue<T> {}
+
+impl<T> PluginQueue<T> {
+    fn register_raw(&self) -> Option<*mut c_void> {
+        let raw = unsafe { (self.plugin.vtable.register_thread)(self.raw) };
+        (!raw.is_null()).then_some(raw)
+    }
+}
+
+impl<T> Drop for PluginQueue<T> {
+    fn drop(&mut self) {
+        // Free the items still in the queue, the plugin only holds pointers to them
+        if let Some(raw) = self.register_raw() {
+            let mut handle = PluginHandle { q: self, raw };
+            while handle.pop().is_some() {}
+        }
+        unsafe { (self.plugin.vtable.destroy)(self.raw) };
+    }
+}
+
+struct PluginHandle<'a, T> {
+    q: &'a PluginQueue<T>,
+    raw: *mut c_void,
+}
+
+impl<T> HandleQueue<T> for PluginHandle<'_, T> {
+    fn push(&mut self, item: T) -> Result<(), T> {
+        let ptr = Box::into_raw(Box::new(item)) as *mut c_void;
+        match unsafe { (self.q.plugin.vtable.push)(self.raw, ptr) } {
+            1 => Ok(()),
+            _ => Err(*unsafe { Box::from_raw(ptr as *mut T) }),
+        }
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        let mut ptr: *mut c_void = std::ptr::null_mut();
+        match unsafe { (self.q.plugin.vtable.pop)(self.raw, &mut ptr) } {
+            1 => Some(*unsafe { Box::from_raw(ptr as *mut T) }),
+            _ => None,
+        }
+    }
+}
+
+impl<T> Drop for PluginHandle<'_, T> {
+    fn drop(&mut self) {
+        unsafe { (self.q.plugin.vtable.unregister_thread)(self.raw) };
+    }
+}
+
+impl<T> ConcurrentQueue<T> for PluginQueue<T> {
+    fn register(&self) -> impl HandleQueue<T> {
+        let raw = self.register_raw().unwrap_or_else(|| {
+            panic!("{} refused to register another handle", self.plugin.name)
+        });
+        PluginHandle { q: self, raw }
+    }
+
+    fn get_id(&self) -> String {
+        self.plugin.name.clone()
+    }
+
+    fn new(size: usize) -> Self {
+        Self::try_new(size).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    fn try_new(size: usize) -> Result<Self, Error> {
+        let plugin = PLUGIN
+            .get()
+            .ok_or_else(|| Error::Config("no queue plugin is loaded".to_string()))?;
+        let raw = unsafe { (plugin.vtable.create)(size) };
+        if raw.is_null() {
+            return Err(Error::QueueConstruction(format!(
+                "{} returned a null queue",
+                plugin.name
+            )));
+        }
+        Ok(PluginQueue {
+            plugin,
+            raw,
+            phantom_data: PhantomData,
+        })
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        self.plugin
+            .vtable
+            .capacity
+            .map(|capacity| unsafe { capacity(self.raw) })
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            max_threads: (self.plugin.vtable.max_threads > 0)
+                .then_some(self.plugin.vtable.max_threads),
+            ..Capabilities::MPMC
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::{self, TrackedItem};
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    /// A bounded queue behind the plugin ABI, handles point to their queue.
+    struct LockedQueue {
+        items: Mutex<VecDeque<usize>>,
+        capacity: usize,
+    }
+
+    unsafe extern "C" fn name() -> *const c_char {
+        c"locked_queue".as_ptr()
+    }
+
+    unsafe extern "C" fn create(capacity: usize) -> *mut c_void {
+        let queue = LockedQueue {
+            items: Mutex::new(VecDeque::new()),
+            capacity,
+        };
+        Box::into_raw(Box::new(queue)) as *mut c_void
+    }
+
+    unsafe extern "C" fn destroy(queue: *mut c_void) {
+        drop(unsafe { Box::from_raw(queue as *mut LockedQueue) });
+    }
+
+    unsafe extern "C" fn register_thread(queue: *mut c_void) -> *mut c_void {
+        Box::into_raw(Box::new(queue)) as *mut c_void
+    }
+
+    unsafe extern "C" fn unregister_thread(handle: *mut c_void) {
+        drop(unsafe { Box::from_raw(handle as *mut *mut c_void) });
+    }
+
+    unsafe fn queue<'a>(handle: *mut c_void) -> &'a LockedQueue {
+        unsafe { &*(*(handle as *mut *mut c_void) as *const LockedQueue) }
+    }
+
+    unsafe extern "C" fn push(handle: *mut c_void, item: *mut c_void) -> c_int {
+        let queue = unsafe { queue(handle) };
+        let mut items = queue.items.lock().unwrap();
+        if items.len() == queue.capacity {
+            return 0;
+        }
+        items.push_back(item as usize);
+        1
+    }
+
+    unsafe extern "C" fn pop(handle: *mut c_void, item: *mut *mut c_void) -> c_int {
+        match unsafe { queue(handle) }.items.lock().unwrap().pop_front() {
+            Some(popped) => {
+                unsafe { *item = popped as *mut c_void };
+                1
+            }
+            None => 0,
+        }
+    }
+
+    unsafe extern "C" fn capacity(queue: *mut c_void) -> usize {
+        unsafe { &*(queue as *const LockedQueue) }.capacity
+    }
+
+    static LOCKED_QUEUE: QueuePlugin = QueuePlugin {
+        abi_version: ABI_VERSION,
+        name,
+        create,
+        destroy,
+        register_thread,
+        unregister_thread,
+        push,
+        pop,
+        capacity: Some(capacity),
+        max_threads: 0,
+    };
+
+    static NEWER_ABI: QueuePlugin = QueuePlugin {
+        abi_version: ABI_VERSION + 1,
+        ..LOCKED_QUEUE
+    };
+
+    /// Installs the locked queue, every test shares it.
+    fn locked_queue() -> &'static Plugin {
+        PLUGIN.get_or_init(|| Plugin::from_vtable(&LOCKED_QUEUE, None).unwrap())
+    }
+
+    #[test]
+    fn plugin_queue_passes_conformance_checks() {
+        locked_queue();
+        conformance::sequential_fifo::<PluginQueue<usize>>();
+        conformance::empty_pop::<PluginQueue<usize>>();
+        conformance::bounded_capacity::<PluginQueue<usize>>();
+        conformance::producer_order::<PluginQueue<usize>>();
+        conformance::conservation::<PluginQueue<usize>>();
+        conformance::drops_remaining_once::<PluginQueue<TrackedItem>>();
+        conformance::drop_safety::<PluginQueue<TrackedItem>>();
+    }
+
+    #[test]
+    fn queue_reports_the_plugin() {
+        let plugin = locked_queue();
+        let q: PluginQueue<usize> = PluginQueue::new(8);
+        assert_eq!(q.get_id(), plugin.name());
+        assert_eq!(q.capacity(), Some(8));
+        assert_eq!(q.capabilities(), Capabilities::MPMC);
+    }
+
+    #[test]
+    fn only_one_plugin_is_installed() {
+        locked_queue();
+        let second = Plugin::from_vtable(&LOCKED_QUEUE, None).unwrap();
+        assert!(matches!(install(second), Err(Error::Config(_))));
+    }
+
+    #[test]
+    fn other_abi_versions_are_refused() {
+        assert!(matches!(
+            Plugin::from_vtable(&NEWER_ABI, None),
+            Err(Error::Config(_))
+        ));
+    }
+
+    #[test]
+    fn missing_library_is_a_config_error() {
+        let result = Plugin::load(Path::new("/nonexistent/libqueue.so"));
+        assert!(matches!(result, Err(Error::Config(_))));
+    }
+}
@@ -0,0 +1,72 @@
+//! Seeded random number generators, so that a run can be repeated with the
+//! same random choices. Every thread of a runner draws from its own
+//! generator, derived from the seed of the run, the index of the thread and
+//! what the numbers are used for.
+
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+
+/// What the numbers of a generator are used for. Every use gets its own
+/// stream, so that e.g. the think times of a thread don't shift when it
+/// chooses its operations differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    /// Choosing between pushes and pops.
+    Operations = 1,
+    /// Drawing think times.
+    ThinkTime,
+    /// Drawing the arrivals of the open-loop producers.
+    Arrivals,
+    /// Building the inputs of the task-parallel workloads.
+    Tasks,
+    /// Choosing the threads the progress runner stalls.
+    Stalls,
+    /// The busy-wait delay of the order checks.
+    Delay,
+}
+
+/// Draws a seed for runs without `--seed`.
+pub fn random_seed() -> u64 {
+    rand::random()
+}
+
+/// Returns the generator of thread `thread` for `stream` in a run with `seed`.
+/// The same arguments always give the same numbers on the same build.
+pub fn thread_rng(seed: u64, stream: Stream, thread: usize) -> SmallRng {
+    SmallRng::seed_from_u64(mix(mix(seed ^ mix(stream as u64)) ^ thread as u64))
+}
+
+/// The splitmix64 finalizer, so that close seeds and thread indices still
+/// give unrelated generators.
+fn mix(mut value: u64) -> u64 {
+    value = value.wrapping_add(0x9e3779b97f4a7c15);
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+    value ^ (value >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    fn draw(seed: u64, stream: Stream, thread: usize) -> Vec<u64> {
+        let mut rng = thread_rng(seed, stream, thread);
+        (0..16).map(|_| rng.random()).collect()
+    }
+
+    #[test]
+    fn same_seed_gives_same_numbers() {
+        assert_eq!(draw(42, Stream::Operations, 3), draw(42, Stream::Operations, 3));
+    }
+
+    #[test]
+    fn generators_are_independent() {
+        let base = draw(42, Stream::Operations, 0);
+        assert_ne!(base, draw(43, Stream::Operations, 0));
+        assert_ne!(base, draw(42, Stream::Operations, 1));
+        assert_ne!(base, draw(42, Stream::ThinkTime, 0));
+        // Swapping the seed and the thread doesn't give the same generator
+        assert_ne!(draw(1, Stream::Operations, 2), draw(2, Stream::Operations, 1));
+    }
+}
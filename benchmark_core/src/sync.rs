@@ -0,0 +1,10 @@
+//! Atomics and threads for the lock-free queues.
+//! Built with `RUSTFLAGS="--cfg loom"` these come from `loom`, so that the
+//! scenarios in `model_check` can explore every interleaving of the queue
+//! operations, otherwise they are the ones from `std`.
+
+#[cfg(loom)]
+pub use loom::{hint, sync::atomic, thread, thread_local};
+
+#[cfg(not(loom))]
+pub use std::{hint, sync::atomic, thread, thread_local};
@@ -0,0 +1,335 @@
+//! Per-thread operation scripts, so that different queues can be given the
+//! exact same work. A trace is either recorded from a run of `enq-deq` or
+//! generated from a seed, and run with the `replay` runner.
+//!
+//! The file format is compact: after the magic bytes and a version, the
+//! amount of threads follows, then every thread as its amount of operations,
+//! the length of its encoded operations in bytes and the operations
+//! themselves. Every operation is one LEB128 varint holding the think time
+//! after the operation [ns], shifted left by one, with the lowest bit set for
+//! pops. Operations without think time take a single byte.
+
+use crate::work::{self, ThinkTimeDistribution};
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 6] = b"QTRACE";
+const VERSION: u8 = 1;
+
+/// The kinds of operations in a trace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceOpKind {
+    Push,
+    Pop,
+}
+
+/// One step of a thread: an operation and the think time after it [ns].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceOp {
+    pub kind: TraceOpKind,
+    pub think_time: u64,
+}
+
+impl TraceOp {
+    fn encode(&self) -> u64 {
+        (self.think_time << 1) | (self.kind == TraceOpKind::Pop) as u64
+    }
+
+    fn decode(value: u64) -> Self {
+        TraceOp {
+            kind: if value & 1 == 1 {
+                TraceOpKind::Pop
+            } else {
+                TraceOpKind::Push
+            },
+            think_time: value >> 1,
+        }
+    }
+}
+
+/// The operations of one thread, kept encoded like in the file, so that
+/// recording a long run doesn't take more memory than the file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ThreadTrace {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl ThreadTrace {
+    pub fn new() -> Self {
+        ThreadTrace::default()
+    }
+
+    /// Reserves room for `ops` operations without think time, which take a
+    /// byte each.
+    pub fn with_capacity(ops: usize) -> Self {
+        ThreadTrace {
+            bytes: Vec::with_capacity(ops),
+            len: 0,
+        }
+    }
+
+    /// Appends an operation. Think times above `u64::MAX >> 1` are clamped.
+    pub fn push(&mut self, op: TraceOp) {
+        let op = TraceOp {
+            think_time: op.think_time.min(u64::MAX >> 1),
+            ..op
+        };
+        write_varint(&mut self.bytes, op.encode());
+        self.len += 1;
+    }
+
+    /// Returns the amount of operations.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the operations in order.
+    pub fn iter(&self) -> impl Iterator<Item = TraceOp> + '_ {
+        let mut bytes = self.bytes.as_slice();
+        (0..self.len).map(move |_| {
+            // The bytes were validated when the trace was built or read
+            TraceOp::decode(read_varint(&mut bytes).expect("corrupt thread trace"))
+        })
+    }
+}
+
+/// Operation scripts for a set of threads.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trace {
+    threads: Vec<ThreadTrace>,
+}
+
+impl Trace {
+    pub fn new(threads: Vec<ThreadTrace>) -> Self {
+        Trace { threads }
+    }
+
+    /// Generates a trace from `seed`. Every thread does `ops` operations,
+    /// each a push with probability `spread` like in `enq-deq`, followed by
+    /// a think time drawn from `distribution` with mean `think_time` [ns].
+    /// The same arguments always give the same trace on the same build, to
+    /// share a trace between machines save it to a file.
+    pub fn generate(
+        seed: u64,
+        thread_count: usize,
+        ops: usize,
+        spread: f64,
+        think_time: u64,
+        distribution: ThinkTimeDistribution,
+    ) -> Self {
+        let mut seeds = SmallRng::seed_from_u64(seed);
+        let threads = (0..thread_count)
+            .map(|_| {
+                let mut rng = SmallRng::from_rng(&mut seeds);
+                let mut thread = ThreadTrace::new();
+                for _ in 0..ops {
+                    let kind = if rng.random::<f64>() > spread {
+                        TraceOpKind::Pop
+                    } else {
+                        TraceOpKind::Push
+                    };
+                    let think_time = if think_time == 0 {
+                        0
+                    } else {
+                        work::sample_think_time(&mut rng, distribution, think_time)
+                    };
+                    thread.push(TraceOp { kind, think_time });
+                }
+                thread
+            })
+            .collect();
+        Trace { threads }
+    }
+
+    pub fn threads(&self) -> &[ThreadTrace] {
+        &self.threads
+    }
+
+    pub fn thread_count(&self) -> usize {
+        self.threads.len()
+    }
+
+    /// Returns the amount of operations of all threads.
+    pub fn ops(&self) -> usize {
+        self.threads.iter().map(ThreadTrace::len).sum()
+    }
+
+    /// Returns a fingerprint of the trace, the FNV-1a hash of its encoding,
+    /// so that results of the same trace can be matched.
+    pub fn id(&self) -> String {
+        let mut encoded = vec![];
+        self.write(&mut encoded).expect("writing to a Vec can't fail");
+        let hash = encoded.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+        });
+        format!("{:016x}", hash)
+    }
+
+    /// Writes the trace in the file format.
+    pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        let mut header = MAGIC.to_vec();
+        header.push(VERSION);
+        write_varint(&mut header, self.threads.len() as u64);
+        writer.write_all(&header)?;
+        for thread in &self.threads {
+            let mut lengths = vec![];
+            write_varint(&mut lengths, thread.len as u64);
+            write_varint(&mut lengths, thread.bytes.len() as u64);
+            writer.write_all(&lengths)?;
+            writer.write_all(&thread.bytes)?;
+        }
+        writer.flush()
+    }
+
+    /// Reads a trace in the file format, checking that it is complete.
+    pub fn read(mut reader: impl Read) -> io::Result<Self> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        let mut bytes = bytes.as_slice();
+        let header = bytes.get(..MAGIC.len() + 1).ok_or_else(|| invalid("too short for a trace"))?;
+        if &header[..MAGIC.len()] != MAGIC {
+            return Err(invalid("not a trace file"));
+        }
+        if header[MAGIC.len()] != VERSION {
+            return Err(invalid(&format!("unsupported trace version {}", header[MAGIC.len()])));
+        }
+        bytes = &bytes[MAGIC.len() + 1..];
+        let thread_count = read_varint(&mut bytes)?;
+        let mut threads = vec![];
+        for _ in 0..thread_count {
+            let len = read_varint(&mut bytes)? as usize;
+            let byte_len = read_varint(&mut bytes)? as usize;
+            if byte_len > bytes.len() {
+                return Err(invalid("thread trace is truncated"));
+            }
+            let (thread_bytes, rest) = bytes.split_at(byte_len);
+            let mut ops = thread_bytes;
+            for _ in 0..len {
+                read_varint(&mut ops)?;
+            }
+            if !ops.is_empty() {
+                return Err(invalid("thread trace has more bytes than operations"));
+            }
+            threads.push(ThreadTrace {
+                bytes: thread_bytes.to_vec(),
+                len,
+            });
+            bytes = rest;
+        }
+        if !bytes.is_empty() {
+            return Err(invalid("trailing bytes after the last thread"));
+        }
+        Ok(Trace { threads })
+    }
+
+    /// Writes the trace to the file at `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.write(BufWriter::new(File::create(path)?))
+    }
+
+    /// Reads the trace from the file at `path`.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Trace::read(BufReader::new(File::open(path)?))
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Appends `value` as an LEB128 varint.
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// Reads an LEB128 varint from the front of `bytes` and advances past it.
+fn read_varint(bytes: &mut &[u8]) -> io::Result<u64> {
+    let mut value = 0u64;
+    for (i, byte) in bytes.iter().enumerate() {
+        if i == 10 {
+            break;
+        }
+        value |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            *bytes = &bytes[i + 1..];
+            return Ok(value);
+        }
+    }
+    Err(invalid("truncated or overlong varint"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn varints_round_trip() {
+        for value in [0, 1, 127, 128, 300, 1 << 35, u64::MAX] {
+            let mut bytes = vec![];
+            write_varint(&mut bytes, value);
+            let mut slice = bytes.as_slice();
+            assert_eq!(read_varint(&mut slice).unwrap(), value);
+            assert!(slice.is_empty());
+        }
+        // Small values take a single byte
+        let mut bytes = vec![];
+        write_varint(&mut bytes, 127);
+        assert_eq!(bytes.len(), 1);
+    }
+
+    #[test]
+    fn trace_round_trips() {
+        let trace = Trace::generate(7, 3, 500, 0.3, 100, ThinkTimeDistribution::Exponential);
+        let mut bytes = vec![];
+        trace.write(&mut bytes).unwrap();
+        let read = Trace::read(bytes.as_slice()).unwrap();
+        assert_eq!(read, trace);
+        assert_eq!(read.id(), trace.id());
+        assert_eq!(read.ops(), 1500);
+    }
+
+    #[test]
+    fn generation_is_deterministic() {
+        let generate = |seed| Trace::generate(seed, 4, 1000, 0.5, 100, ThinkTimeDistribution::Uniform);
+        assert_eq!(generate(1), generate(1));
+        assert_ne!(generate(1).id(), generate(2).id());
+
+        // The spread is the share of pushes
+        let trace = Trace::generate(3, 1, 10_000, 0.3, 0, ThinkTimeDistribution::Fixed);
+        let pushes = trace.threads()[0]
+            .iter()
+            .filter(|op| op.kind == TraceOpKind::Push)
+            .count();
+        assert!((2700..3300).contains(&pushes), "{pushes}");
+        assert!(trace.threads()[0].iter().all(|op| op.think_time == 0));
+    }
+
+    #[test]
+    fn rejects_invalid_traces() {
+        let trace = Trace::generate(5, 2, 10, 0.5, 1000, ThinkTimeDistribution::Fixed);
+        let mut bytes = vec![];
+        trace.write(&mut bytes).unwrap();
+
+        for broken in [
+            &bytes[..bytes.len() - 1],
+            &bytes[..4],
+            &[bytes.as_slice(), &[0]].concat(),
+            b"NOTATRACE".as_slice(),
+        ] {
+            let err = Trace::read(broken).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
@@ -1,12 +1,111 @@
+use crate::error::Error;
+use std::sync::Mutex;
+use std::time::Duration;
+
 /// Trait that all queues need to implement
 pub trait ConcurrentQueue<T> {
-    /// Returns a handle that exposes the queue API
+    /// Returns a handle that exposes the queue API.
+    /// The handle stays registered until it is dropped, and dropping it must
+    /// release any per-thread state it holds, like a thread id, so that later
+    /// registrations can reuse it.
     fn register(&self) -> impl HandleQueue<T>;
     /// Returns the name of the queue.
     fn get_id(&self) -> String;
     /// Used to create a new queue.
     /// `size` is discarded for unbounded queues.
     fn new(size: usize) -> Self;
+    /// Fallible version of `new`, used by the benchmarks.
+    /// Queues that can fail to allocate or that reject some sizes should
+    /// override this and report it instead of panicking.
+    fn try_new(size: usize) -> Result<Self, Error>
+    where
+        Self: Sized,
+    {
+        Ok(Self::new(size))
+    }
+    /// Returns the amount of items the queue can hold, or `None` if the
+    /// queue is unbounded.
+    fn capacity(&self) -> Option<usize> {
+        None
+    }
+    /// Returns true if pushes to the queue can fail because it is full.
+    fn is_bounded(&self) -> bool {
+        self.capacity().is_some()
+    }
+    /// Returns a handle that also exposes batch operations.
+    /// Queues with native bulk operations should override this, the default
+    /// handle pushes and pops the batch one element at a time.
+    fn register_batch(&self) -> impl HandleQueueBatch<T> {
+        ElementWise(self.register())
+    }
+    /// Returns how many threads may push and pop concurrently.
+    /// Queues designed for a single producer or consumer must override this
+    /// so that runners refuse configurations the queue can't handle.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::MPMC
+    }
+    /// Returns a handle that is only used to push.
+    /// Queues with separate producer and consumer sides can override this,
+    /// the default is the handle from `register_batch`.
+    fn register_producer(&self) -> impl HandleQueueBatch<T> {
+        self.register_batch()
+    }
+    /// Returns a handle that is only used to pop.
+    /// Queues with separate producer and consumer sides can override this,
+    /// the default is the handle from `register_batch`.
+    fn register_consumer(&self) -> impl HandleQueueBatch<T> {
+        self.register_batch()
+    }
+}
+
+/// How many threads a queue supports in each role at the same time.
+/// `None` means any amount.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    pub max_producers: Option<usize>,
+    pub max_consumers: Option<usize>,
+    /// How many handles may be registered at the same time, for queues that
+    /// hand out thread ids from a fixed table.
+    pub max_threads: Option<usize>,
+}
+
+impl Capabilities {
+    /// Multiple producers, multiple consumers.
+    pub const MPMC: Capabilities = Capabilities {
+        max_producers: None,
+        max_consumers: None,
+        max_threads: None,
+    };
+    /// Multiple producers, single consumer.
+    pub const MPSC: Capabilities = Capabilities {
+        max_producers: None,
+        max_consumers: Some(1),
+        max_threads: None,
+    };
+    /// Single producer, multiple consumers.
+    pub const SPMC: Capabilities = Capabilities {
+        max_producers: Some(1),
+        max_consumers: None,
+        max_threads: None,
+    };
+    /// Single producer, single consumer.
+    pub const SPSC: Capabilities = Capabilities {
+        max_producers: Some(1),
+        max_consumers: Some(1),
+        max_threads: None,
+    };
+
+    /// Returns true if the queue can be used by `producers` pushing threads
+    /// and `consumers` popping threads at the same time.
+    pub fn allows(&self, producers: usize, consumers: usize) -> bool {
+        self.max_producers.is_none_or(|max| producers <= max)
+            && self.max_consumers.is_none_or(|max| consumers <= max)
+    }
+
+    /// Returns true if `handles` handles can be registered at the same time.
+    pub fn allows_handles(&self, handles: usize) -> bool {
+        self.max_threads.is_none_or(|max| handles <= max)
+    }
 }
 
 /// Trait that exposes the correct API for queues
@@ -16,6 +115,156 @@ pub trait HandleQueue<T> {
     fn push(&mut self, item: T) -> Result<(), T>;
     /// Pops an item from the queue.
     fn pop(&mut self) -> Option<T>;
+    /// Unregisters the handle. The same as dropping it, but makes the end of
+    /// the handle's lifecycle explicit.
+    fn unregister(self)
+    where
+        Self: Sized,
+    {
+        drop(self)
+    }
+}
+
+/// Trait that exposes batch operations for queues
+pub trait HandleQueueBatch<T>: HandleQueue<T> {
+    /// Pushes the items in order and removes the pushed ones from `items`.
+    /// Items that could not be pushed, e.g. because the queue is full, are
+    /// left in `items`. Returns how many items were pushed.
+    fn push_batch(&mut self, items: &mut Vec<T>) -> usize {
+        let mut pushed = 0;
+        let mut batch = std::mem::take(items).into_iter();
+        while let Some(item) = batch.next() {
+            if let Err(item) = self.push(item) {
+                items.push(item);
+                items.extend(batch);
+                break;
+            }
+            pushed += 1;
+        }
+        pushed
+    }
+    /// Pops up to `max` items and appends them to `buffer`.
+    /// Returns how many items were popped.
+    fn pop_batch(&mut self, buffer: &mut Vec<T>, max: usize) -> usize {
+        let mut popped = 0;
+        while popped < max {
+            match self.pop() {
+                Some(item) => buffer.push(item),
+                None => break,
+            }
+            popped += 1;
+        }
+        popped
+    }
+}
+
+/// Trait that exposes blocking operations for channel-like queues
+pub trait BlockingHandleQueue<T>: HandleQueue<T> {
+    /// Pops an item, blocking until one is available.
+    /// Returns `None` once the queue is closed and empty.
+    fn pop_blocking(&mut self) -> Option<T>;
+    /// Pops an item, blocking for at most `timeout`.
+    /// Returns `None` if no item arrived in time or the queue is closed and empty.
+    fn pop_timeout(&mut self, timeout: Duration) -> Option<T>;
+    /// Closes the queue for every handle. Pushes fail from now on and
+    /// blocked pops wake up once the remaining items are popped.
+    fn close(&mut self);
+}
+
+/// Trait for queues that can hand out blocking handles
+pub trait BlockingConcurrentQueue<T>: ConcurrentQueue<T> {
+    /// Returns a handle that also exposes the blocking API
+    fn register_blocking(&self) -> impl BlockingHandleQueue<T>;
+}
+
+/// Handle wrapper giving any handle the element-by-element batch operations.
+pub struct ElementWise<H>(pub H);
+
+impl<T, H: HandleQueue<T>> HandleQueue<T> for ElementWise<H> {
+    fn push(&mut self, item: T) -> Result<(), T> {
+        self.0.push(item)
+    }
+    fn pop(&mut self) -> Option<T> {
+        self.0.pop()
+    }
+}
+
+impl<T, H: HandleQueue<T>> HandleQueueBatch<T> for ElementWise<H> {}
+
+/// Hands out thread ids for queues that keep per-thread state in tables
+/// indexed by a small integer id. Every queue should own its allocator, and
+/// every handle should hold an `AllocatedId` for its lifetime. Ids of dropped
+/// handles are recycled, so any amount of threads can use the queue over time
+/// as long as at most `max` handles are registered at once.
+#[derive(Debug)]
+pub struct ThreadIdAllocator {
+    max: usize,
+    /// The lowest id that was never handed out, and the released ids.
+    ids: Mutex<(usize, Vec<usize>)>,
+}
+
+impl ThreadIdAllocator {
+    /// Creates an allocator for the ids `0..max`.
+    pub fn new(max: usize) -> Self {
+        ThreadIdAllocator {
+            max,
+            ids: Mutex::new((0, Vec::new())),
+        }
+    }
+
+    /// Returns how many ids can be in use at the same time.
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    /// Returns an unused id, preferring released ones, or `None` if all `max`
+    /// ids are in use.
+    pub fn acquire(&self) -> Option<AllocatedId<'_>> {
+        let mut ids = self.ids.lock().unwrap();
+        let id = match ids.1.pop() {
+            Some(id) => id,
+            None if ids.0 < self.max => {
+                ids.0 += 1;
+                ids.0 - 1
+            }
+            None => return None,
+        };
+        Some(AllocatedId {
+            id,
+            allocator: self,
+        })
+    }
+
+    /// Returns how many ids are in use.
+    pub fn in_use(&self) -> usize {
+        let ids = self.ids.lock().unwrap();
+        ids.0 - ids.1.len()
+    }
+}
+
+/// An id from a `ThreadIdAllocator`, released when dropped.
+#[derive(Debug)]
+pub struct AllocatedId<'a> {
+    id: usize,
+    allocator: &'a ThreadIdAllocator,
+}
+
+impl AllocatedId<'_> {
+    /// Returns the id.
+    pub fn get(&self) -> usize {
+        self.id
+    }
+}
+
+impl Drop for AllocatedId<'_> {
+    fn drop(&mut self) {
+        // Don't panic in drop if another thread panicked while holding the lock
+        let mut ids = match self.allocator.ids.lock() {
+            Ok(ids) => ids,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        ids.1.push(self.id);
+    }
 }
 
 /// Trait that all priority queues need to implement
@@ -27,6 +276,15 @@ pub trait ConcurrentPriorityQueue<P: Ord, T> {
     /// Used to create a new queue.
     /// `size` is discarded for unbounded queues.
     fn new(size: usize) -> Self;
+    /// Fallible version of `new`, used by the benchmarks.
+    /// Queues that can fail to allocate or that reject some sizes should
+    /// override this and report it instead of panicking.
+    fn try_new(size: usize) -> Result<Self, Error>
+    where
+        Self: Sized,
+    {
+        Ok(Self::new(size))
+    }
 }
 
 /// Trait that exposes the correct API for priority queues
@@ -40,3 +298,42 @@ pub trait HandlePriorityQueue<P: Ord, T> {
     /// Checks if the priority queue is empty
     fn is_empty(&mut self) -> bool;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_are_recycled() {
+        let allocator = ThreadIdAllocator::new(2);
+        let a = allocator.acquire().unwrap();
+        let b = allocator.acquire().unwrap();
+        assert_eq!((a.get(), b.get()), (0, 1));
+        assert!(allocator.acquire().is_none());
+        drop(a);
+        assert_eq!(allocator.in_use(), 1);
+        let c = allocator.acquire().unwrap();
+        assert_eq!(c.get(), 0);
+        assert!(allocator.acquire().is_none());
+    }
+
+    #[test]
+    fn ids_are_unique_across_threads() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        let allocator = ThreadIdAllocator::new(8);
+        let held: Vec<AtomicBool> = (0..8).map(|_| AtomicBool::new(false)).collect();
+        std::thread::scope(|s| {
+            for _ in 0..32 {
+                s.spawn(|| {
+                    for _ in 0..100 {
+                        if let Some(id) = allocator.acquire() {
+                            assert!(!held[id.get()].swap(true, Ordering::SeqCst));
+                            held[id.get()].store(false, Ordering::SeqCst);
+                        }
+                    }
+                });
+            }
+        });
+        assert_eq!(allocator.in_use(), 0);
+    }
+}
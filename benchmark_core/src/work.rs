@@ -0,0 +1,298 @@
+use crate::rng::{self, Stream};
+use clap::ValueEnum;
+use rand::rngs::SmallRng;
+use rand::Rng;
+use std::fmt::Display;
+use std::hint::black_box;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Distance between two touched bytes in the work buffer. A page plus a cache
+/// line, so consecutive touches neither share a cache line nor a page and the
+/// hardware prefetcher can't hide the misses.
+const TOUCH_STRIDE: usize = 4096 + 64;
+
+/// Amount of iterations timed when calibrating the busy-wait loop.
+const CALIBRATION_ITERATIONS: u64 = 200_000;
+
+/// Iterations of the busy-wait loop per nanosecond for each work buffer size
+/// that was calibrated so far. Every worker of the run shares them, so
+/// threads started during the run don't spend their lifetime calibrating.
+static CALIBRATIONS: Mutex<Vec<(usize, f64)>> = Mutex::new(Vec::new());
+
+/// Distributions the think time between two operations can be drawn from.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThinkTimeDistribution {
+    /// Always think for exactly the mean.
+    Fixed,
+    /// Exponentially distributed around the mean.
+    Exponential,
+    /// Uniformly distributed between zero and twice the mean.
+    Uniform,
+}
+
+impl Display for ThinkTimeDistribution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ThinkTimeDistribution::Fixed => write!(f, "fixed"),
+            ThinkTimeDistribution::Exponential => write!(f, "exponential"),
+            ThinkTimeDistribution::Uniform => write!(f, "uniform"),
+        }
+    }
+}
+
+/// Thread-local work model simulating the work a thread does between two
+/// queue operations.
+/// Busy-waits for a think time drawn from the configured distribution. If a
+/// work buffer is configured the busy-wait touches that buffer instead of
+/// spinning, so the thread also puts pressure on the caches.
+#[derive(Debug)]
+pub struct Worker {
+    think_time: u64,
+    distribution: ThinkTimeDistribution,
+    rng: SmallRng,
+    buffer: Vec<u8>,
+    position: usize,
+    iterations_per_ns: f64,
+    total_think_time: Duration,
+    thinks: usize,
+}
+
+impl Worker {
+    /// Creates a worker thinking for `think_time` ns on average and touching
+    /// a private buffer of `buffer_size` bytes. A buffer size of 0 spins instead.
+    /// The think times are drawn from `rng`.
+    pub fn new(
+        think_time: u64,
+        distribution: ThinkTimeDistribution,
+        buffer_size: usize,
+        rng: SmallRng,
+    ) -> Self {
+        let mut worker = Worker {
+            think_time,
+            distribution,
+            rng,
+            buffer: vec![0; buffer_size],
+            position: 0,
+            iterations_per_ns: 0.0,
+            total_think_time: Duration::ZERO,
+            thinks: 0,
+        };
+        if think_time > 0 {
+            worker.calibrate();
+        }
+        worker
+    }
+
+    /// Creates the worker of thread `thread` from the general arguments of
+    /// the benchmark, drawing from the think time stream of the seed.
+    pub fn from_args(args: &crate::arguments::GeneralArgs, thread: usize) -> Self {
+        let rng = rng::thread_rng(args.seed.unwrap_or_default(), Stream::ThinkTime, thread);
+        Worker::new(args.think_time, args.think_dist, args.work_buffer, rng)
+    }
+
+    /// Sets how many iterations of the busy-wait loop make up a nanosecond,
+    /// timing the loop if no worker of the run with the same buffer size did.
+    /// `new` calibrates workers with a think time, workers created without
+    /// one that still call `think_for` should be calibrated before timing starts.
+    pub fn calibrate(&mut self) {
+        let mut calibrations = CALIBRATIONS.lock().unwrap();
+        let size = self.buffer.len();
+        self.iterations_per_ns = match calibrations.iter().find(|(s, _)| *s == size) {
+            Some(&(_, iterations_per_ns)) => iterations_per_ns,
+            None => {
+                let iterations_per_ns = self.time_work();
+                calibrations.push((size, iterations_per_ns));
+                iterations_per_ns
+            }
+        };
+    }
+
+    /// Times the busy-wait loop and returns how many iterations make up a
+    /// nanosecond. The first round only warms up the caches and the buffer.
+    fn time_work(&mut self) -> f64 {
+        let mut elapsed = Duration::ZERO;
+        for _ in 0..2 {
+            let start = Instant::now();
+            self.work(CALIBRATION_ITERATIONS);
+            elapsed = start.elapsed();
+        }
+        CALIBRATION_ITERATIONS as f64 / u128::max(elapsed.as_nanos(), 1) as f64
+    }
+
+    /// Runs `iterations` iterations of the busy-wait loop.
+    fn work(&mut self, iterations: u64) {
+        if self.buffer.is_empty() {
+            for i in 0..iterations {
+                black_box(i);
+                std::hint::spin_loop();
+            }
+        } else {
+            let len = self.buffer.len();
+            for _ in 0..iterations {
+                let byte = &mut self.buffer[self.position];
+                *byte = black_box(byte.wrapping_add(1));
+                self.position = (self.position + TOUCH_STRIDE) % len;
+            }
+        }
+    }
+
+    /// Draws the next think time [ns] from the distribution.
+    fn sample(&mut self) -> u64 {
+        sample_think_time(&mut self.rng, self.distribution, self.think_time)
+    }
+
+    /// Simulates the work between two operations. Returns the think time
+    /// [ns] that was drawn, 0 if the worker doesn't think.
+    pub fn think(&mut self) -> u64 {
+        if self.think_time == 0 {
+            return 0;
+        }
+        let think_time = self.sample();
+        self.think_for(think_time);
+        think_time
+    }
+
+    /// Simulates work for exactly `think_time` ns instead of drawing it, e.g.
+    /// to replay the think times of a trace.
+    pub fn think_for(&mut self, think_time: u64) {
+        // Only a fallback, calibrating here counts towards the timed part
+        if self.iterations_per_ns == 0.0 {
+            self.calibrate();
+        }
+        let start = Instant::now();
+        self.work((think_time as f64 * self.iterations_per_ns) as u64);
+        self.total_think_time += start.elapsed();
+        self.thinks += 1;
+    }
+
+    /// Returns the total time spent thinking.
+    pub fn total_think_time(&self) -> Duration {
+        self.total_think_time
+    }
+
+    /// Returns how many times the thread has thought.
+    pub fn thinks(&self) -> usize {
+        self.thinks
+    }
+}
+
+/// Draws a think time [ns] with mean `think_time` from `distribution`.
+pub fn sample_think_time(
+    rng: &mut impl Rng,
+    distribution: ThinkTimeDistribution,
+    think_time: u64,
+) -> u64 {
+    match distribution {
+        ThinkTimeDistribution::Fixed => think_time,
+        ThinkTimeDistribution::Exponential => {
+            // Inverse transform sampling, 1 - u is in (0, 1] so ln never sees 0.
+            let u: f64 = rng.random();
+            (-(think_time as f64) * (1.0 - u).ln()) as u64
+        }
+        ThinkTimeDistribution::Uniform => rng.random_range(0..=2 * think_time),
+    }
+}
+
+/// Calculates the achieved mean think time [ns] from the totals of all threads.
+pub fn mean_think_time(total_think_time_ns: u64, thinks: usize) -> f64 {
+    if thinks == 0 {
+        return 0.0;
+    }
+    total_think_time_ns as f64 / thinks as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arguments::GeneralArgs;
+
+    fn worker(think_time: u64, distribution: ThinkTimeDistribution, buffer_size: usize) -> Worker {
+        Worker::new(think_time, distribution, buffer_size, rng::thread_rng(0, Stream::ThinkTime, 0))
+    }
+
+    fn sample_mean(distribution: ThinkTimeDistribution, think_time: u64) -> f64 {
+        let mut worker = worker(think_time, distribution, 0);
+        let samples = 100_000;
+        (0..samples).map(|_| worker.sample() as f64).sum::<f64>() / samples as f64
+    }
+
+    #[test]
+    fn distributions_have_the_configured_mean() {
+        for distribution in [
+            ThinkTimeDistribution::Fixed,
+            ThinkTimeDistribution::Exponential,
+            ThinkTimeDistribution::Uniform,
+        ] {
+            let mean = sample_mean(distribution, 1000);
+            assert!((mean - 1000.0).abs() < 50.0, "{distribution}: {mean}");
+        }
+    }
+
+    #[test]
+    fn seed_decides_the_think_times() {
+        let samples = |seed, thread| {
+            let args = GeneralArgs {
+                think_time: 1000,
+                think_dist: ThinkTimeDistribution::Exponential,
+                seed: Some(seed),
+                ..Default::default()
+            };
+            let mut worker = Worker::from_args(&args, thread);
+            (0..100).map(|_| worker.sample()).collect::<Vec<_>>()
+        };
+        assert_eq!(samples(7, 0), samples(7, 0));
+        assert_ne!(samples(7, 0), samples(7, 1));
+        assert_ne!(samples(7, 0), samples(8, 0));
+    }
+
+    #[test]
+    fn zero_think_time_does_no_work() {
+        let mut worker = worker(0, ThinkTimeDistribution::Exponential, 1 << 16);
+        worker.think();
+        worker.think();
+        assert_eq!(worker.thinks(), 0);
+        assert_eq!(worker.total_think_time(), Duration::ZERO);
+        assert_eq!(mean_think_time(0, worker.thinks()), 0.0);
+    }
+
+    #[test]
+    fn calibrated_worker_does_not_calibrate_again() {
+        let mut worker = worker(0, ThinkTimeDistribution::Fixed, 0);
+        assert_eq!(worker.iterations_per_ns, 0.0);
+        worker.calibrate();
+        let iterations_per_ns = worker.iterations_per_ns;
+        assert!(iterations_per_ns > 0.0);
+        worker.think_for(1000);
+        assert_eq!(worker.iterations_per_ns, iterations_per_ns);
+        assert_eq!(worker.thinks(), 1);
+    }
+
+    #[test]
+    fn workers_share_the_calibration() {
+        // A buffer size no other test uses, so this test times the loop first
+        let buffer_size = 3 * TOUCH_STRIDE;
+        let first = worker(1000, ThinkTimeDistribution::Fixed, buffer_size);
+        assert!(first.iterations_per_ns > 0.0);
+        for _ in 0..10 {
+            let worker = worker(1000, ThinkTimeDistribution::Fixed, buffer_size);
+            assert_eq!(worker.iterations_per_ns, first.iterations_per_ns);
+        }
+    }
+
+    #[test]
+    fn thinking_takes_roughly_the_think_time() {
+        for buffer_size in [0, 1 << 20] {
+            let mut worker = worker(20_000, ThinkTimeDistribution::Fixed, buffer_size);
+            for _ in 0..50 {
+                worker.think();
+            }
+            let mean = mean_think_time(
+                worker.total_think_time().as_nanos() as u64,
+                worker.thinks(),
+            );
+            // Loose bounds, CI machines are noisy.
+            assert!(mean > 2_000.0 && mean < 200_000.0, "{buffer_size}: {mean}");
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use benchmark_core::error::Error;
 use benchmark_core::traits::{ConcurrentQueue, HandleQueue};
 use crossbeam::queue::ArrayQueue;
 
@@ -24,6 +25,17 @@ impl <T> ConcurrentQueue<T> for AQueue<T> {
             array_queue: ArrayQueue::new(size),
         }
     }
+    fn try_new(size: usize) -> Result<Self, Error> {
+        if size == 0 {
+            return Err(Error::QueueConstruction(
+                "array_queue needs a capacity of at least 1".to_string(),
+            ));
+        }
+        Ok(Self::new(size))
+    }
+    fn capacity(&self) -> Option<usize> {
+        Some(self.array_queue.capacity())
+    }
 }
 
 impl<T> HandleQueue<T> for AQueueHandle<'_, T>{
@@ -50,6 +62,11 @@ mod tests {
         assert_eq!(q.array_queue.pop().unwrap(), 1);
     }
     #[test]
+    fn try_new_rejects_zero_capacity() {
+        assert!(<AQueue<i32>>::try_new(0).is_err());
+        assert_eq!(<AQueue<i32>>::try_new(10).unwrap().capacity(), Some(10));
+    }
+    #[test]
     fn register_bq() {
         let q: AQueue<i32> = AQueue {
             array_queue: ArrayQueue::new(100)
@@ -61,11 +78,13 @@ mod tests {
     #[test]
     #[ignore]
     fn test_order() {
-        let q: AQueue<i32> = AQueue {
+        let q: AQueue<u64> = AQueue {
             array_queue: ArrayQueue::new(10)
         };
-        if benchmark_core::order::benchmark_order_i32(q, 10, 5, true, 10).is_err() {
-            panic!();
+        if let Err(e) = benchmark_core::order::benchmark_order(q, 5, 5, 5, true, 10) {
+            panic!("{e}");
         }
     }
+
+    benchmark_core::queue_conformance_tests!(AQueue);
 }
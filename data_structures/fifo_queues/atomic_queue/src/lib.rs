@@ -1,14 +1,18 @@
 use benchmark_core::traits::{ConcurrentQueue, HandleQueue};
+use std::mem::ManuallyDrop;
 
+/// The upstream queue drops the item of a push to a full queue. Its items
+/// are wrapped in `ManuallyDrop` so that a copy of the item can be handed
+/// back instead, which leaves dropping the items in the queue to `Drop`.
 pub struct AtomicQueue<T> {
-    pub queue: atomic_queue_upstream::Queue<T>,
+    pub queue: atomic_queue_upstream::Queue<ManuallyDrop<T>>,
 }
 
 pub struct AtomicQueueHandle<'a, T> {
     queue: &'a AtomicQueue<T>
 }
 
-impl<T: Default> ConcurrentQueue<T> for AtomicQueue<T> {
+impl<T> ConcurrentQueue<T> for AtomicQueue<T> {
     fn register(&self) -> impl HandleQueue<T> {
         AtomicQueueHandle {
             queue: self,
@@ -22,18 +26,35 @@ impl<T: Default> ConcurrentQueue<T> for AtomicQueue<T> {
             queue: atomic_queue_upstream::bounded(size),
         }
     }
+    fn capacity(&self) -> Option<usize> {
+        Some(self.queue.capacity())
+    }
 }
 
-impl<T: Default> HandleQueue<T> for AtomicQueueHandle<'_, T> {
+impl<T> HandleQueue<T> for AtomicQueueHandle<'_, T> {
     fn push(&mut self, item: T) -> Result<(), T>{
+        let item = ManuallyDrop::new(item);
+        // Safety: only one of the two is used, the copy if the push failed
+        // and dropped the other without running its drop, and otherwise the
+        // one in the queue
+        let copy = unsafe { std::ptr::read(&*item) };
         if !self.queue.queue.push(item) {
-            return Err(T::default());
+            return Err(copy);
         }
+        std::mem::forget(copy);
         Ok(())
     }
 
     fn pop(&mut self) -> Option<T> {
-        self.queue.queue.pop()
+        self.queue.queue.pop().map(ManuallyDrop::into_inner)
+    }
+}
+
+impl<T> Drop for AtomicQueue<T> {
+    fn drop(&mut self) {
+        while let Some(item) = self.queue.pop() {
+            drop(ManuallyDrop::into_inner(item));
+        }
     }
 }
 
@@ -46,8 +67,8 @@ mod tests {
     #[test]
     fn create_atomic_queue() {
         let q: AtomicQueue<i32> = AtomicQueue::new(1000);
-        q.queue.push(1);
-        assert_eq!(q.queue.pop().unwrap(), 1);
+        q.queue.push(ManuallyDrop::new(1));
+        assert_eq!(*q.queue.pop().unwrap(), 1);
     }
     #[test]
     fn register_atomic_queue() {
@@ -60,10 +81,11 @@ mod tests {
     #[ignore]
     fn test_order() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let q: AtomicQueue<i32> = AtomicQueue::new(10);
-        if benchmark_core::order::benchmark_order_i32(q, 20, 5, true, 10).is_err() {
-            panic!();
+        let q: AtomicQueue<u64> = AtomicQueue::new(10);
+        if let Err(e) = benchmark_core::order::benchmark_order(q, 10, 10, 5, true, 10) {
+            panic!("{e}");
         }
     }
 
+    benchmark_core::queue_conformance_tests!(AtomicQueue);
 }
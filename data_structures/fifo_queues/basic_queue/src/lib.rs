@@ -93,11 +93,13 @@ mod tests {
     #[ignore]
     fn test_order() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let q: BasicQueue<i32> = BasicQueue {
+        let q: BasicQueue<u64> = BasicQueue {
             bqueue: BQueue::new()
         };
-        if benchmark_core::order::benchmark_order_i32(q, 20, 5, true, 10).is_err() {
-            panic!();
+        if let Err(e) = benchmark_core::order::benchmark_order(q, 10, 10, 5, true, 10) {
+            panic!("{e}");
         }
     }
+
+    benchmark_core::queue_conformance_tests!(BasicQueue);
 }
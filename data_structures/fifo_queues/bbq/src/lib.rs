@@ -1,8 +1,10 @@
+use benchmark_core::error::Error;
 use benchmark_core::traits::{ConcurrentQueue, HandleQueue};
 use bbq_upstream::BlockingQueue;
 
 pub struct BBQueue<T>{
     pub queue: bbq_upstream::Bbq<T>,
+    capacity: usize,
 }
 
 pub struct BBQHandle<'a, T> {
@@ -19,9 +21,19 @@ impl<T: Default> ConcurrentQueue<T> for BBQueue <T>{
         String::from("bbq")
     }
     fn new(size: usize) -> Self {
-        BBQueue {
-            queue: bbq_upstream::Bbq::new(size, size).expect("Should never get here..."),
-        }
+        Self::try_new(size).unwrap_or_else(|e| panic!("{e}"))
+    }
+    fn try_new(size: usize) -> Result<Self, Error> {
+        let queue = bbq_upstream::Bbq::new(size, size).map_err(|e| {
+            Error::QueueConstruction(format!("bbq with {size} blocks of {size} items: {e:?}"))
+        })?;
+        Ok(BBQueue {
+            queue,
+            capacity: size * size,
+        })
+    }
+    fn capacity(&self) -> Option<usize> {
+        Some(self.capacity)
     }
 }
 
@@ -50,9 +62,15 @@ mod tests {
     #[ignore]
     fn test_order() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let q: BBQueue<i32> = BBQueue::new(100000);
-        if benchmark_core::order::benchmark_order_i32(q, 20, 5, true, 10).is_err() {
-            panic!();
+        let q: BBQueue<u64> = BBQueue::new(100000);
+        if let Err(e) = benchmark_core::order::benchmark_order(q, 10, 10, 5, true, 10) {
+            panic!("{e}");
         }
     }
+
+    benchmark_core::queue_conformance_tests!(
+        BBQueue,
+        skip: [bounded_capacity],
+        reason: "pushes to a full queue block until there is room instead of failing"
+    );
 }
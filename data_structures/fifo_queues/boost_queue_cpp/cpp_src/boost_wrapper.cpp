@@ -10,7 +10,13 @@ struct BoostLockfreeQueueImpl {
 };
 
 BoostLockfreeQueue boost_queue_create(unsigned int capacity) {
-    return new BoostLockfreeQueueImpl(capacity);
+    // Exceptions can't cross the C boundary, a failed allocation is
+    // reported as a null queue instead
+    try {
+        return new BoostLockfreeQueueImpl(capacity);
+    } catch (...) {
+        return nullptr;
+    }
 }
 
 void boost_queue_destroy(BoostLockfreeQueue queue) {
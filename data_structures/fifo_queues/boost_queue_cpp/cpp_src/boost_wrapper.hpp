@@ -6,6 +6,7 @@ extern "C" {
 #endif
 
 typedef struct BoostLockfreeQueueImpl* BoostLockfreeQueue;
+// Returns NULL if the queue could not be created
 BoostLockfreeQueue boost_queue_create(unsigned int capacity);
 void boost_queue_destroy(BoostLockfreeQueue queue);
 int boost_queue_push(BoostLockfreeQueue queue, void* item);
@@ -2,111 +2,57 @@
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
 
-use benchmark_core::traits::{ConcurrentQueue, HandleQueue};
+use ffi_queue::{ExternQueue, Vtable};
+use std::ffi::c_void;
 
 // Include the generated bindings
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 
-// A safe Rust wrapper around the C bindings
-pub struct BoostCppQueue<T> {
-    raw: BoostLockfreeQueue,
-    phantom_data: std::marker::PhantomData<T>,
-}
-
-unsafe impl<T> Send for BoostCppQueue<T> {}
-unsafe impl<T> Sync for BoostCppQueue<T> {}
+/// The functions of `boost_wrapper.hpp`
+pub struct BoostVtable;
 
-#[allow(clippy::not_unsafe_ptr_arg_deref)]
-impl<T> BoostCppQueue<T> {
-
-    pub fn push(&self, item: *mut std::ffi::c_void) -> bool {
-        unsafe {
-            boost_queue_push(self.raw, item) == 1
-        }
-    }
+impl Vtable for BoostVtable {
+    type Impl = BoostLockfreeQueueImpl;
+    const NAME: &'static str = "boost_cpp";
 
-    pub fn pop(&self) -> Option<*mut std::ffi::c_void> {
-        let mut item: *mut std::ffi::c_void = std::ptr::null_mut();
-        let success = unsafe { boost_queue_pop(self.raw, &mut item) == 1 };
-        if success {
-            Some(item)
-        } else {
-            None
-        }
+    unsafe fn create(capacity: usize) -> BoostLockfreeQueue {
+        boost_queue_create(capacity as u32)
     }
 
-}
-
-impl<T> Drop for BoostCppQueue<T> {
-    fn drop(&mut self) {
-        unsafe { boost_queue_destroy(self.raw) };
+    unsafe fn destroy(queue: BoostLockfreeQueue) {
+        boost_queue_destroy(queue)
     }
-}
-
-struct BoostCppQueueHandle<'a,T> {
-    pub q: &'a BoostCppQueue<T>
-}
 
-impl<T> HandleQueue<T> for BoostCppQueueHandle<'_,T> {
-    fn push(&mut self, item: T) -> Result<(), T> {
-        let ptr: *mut std::ffi::c_void = Box::<T>::into_raw(Box::new(item)) as *mut std::ffi::c_void;
-        match self.q.push(ptr) {
-            true => Ok(()),
-            false => {
-                // Really unsure if this is possible
-                let reclaimed: Box<T> = unsafe { Box::from_raw(ptr as *mut T) };
-                Err(*reclaimed)
-            },
-        }
+    unsafe fn push(queue: BoostLockfreeQueue, item: *mut c_void, _tid: usize) -> bool {
+        boost_queue_push(queue, item) == 1
     }
 
-    fn pop(&mut self) -> Option<T> {
-        let res = self.q.pop()?;
-        let val = unsafe { Box::from_raw(res as *const T as *mut T) };
-        Some(*val)
+    unsafe fn pop(queue: BoostLockfreeQueue, item: *mut *mut c_void, _tid: usize) -> bool {
+        boost_queue_pop(queue, item) == 1
     }
 }
 
-impl<T> ConcurrentQueue<T> for BoostCppQueue<T> {
-    fn register(&self) -> impl HandleQueue<T> {
-        BoostCppQueueHandle {
-            q: self,
-        }
-    }
-
-    fn get_id(&self) -> String {
-        String::from("boost_cpp")
-    }
-
-    fn new(capacity: usize) -> Self {
-        let raw = unsafe { boost_queue_create(capacity as u32) };
-        BoostCppQueue { raw, phantom_data: std::marker::PhantomData}
-    }
-}
+// A safe Rust wrapper around the C bindings
+pub type BoostCppQueue<T> = ExternQueue<T, BoostVtable>;
 
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use benchmark_core::traits::{ConcurrentQueue, HandleQueue};
 
     #[test]
     fn create_boost_queue() {
         let q: BoostCppQueue<i32> = BoostCppQueue::new(1000);
-        let _ = q.push(Box::<i32>::into_raw(Box::new(32)) as *mut std::ffi::c_void);
-        let _ = q.push(Box::<i32>::into_raw(Box::new(33)) as *mut std::ffi::c_void);
-        let _ = q.push(Box::<i32>::into_raw(Box::new(34)) as *mut std::ffi::c_void);
-        let _ = q.push(Box::<i32>::into_raw(Box::new(35)) as *mut std::ffi::c_void);
-        let _ = q.push(Box::<i32>::into_raw(Box::new(36)) as *mut std::ffi::c_void);
-        let val = unsafe {*(q.pop().unwrap() as *const i32)};
-        assert_eq!(val, 32);
-        let val = unsafe {*(q.pop().unwrap() as *const i32)};
-        assert_eq!(val, 33);
-        let val = unsafe {*(q.pop().unwrap() as *const i32)};
-        assert_eq!(val, 34);
-        let val = unsafe {*(q.pop().unwrap() as *const i32)};
-        assert_eq!(val, 35);
-        let val = unsafe {*(q.pop().unwrap() as *const i32)};
-        assert_eq!(val, 36);
+        for i in 32..37 {
+            let item = Box::into_raw(Box::new(i)) as *mut c_void;
+            assert!(unsafe { BoostVtable::push(q.as_raw(), item, 0) });
+        }
+        for i in 32..37 {
+            let mut item = std::ptr::null_mut();
+            assert!(unsafe { BoostVtable::pop(q.as_raw(), &mut item, 0) });
+            assert_eq!(*unsafe { Box::from_raw(item as *mut i32) }, i);
+        }
     }
     #[test]
     fn register_boost_queue() {
@@ -125,9 +71,11 @@ mod tests {
     #[ignore]
     fn test_order() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let q: BoostCppQueue<i32> = BoostCppQueue::new(10);
-        if benchmark_core::order::benchmark_order_i32(q, 20, 5, true, 10).is_err() {
-            panic!();
+        let q: BoostCppQueue<u64> = BoostCppQueue::new(10);
+        if let Err(e) = benchmark_core::order::benchmark_order(q, 10, 10, 5, true, 10) {
+            panic!("{e}");
         }
     }
+
+    benchmark_core::queue_conformance_tests!(BoostCppQueue);
 }
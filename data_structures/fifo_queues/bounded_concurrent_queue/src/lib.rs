@@ -1,6 +1,7 @@
 use concurrent_queue::PushError;
 use log::warn;
 
+use benchmark_core::error::Error;
 use benchmark_core::traits::{ConcurrentQueue, HandleQueue};
 
 pub struct BoundedCQueueHandle<'a, T> {
@@ -25,6 +26,17 @@ impl<T> ConcurrentQueue<T> for BoundedCQueue<T> {
             cq: concurrent_queue::ConcurrentQueue::bounded(size),
         }
     }
+    fn try_new(size: usize) -> Result<Self, Error> {
+        if size == 0 {
+            return Err(Error::QueueConstruction(
+                "bounded_concurrent_queue needs a capacity of at least 1".to_string(),
+            ));
+        }
+        Ok(Self::new(size))
+    }
+    fn capacity(&self) -> Option<usize> {
+        self.cq.capacity()
+    }
 }
 
 impl<T> HandleQueue<T> for BoundedCQueueHandle<'_, T> {
@@ -63,6 +75,11 @@ mod tests {
         assert_eq!(q.cq.pop().unwrap(), 1);
     }
     #[test]
+    fn try_new_rejects_zero_capacity() {
+        assert!(<BoundedCQueue<i32>>::try_new(0).is_err());
+        assert_eq!(<BoundedCQueue<i32>>::try_new(10).unwrap().capacity(), Some(10));
+    }
+    #[test]
     fn test_handle() {
         let q: BoundedCQueue<i32> = BoundedCQueue {
             cq: concurrent_queue::ConcurrentQueue::bounded(100)
@@ -84,11 +101,13 @@ mod tests {
     #[ignore]
     fn test_order() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let q: BoundedCQueue<i32> = BoundedCQueue {
+        let q: BoundedCQueue<u64> = BoundedCQueue {
             cq: concurrent_queue::ConcurrentQueue::bounded(1)
         };
-        if benchmark_core::order::benchmark_order_i32(q, 20, 5, true, 10).is_err() {
-            panic!();
+        if let Err(e) = benchmark_core::order::benchmark_order(q, 10, 10, 5, true, 10) {
+            panic!("{e}");
         }
     }
+
+    benchmark_core::queue_conformance_tests!(BoundedCQueue);
 }
@@ -1,3 +1,4 @@
+use benchmark_core::error::Error;
 use benchmark_core::traits::{ConcurrentQueue, HandleQueue};
 use std::sync::Mutex;
 
@@ -9,10 +10,10 @@ pub struct BRingBuffer<T> {
     empty: Mutex<bool>
 }
 
-impl<T: Clone + Default> BRingBuffer<T> {
+impl<T: Default> BRingBuffer<T> {
     pub fn new(capacity: usize) -> Self{
         BRingBuffer{
-            bounded_ringbuffer: Mutex::new(vec![T::default(); capacity + 1]),
+            bounded_ringbuffer: Mutex::new((0..capacity + 1).map(|_| T::default()).collect()),
             capacity: capacity + 1,
             head: Mutex::new(0),
             tail: Mutex::new(0),
@@ -21,7 +22,7 @@ impl<T: Clone + Default> BRingBuffer<T> {
     }
 
     pub fn pop(&self) -> Option<T>{
-        let buf = self.bounded_ringbuffer.lock().unwrap();
+        let mut buf = self.bounded_ringbuffer.lock().unwrap();
         let tail = self.tail.lock().unwrap();
         let mut head = self.head.lock().unwrap();
         let mut empty = self.empty.lock().unwrap();
@@ -30,7 +31,8 @@ impl<T: Clone + Default> BRingBuffer<T> {
             return None;
         }
 
-        let item = buf[*head].clone();
+        // Take the item out so that the buffer doesn't keep it alive
+        let item = std::mem::take(&mut buf[*head]);
         *head = (*head + 1) % self.capacity;
 
         *empty = *head == *tail;
@@ -38,21 +40,29 @@ impl<T: Clone + Default> BRingBuffer<T> {
         Some(item)
     }
 
-    pub fn push(&self, item: T){
+    /// Pushes an item to the buffer.
+    /// If the buffer is full, the item is returned instead of overwriting the oldest element.
+    pub fn push(&self, item: T) -> Result<(), T>{
         let mut buf = self.bounded_ringbuffer.lock().unwrap();
         let mut tail = self.tail.lock().unwrap();
-        let mut head = self.head.lock().unwrap();
+        let head = self.head.lock().unwrap();
         let mut empty = self.empty.lock().unwrap();
 
+        if (*tail + 1) % self.capacity == *head {
+            return Err(item);
+        }
+
         buf[*tail] = item;
         *tail = (*tail + 1) % self.capacity;
 
         *empty = false;
 
-        if *tail == *head{
-            *head = (*head + 1) % self.capacity; //overwrite atm
-        }
+        Ok(())
+    }
 
+    /// Returns the amount of items the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.capacity - 1
     }
 }
 
@@ -67,19 +77,16 @@ pub struct BRingBufferHandle<'a, T>{
 }
 
 
-impl<T> HandleQueue<T> for BRingBufferHandle<'_, T>{
+impl<T: Default> HandleQueue<T> for BRingBufferHandle<'_, T>{
     fn pop(&mut self) -> Option<T>{
-        let mut buf = self.queue.brbuffer.bounded_ringbuffer.lock().unwrap();
-        buf.pop()
+        self.queue.brbuffer.pop()
     }
     fn push(&mut self, item: T) -> Result<(), T>{
-        let mut buf = self.queue.brbuffer.bounded_ringbuffer.lock().unwrap();
-        buf.push(item);
-        Ok(())
+        self.queue.brbuffer.push(item)
     }
 }
 
-impl <T: Clone + Default>ConcurrentQueue<T> for BoundedRingBuffer<T>{
+impl <T: Default>ConcurrentQueue<T> for BoundedRingBuffer<T>{
     fn register(&self) -> impl HandleQueue<T>{
         BRingBufferHandle::<T> {
             queue: self,
@@ -93,17 +100,29 @@ impl <T: Clone + Default>ConcurrentQueue<T> for BoundedRingBuffer<T>{
             brbuffer: BRingBuffer::new(size),
         }
     }
+    fn try_new(size: usize) -> Result<Self, Error> {
+        if size == 0 {
+            return Err(Error::QueueConstruction(
+                "bounded_ringbuffer needs a capacity of at least 1".to_string(),
+            ));
+        }
+        Ok(Self::new(size))
+    }
+    fn capacity(&self) -> Option<usize> {
+        Some(self.brbuffer.capacity())
+    }
 }
 
 
 #[cfg(test)]
 mod tests{
     use super::*;
+    use std::sync::Arc;
 
     #[test]
     fn push_and_pop_single() {
         let buffer = BRingBuffer::new(3);
-        buffer.push(5);
+        buffer.push(5).unwrap();
         assert_eq!(buffer.pop(), Some(5));
     }
 
@@ -112,9 +131,9 @@ mod tests{
     #[test]
     fn push_and_pop_multiple() {
         let buffer = BRingBuffer::new(3);
-        buffer.push(1);
-        buffer.push(2);
-        buffer.push(3);
+        buffer.push(1).unwrap();
+        buffer.push(2).unwrap();
+        buffer.push(3).unwrap();
         assert_eq!(buffer.pop(), Some(1));
         assert_eq!(buffer.pop(), Some(2));
         assert_eq!(buffer.pop(), Some(3));
@@ -123,30 +142,30 @@ mod tests{
     }
 
     #[test]
-    fn overwrite_old_elements() {
+    fn reject_when_full() {
         let buffer = BRingBuffer::new(3);
-        buffer.push(1);
-        buffer.push(2);
-        buffer.push(3);
-        buffer.push(4); // Overwrites oldest element (1)
+        buffer.push(1).unwrap();
+        buffer.push(2).unwrap();
+        buffer.push(3).unwrap();
+        assert_eq!(buffer.push(4), Err(4)); // Full, the oldest element is kept
+        assert_eq!(buffer.pop(), Some(1));
         assert_eq!(buffer.pop(), Some(2));
         assert_eq!(buffer.pop(), Some(3));
-        assert_eq!(buffer.pop(), Some(4));
         assert_eq!(buffer.pop(), None);
     }
 
     #[test]
     fn refill_buffer() {
         let buffer = BRingBuffer::new(2);
-        buffer.push(1);
-        buffer.push(2);
+        buffer.push(1).unwrap();
+        buffer.push(2).unwrap();
         assert_eq!(buffer.pop(), Some(1));
         assert_eq!(buffer.pop(), Some(2));
         assert_eq!(buffer.pop(), None); // Buffer empty
 
         // Refill after emptying
-        buffer.push(3);
-        buffer.push(4);
+        buffer.push(3).unwrap();
+        buffer.push(4).unwrap();
         assert_eq!(buffer.pop(), Some(3));
         assert_eq!(buffer.pop(), Some(4));
         assert_eq!(buffer.pop(), None);
@@ -154,13 +173,17 @@ mod tests{
 
     #[test]
     fn handle_full_buffer() {
-        let buffer = BRingBuffer::new(2);
-        buffer.push(10);
-        buffer.push(20);
-        buffer.push(30); // Overwrites 10
-        assert_eq!(buffer.pop(), Some(20)); // 10 was overwritten
-        assert_eq!(buffer.pop(), Some(30));
-        assert_eq!(buffer.pop(), None);
+        let buffer: BoundedRingBuffer<i32> = BoundedRingBuffer::new(2);
+        assert_eq!(buffer.capacity(), Some(2));
+        let mut handle = buffer.register();
+        handle.push(10).unwrap();
+        handle.push(20).unwrap();
+        assert_eq!(handle.push(30), Err(30));
+        assert_eq!(handle.pop(), Some(10));
+        handle.push(30).unwrap();
+        assert_eq!(handle.pop(), Some(20));
+        assert_eq!(handle.pop(), Some(30));
+        assert_eq!(handle.pop(), None);
     }
 
     #[test]
@@ -169,14 +192,24 @@ mod tests{
         assert_eq!(buffer.pop(), None);
     }
 
+    #[test]
+    fn pop_releases_item() {
+        let buffer = BRingBuffer::new(2);
+        let item = Arc::new(1);
+        buffer.push(Arc::clone(&item)).unwrap();
+        drop(buffer.pop());
+        assert_eq!(Arc::strong_count(&item), 1, "the buffer kept a popped item");
+    }
+
     #[test]
     #[ignore]
     fn test_order() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let q: BoundedRingBuffer<i32> = BoundedRingBuffer::new(10);
-        if benchmark_core::order::benchmark_order_i32(q, 20, 5, true, 10).is_err() {
-            panic!();
+        let q: BoundedRingBuffer<u64> = BoundedRingBuffer::new(10);
+        if let Err(e) = benchmark_core::order::benchmark_order(q, 10, 10, 5, true, 10) {
+            panic!("{e}");
         }
     }
 
+    benchmark_core::queue_conformance_tests!(BoundedRingBuffer);
 }
@@ -1,11 +1,18 @@
-use std::{ptr::null_mut, sync::atomic::{AtomicPtr as RawAtomicPtr, AtomicUsize, Ordering::SeqCst}};
+use std::ptr::null_mut;
+use benchmark_core::sync::atomic::{AtomicPtr as RawAtomicPtr, AtomicUsize, Ordering::SeqCst};
 
 use haphazard::{AtomicPtr as HpAtomicPtr, HazardPointer};
 use crossbeam::utils::CachePadded;
 use log::trace;
 use benchmark_core::traits::{ConcurrentQueue, HandleQueue};
 
+#[cfg(not(loom))]
 const BUFFER_SIZE: usize = 1024;
+// Small enough for the model checks to fill a node
+#[cfg(loom)]
+const BUFFER_SIZE: usize = 4;
+/// Put in a slot by the dequeue that took its item.
+const TAKEN: usize = 1;
 
 struct Node<T> {
     enqueue_index: CachePadded<AtomicUsize>,
@@ -26,7 +33,7 @@ impl<T> Node<T> {
             enqueue_index: CachePadded::new(1.into()),
             dequeue_index: CachePadded::new(0.into()),
             next: unsafe { CachePadded::new(HpAtomicPtr::new(core::ptr::null_mut())) },
-            array: [const { RawAtomicPtr::new(core::ptr::null_mut()) }; BUFFER_SIZE],
+            array: std::array::from_fn(|_| RawAtomicPtr::new(core::ptr::null_mut())),
         };
         // NOTE: Copies the address.
         node.array[0] = RawAtomicPtr::new(data_ptr);
@@ -38,7 +45,7 @@ impl<T> Node<T> {
             enqueue_index: CachePadded::new(0.into()),
             dequeue_index: CachePadded::new(0.into()),
             next: unsafe { CachePadded::new(HpAtomicPtr::new(core::ptr::null_mut())) },
-            array: [const { RawAtomicPtr::new(core::ptr::null_mut()) }; BUFFER_SIZE],
+            array: std::array::from_fn(|_| RawAtomicPtr::new(core::ptr::null_mut())),
         }
     }
 }
@@ -98,7 +105,7 @@ impl<T> FAAAQueue<T> {
                 }
                 continue;
             }
-            let item_ptr = lhead.array[idx].swap(1u64 as *mut u64 as *mut T, SeqCst);
+            let item_ptr = lhead.array[idx].swap(TAKEN as *mut T, SeqCst);
             if item_ptr.is_null() {continue;}
             let item = *unsafe { Box::from_raw(item_ptr) };
             return Some(item);
@@ -111,20 +118,21 @@ impl<T> FAAAQueue<T> {
 impl<T> Drop for FAAAQueue<T> {
     fn drop(&mut self) {
         trace!("Starting drop FAAArrayQueue");
-        let head: Box<Node<T>> = unsafe { Box::from_raw(self.head.load_ptr()) };
-        let mut next = head.next;
+        // The head node can still hold items, so start there
+        let mut next = self.head.load_ptr();
 
-        while !next.load_ptr().is_null() {
-            let node: Box<Node<T>> = unsafe { Box::from_raw(next.load_ptr()) };
-            for data in node.array
+        while !next.is_null() {
+            let node: Box<Node<T>> = unsafe { Box::from_raw(next) };
+            for data in &node.array
             {
                 let reclaimed_mem = data.load(SeqCst);
-                if !reclaimed_mem.is_null() {
-                    unsafe { drop(Box::from_raw(data.load(SeqCst))) };
+                // Dequeued slots hold the taken marker instead of an item
+                if !reclaimed_mem.is_null() && reclaimed_mem != TAKEN as *mut T {
+                    unsafe { drop(Box::from_raw(reclaimed_mem)) };
                 }
             }
 
-            next = node.next;
+            next = node.next.load_ptr();
         }
         trace!("Done dropping");
     }
@@ -173,6 +181,7 @@ mod tests {
     use log::info;
 
     use super::*;
+    use benchmark_core::conformance::{TrackedItem, Tracking};
 
     #[test]
     fn create_faaaq_queue() {
@@ -195,12 +204,27 @@ mod tests {
         assert_eq!(handle.pop().unwrap(), 1);
     }
     #[test]
+    fn drop_frees_items() {
+        let tracking = Tracking::start();
+        let q: FAAAQueue<TrackedItem> = FAAAQueue::new(1000);
+        let mut hp = HazardPointer::new();
+        // Leave taken slots and items in the head node and items in the next
+        for id in 0..BUFFER_SIZE + 3 {
+            q.enqueue(TrackedItem::new(id), &mut hp);
+        }
+        for _ in 0..2 {
+            q.dequeue(&mut hp).unwrap().assert_alive();
+        }
+        drop(q);
+        tracking.assert_all_dropped_once();
+    }
+    #[test]
     #[ignore]
     fn test_order() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let q: FAAAQueue<i32> = FAAAQueue::new(10);
-        if benchmark_core::order::benchmark_order_i32(q, 20, 5, true, 10).is_err() {
-            panic!();
+        let q: FAAAQueue<u64> = FAAAQueue::new(10);
+        if let Err(e) = benchmark_core::order::benchmark_order(q, 10, 10, 5, true, 10) {
+            panic!("{e}");
         }
     }
     #[test]
@@ -257,4 +281,7 @@ mod tests {
         }
         assert_eq!(thesum, sum.into_inner());
     }
+
+    benchmark_core::queue_conformance_tests!(FAAAQueue);
+    benchmark_core::queue_model_checks!(FAAAQueue, size: 8, segment: BUFFER_SIZE);
 }
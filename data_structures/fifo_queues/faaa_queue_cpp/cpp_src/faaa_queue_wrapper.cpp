@@ -8,7 +8,13 @@ struct FAAAQImpl {
 };
 
 FAAAQ faaaq_create(int max_threads) {
-    return new FAAAQImpl(max_threads);
+    // Exceptions can't cross the C boundary, a failed allocation is
+    // reported as a null queue instead
+    try {
+        return new FAAAQImpl(max_threads);
+    } catch (...) {
+        return nullptr;
+    }
 }
 
 void faaaq_destroy(FAAAQ queue) {
@@ -7,6 +7,7 @@ extern "C" {
 
 typedef struct FAAAQImpl* FAAAQ;
 
+// Returns NULL if the queue could not be created
 FAAAQ faaaq_create(int max_threads);
 void faaaq_destroy(FAAAQ queue);
 int faaaq_push(FAAAQ queue, void* item, const int tid);
@@ -2,152 +2,70 @@
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
 
-use std::{cell::Cell, sync::atomic::{AtomicI32, Ordering}};
-
-use benchmark_core::traits::{ConcurrentQueue, HandleQueue};
+use ffi_queue::{ExternQueue, Vtable};
+use std::ffi::c_void;
 
 // Include the generated bindings
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 
-thread_local! {
-    static THREAD_ID: Cell<Option<i32>> = const { Cell::new(None) };
-}
-
-static MAX_THREADS: i32 = 512;
+const MAX_THREADS: usize = 512;
 
+/// The functions of `faaa_queue_wrapper.hpp`
+pub struct FAAAQVtable;
 
-// A safe Rust wrapper around the C bindings
-pub struct FAAAQueue<T> {
-    raw: FAAAQ,
-    next_thread_id: AtomicI32,
-    phantom_data: std::marker::PhantomData<T>,
-}
-
-unsafe impl<T> Send for FAAAQueue<T> {}
-unsafe impl<T> Sync for FAAAQueue<T> {}
+impl Vtable for FAAAQVtable {
+    type Impl = FAAAQImpl;
+    const NAME: &'static str = "faaa_queue_cpp";
+    const MAX_THREADS: Option<usize> = Some(MAX_THREADS);
 
-#[allow(clippy::not_unsafe_ptr_arg_deref)]
-impl<T> FAAAQueue<T> {
-
-    pub fn push(&self, item: *mut std::ffi::c_void) -> bool {
-        let tid = self.get_thread_id();
-        unsafe { faaaq_push(self.raw, item, tid) == 1 }
+    unsafe fn create(_capacity: usize) -> FAAAQ {
+        faaaq_create(MAX_THREADS as i32)
     }
 
-    pub fn pop(&self) -> Option<*mut std::ffi::c_void> {
-        let mut item: *mut std::ffi::c_void = std::ptr::null_mut();
-        let tid = self.get_thread_id();
-        let success = unsafe { faaaq_pop(self.raw, &mut item, tid) == 1 };
-        if success {
-            Some(item)
-        } else {
-            None
-        }
+    unsafe fn destroy(queue: FAAAQ) {
+        faaaq_destroy(queue)
     }
-    fn get_thread_id(&self) -> i32 {
-        THREAD_ID.with(|id| {
-            if let Some(tid) = id.get() {
-                tid
-            } else {
-                let new_id = self.next_thread_id.fetch_add(1, Ordering::Relaxed);
-                id.set(Some(new_id));
-                new_id
-            }
-        })
-    }
-    fn new() -> Self {
-        THREAD_ID.with(|id| {
-            id.set(Some(0));
-        });
-        let raw = unsafe { faaaq_create(MAX_THREADS) };
-        Self {
-            raw,
-            next_thread_id: AtomicI32::new(1),
-            phantom_data: std::marker::PhantomData,
-        }
-    }
-
-}
-
-impl<T> Drop for FAAAQueue<T> {
-    fn drop(&mut self) {
-        unsafe { faaaq_destroy(self.raw) };
 
+    unsafe fn push(queue: FAAAQ, item: *mut c_void, tid: usize) -> bool {
+        faaaq_push(queue, item, tid as i32) == 1
     }
-}
-
-struct FAAAQHandle<'a, T> {
-    pub q: &'a FAAAQueue<T>
-}
 
-impl<T> HandleQueue<T> for FAAAQHandle<'_, T> {
-    fn push(&mut self, item: T) -> Result <(), T>{
-        let ptr: *mut std::ffi::c_void = Box::<T>::into_raw(Box::new(item)) as *mut std::ffi::c_void;
-        match self.q.push(ptr) {
-            true => Ok(()),
-            false => {
-                let reclaimed: Box<T> = unsafe { Box::from_raw(ptr as *mut T) };
-                Err(*reclaimed)
-            },
-        }
-    }
-
-    fn pop(&mut self) -> Option<T> {
-        let res = self.q.pop()?;
-        let val = unsafe { Box::from_raw(res as *const T as *mut T) };
-        Some(*val)
+    unsafe fn pop(queue: FAAAQ, item: *mut *mut c_void, tid: usize) -> bool {
+        faaaq_pop(queue, item, tid as i32) == 1
     }
 }
 
-impl<T> ConcurrentQueue<T> for FAAAQueue<T> {
-    fn register(&self) -> impl HandleQueue<T> {
-        FAAAQHandle {
-            q: self,
-        }
-    }
-
-    fn get_id(&self) -> String {
-        String::from("faaa_queue_cpp")
-    }
-
-    fn new(_capacity: usize) -> Self {
-        FAAAQueue::new()
-    }
-}
+// A safe Rust wrapper around the C bindings
+pub type FAAAQueue<T> = ExternQueue<T, FAAAQVtable>;
 
 #[cfg(test)]
 mod tests {
 
-    use Ordering::SeqCst;
+    use std::sync::atomic::{AtomicI32, Ordering::SeqCst};
 
     use super::*;
+    use benchmark_core::conformance::{TrackedItem, Tracking};
+    use benchmark_core::traits::{ConcurrentQueue, HandleQueue};
 
     #[test]
     fn create_faaaq() {
-        println!("creating faaaq for creation test");
-        let q: FAAAQueue<i32> = FAAAQueue::new();
-        let _ = q.push(Box::<i32>::into_raw(Box::new(32)) as *mut std::ffi::c_void);
-        let _ = q.push(Box::<i32>::into_raw(Box::new(33)) as *mut std::ffi::c_void);
-        let _ = q.push(Box::<i32>::into_raw(Box::new(34)) as *mut std::ffi::c_void);
-        let _ = q.push(Box::<i32>::into_raw(Box::new(35)) as *mut std::ffi::c_void);
-        let _ = q.push(Box::<i32>::into_raw(Box::new(36)) as *mut std::ffi::c_void);
-        println!("pushed values");
-        let val = unsafe {*(q.pop().unwrap() as *const i32)};
-        println!("dereference value");
-        assert_eq!(val, 32);
-        let val = unsafe {*(q.pop().unwrap() as *const i32)};
-        assert_eq!(val, 33);
-        let val = unsafe {*(q.pop().unwrap() as *const i32)};
-        assert_eq!(val, 34);
-        let val = unsafe {*(q.pop().unwrap() as *const i32)};
-        assert_eq!(val, 35);
-        let val = unsafe {*(q.pop().unwrap() as *const i32)};
-        assert_eq!(val, 36);
+        let q: FAAAQueue<i32> = FAAAQueue::new(0);
+        let id = q.thread_ids().unwrap().acquire().unwrap();
+        let tid = id.get();
+        for i in 32..37 {
+            let item = Box::into_raw(Box::new(i)) as *mut c_void;
+            assert!(unsafe { FAAAQVtable::push(q.as_raw(), item, tid) });
+        }
+        for i in 32..37 {
+            let mut item = std::ptr::null_mut();
+            assert!(unsafe { FAAAQVtable::pop(q.as_raw(), &mut item, tid) });
+            assert_eq!(*unsafe { Box::from_raw(item as *mut i32) }, i);
+        }
     }
     #[test]
     fn register_faaaq() {
         println!("creating faaaq for register test");
-        let q: FAAAQueue<i32> = FAAAQueue::new();
+        let q: FAAAQueue<i32> = FAAAQueue::new(0);
         let mut handle = q.register();
         handle.push(1).unwrap();
         handle.push(2).unwrap();
@@ -159,19 +77,36 @@ mod tests {
         assert_eq!(handle.pop().unwrap(), 4);
     }
     #[test]
+    fn recycles_thread_ids() {
+        // More threads than ids over time, spread over two queues
+        let q1: FAAAQueue<usize> = FAAAQueue::new(0);
+        let q2: FAAAQueue<usize> = FAAAQueue::new(0);
+        for i in 0..MAX_THREADS + 1 {
+            std::thread::scope(|s| {
+                s.spawn(|| {
+                    q1.register().push(i).unwrap();
+                    q2.register().push(i).unwrap();
+                });
+            });
+        }
+        assert_eq!(q1.thread_ids().unwrap().in_use(), 0);
+        assert_eq!(q1.register().pop(), Some(0));
+        assert_eq!(q2.register().pop(), Some(0));
+    }
+    #[test]
     #[ignore]
     fn test_order() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let q: FAAAQueue<i32> = FAAAQueue::new();
-        if benchmark_core::order::benchmark_order_i32(q, 20, 5, true, 10).is_err() {
-            panic!();
+        let q: FAAAQueue<u64> = FAAAQueue::new(0);
+        if let Err(e) = benchmark_core::order::benchmark_order(q, 10, 10, 5, true, 10) {
+            panic!("{e}");
         }
     }
 
     #[test]
     fn test_almost_full() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let queue: FAAAQueue<usize> = FAAAQueue::new();
+        let queue: FAAAQueue<usize> = FAAAQueue::new(0);
         let mut q = queue.register();
         for i in 0..1024{
             q.push(i).unwrap();
@@ -183,7 +118,7 @@ mod tests {
     #[test]
     fn test_double_buf_size() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let queue: FAAAQueue<usize> = FAAAQueue::new();
+        let queue: FAAAQueue<usize> = FAAAQueue::new(0);
         let mut q = queue.register();
         for i in 0..1024* 2{
             q.push(i).unwrap();
@@ -193,9 +128,23 @@ mod tests {
         }
     }
     #[test]
+    fn drop_frees_items() {
+        // The C++ queue only holds pointers, so the wrapper frees the items
+        let tracking = Tracking::start();
+        let queue: FAAAQueue<TrackedItem> = FAAAQueue::new(0);
+        let mut q = queue.register();
+        for id in 0..1024 * 2 {
+            q.push(TrackedItem::new(id)).unwrap();
+        }
+        q.pop().unwrap().assert_alive();
+        drop(q);
+        drop(queue);
+        tracking.assert_all_dropped_once();
+    }
+    #[test]
     fn multi_thread() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let q: FAAAQueue<i32> = FAAAQueue::new();
+        let q: FAAAQueue<i32> = FAAAQueue::new(0);
         let barrier = std::sync::Barrier::new(50);
         let sum = AtomicI32::new(0);
         std::thread::scope(|s| {
@@ -222,4 +171,6 @@ mod tests {
         }
         assert_eq!(thesum, sum.into_inner());
     }
+
+    benchmark_core::queue_conformance_tests!(FAAAQueue);
 }
@@ -1,8 +1,12 @@
 use std::arch::asm;
 #[allow(unused_imports)]
-use std::{mem::MaybeUninit, ptr::null_mut, sync::atomic::{AtomicBool, AtomicPtr as RawAtomicPtr, AtomicU64, AtomicUsize, Ordering}};
+use std::{mem::MaybeUninit, ptr::null_mut};
+// cas2 swaps a whole cell as one 16 byte word, so the cells stay std atomics
+use std::sync::atomic::{AtomicPtr as RawAtomicPtr, AtomicU64 as CellAtomicU64};
 #[allow(unused_imports)]
-use std::sync::atomic::Ordering::SeqCst as SeqCst;
+use benchmark_core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+#[allow(unused_imports)]
+use benchmark_core::sync::atomic::Ordering::SeqCst as SeqCst;
 #[allow(unused_imports)]
 use haphazard::{raw::Pointer, AtomicPtr as HpAtomicPtr, HazardPointer};
 #[allow(unused_imports)]
@@ -11,7 +15,11 @@ use log::{debug, error, trace};
 use benchmark_core::traits::{ConcurrentQueue, HandleQueue};
 use crossbeam::utils::CachePadded;
 
+#[cfg(not(loom))]
 static RING_SIZE: usize = 1024;
+// Small enough for the model checks to fill and wrap around a ring
+#[cfg(loom)]
+static RING_SIZE: usize = 4;
 // static MAX_THREADS: usize = 256;
 
 pub struct LCRQueue<T: std::fmt::Debug> {
@@ -95,16 +103,31 @@ impl<T: std::fmt::Debug> LCRQueue<T> {
                 hp.reset_protection();
                 return None;
             }
-            if let Ok(curr) = unsafe { self.head.compare_exchange_ptr(to_mut_ptr(crq), crq_next) } {
-                let old_ptr = curr.unwrap();
-                // self.crq_count.fetch_sub(1, Ordering::Relaxed);
-                unsafe {
-                    old_ptr.retire();
-                }
+            let v = self.leave_crq(crq, crq_next);
+            if v.is_some() {
+                return v;
             }
             hp.reset_protection();
         }
     }
+    /// Moves head from `crq`, which a dequeue found empty, to `crq_next`.
+    /// Returns an item instead if one got into `crq` in the meantime.
+    fn leave_crq(&self, crq: &CRQ<T>, crq_next: *mut CRQ<T>) -> Option<T> {
+        // The CRQ was closed before next was set, so items enqueued
+        // since the first dequeue are still in it
+        let v = crq.dequeue();
+        if v.is_some() {
+            return v;
+        }
+        if let Ok(curr) = unsafe { self.head.compare_exchange_ptr(to_mut_ptr(crq), crq_next) } {
+            let old_ptr = curr.unwrap();
+            // self.crq_count.fetch_sub(1, Ordering::Relaxed);
+            unsafe {
+                old_ptr.retire();
+            }
+        }
+        None
+    }
     fn enqueue(&self, item: T, hp: &mut HazardPointer) {
         trace!("Starting LCRQ enqueue");
         let mut inner_item = Box::into_raw(Box::new(item));
@@ -153,14 +176,14 @@ impl<T: std::fmt::Debug> LCRQueue<T> {
 #[derive(std::fmt::Debug)]
 #[repr(C, align(16))]
 struct Cell<E: std::fmt::Debug> {
-    safe_and_idx: AtomicU64,
+    safe_and_idx: CellAtomicU64,
     value: RawAtomicPtr<E>,
 }
 
 impl<E: std::fmt::Debug> Cell<E> {
     fn new() -> Self {
         Self {
-            safe_and_idx: AtomicU64::new(1),
+            safe_and_idx: CellAtomicU64::new(1),
             value: RawAtomicPtr::new(null_mut()),
         }
     }
@@ -231,9 +254,11 @@ impl<T: std::fmt::Debug> CRQ<T> {
                             }
                         }
                     } else {
-                        // mark node unsafe to prevent future enqueue
+                        // mark node unsafe to prevent future enqueue. The
+                        // node keeps its index, so the dequeuer it belongs to
+                        // can still take the item
                         trace!("Inner dequeue: Marking node unsafe to prevent future enqueue");
-                        if cas2_w(node, create_safe_idx(safe, h), val, create_safe_idx(false, h), val) {
+                        if cas2_w(node, create_safe_idx(safe, idx), val, create_safe_idx(false, idx), val) {
                             // Line 52
                             break;
                         }
@@ -409,6 +434,7 @@ impl<T: std::fmt::Debug> HandleQueue<T> for LCRQueueHandle<'_, T> {
 #[cfg(test)]
 mod tests {
     use std::sync::atomic::AtomicI32;
+    use std::time::Duration;
 
     use log::info;
 
@@ -509,12 +535,49 @@ mod tests {
         assert_eq!(thesum, sum.into_inner());
     }
     #[test]
+    fn dequeue_keeps_items_of_closed_crq() {
+        let q: LCRQueue<i32> = LCRQueue::new();
+        let mut hp = HazardPointer::new();
+        let crq = q.head.safe_load(&mut hp).unwrap();
+        // A dequeuer finds the CRQ empty
+        assert_eq!(crq.dequeue(), None);
+        // Before it moves head, an item is enqueued and another enqueuer
+        // closes the CRQ and appends a new one
+        assert!(crq.enqueue(Box::into_raw(Box::new(1))).is_ok());
+        crq.closed.store(true, SeqCst);
+        let crq_next = Box::into_raw(Box::new(CRQ::new()));
+        assert!(unsafe { crq.next.compare_exchange_ptr(null_mut(), crq_next) }.is_ok());
+        assert_eq!(q.leave_crq(crq, crq_next), Some(1));
+        assert_eq!(q.dequeue(&mut HazardPointer::new()), None);
+    }
+    #[test]
+    fn dequeue_passes_item_of_stalled_dequeuer() {
+        // Leaked, the dequeue below might never return
+        let crq: &'static CRQ<i32> = Box::leak(Box::new(CRQ::new()));
+        assert!(crq.enqueue(Box::into_raw(Box::new(1))).is_ok());
+        // The dequeuer of index 0 stalled before taking its item, and the
+        // others went around the ring back to its cell
+        crq.head.store(RING_SIZE as u64, SeqCst);
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || tx.send(crq.dequeue()).unwrap());
+        let v = rx
+            .recv_timeout(Duration::from_secs(5))
+            .expect("the dequeue waited for the stalled dequeuer");
+        assert_eq!(v, None);
+        // The cell is closed to enqueuers, but still belongs to index 0
+        assert_eq!(crq.ring[0].safe_and_idx(), (false, 0));
+        assert!(!crq.ring[0].value.load(SeqCst).is_null());
+    }
+    #[test]
     #[ignore]
     fn test_order() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let q: LCRQueue<i32> = LCRQueue::new();
-        if benchmark_core::order::benchmark_order_i32(q, 20, 5, true, 10).is_err() {
-            panic!();
+        let q: LCRQueue<u64> = LCRQueue::new();
+        if let Err(e) = benchmark_core::order::benchmark_order(q, 10, 10, 5, true, 10) {
+            panic!("{e}");
         }
     }
+
+    benchmark_core::queue_conformance_tests!(LCRQueue);
+    benchmark_core::queue_model_checks!(LCRQueue, size: 8, segment: RING_SIZE);
 }
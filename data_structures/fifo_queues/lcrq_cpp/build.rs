@@ -1,45 +1,8 @@
 fn main() {
-    use std::env;
-    use std::path::PathBuf;
-
-
-    let mut build = cc::Build::new();
-    build.cpp(true).flag("-std=c++20").include("/usr/include");
-
-
-    let mut bindgen = bindgen::Builder::default()
-        .clang_arg("-I/usr/include");
-
-    // Configure for LCRQ
-    {
-        let queue_location = "cpp_src";
-        println!("cargo:rerun-if-changed={}/lcrq_wrapper.hpp", queue_location);
-        println!("cargo:rerun-if-changed={}/lcrq_wrapper.cpp", queue_location);
-        println!("cargo:rerun-if-changed={}/LCRQueue.hpp", queue_location);
-        println!("cargo:rerun-if-changed={}/HazardPointers.hpp", queue_location);
-
-        build.file(format!("{}/lcrq_wrapper.cpp", queue_location))
-            .define("USE_LCRQUEUE", None);
-
-        bindgen = bindgen
-            .header(format!("{}/lcrq_wrapper.hpp", queue_location))
-            .allowlist_function("lcrq_.*")
-            .allowlist_type("LCRQ.*")
-            .opaque_type("LCRQImpl");
-    }
-
-    // Compile the C++ code
-    build.compile("queue_wrapper");
-
-    // Generate bindings
-    let bindings = bindgen
-        .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()))
-        .generate()
-        .expect("Unable to generate bindings");
-
-    // Write the bindings
-    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
-    bindings
-        .write_to_file(out_path.join("bindings.rs"))
-        .expect("Couldn't write bindings!");
+    ffi_queue::build::Wrapper::new("cpp_src", "lcrq_wrapper")
+        .define("USE_LCRQUEUE")
+        .depends_on(&["LCRQueue.hpp", "HazardPointers.hpp"])
+        .allowlist("lcrq_.*", "LCRQ.*")
+        .opaque_type("LCRQImpl")
+        .compile();
 }
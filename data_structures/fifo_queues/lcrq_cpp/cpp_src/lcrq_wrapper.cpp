@@ -9,7 +9,13 @@ struct LCRQImpl {
 };
 
 LCRQ lcrq_create(int max_threads) {
-    return new LCRQImpl(max_threads);
+    // Exceptions can't cross the C boundary, a failed allocation is
+    // reported as a null queue instead
+    try {
+        return new LCRQImpl(max_threads);
+    } catch (...) {
+        return nullptr;
+    }
 }
 
 void lcrq_destroy(LCRQ queue) {
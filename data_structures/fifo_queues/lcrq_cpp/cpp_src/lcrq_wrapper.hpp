@@ -7,6 +7,7 @@ extern "C" {
 
 typedef struct LCRQImpl* LCRQ;
 
+// Returns NULL if the queue could not be created
 LCRQ lcrq_create(int max_threads);
 void lcrq_destroy(LCRQ queue);
 int lcrq_push(LCRQ queue, void* item, const int tid);
@@ -2,145 +2,67 @@
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
 
-use std::{cell::Cell, sync::atomic::{AtomicI32, Ordering}};
-
-use benchmark_core::traits::{ConcurrentQueue, HandleQueue};
+use ffi_queue::{ExternQueue, Vtable};
+use std::ffi::c_void;
 
 // Include the generated bindings
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 
-thread_local! {
-    static THREAD_ID: Cell<Option<i32>> = const { Cell::new(None) };
-}
-
-static MAX_THREADS: i32 = 512;
-
-
-// A safe Rust wrapper around the C bindings
-pub struct LCRQueue<T> {
-    raw: LCRQ,
-    next_thread_id: AtomicI32,
-    phantom_data: std::marker::PhantomData<T>,
-}
-
-unsafe impl<T> Send for LCRQueue<T> {}
-unsafe impl<T> Sync for LCRQueue<T> {}
+const MAX_THREADS: usize = 512;
 
-#[allow(clippy::not_unsafe_ptr_arg_deref)]
-impl<T> LCRQueue<T> {
+/// The functions of `lcrq_wrapper.hpp`
+pub struct LCRQVtable;
 
-    pub fn push(&self, item: *mut std::ffi::c_void) -> bool {
-        let tid = self.get_thread_id();
-        unsafe { lcrq_push(self.raw, item, tid) == 1 }
-    }
+impl Vtable for LCRQVtable {
+    type Impl = LCRQImpl;
+    const NAME: &'static str = "lcrq_cpp";
+    const MAX_THREADS: Option<usize> = Some(MAX_THREADS);
 
-    pub fn pop(&self) -> Option<*mut std::ffi::c_void> {
-        let mut item: *mut std::ffi::c_void = std::ptr::null_mut();
-        let tid = self.get_thread_id();
-        let success = unsafe { lcrq_pop(self.raw, &mut item, tid) == 1 };
-        if success {
-            Some(item)
-        } else {
-            None
-        }
-    }
-    fn get_thread_id(&self) -> i32 {
-        THREAD_ID.with(|id| {
-            if let Some(tid) = id.get() {
-                tid
-            } else {
-                let new_id = self.next_thread_id.fetch_add(1, Ordering::Relaxed);
-                id.set(Some(new_id));
-                new_id
-            }
-        })
-    }
-    fn new() -> Self {
-        THREAD_ID.with(|id| {
-            id.set(Some(0));
-        });
-        let raw = unsafe { lcrq_create(MAX_THREADS) };
-        Self { raw, next_thread_id: AtomicI32::new(1), phantom_data: std::marker::PhantomData}
+    unsafe fn create(_capacity: usize) -> LCRQ {
+        lcrq_create(MAX_THREADS as i32)
     }
-}
-
-impl<T> Drop for LCRQueue<T> {
-    fn drop(&mut self) {
-        unsafe { lcrq_destroy(self.raw) };
 
+    unsafe fn destroy(queue: LCRQ) {
+        lcrq_destroy(queue)
     }
-}
-
-struct LCRQHandle<'a, T> {
-    pub q: &'a LCRQueue<T>
-}
 
-impl<T> HandleQueue<T> for LCRQHandle<'_, T> {
-    fn push(&mut self, item: T) -> Result<(), T> {
-        let ptr: *mut std::ffi::c_void = Box::<T>::into_raw(Box::new(item)) as *mut std::ffi::c_void;
-        match self.q.push(ptr) {
-            true => Ok(()),
-            false => {
-                let reclaimed: Box<T> = unsafe { Box::from_raw(ptr as *mut T) };
-                Err(*reclaimed)
-            },
-        }
+    unsafe fn push(queue: LCRQ, item: *mut c_void, tid: usize) -> bool {
+        lcrq_push(queue, item, tid as i32) == 1
     }
 
-    fn pop(&mut self) -> Option<T> {
-        let res = self.q.pop()?;
-        let val = unsafe { Box::from_raw(res as *const T as *mut T) };
-        Some(*val)
+    unsafe fn pop(queue: LCRQ, item: *mut *mut c_void, tid: usize) -> bool {
+        lcrq_pop(queue, item, tid as i32) == 1
     }
 }
 
-impl<T> ConcurrentQueue<T> for LCRQueue<T> {
-    fn register(&self) -> impl HandleQueue<T> {
-        LCRQHandle {
-            q: self,
-        }
-    }
-
-    fn get_id(&self) -> String {
-        String::from("lcrq_cpp")
-    }
-
-    fn new(_capacity: usize) -> Self {
-        LCRQueue::new()
-    }
-}
+// A safe Rust wrapper around the C bindings
+pub type LCRQueue<T> = ExternQueue<T, LCRQVtable>;
 
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use benchmark_core::traits::{ConcurrentQueue, HandleQueue};
 
     #[test]
     fn create_lcrq() {
-        println!("creating lcrq for creation test");
-        let q: LCRQueue<i32> = LCRQueue::new();
-        let _ = q.push(Box::<i32>::into_raw(Box::new(32)) as *mut std::ffi::c_void);
-        let _ = q.push(Box::<i32>::into_raw(Box::new(33)) as *mut std::ffi::c_void);
-        let _ = q.push(Box::<i32>::into_raw(Box::new(34)) as *mut std::ffi::c_void);
-        let _ = q.push(Box::<i32>::into_raw(Box::new(35)) as *mut std::ffi::c_void);
-        let _ = q.push(Box::<i32>::into_raw(Box::new(36)) as *mut std::ffi::c_void);
-        println!("pushed values");
-        let val = unsafe {*(q.pop().unwrap() as *const i32)};
-        println!("dereference value");
-        assert_eq!(val, 32);
-        let val = unsafe {*(q.pop().unwrap() as *const i32)};
-        assert_eq!(val, 33);
-        let val = unsafe {*(q.pop().unwrap() as *const i32)};
-        assert_eq!(val, 34);
-        let val = unsafe {*(q.pop().unwrap() as *const i32)};
-        assert_eq!(val, 35);
-        let val = unsafe {*(q.pop().unwrap() as *const i32)};
-        assert_eq!(val, 36);
+        let q: LCRQueue<i32> = LCRQueue::new(0);
+        let id = q.thread_ids().unwrap().acquire().unwrap();
+        let tid = id.get();
+        for i in 32..37 {
+            let item = Box::into_raw(Box::new(i)) as *mut c_void;
+            assert!(unsafe { LCRQVtable::push(q.as_raw(), item, tid) });
+        }
+        for i in 32..37 {
+            let mut item = std::ptr::null_mut();
+            assert!(unsafe { LCRQVtable::pop(q.as_raw(), &mut item, tid) });
+            assert_eq!(*unsafe { Box::from_raw(item as *mut i32) }, i);
+        }
     }
     #[test]
     fn register_lcrq() {
         println!("creating lcrq for register test");
-        let q: LCRQueue<i32> = LCRQueue::new();
+        let q: LCRQueue<i32> = LCRQueue::new(0);
         let mut handle = q.register();
         handle.push(1).unwrap();
         handle.push(2).unwrap();
@@ -152,12 +74,31 @@ mod tests {
         assert_eq!(handle.pop().unwrap(), 4);
     }
     #[test]
+    fn recycles_thread_ids() {
+        // More threads than ids over time, spread over two queues
+        let q1: LCRQueue<usize> = LCRQueue::new(0);
+        let q2: LCRQueue<usize> = LCRQueue::new(0);
+        for i in 0..MAX_THREADS + 1 {
+            std::thread::scope(|s| {
+                s.spawn(|| {
+                    q1.register().push(i).unwrap();
+                    q2.register().push(i).unwrap();
+                });
+            });
+        }
+        assert_eq!(q1.thread_ids().unwrap().in_use(), 0);
+        assert_eq!(q1.register().pop(), Some(0));
+        assert_eq!(q2.register().pop(), Some(0));
+    }
+    #[test]
     #[ignore]
     fn test_order() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let q: LCRQueue<i32> = LCRQueue::new();
-        if benchmark_core::order::benchmark_order_i32(q, 20, 5, true, 10).is_err() {
-            panic!();
+        let q: LCRQueue<u64> = LCRQueue::new(0);
+        if let Err(e) = benchmark_core::order::benchmark_order(q, 10, 10, 5, true, 10) {
+            panic!("{e}");
         }
     }
+
+    benchmark_core::queue_conformance_tests!(LCRQueue);
 }
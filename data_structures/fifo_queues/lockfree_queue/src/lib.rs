@@ -63,11 +63,13 @@ mod tests {
     #[ignore]
     fn test_order() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let q: LockfreeQueue<i32> = LockfreeQueue {
+        let q: LockfreeQueue<u64> = LockfreeQueue {
             lfq: lockfree::queue::Queue::new()
         };
-        if benchmark_core::order::benchmark_order_i32(q, 20, 5, true, 10).is_err() {
-            panic!();
+        if let Err(e) = benchmark_core::order::benchmark_order(q, 10, 10, 5, true, 10) {
+            panic!("{e}");
         }
     }
+
+    benchmark_core::queue_conformance_tests!(LockfreeQueue);
 }
@@ -1,17 +1,29 @@
-use std::{ptr::null_mut, sync::atomic::{AtomicBool, AtomicPtr as RawAtomicPtr, AtomicU64, AtomicUsize, Ordering}};
-use std::sync::atomic::Ordering::SeqCst as SeqCst;
+use std::ptr::null_mut;
+use benchmark_core::sync::atomic::{AtomicBool, AtomicPtr as RawAtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use benchmark_core::sync::atomic::Ordering::SeqCst as SeqCst;
+use benchmark_core::sync::thread_local;
 use haphazard::{AtomicPtr as HpAtomicPtr, HazardPointer};
 use log::{debug, trace};
 
 use benchmark_core::traits::{ConcurrentQueue, HandleQueue};
 use crossbeam::utils::CachePadded;
 
+#[cfg(not(loom))]
 static RING_SIZE: u64 = 1024;
+// Small enough for the model checks to fill and wrap around a ring
+#[cfg(loom)]
+static RING_SIZE: u64 = 4;
 // static MAX_THREADS: usize = 256;
 
+#[cfg(not(loom))]
 thread_local! {
     static THREAD_ID: std::cell::Cell<Option<usize>> = const {std::cell::Cell::new(None)};
 }
+// loom's thread_local! doesn't take const initializers
+#[cfg(loom)]
+thread_local! {
+    static THREAD_ID: std::cell::Cell<Option<usize>> = std::cell::Cell::new(None);
+}
 
 
 pub struct LPRQueue<E> {
@@ -49,24 +61,36 @@ impl<E: std::fmt::Debug> LPRQueue<E> {
                 Err(val) => inner_item = val,
             }
             // trace!("Enqueue failed. PRQ is full.");
-            let new_tail_ptr = Box::into_raw(Box::new(PRQ::new()));
-            let new_tail = unsafe { new_tail_ptr.as_ref().unwrap() };
-            // trace!("trying new enqueue, value of item is: {:?}", unsafe { inner_item.as_ref() });
-            let _ = new_tail.enqueue(inner_item, thread_id);
-            if unsafe { prq.next.compare_exchange_ptr(null_mut(), new_tail_ptr).is_ok() } {
-
-                // trace!("switched next pointer to new tail");
-                match unsafe { self.tail.compare_exchange_ptr(prq as *const _ as *mut _, new_tail_ptr) } {
-                    Ok(_) => trace!("tail swap success"),
-                    Err(_) => trace!("tail swap failure"),
-                }
-                return;
-            } else {
-                unsafe { drop(Box::from_raw(new_tail_ptr)); }
-                let _ = unsafe { self.tail.compare_exchange_ptr(prq as *const _ as *mut _, prq.next.load_ptr()) };
+            match self.append_prq(prq, inner_item, thread_id) {
+                Ok(()) => return,
+                Err(val) => inner_item = val,
             }
         }
     }
+    /// Appends a new PRQ holding `item` after `prq`, which is full. Hands
+    /// `item` back if another enqueuer appended its PRQ first.
+    fn append_prq(&self, prq: &PRQ<E>, item: *mut E, thread_id: usize) -> Result<(), *mut E> {
+        let new_tail_ptr = Box::into_raw(Box::new(PRQ::new()));
+        let new_tail = unsafe { new_tail_ptr.as_ref().unwrap() };
+        // trace!("trying new enqueue, value of item is: {:?}", unsafe { item.as_ref() });
+        let _ = new_tail.enqueue(item, thread_id);
+        if unsafe { prq.next.compare_exchange_ptr(null_mut(), new_tail_ptr).is_ok() } {
+
+            // trace!("switched next pointer to new tail");
+            match unsafe { self.tail.compare_exchange_ptr(prq as *const _ as *mut _, new_tail_ptr) } {
+                Ok(_) => trace!("tail swap success"),
+                Err(_) => trace!("tail swap failure"),
+            }
+            Ok(())
+        } else {
+            // Take the item back out, dropping the PRQ drops its items
+            let reclaimed_new = unsafe { Box::from_raw(new_tail_ptr) };
+            let item = Box::into_raw(Box::new(reclaimed_new.dequeue(thread_id).unwrap()));
+            drop(reclaimed_new);
+            let _ = unsafe { self.tail.compare_exchange_ptr(prq as *const _ as *mut _, prq.next.load_ptr()) };
+            Err(item)
+        }
+    }
     fn dequeue(&self, hp: &mut HazardPointer) -> Option<E> {
         let thread_id = self.get_thread_id();
         loop {
@@ -134,6 +158,17 @@ impl<E> Cell<E> {
     }
 }
 
+impl<E> Drop for Cell<E> {
+    fn drop(&mut self) {
+        // A cell holds an item, nothing, or the bottom value of an enqueuer
+        let ptr: *mut E = self.value.load(Ordering::SeqCst);
+        if !ptr.is_null() && !is_bottom(ptr) {
+            trace!("Dropping item in Cell.");
+            unsafe { drop(Box::from_raw(ptr)) };
+        }
+    }
+}
+
 #[allow(non_snake_case)]
 #[allow(clippy::upper_case_acronyms)]
 struct PRQ<E> {
@@ -365,6 +400,9 @@ impl<T: std::fmt::Debug> HandleQueue<T> for LPRQueueHandle<'_, T> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    // The tests run real threads, also when built for loom
+    use std::sync::atomic::AtomicUsize;
+    use benchmark_core::conformance::{TrackedItem, Tracking};
 
     #[test]
     fn create_lprqueue() {
@@ -440,12 +478,46 @@ mod tests {
         assert_eq!(thesum, sum.into_inner());
     }
     #[test]
+    fn drop_frees_items() {
+        let tracking = Tracking::start();
+        let q: LPRQueue<TrackedItem> = LPRQueue::new();
+        let mut hp = HazardPointer::new();
+        // Fill more than one PRQ
+        for id in 0..RING_SIZE as usize * 2 {
+            q.enqueue(TrackedItem::new(id), &mut hp);
+        }
+        q.dequeue(&mut hp).unwrap().assert_alive();
+        drop(q);
+        tracking.assert_all_dropped_once();
+    }
+    #[test]
+    fn losing_enqueuer_keeps_its_item() {
+        let tracking = Tracking::start();
+        let q: LPRQueue<TrackedItem> = LPRQueue::new();
+        let mut hp = HazardPointer::new();
+        let prq = q.tail.safe_load(&mut hp).unwrap();
+        // Another enqueuer appends a PRQ first
+        let other = Box::into_raw(Box::new(PRQ::new()));
+        assert!(unsafe { prq.next.compare_exchange_ptr(null_mut(), other) }.is_ok());
+        let item = Box::into_raw(Box::new(TrackedItem::new(1)));
+        let item = q.append_prq(prq, item, q.get_thread_id()).unwrap_err();
+        let item = unsafe { Box::from_raw(item) };
+        item.assert_alive();
+        assert_eq!(item.id, 1);
+        drop(item);
+        drop(q);
+        tracking.assert_all_dropped_once();
+    }
+    #[test]
     #[ignore]
     fn test_order() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let q: LPRQueue<i32> = LPRQueue::new();
-        if benchmark_core::order::benchmark_order_i32(q, 50, 10, true, 10).is_err() {
-            panic!();
+        let q: LPRQueue<u64> = LPRQueue::new();
+        if let Err(e) = benchmark_core::order::benchmark_order(q, 25, 25, 10, true, 10) {
+            panic!("{e}");
         }
     }
+
+    benchmark_core::queue_conformance_tests!(LPRQueue);
+    benchmark_core::queue_model_checks!(LPRQueue, size: 8, segment: RING_SIZE);
 }
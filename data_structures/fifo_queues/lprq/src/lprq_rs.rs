@@ -58,9 +58,9 @@ mod tests {
     #[test]
     #[ignore]
     fn test_order() {
-        let q: LPRQRS<i32> = LPRQRS::new(100);
-        if benchmark_core::order::benchmark_order_i32(q, 10, 5, true, 10).is_err() {
-            panic!();
+        let q: LPRQRS<u64> = LPRQRS::new(100);
+        if let Err(e) = benchmark_core::order::benchmark_order(q, 5, 5, 5, true, 10) {
+            panic!("{e}");
         }
     }
 }
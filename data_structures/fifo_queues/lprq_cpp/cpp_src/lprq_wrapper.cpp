@@ -9,7 +9,13 @@ struct LPRQImpl {
 };
 
 LPRQ lprq_create(int max_threads) {
-    return new LPRQImpl(max_threads);
+    // Exceptions can't cross the C boundary, a failed allocation is
+    // reported as a null queue instead
+    try {
+        return new LPRQImpl(max_threads);
+    } catch (...) {
+        return nullptr;
+    }
 }
 
 void lprq_destroy(LPRQ queue) {
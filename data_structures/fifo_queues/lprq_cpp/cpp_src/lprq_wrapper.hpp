@@ -6,6 +6,7 @@ extern "C" {
 
 typedef struct LPRQImpl* LPRQ;
 
+// Returns NULL if the queue could not be created
 LPRQ lprq_create(int max_threads);
 void lprq_destroy(LPRQ queue);
 int lprq_push(LPRQ queue, void* item, const int tid);
@@ -9,7 +9,13 @@ struct MoodyCamelConcurrentQueueImpl {
 };
 
 MoodyCamelConcurrentQueue moody_camel_create() {
-    return new MoodyCamelConcurrentQueueImpl();
+    // Exceptions can't cross the C boundary, a failed allocation is
+    // reported as a null queue instead
+    try {
+        return new MoodyCamelConcurrentQueueImpl();
+    } catch (...) {
+        return nullptr;
+    }
 }
 
 void moody_camel_destroy(MoodyCamelConcurrentQueue queue) {
@@ -23,3 +29,11 @@ int moody_camel_push(MoodyCamelConcurrentQueue queue, void *item) {
 int moody_camel_pop(MoodyCamelConcurrentQueue queue, void **item) {
     return queue->queue.try_dequeue(*item) ? 1 : 0;
 }
+
+int moody_camel_push_bulk(MoodyCamelConcurrentQueue queue, void **items, size_t count) {
+    return queue->queue.enqueue_bulk(items, count) ? 1 : 0;
+}
+
+size_t moody_camel_pop_bulk(MoodyCamelConcurrentQueue queue, void **items, size_t max) {
+    return queue->queue.try_dequeue_bulk(items, max);
+}
@@ -1,11 +1,14 @@
 #pragma once
 
+#include <stddef.h>
+
 #ifdef __cplusplus
 extern "C" {
 #endif
 
 typedef struct MoodyCamelConcurrentQueueImpl* MoodyCamelConcurrentQueue;
 
+// Returns NULL if the queue could not be created
 MoodyCamelConcurrentQueue moody_camel_create();
 
 void moody_camel_destroy(MoodyCamelConcurrentQueue queue);
@@ -14,6 +17,10 @@ int moody_camel_push(MoodyCamelConcurrentQueue queue, void* item);
 
 int moody_camel_pop(MoodyCamelConcurrentQueue queue, void** item);
 
+int moody_camel_push_bulk(MoodyCamelConcurrentQueue queue, void** items, size_t count);
+
+size_t moody_camel_pop_bulk(MoodyCamelConcurrentQueue queue, void** items, size_t max);
+
 #ifdef __cplusplus
 }
 #endif
@@ -2,118 +2,73 @@
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
 
-use benchmark_core::traits::{ConcurrentQueue, HandleQueue};
+use ffi_queue::{ExternQueue, Vtable};
+use std::ffi::c_void;
 
 // Include the generated bindings
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 
-// A safe Rust wrapper around the C bindings
-pub struct MoodyCamelCppQueue<T> {
-    raw: MoodyCamelConcurrentQueue,
-    phantom_data: std::marker::PhantomData<T>
-}
-
-unsafe impl<T> Send for MoodyCamelCppQueue<T> {}
-unsafe impl<T> Sync for MoodyCamelCppQueue<T> {}
+/// The functions of `moodycamel_wrapper.hpp`
+pub struct MoodyCamelVtable;
 
-#[allow(clippy::not_unsafe_ptr_arg_deref)]
-impl<T> MoodyCamelCppQueue<T> {
+impl Vtable for MoodyCamelVtable {
+    type Impl = MoodyCamelConcurrentQueueImpl;
+    const NAME: &'static str = "moodycamel_cpp";
 
-    pub fn push(&self, item: *mut std::ffi::c_void) -> bool {
-        unsafe { moody_camel_push(self.raw, item) == 1 }
+    unsafe fn create(_capacity: usize) -> MoodyCamelConcurrentQueue {
+        moody_camel_create()
     }
 
-    pub fn pop(&self) -> Option<*mut std::ffi::c_void> {
-        let mut item: *mut std::ffi::c_void = std::ptr::null_mut();
-        let success = unsafe { moody_camel_pop(self.raw, &mut item) == 1 };
-        if success {
-            Some(item)
-        } else {
-            None
-        }
-    }
-    fn new() -> Self {
-        let raw = unsafe { moody_camel_create() };
-        MoodyCamelCppQueue { raw, phantom_data: std::marker::PhantomData}
+    unsafe fn destroy(queue: MoodyCamelConcurrentQueue) {
+        moody_camel_destroy(queue)
     }
 
-}
-
-impl<T> Drop for MoodyCamelCppQueue<T> {
-    fn drop(&mut self) {
-        unsafe { moody_camel_destroy(self.raw) };
+    unsafe fn push(queue: MoodyCamelConcurrentQueue, item: *mut c_void, _tid: usize) -> bool {
+        moody_camel_push(queue, item) == 1
     }
-}
-
-struct MoodyCamelCppQueueHandle<'a,T> {
-    pub q: &'a MoodyCamelCppQueue<T>
-}
 
-impl<T> HandleQueue<T> for MoodyCamelCppQueueHandle<'_,T> {
-    fn push(&mut self, item: T) -> Result<(), T>{
-        let ptr: *mut std::ffi::c_void = Box::<T>::into_raw(Box::new(item)) as *mut std::ffi::c_void;
-        match self.q.push(ptr) {
-            true => {
-                Ok(())
-            },
-            false => {
-                // Unsure about this
-                let reclaimed_mem: Box<T> = unsafe { Box::from_raw(ptr as *mut T) };
-                Err(*reclaimed_mem)
-            },
-        }
+    unsafe fn pop(queue: MoodyCamelConcurrentQueue, item: *mut *mut c_void, _tid: usize) -> bool {
+        moody_camel_pop(queue, item) == 1
     }
 
-    fn pop(&mut self) -> Option<T> {
-        let res = self.q.pop()?;
-        let val = unsafe { Box::from_raw(res as *const T as *mut T) };
-        Some(*val)
-    }
-}
-
-impl<T> ConcurrentQueue<T> for MoodyCamelCppQueue<T> {
-    fn register(&self) -> impl HandleQueue<T> {
-        MoodyCamelCppQueueHandle {
-            q: self,
+    unsafe fn push_bulk(queue: MoodyCamelConcurrentQueue, items: &mut [*mut c_void], _tid: usize) -> usize {
+        // The bulk push is all or nothing
+        match moody_camel_push_bulk(queue, items.as_mut_ptr(), items.len()) {
+            1 => items.len(),
+            _ => 0,
         }
     }
 
-    fn get_id(&self) -> String {
-        String::from("moodycamel_cpp")
-    }
-
-    fn new(_capacity: usize) -> Self {
-        MoodyCamelCppQueue::new()
+    unsafe fn pop_bulk(queue: MoodyCamelConcurrentQueue, items: &mut [*mut c_void], _tid: usize) -> usize {
+        moody_camel_pop_bulk(queue, items.as_mut_ptr(), items.len())
     }
 }
 
+// A safe Rust wrapper around the C bindings
+pub type MoodyCamelCppQueue<T> = ExternQueue<T, MoodyCamelVtable>;
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use benchmark_core::traits::{ConcurrentQueue, HandleQueue, HandleQueueBatch};
 
     #[test]
     fn create_moody_camel() {
-        let q: MoodyCamelCppQueue<i32> = MoodyCamelCppQueue::new();
-        let _ = q.push(Box::<i32>::into_raw(Box::new(32)) as *mut std::ffi::c_void);
-        let _ = q.push(Box::<i32>::into_raw(Box::new(33)) as *mut std::ffi::c_void);
-        let _ = q.push(Box::<i32>::into_raw(Box::new(34)) as *mut std::ffi::c_void);
-        let _ = q.push(Box::<i32>::into_raw(Box::new(35)) as *mut std::ffi::c_void);
-        let _ = q.push(Box::<i32>::into_raw(Box::new(36)) as *mut std::ffi::c_void);
-        let val = unsafe {*(q.pop().unwrap() as *const i32)};
-        assert_eq!(val, 32);
-        let val = unsafe {*(q.pop().unwrap() as *const i32)};
-        assert_eq!(val, 33);
-        let val = unsafe {*(q.pop().unwrap() as *const i32)};
-        assert_eq!(val, 34);
-        let val = unsafe {*(q.pop().unwrap() as *const i32)};
-        assert_eq!(val, 35);
-        let val = unsafe {*(q.pop().unwrap() as *const i32)};
-        assert_eq!(val, 36);
+        let q: MoodyCamelCppQueue<i32> = MoodyCamelCppQueue::new(0);
+        for i in 32..37 {
+            let item = Box::into_raw(Box::new(i)) as *mut c_void;
+            assert!(unsafe { MoodyCamelVtable::push(q.as_raw(), item, 0) });
+        }
+        for i in 32..37 {
+            let mut item = std::ptr::null_mut();
+            assert!(unsafe { MoodyCamelVtable::pop(q.as_raw(), &mut item, 0) });
+            assert_eq!(*unsafe { Box::from_raw(item as *mut i32) }, i);
+        }
     }
     #[test]
     fn register_moody_camel() {
-        let q: MoodyCamelCppQueue<Box<i32>> = MoodyCamelCppQueue::new();
+        let q: MoodyCamelCppQueue<Box<i32>> = MoodyCamelCppQueue::new(0);
         let mut handle = q.register();
         handle.push(Box::new(1)).unwrap();
         handle.push(Box::new(2)).unwrap();
@@ -125,12 +80,27 @@ mod tests {
         assert_eq!(*handle.pop().unwrap(), 4);
     }
     #[test]
+    fn batch_moody_camel() {
+        let q: MoodyCamelCppQueue<Box<i32>> = MoodyCamelCppQueue::new(0);
+        let mut handle = q.register_batch();
+        let mut items: Vec<Box<i32>> = (0..10).map(Box::new).collect();
+        assert_eq!(handle.push_batch(&mut items), 10);
+        assert!(items.is_empty());
+        let mut buffer = vec![];
+        assert_eq!(handle.pop_batch(&mut buffer, 4), 4);
+        assert_eq!(handle.pop_batch(&mut buffer, 100), 6);
+        assert_eq!(handle.pop_batch(&mut buffer, 100), 0);
+        assert_eq!(buffer.into_iter().map(|b| *b).collect::<Vec<_>>(), (0..10).collect::<Vec<_>>());
+    }
+    #[test]
     #[ignore]
     fn test_order() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let q: MoodyCamelCppQueue<i32> = MoodyCamelCppQueue::new();
-        if benchmark_core::order::benchmark_order_i32(q, 20, 5, true, 10).is_err() {
-            panic!();
+        let q: MoodyCamelCppQueue<u64> = MoodyCamelCppQueue::new(0);
+        if let Err(e) = benchmark_core::order::benchmark_order(q, 10, 10, 5, true, 10) {
+            panic!("{e}");
         }
     }
+
+    benchmark_core::queue_conformance_tests!(MoodyCamelCppQueue);
 }
@@ -0,0 +1,103 @@
+// A bounded ring buffer behind a mutex, as an example of a queue plugin.
+// Build it from the root of the repository with:
+//   cc -O2 -shared -fPIC -I benchmark_core/include -o liblocked_queue.so
+//      data_structures/fifo_queues/plugin_queue/examples/locked_queue.c
+
+#include <pthread.h>
+#include <stdlib.h>
+
+#include "queue_plugin.h"
+
+typedef struct {
+    pthread_mutex_t lock;
+    void** items;
+    size_t capacity;
+    size_t head;
+    size_t len;
+} locked_queue;
+
+static const char* name(void) {
+    return "locked_queue_c";
+}
+
+static void* create(size_t capacity) {
+    if (capacity == 0) {
+        return NULL;
+    }
+    locked_queue* q = calloc(1, sizeof(locked_queue));
+    if (q == NULL) {
+        return NULL;
+    }
+    q->items = calloc(capacity, sizeof(void*));
+    if (q->items == NULL) {
+        free(q);
+        return NULL;
+    }
+    q->capacity = capacity;
+    pthread_mutex_init(&q->lock, NULL);
+    return q;
+}
+
+static void destroy(void* queue) {
+    locked_queue* q = queue;
+    pthread_mutex_destroy(&q->lock);
+    free(q->items);
+    free(q);
+}
+
+// Threads don't need any state of their own, the handle is the queue.
+static void* register_thread(void* queue) {
+    return queue;
+}
+
+static void unregister_thread(void* handle) {
+    (void)handle;
+}
+
+static int push(void* handle, void* item) {
+    locked_queue* q = handle;
+    int pushed = 0;
+    pthread_mutex_lock(&q->lock);
+    if (q->len < q->capacity) {
+        q->items[(q->head + q->len) % q->capacity] = item;
+        q->len++;
+        pushed = 1;
+    }
+    pthread_mutex_unlock(&q->lock);
+    return pushed;
+}
+
+static int pop(void* handle, void** item) {
+    locked_queue* q = handle;
+    int popped = 0;
+    pthread_mutex_lock(&q->lock);
+    if (q->len > 0) {
+        *item = q->items[q->head];
+        q->head = (q->head + 1) % q->capacity;
+        q->len--;
+        popped = 1;
+    }
+    pthread_mutex_unlock(&q->lock);
+    return popped;
+}
+
+static size_t capacity(void* queue) {
+    return ((locked_queue*)queue)->capacity;
+}
+
+static const queue_plugin plugin = {
+    .abi_version = QUEUE_PLUGIN_ABI_VERSION,
+    .name = name,
+    .create = create,
+    .destroy = destroy,
+    .register_thread = register_thread,
+    .unregister_thread = unregister_thread,
+    .push = push,
+    .pop = pop,
+    .capacity = capacity,
+    .max_threads = 0,
+};
+
+const queue_plugin* queue_plugin_entry(void) {
+    return &plugin;
+}
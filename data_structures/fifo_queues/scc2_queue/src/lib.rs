@@ -58,10 +58,11 @@ mod tests {
     #[ignore]
     fn test_order() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let q: SCC2Queue<i32> = SCC2Queue::new(10);
-        if benchmark_core::order::benchmark_order_i32(q, 20, 5, true, 10).is_err() {
-            panic!();
+        let q: SCC2Queue<u64> = SCC2Queue::new(10);
+        if let Err(e) = benchmark_core::order::benchmark_order(q, 10, 10, 5, true, 10) {
+            panic!("{e}");
         }
     }
-}
 
+    benchmark_core::queue_conformance_tests!(SCC2Queue, copy_items);
+}
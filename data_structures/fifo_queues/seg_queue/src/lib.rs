@@ -58,9 +58,11 @@ mod tests {
     #[test]
     #[ignore]
     fn test_order() {
-        let q: SQueue<i32> = SQueue::new(100);
-        if benchmark_core::order::benchmark_order_i32(q, 10, 5, true, 10).is_err() {
-            panic!();
+        let q: SQueue<u64> = SQueue::new(100);
+        if let Err(e) = benchmark_core::order::benchmark_order(q, 5, 5, 5, true, 10) {
+            panic!("{e}");
         }
     }
+
+    benchmark_core::queue_conformance_tests!(SQueue);
 }
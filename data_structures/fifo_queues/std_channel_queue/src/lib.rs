@@ -0,0 +1,204 @@
+use benchmark_core::traits::{BlockingConcurrentQueue, BlockingHandleQueue, ConcurrentQueue, HandleQueue};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Mutex, RwLock, TryLockError};
+use std::time::Duration;
+
+/// Reference implementation of the blocking API on top of an unbounded
+/// `std::sync::mpsc` channel.
+/// The std receiver can't be shared between threads, so consumers take turns
+/// through a mutex. A consumer waiting in `pop_timeout` first has to get the
+/// mutex, so it may wait longer than the timeout while another consumer is
+/// blocked on the channel. `pop` doesn't wait for the mutex, it returns
+/// `None` while another consumer holds it.
+/// Closing sends a `Close` message behind the remaining items. A consumer
+/// receiving it puts it back for the next one, so every waiting consumer
+/// wakes up once the items are gone. Pushes send while holding the read lock
+/// of `closed` and closing takes the write lock, so no item ends up behind
+/// the `Close` message.
+pub struct ChannelQueue<T>{
+    sender: Sender<Message<T>>,
+    receiver: Mutex<Receiver<Message<T>>>,
+    closed: RwLock<bool>,
+}
+
+enum Message<T> {
+    Item(T),
+    Close,
+}
+
+pub struct ChannelQueueHandle<'a, T>{
+    queue: & 'a ChannelQueue<T>
+}
+
+impl<T> ChannelQueueHandle<'_, T> {
+    fn receive(&self, message: Option<Message<T>>) -> Option<T> {
+        match message? {
+            Message::Item(item) => Some(item),
+            Message::Close => {
+                // Pass the close on to the next consumer
+                let _ = self.queue.sender.send(Message::Close);
+                None
+            }
+        }
+    }
+}
+
+impl <T> ConcurrentQueue<T> for ChannelQueue<T>{
+    fn register(&self) -> impl HandleQueue<T>{
+        ChannelQueueHandle{
+            queue: self,
+        }
+    }
+
+    fn get_id(&self) -> String {
+        String::from("std_channel_queue")
+    }
+
+    fn new(_size: usize) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        ChannelQueue {
+            sender,
+            receiver: Mutex::new(receiver),
+            closed: RwLock::new(false),
+        }
+    }
+}
+
+impl <T> BlockingConcurrentQueue<T> for ChannelQueue<T>{
+    fn register_blocking(&self) -> impl BlockingHandleQueue<T>{
+        ChannelQueueHandle{
+            queue: self,
+        }
+    }
+}
+
+impl <T> HandleQueue<T> for ChannelQueueHandle<'_, T> {
+    fn push(&mut self, value: T) -> Result<(), T> {
+        let closed = self.queue.closed.read().unwrap();
+        if *closed {
+            return Err(value);
+        }
+        match self.queue.sender.send(Message::Item(value)) {
+            Ok(_) => Ok(()),
+            Err(mpsc::SendError(Message::Item(value))) => Err(value),
+            Err(_) => unreachable!("only items are pushed"),
+        }
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        // A consumer blocked in `pop_blocking` holds the receiver until an
+        // item arrives, so waiting for it would block
+        let message = match self.queue.receiver.try_lock() {
+            Ok(receiver) => receiver.try_recv().ok(),
+            Err(TryLockError::WouldBlock) => None,
+            Err(TryLockError::Poisoned(e)) => panic!("{e}"),
+        };
+        self.receive(message)
+    }
+}
+
+impl <T> BlockingHandleQueue<T> for ChannelQueueHandle<'_, T> {
+    fn pop_blocking(&mut self) -> Option<T> {
+        let message = self.queue.receiver.lock().unwrap().recv().ok();
+        self.receive(message)
+    }
+
+    fn pop_timeout(&mut self, timeout: Duration) -> Option<T> {
+        let message = self.queue.receiver.lock().unwrap().recv_timeout(timeout).ok();
+        self.receive(message)
+    }
+
+    fn close(&mut self) {
+        let mut closed = self.queue.closed.write().unwrap();
+        if !*closed {
+            *closed = true;
+            let _ = self.queue.sender.send(Message::Close);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_cq() {
+        let q: ChannelQueue<i32> = ChannelQueue::new(100);
+        q.sender.send(Message::Item(1)).unwrap();
+        assert!(matches!(q.receiver.lock().unwrap().recv().unwrap(), Message::Item(1)));
+    }
+    #[test]
+    fn register_cq() {
+        let q: ChannelQueue<i32> = ChannelQueue::new(100);
+        let mut handle = q.register();
+        handle.push(1).unwrap();
+        assert_eq!(handle.pop().unwrap(), 1);
+    }
+    #[test]
+    fn close_cq() {
+        let q: ChannelQueue<i32> = ChannelQueue::new(100);
+        std::thread::scope(|s| {
+            let consumers: Vec<_> = (0..2).map(|_| s.spawn(|| {
+                let mut handle = q.register_blocking();
+                let mut popped = vec![];
+                while let Some(item) = handle.pop_blocking() {
+                    popped.push(item);
+                }
+                popped
+            })).collect();
+            let mut handle = q.register_blocking();
+            handle.push(1).unwrap();
+            handle.push(2).unwrap();
+            handle.close();
+            assert!(handle.push(3).is_err());
+            let popped: usize = consumers.into_iter().map(|c| c.join().unwrap().len()).sum();
+            assert_eq!(popped, 2);
+        });
+        assert!(q.register_blocking().pop_timeout(Duration::from_millis(1)).is_none());
+    }
+    #[test]
+    fn pop_with_blocked_consumer() {
+        let q: ChannelQueue<i32> = ChannelQueue::new(100);
+        std::thread::scope(|s| {
+            let consumer = s.spawn(|| q.register_blocking().pop_blocking());
+            // Wait until the consumer is blocked on the channel
+            while q.receiver.try_lock().is_ok() {
+                std::thread::yield_now();
+            }
+            let mut handle = q.register();
+            assert_eq!(handle.pop(), None);
+            handle.push(1).unwrap();
+            assert_eq!(consumer.join().unwrap(), Some(1));
+        });
+    }
+    #[test]
+    fn no_item_behind_close() {
+        let q: ChannelQueue<i32> = ChannelQueue::new(100);
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let mut handle = q.register();
+                    while handle.push(1).is_ok() {}
+                });
+            }
+            std::thread::sleep(Duration::from_millis(10));
+            q.register_blocking().close();
+        });
+        let mut closed = false;
+        while let Ok(message) = q.receiver.lock().unwrap().try_recv() {
+            assert!(!closed, "item behind the close message");
+            closed = matches!(message, Message::Close);
+        }
+        assert!(closed);
+    }
+    #[test]
+    #[ignore]
+    fn test_order() {
+        let q: ChannelQueue<u64> = ChannelQueue::new(100);
+        if let Err(e) = benchmark_core::order::benchmark_order(q, 5, 5, 5, true, 10) {
+            panic!("{e}");
+        }
+    }
+
+    benchmark_core::queue_conformance_tests!(ChannelQueue);
+}
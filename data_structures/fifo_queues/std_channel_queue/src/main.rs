@@ -0,0 +1,22 @@
+use log::*;
+use benchmark_core::benchmarks::fifo_queue_benchmarks::benchmark_blocking_fifo_queue;
+
+
+fn main() {
+    // initialize env_logger if not in silent release mode
+    #[cfg(not(all(not(debug_assertions), feature = "silent-release")))]
+    {
+        env_logger::init();
+        debug!("envlogger init");
+    }
+    log::info!("Starting benchmark");
+
+    match benchmark_blocking_fifo_queue::<std_channel_queue::ChannelQueue<usize>>("std_channel_queue") {
+        Ok(_) => println!("Benchmark done."),
+        Err(e) => {
+            eprintln!("Benchmark received error: {}", e);
+            println!("Benchmark exiting due to error.");
+            std::process::exit(e.exit_code());
+        }
+    }
+}
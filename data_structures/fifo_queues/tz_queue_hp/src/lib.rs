@@ -2,7 +2,8 @@
 use log::{debug, trace};
 
 use benchmark_core::traits::{ConcurrentQueue, HandleQueue};
-use std::{fmt::{Debug, Display}, sync::atomic::{AtomicUsize, Ordering}};
+use std::fmt::{Debug, Display};
+use benchmark_core::sync::atomic::{AtomicUsize, Ordering};
 use haphazard::HazardPointer;
 
 #[derive(Copy, Clone, Debug)]
@@ -198,6 +199,9 @@ impl<T: Copy + Send + Sync + Display> ConcurrentQueue<T> for TZQueue<T> {
     fn get_id(&self) -> String {
         String::from("tz_queue_hp")
     }
+    fn capacity(&self) -> Option<usize> {
+        Some(self.max_num - 1)
+    }
     fn register(&self) -> impl benchmark_core::traits::HandleQueue<T> {
         TZQueueHandle {
             q:      self,
@@ -350,9 +354,25 @@ mod tests {
     #[ignore]
     fn test_order() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let q: TZQueue<i32> = TZQueue::new(1000000);
-        if benchmark_core::order::benchmark_order_i32(q, 20, 5, true, 10).is_err() {
-            panic!();
+        let q: TZQueue<u64> = TZQueue::new(1000000);
+        if let Err(e) = benchmark_core::order::benchmark_order(q, 10, 10, 5, true, 10) {
+            panic!("{e}");
         }
     }
+
+    benchmark_core::queue_conformance_tests!(
+        TZQueue,
+        copy_items,
+        skip: [producer_order, conservation],
+        reason: "reorders and loses items under contention, see Known issues in the readme"
+    );
+    // The ring has two slots more than the capacity. The slots are haphazard
+    // atomic pointers that loom doesn't see, so it doesn't find the reordering
+    benchmark_core::queue_model_checks!(
+        TZQueue,
+        size: 4,
+        ring: 6,
+        skip: [two_producers_one_consumer],
+        reason: "reorders items under contention, see Known issues in the readme"
+    );
 }
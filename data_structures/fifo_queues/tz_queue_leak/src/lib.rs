@@ -189,6 +189,9 @@ impl<T: Copy + Send + Sync + Display> ConcurrentQueue<T> for TZQueue<T> {
     fn get_id(&self) -> String {
         String::from("tz_queue")
     }
+    fn capacity(&self) -> Option<usize> {
+        Some(self.max_num - 1)
+    }
     fn register(&self) -> impl HandleQueue<T> {
         TZQueueHandle {
             q: self
@@ -333,9 +336,16 @@ mod tests {
     #[ignore]
     fn test_order() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let q: TZQueue<i32> = TZQueue::new(10);
-        if benchmark_core::order::benchmark_order_i32(q, 20, 5, true, 10).is_err() {
-            panic!();
+        let q: TZQueue<u64> = TZQueue::new(10);
+        if let Err(e) = benchmark_core::order::benchmark_order(q, 10, 10, 5, true, 10) {
+            panic!("{e}");
         }
     }
+
+    benchmark_core::queue_conformance_tests!(
+        TZQueue,
+        copy_items,
+        skip: [producer_order, conservation],
+        reason: "reorders and loses items under contention, see Known issues in the readme"
+    );
 }
@@ -6,7 +6,8 @@ pub struct WFQueueHandle<'a, T> {
 }
 
 pub struct WFQueue<T> {
-    pub q: WfQueue<Box<T>>
+    pub q: WfQueue<Box<T>>,
+    capacity: usize,
 }
 
 impl<T> ConcurrentQueue<T> for WFQueue<T>
@@ -25,9 +26,14 @@ where
 
     fn new(_size: usize) -> Self {
         WFQueue {
-            q: WfQueue::new(_size)
+            q: WfQueue::new(_size),
+            capacity: _size,
         }
     }
+
+    fn capacity(&self) -> Option<usize> {
+        Some(self.capacity)
+    }
 }
 
 impl<T> HandleQueue<T> for WFQueueHandle<'_, T>
@@ -68,9 +74,11 @@ mod tests {
     #[ignore]
     fn test_order() {
         let _ = env_logger::builder().is_test(true).try_init();
-        let q: WFQueue<Box<i32>> = WFQueue::new(10);
-        if benchmark_core::order::benchmark_order_box(q, 20, 5, true, 10).is_err() {
-            panic!();
+        let q: WFQueue<Box<u64>> = WFQueue::new(10);
+        if let Err(e) = benchmark_core::order::benchmark_order(q, 10, 10, 5, true, 10) {
+            panic!("{e}");
         }
     }
+
+    benchmark_core::queue_conformance_tests!(WFQueue);
 }
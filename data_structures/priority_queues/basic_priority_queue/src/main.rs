@@ -19,6 +19,7 @@ fn main() {
         Err(e) => {
             eprintln!("Benchmark received error: {}", e);
             println!("Benchmark exiting due to error.");
+            std::process::exit(e.exit_code());
         }
     }
 }
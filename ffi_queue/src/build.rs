@@ -0,0 +1,101 @@
+//! Helpers for the build scripts of queue crates.
+
+use std::path::PathBuf;
+
+/// A C wrapper around a C++ queue, made of `<name>.hpp` and `<name>.cpp`
+/// in one directory. Compiling it also generates Rust bindings for the
+/// header in `$OUT_DIR/bindings.rs`.
+/// ```ignore
+/// ffi_queue::build::Wrapper::new("cpp_src", "lcrq_wrapper")
+///     .define("USE_LCRQUEUE")
+///     .depends_on(&["LCRQueue.hpp", "HazardPointers.hpp"])
+///     .allowlist("lcrq_.*", "LCRQ.*")
+///     .opaque_type("LCRQImpl")
+///     .compile();
+/// ```
+pub struct Wrapper {
+    dir: String,
+    name: String,
+    build: cc::Build,
+    bindgen: bindgen::Builder,
+}
+
+impl Wrapper {
+    /// Creates the wrapper `<dir>/<name>.hpp` and `<dir>/<name>.cpp`, with
+    /// paths relative to the crate.
+    pub fn new(dir: &str, name: &str) -> Self {
+        let mut build = cc::Build::new();
+        build.cpp(true).flag("-std=c++20").include("/usr/include");
+        let bindgen = bindgen::Builder::default().clang_arg("-I/usr/include");
+        Wrapper {
+            dir: dir.to_string(),
+            name: name.to_string(),
+            build,
+            bindgen,
+        }
+    }
+
+    /// Defines the preprocessor macro `name` when compiling the wrapper.
+    pub fn define(mut self, name: &str) -> Self {
+        self.build.define(name, None);
+        self
+    }
+
+    /// Adds `<dir>/<path>` to the include paths of the compiler and bindgen.
+    pub fn include(mut self, path: &str) -> Self {
+        let path = format!("{}/{path}", self.dir);
+        self.build.include(&path);
+        self.bindgen = self.bindgen.clang_arg(format!("-I{path}"));
+        self
+    }
+
+    /// Rebuilds the wrapper when one of the headers it includes changes.
+    /// Paths are relative to the directory of the wrapper.
+    pub fn depends_on(self, headers: &[&str]) -> Self {
+        for header in headers {
+            println!("cargo:rerun-if-changed={}/{header}", self.dir);
+        }
+        self
+    }
+
+    /// Only generates bindings for the functions and types matching the
+    /// regular expressions, e.g. the prefix of the wrapper functions.
+    pub fn allowlist(mut self, functions: &str, types: &str) -> Self {
+        self.bindgen = self
+            .bindgen
+            .allowlist_function(functions)
+            .allowlist_type(types);
+        self
+    }
+
+    /// Keeps the type behind the queue pointer opaque in the bindings.
+    pub fn opaque_type(mut self, name: &str) -> Self {
+        self.bindgen = self.bindgen.opaque_type(name);
+        self
+    }
+
+    /// Compiles the wrapper and writes the bindings.
+    pub fn compile(mut self) {
+        let header = format!("{}/{}.hpp", self.dir, self.name);
+        let source = format!("{}/{}.cpp", self.dir, self.name);
+        println!("cargo:rerun-if-changed={header}");
+        println!("cargo:rerun-if-changed={source}");
+
+        // Compile the C++ code
+        self.build.file(&source).compile("queue_wrapper");
+
+        // Generate bindings
+        let bindings = self
+            .bindgen
+            .header(header)
+            .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()))
+            .generate()
+            .expect("Unable to generate bindings");
+
+        // Write the bindings
+        let out_path = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+        bindings
+            .write_to_file(out_path.join("bindings.rs"))
+            .expect("Couldn't write bindings!");
+    }
+}
@@ -0,0 +1,14 @@
+//! Glue for queues written in C or C++.
+//!
+//! A queue crate declares the C functions of its wrapper with a `Vtable`
+//! and benchmarks `ExternQueue<T, YourVtable>`, which boxes the items and
+//! hands out thread ids. Its build script compiles the wrapper and generates
+//! the bindings with `build::Wrapper`, enabled by the `build` feature.
+
+#[cfg(feature = "build")]
+pub mod build;
+#[cfg(feature = "queue")]
+mod queue;
+
+#[cfg(feature = "queue")]
+pub use queue::{ExternQueue, Vtable};
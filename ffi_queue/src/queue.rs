@@ -0,0 +1,389 @@
+use benchmark_core::error::Error;
+use benchmark_core::traits::{
+    AllocatedId, Capabilities, ConcurrentQueue, HandleQueue, HandleQueueBatch,
+    ThreadIdAllocator,
+};
+use std::ffi::c_void;
+use std::marker::PhantomData;
+
+/// The C functions of a wrapped queue.
+///
+/// Items are passed as pointers to boxed values, the C++ queue only stores
+/// and returns them. Queues that keep per-thread state get a thread id from
+/// `0..MAX_THREADS` with every call, which no other handle uses at the same
+/// time. Queues without thread ids get 0.
+///
+/// # Safety
+/// The functions are only called with a queue returned by `create` that
+/// was not destroyed yet, and with items that `ExternQueue` boxed.
+#[allow(clippy::missing_safety_doc)]
+pub trait Vtable {
+    /// The opaque type behind the queue pointer, as generated by bindgen.
+    type Impl;
+    /// The name the queue reports with `get_id`.
+    const NAME: &'static str;
+    /// The size of the thread id tables of the queue, `None` for queues
+    /// that don't take thread ids.
+    const MAX_THREADS: Option<usize> = None;
+
+    /// Creates a queue. `capacity` is ignored by unbounded queues.
+    /// Returns null if the queue could not be created.
+    unsafe fn create(capacity: usize) -> *mut Self::Impl;
+    /// Destroys an empty queue.
+    unsafe fn destroy(queue: *mut Self::Impl);
+    /// Pushes `item`, returns false if the queue is full.
+    unsafe fn push(queue: *mut Self::Impl, item: *mut c_void, tid: usize) -> bool;
+    /// Pops an item into `item`, returns false if the queue is empty.
+    unsafe fn pop(queue: *mut Self::Impl, item: *mut *mut c_void, tid: usize) -> bool;
+    /// Returns how many items the queue holds at most, `None` for unbounded
+    /// queues.
+    unsafe fn capacity(_queue: *mut Self::Impl) -> Option<usize> {
+        None
+    }
+    /// Pushes `items` from the front until the queue is full, returns how
+    /// many were pushed. Pushes one item at a time unless overridden.
+    unsafe fn push_bulk(queue: *mut Self::Impl, items: &mut [*mut c_void], tid: usize) -> usize {
+        let mut pushed = 0;
+        while pushed < items.len() && unsafe { Self::push(queue, items[pushed], tid) } {
+            pushed += 1;
+        }
+        pushed
+    }
+    /// Pops up to `items.len()` items into `items`, returns how many were
+    /// popped. Pops one item at a time unless overridden.
+    unsafe fn pop_bulk(queue: *mut Self::Impl, items: &mut [*mut c_void], tid: usize) -> usize {
+        let mut popped = 0;
+        while popped < items.len() && unsafe { Self::pop(queue, &mut items[popped], tid) } {
+            popped += 1;
+        }
+        popped
+    }
+}
+
+/// A `ConcurrentQueue` over the C functions of `V`.
+pub struct ExternQueue<T, V: Vtable> {
+    raw: *mut V::Impl,
+    thread_ids: Option<ThreadIdAllocator>,
+    phantom_data: PhantomData<(T, V)>,
+}
+
+// The wrapped queues are concurrent queues, and they only hold pointers to
+// the items. The items move between threads through them, so they must be Send.
+unsafe impl<T: Send, V: Vtable> Send for ExternQueue<T, V> {}
+unsafe impl<T: Send, V: Vtable> Sync for ExternQueue<T, V> {}
+
+impl<T, V: Vtable> ExternQueue<T, V> {
+    /// Returns the pointer to the C++ queue, to call its functions directly.
+    pub fn as_raw(&self) -> *mut V::Impl {
+        self.raw
+    }
+
+    /// Returns the allocator of the thread ids, `None` for queues that
+    /// don't take thread ids.
+    pub fn thread_ids(&self) -> Option<&ThreadIdAllocator> {
+        self.thread_ids.as_ref()
+    }
+
+    fn handle(&self) -> ExternHandle<'_, T, V> {
+        let tid = self.thread_ids.as_ref().map(|ids| {
+            ids.acquire().unwrap_or_else(|| {
+                panic!(
+                    "{} supports at most {} registered handles at once",
+                    V::NAME,
+                    ids.max()
+                )
+            })
+        });
+        ExternHandle {
+            q: self,
+            tid,
+            ptrs: Vec::new(),
+        }
+    }
+}
+
+impl<T, V: Vtable> Drop for ExternQueue<T, V> {
+    fn drop(&mut self) {
+        // Free the items still in the queue, the C++ queue only holds pointers to them.
+        // No handle outlives the queue, so every thread id is free.
+        let mut item: *mut c_void = std::ptr::null_mut();
+        while unsafe { V::pop(self.raw, &mut item, 0) } {
+            drop(unsafe { Box::from_raw(item as *mut T) });
+        }
+        unsafe { V::destroy(self.raw) };
+    }
+}
+
+struct ExternHandle<'a, T, V: Vtable> {
+    q: &'a ExternQueue<T, V>,
+    tid: Option<AllocatedId<'a>>,
+    // Reused for the pointers of bulk operations
+    ptrs: Vec<*mut c_void>,
+}
+
+impl<T, V: Vtable> ExternHandle<'_, T, V> {
+    fn tid(&self) -> usize {
+        self.tid.as_ref().map_or(0, AllocatedId::get)
+    }
+}
+
+impl<T, V: Vtable> HandleQueue<T> for ExternHandle<'_, T, V> {
+    fn push(&mut self, item: T) -> Result<(), T> {
+        let ptr = Box::into_raw(Box::new(item)) as *mut c_void;
+        match unsafe { V::push(self.q.raw, ptr, self.tid()) } {
+            true => Ok(()),
+            false => Err(*unsafe { Box::from_raw(ptr as *mut T) }),
+        }
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        let mut item: *mut c_void = std::ptr::null_mut();
+        match unsafe { V::pop(self.q.raw, &mut item, self.tid()) } {
+            true => Some(*unsafe { Box::from_raw(item as *mut T) }),
+            false => None,
+        }
+    }
+}
+
+impl<T, V: Vtable> HandleQueueBatch<T> for ExternHandle<'_, T, V> {
+    fn push_batch(&mut self, items: &mut Vec<T>) -> usize {
+        self.ptrs.clear();
+        self.ptrs.extend(
+            items
+                .drain(..)
+                .map(|item| Box::into_raw(Box::new(item)) as *mut c_void),
+        );
+        let tid = self.tid();
+        let pushed = unsafe { V::push_bulk(self.q.raw, &mut self.ptrs, tid) };
+        // Give back the items that did not fit
+        items.extend(
+            self.ptrs
+                .drain(pushed..)
+                .map(|ptr| *unsafe { Box::from_raw(ptr as *mut T) }),
+        );
+        pushed
+    }
+
+    fn pop_batch(&mut self, buffer: &mut Vec<T>, max: usize) -> usize {
+        self.ptrs.clear();
+        self.ptrs.resize(max, std::ptr::null_mut());
+        let tid = self.tid();
+        let popped = unsafe { V::pop_bulk(self.q.raw, &mut self.ptrs, tid) };
+        buffer.extend(
+            self.ptrs[..popped]
+                .iter()
+                .map(|&ptr| *unsafe { Box::from_raw(ptr as *mut T) }),
+        );
+        popped
+    }
+}
+
+impl<T, V: Vtable> ConcurrentQueue<T> for ExternQueue<T, V> {
+    fn register(&self) -> impl HandleQueue<T> {
+        self.handle()
+    }
+
+    fn register_batch(&self) -> impl HandleQueueBatch<T> {
+        self.handle()
+    }
+
+    fn get_id(&self) -> String {
+        String::from(V::NAME)
+    }
+
+    fn new(capacity: usize) -> Self {
+        Self::try_new(capacity).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    fn try_new(capacity: usize) -> Result<Self, Error> {
+        let raw = unsafe { V::create(capacity) };
+        if raw.is_null() {
+            return Err(Error::QueueConstruction(format!(
+                "{} returned a null queue for capacity {capacity}",
+                V::NAME
+            )));
+        }
+        Ok(ExternQueue {
+            raw,
+            thread_ids: V::MAX_THREADS.map(ThreadIdAllocator::new),
+            phantom_data: PhantomData,
+        })
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        unsafe { V::capacity(self.raw) }
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        // Thread ids index fixed tables on the C++ side, every handle holds
+        // one until it is dropped
+        Capabilities {
+            max_threads: V::MAX_THREADS,
+            ..Capabilities::MPMC
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    /// A queue behind a mutex that checks the thread ids it gets.
+    struct LockedQueue {
+        items: Mutex<VecDeque<usize>>,
+        capacity: usize,
+    }
+
+    struct Locked;
+
+    impl Vtable for Locked {
+        type Impl = LockedQueue;
+        const NAME: &'static str = "locked_queue";
+        const MAX_THREADS: Option<usize> = Some(4);
+
+        unsafe fn create(capacity: usize) -> *mut LockedQueue {
+            let queue = LockedQueue {
+                items: Mutex::new(VecDeque::new()),
+                capacity,
+            };
+            Box::into_raw(Box::new(queue))
+        }
+
+        unsafe fn destroy(queue: *mut LockedQueue) {
+            let queue = unsafe { Box::from_raw(queue) };
+            assert!(queue.items.lock().unwrap().is_empty());
+        }
+
+        unsafe fn push(queue: *mut LockedQueue, item: *mut c_void, tid: usize) -> bool {
+            assert!(tid < 4);
+            let queue = unsafe { &*queue };
+            let mut items = queue.items.lock().unwrap();
+            if items.len() == queue.capacity {
+                return false;
+            }
+            items.push_back(item as usize);
+            true
+        }
+
+        unsafe fn pop(queue: *mut LockedQueue, item: *mut *mut c_void, tid: usize) -> bool {
+            assert!(tid < 4);
+            match unsafe { &*queue }.items.lock().unwrap().pop_front() {
+                Some(popped) => {
+                    unsafe { *item = popped as *mut c_void };
+                    true
+                }
+                None => false,
+            }
+        }
+
+        unsafe fn capacity(queue: *mut LockedQueue) -> Option<usize> {
+            Some(unsafe { &*queue }.capacity)
+        }
+
+        unsafe fn push_bulk(
+            queue: *mut LockedQueue,
+            items: &mut [*mut c_void],
+            _tid: usize,
+        ) -> usize {
+            let queue = unsafe { &*queue };
+            let mut queued = queue.items.lock().unwrap();
+            if queued.len() + items.len() > queue.capacity {
+                return 0;
+            }
+            queued.extend(items.iter().map(|&item| item as usize));
+            items.len()
+        }
+
+        unsafe fn pop_bulk(
+            queue: *mut LockedQueue,
+            items: &mut [*mut c_void],
+            _tid: usize,
+        ) -> usize {
+            let mut queued = unsafe { &*queue }.items.lock().unwrap();
+            let popped = items.len().min(queued.len());
+            for (slot, item) in items.iter_mut().zip(queued.drain(..popped)) {
+                *slot = item as *mut c_void;
+            }
+            popped
+        }
+    }
+
+    type LockedExternQueue<T> = ExternQueue<T, Locked>;
+
+    /// `Locked` without its bulk operations.
+    struct OneByOne;
+
+    impl Vtable for OneByOne {
+        type Impl = LockedQueue;
+        const NAME: &'static str = "one_by_one_queue";
+
+        unsafe fn create(capacity: usize) -> *mut LockedQueue {
+            unsafe { Locked::create(capacity) }
+        }
+
+        unsafe fn destroy(queue: *mut LockedQueue) {
+            unsafe { Locked::destroy(queue) }
+        }
+
+        unsafe fn push(queue: *mut LockedQueue, item: *mut c_void, _tid: usize) -> bool {
+            unsafe { Locked::push(queue, item, 0) }
+        }
+
+        unsafe fn pop(queue: *mut LockedQueue, item: *mut *mut c_void, _tid: usize) -> bool {
+            unsafe { Locked::pop(queue, item, 0) }
+        }
+    }
+
+    #[test]
+    fn recycles_thread_ids() {
+        let q: LockedExternQueue<usize> = LockedExternQueue::new(16);
+        for i in 0..8 {
+            std::thread::scope(|s| {
+                s.spawn(|| q.register().push(i).unwrap());
+            });
+        }
+        assert_eq!(q.thread_ids().unwrap().in_use(), 0);
+        assert_eq!(q.capabilities().max_threads, Some(4));
+        assert_eq!(q.register().pop(), Some(0));
+    }
+
+    #[test]
+    fn bulk_push_is_all_or_nothing() {
+        let q: LockedExternQueue<Box<usize>> = LockedExternQueue::new(8);
+        let mut handle = q.register_batch();
+        let mut items: Vec<Box<usize>> = (0..6).map(Box::new).collect();
+        assert_eq!(handle.push_batch(&mut items), 6);
+        let mut items: Vec<Box<usize>> = (6..10).map(Box::new).collect();
+        assert_eq!(handle.push_batch(&mut items), 0);
+        assert_eq!(items.len(), 4);
+        let mut buffer = vec![];
+        assert_eq!(handle.pop_batch(&mut buffer, 4), 4);
+        assert_eq!(handle.pop_batch(&mut buffer, 100), 2);
+        assert_eq!(
+            buffer.into_iter().map(|b| *b).collect::<Vec<_>>(),
+            (0..6).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn default_bulk_ops_go_one_by_one() {
+        let q: ExternQueue<Box<usize>, OneByOne> = ExternQueue::try_new(8).unwrap();
+        let mut handle = q.register_batch();
+        let mut items: Vec<Box<usize>> = (0..6).map(Box::new).collect();
+        assert_eq!(handle.push_batch(&mut items), 6);
+        let mut items: Vec<Box<usize>> = (6..10).map(Box::new).collect();
+        assert_eq!(handle.push_batch(&mut items), 2);
+        assert_eq!(items.into_iter().map(|b| *b).collect::<Vec<_>>(), [8, 9]);
+        let mut buffer = vec![];
+        assert_eq!(handle.pop_batch(&mut buffer, 5), 5);
+        assert_eq!(handle.pop_batch(&mut buffer, 100), 3);
+        assert_eq!(
+            buffer.into_iter().map(|b| *b).collect::<Vec<_>>(),
+            (0..8).collect::<Vec<_>>()
+        );
+    }
+
+    benchmark_core::queue_conformance_tests!(LockedExternQueue);
+}
@@ -37,7 +37,7 @@ for PACKAGE in "${PACKAGE_ARRAY[@]}"; do
     # Loop through thread counts and run cargo command
     for ((i = PRODUCERS_START; i <= PRODUCERS_END; i += STEP)); do
         echo "Running with producer count: $i"
-        time ./target/release/${PACKAGE} -t 1 -i 10 --path $OUTPUT/$PACKAGE prod-con -p $i -c 1
+        time ./target/release/${PACKAGE} -t 1 -i 10 --path $OUTPUT/$PACKAGE mpsc -p $i
     done
 done
 
@@ -30,8 +30,8 @@ for PACKAGE in "${PACKAGE_ARRAY[@]}"; do
     cargo build --release -p "$PACKAGE"
     # Loop through thread counts and run cargo command
     for ((i = CONSUMERS_START; i <= CONSUMERS_END; i += STEP)); do
-        echo "Running with producer count: $i on feature $PACKAGE"
-        time ./target/release/${PACKAGE} -t 1 -i 10 --path $OUTPUT/$PACKAGE basic -p 1 -c $i
+        echo "Running with consumer count: $i on package $PACKAGE"
+        time ./target/release/${PACKAGE} -t 1 -i 10 --path $OUTPUT/$PACKAGE spmc -c $i
     done
 done
 